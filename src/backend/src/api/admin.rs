@@ -9,9 +9,10 @@ use shared::{
 };
 
 use crate::{
+    bitcoin::utxo_cache::with_utxo_cache,
     state::{read_config, read_state},
     types::StoredPrincipal,
-    utils::guards::caller_is_allowed,
+    utils::{guards::caller_is_allowed, housekeeping::swept_btc_pending_transactions},
 };
 
 /// Gets the canister configuration.
@@ -32,7 +33,49 @@ pub fn http_request(request: HttpRequest) -> HttpResponse {
         .next()
         .unwrap_or_else(|| unreachable!("Even splitting an empty string yields one entry"));
     match path {
-        "/metrics" => get_metrics(),
+        "/metrics" => get_metrics(|w| {
+            #[expect(clippy::cast_precision_loss)]
+            w.encode_counter(
+                "ic_eth_wallet_btc_swept_pending_transactions",
+                swept_btc_pending_transactions() as f64,
+                "Number of stale pending bitcoin transactions removed by the housekeeping sweep \
+                 since the last upgrade",
+            )?;
+            let (hits, misses, entries, utxos) = with_utxo_cache(|cache| {
+                (
+                    cache.hits(),
+                    cache.misses(),
+                    cache.len(),
+                    cache.utxo_count(),
+                )
+            });
+            #[expect(clippy::cast_precision_loss)]
+            w.encode_counter(
+                "ic_eth_wallet_btc_utxo_cache_hits",
+                hits as f64,
+                "Number of multi-page bitcoin UTXO sets served from the cache since the last \
+                 upgrade",
+            )?;
+            #[expect(clippy::cast_precision_loss)]
+            w.encode_counter(
+                "ic_eth_wallet_btc_utxo_cache_misses",
+                misses as f64,
+                "Number of multi-page bitcoin UTXO sets read in full because they were not cached \
+                 at the current tip, since the last upgrade",
+            )?;
+            #[expect(clippy::cast_precision_loss)]
+            w.encode_gauge(
+                "ic_eth_wallet_btc_utxo_cache_entries",
+                entries as f64,
+                "Number of bitcoin UTXO sets in the cache",
+            )?;
+            #[expect(clippy::cast_precision_loss)]
+            w.encode_gauge(
+                "ic_eth_wallet_btc_utxo_cache_utxos",
+                utxos as f64,
+                "Number of bitcoin UTXOs in the cache, summed over its sets",
+            )
+        }),
         _ => HttpResponse {
             status_code: 404,
             headers: vec![],
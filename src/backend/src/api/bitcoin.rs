@@ -1,32 +1,120 @@
 use std::collections::HashSet;
 
-use ic_cdk::{api::time, query, update};
+use candid::Principal;
+use ic_cdk::{
+    api::{
+        management_canister::bitcoin::{BitcoinNetwork, Outpoint, Utxo},
+        time,
+    },
+    query, update,
+};
 use shared::types::{
+    account::BtcAddress,
     bitcoin::{
-        BtcAddPendingTransactionError, BtcAddPendingTransactionRequest,
-        BtcGetFeePercentilesRequest, BtcGetFeePercentilesResponse, BtcGetPendingTransactionsError,
-        BtcGetPendingTransactionsReponse, BtcGetPendingTransactionsRequest, PendingTransaction,
-        SelectedUtxosFeeError, SelectedUtxosFeeRequest, SelectedUtxosFeeResponse,
-        StoredPendingTransaction,
+        BtcAddPendingTransactionError, BtcAddPendingTransactionRequest, BtcAddressType,
+        BtcBuildPsbtError, BtcBuildPsbtRequest, BtcBuildPsbtResponse, BtcChangeOutput,
+        BtcDepositSubscription, BtcDustUtxo, BtcFeePriority, BtcFeeQuote, BtcFeeRate,
+        BtcFeeTiersConfig, BtcGetBalanceError, BtcGetBalanceRequest, BtcGetBalanceResponse,
+        BtcGetConsolidationAdviceError, BtcGetConsolidationAdviceRequest,
+        BtcGetConsolidationAdviceResponse, BtcGetDepositEventsRequest, BtcGetDepositEventsResponse,
+        BtcGetDustReportError, BtcGetDustReportRequest, BtcGetDustReportResponse,
+        BtcGetFeeBumpQuotesError, BtcGetFeeBumpQuotesRequest, BtcGetFeeBumpQuotesResponse,
+        BtcGetFeeHistoryRequest, BtcGetFeeHistoryResponse, BtcGetFeePercentilesRequest,
+        BtcGetFeePercentilesResponse, BtcGetPendingTransactionsError,
+        BtcGetPendingTransactionsReponse, BtcGetPendingTransactionsRequest,
+        BtcGetSendHistoryRequest, BtcGetSendHistoryResponse, BtcPendingTransactionStatus,
+        BtcReplacePendingTransactionError, BtcReplacePendingTransactionRequest,
+        BtcSendTransactionError, BtcSendTransactionRequest, BtcSendTransactionResponse,
+        BtcSubscribeDepositsError, BtcSubscribeDepositsRequest, BtcTxOutput,
+        BtcUnsubscribeDepositsRequest, BtcUpdateFrozenUtxosRequest, PendingTransaction,
+        SelectedUtxosFeeError, SelectedUtxosFeeOutput, SelectedUtxosFeeRequest,
+        SelectedUtxosFeeResponse, StoredPendingTransaction, MIN_CONSOLIDATION_UTXOS,
     },
     result_types::{
-        BtcAddPendingTransactionResult, BtcGetFeePercentilesResult,
-        BtcGetPendingTransactionsResult, BtcSelectUserUtxosFeeResult,
+        BtcAddPendingTransactionResult, BtcBuildPsbtResult, BtcGetBalanceResult,
+        BtcGetConsolidationAdviceResult, BtcGetDustReportResult, BtcGetFeeBumpQuotesResult,
+        BtcGetFeePercentilesResult, BtcGetPendingTransactionsResult,
+        BtcReplacePendingTransactionResult, BtcSelectUserUtxosFeeResult, BtcSendTransactionResult,
+        BtcSubscribeDepositsResult, BtcUpdateFrozenUtxosResult,
     },
+    transaction::{Transaction, TransactionType},
 };
 
 use crate::{
-    bitcoin::{api, pending_tx_model::BtcUserPendingTransactionsModel, utils},
+    bitcoin::{
+        api, deposits, fee_history, frozen_utxos,
+        pending_tx_model::BtcUserPendingTransactionsModel,
+        psbt, send_history, signed_tx,
+        utils::{self, InputType, ScriptType, UtxosSelection},
+        utxo_cache,
+    },
     signer,
-    state::mutate_state,
+    state::{mutate_state, read_config, read_state},
+    types::StoredPrincipal,
+    user_profile::{self, model::UserProfileModel},
     utils::{
-        guards::caller_is_not_anonymous, housekeeping::BTC_SELECT_UTXOS_FEE_RATE_LIMITER,
+        guards::caller_is_not_anonymous,
+        housekeeping::{
+            BTC_BUILD_PSBT_RATE_LIMITER, BTC_CONSOLIDATION_ADVICE_RATE_LIMITER,
+            BTC_GET_BALANCE_RATE_LIMITER, BTC_GET_DUST_REPORT_RATE_LIMITER,
+            BTC_SELECT_UTXOS_FEE_RATE_LIMITER, BTC_SEND_TRANSACTION_RATE_LIMITER,
+            BTC_SUBSCRIBE_DEPOSITS_RATE_LIMITER,
+        },
         rate_limiter,
     },
 };
 
 const MIN_CONFIRMATIONS_ACCEPTED_BTC_TX: u32 = 6;
 
+/// Returns the output types of a pending transaction spending inputs of the given type.
+///
+/// The outputs of pending transactions are not stored, so their size is estimated as if they had
+/// a single destination and a change output.
+fn pending_tx_output_types(input_type: InputType) -> [ScriptType; 2] {
+    [ScriptType::default(), input_type.script_type()]
+}
+
+/// Returns the type of the caller's bitcoin addresses used when a request doesn't specify one.
+fn caller_default_address_type(principal: Principal) -> BtcAddressType {
+    mutate_state(|state| {
+        let user_profile_model =
+            UserProfileModel::new(&mut state.user_profile, &mut state.user_profile_updated);
+        user_profile::service::find_btc_settings(StoredPrincipal(principal), &user_profile_model)
+    })
+    .default_address_type
+}
+
+/// Returns the caller's address of the requested type, or of their default type, and how its
+/// UTXOs are spent.
+async fn caller_btc_address(
+    principal: Principal,
+    network: BitcoinNetwork,
+    address_type: Option<BtcAddressType>,
+) -> Result<(String, InputType), String> {
+    let address_type = address_type.unwrap_or_else(|| caller_default_address_type(principal));
+    let address = signer::btc_principal_to_address(network, &principal, address_type).await?;
+    Ok((address, InputType::from(address_type)))
+}
+
+/// Returns the caller's address as the source of a PSBT, with its x-only internal key if it is a
+/// P2TR address, which key-path signers need to sign its inputs.
+async fn psbt_source(
+    principal: Principal,
+    network: BitcoinNetwork,
+    source_address: &str,
+    input_type: InputType,
+) -> Result<(bitcoin::Address, Option<bitcoin::XOnlyPublicKey>), String> {
+    let source = psbt::parse_address(source_address, network)
+        .ok_or_else(|| "Invalid source address".to_string())?;
+    let tap_internal_key = match input_type {
+        InputType::P2trKeyPath => {
+            Some(signer::btc_principal_to_p2tr_internal_key(&principal).await?)
+        }
+        InputType::P2wpkh => None,
+    };
+    Ok((source, tap_internal_key))
+}
+
 /// Retrieves the current fee percentiles for Bitcoin transactions from the cache
 /// for the specified network. Fee percentiles are measured in millisatoshi per byte
 /// and are periodically updated in the background.
@@ -52,8 +140,51 @@ pub fn btc_get_current_fee_percentiles(
     Ok(BtcGetFeePercentilesResponse { fee_percentiles }).into()
 }
 
+/// Returns the history of the fee rates of the given network over the requested window, oldest
+/// first, with their minimum, median and maximum.
+///
+/// A snapshot of the median fee rate is taken at most every 10 minutes, and the history keeps a
+/// week of snapshots.  Unlike the fee percentiles cache, the history survives upgrades.
+#[query(guard = "caller_is_not_anonymous")]
+#[expect(clippy::needless_pass_by_value)]
+#[must_use]
+pub fn btc_get_fee_history(params: BtcGetFeeHistoryRequest) -> BtcGetFeeHistoryResponse {
+    let since_ns = params
+        .window_ns
+        .map_or(0, |window_ns| time().saturating_sub(window_ns));
+    let snapshots = read_state(|state| {
+        fee_history::get_fee_history(&state.btc_fee_history, params.network, since_ns)
+    });
+    let stats = fee_history::fee_history_stats(&snapshots);
+
+    BtcGetFeeHistoryResponse { snapshots, stats }
+}
+
 /// Selects the user's UTXOs and calculates the fee for a Bitcoin transaction.
 ///
+/// The transaction pays either `amount_satoshis` to a single destination or, for a batch payment,
+/// every one of the requested `outputs`.  Either way, the fee accounts for a change output if
+/// needed.
+///
+/// The fee is paid at the requested `fee_rate`, and the response quotes the fee of the transaction
+/// at every priority tier as well.
+///
+/// Dust UTXOs, worth less than `utils::dust_threshold` at the fee rate, are never selected, nor
+/// spent as explicit `inputs`, unless the request sets `include_dust`.  They are listed by
+/// `btc_get_dust_report`.
+///
+/// The UTXOs frozen by the caller are never selected.  With explicit `inputs`, all of them are
+/// spent instead: they must be current UTXOs of the caller's address that are neither frozen nor
+/// reserved by a pending transaction, but other pending transactions of the caller are allowed.
+///
+/// The unconfirmed change of the caller's pending transactions, registered with their
+/// `change_output`, can be spent as well, up to `MAX_UNCONFIRMED_CHAIN_DEPTH` unconfirmed
+/// ancestors.
+///
+/// With `send_max`, every UTXO worth more than the fee of spending it, or every one of the
+/// `inputs`, is spent without change, and the response reports the `amount_satoshis` that the
+/// destination receives after the fee.
+///
 /// # Errors
 /// Errors are enumerated by: `SelectedUtxosFeeError`.
 #[update(guard = "caller_is_not_anonymous")]
@@ -68,9 +199,10 @@ pub async fn btc_select_user_utxos_fee(
             .map_err(SelectedUtxosFeeError::RateLimited)?;
 
         let principal = ic_cdk::caller();
-        let source_address = signer::btc_principal_to_p2wpkh_address(params.network, &principal)
-            .await
-            .map_err(|msg| SelectedUtxosFeeError::InternalError { msg })?;
+        let (source_address, input_type) =
+            caller_btc_address(principal, params.network, params.address_type)
+                .await
+                .map_err(|msg| SelectedUtxosFeeError::InternalError { msg })?;
         let all_utxos = api::get_all_utxos(
             params.network,
             source_address.clone(),
@@ -82,51 +214,519 @@ pub async fn btc_select_user_utxos_fee(
         )
         .await
         .map_err(|msg| SelectedUtxosFeeError::InternalError { msg })?;
-        let now_ns = time();
 
-        let has_pending_transactions = mutate_state(|state| {
-            let mut model = BtcUserPendingTransactionsModel::new(
-                &mut state.btc_user_pending_transactions,
-                None,
-                None,
-            );
-            model.prune_pending_transactions(principal, &all_utxos, now_ns);
-            !model
-                .get_pending_transactions(&principal, &source_address)
-                .is_empty()
-        });
+        let tiers = read_config(|config| config.btc_fee_tiers.clone().unwrap_or_default());
+        let fee_millisatoshi_per_vbyte = api::get_fee_rate_per_byte(
+            params.network,
+            params
+                .fee_rate
+                .unwrap_or(BtcFeeRate::Priority(BtcFeePriority::Normal)),
+            &tiers,
+        );
+        let dust_threshold = (!params.include_dust.unwrap_or_default())
+            .then(|| utils::dust_threshold(input_type, fee_millisatoshi_per_vbyte));
+        let (inputs, available_utxos) = selectable_utxos(
+            principal,
+            &source_address,
+            all_utxos,
+            params.inputs,
+            dust_threshold,
+        )?;
+        let outputs = params.outputs.unwrap_or_default();
+        let destination_types = destination_types(&outputs, params.destination_address.as_deref());
+        let send_max = params.send_max.unwrap_or_default();
+        let select_utxos = |fee_millisatoshi_per_vbyte| match &inputs {
+            _ if send_max => send_max_utxos(
+                inputs.as_ref(),
+                &available_utxos,
+                fee_millisatoshi_per_vbyte,
+                input_type,
+                &destination_types,
+            ),
+            Some(inputs) => utils::manual_selection(
+                params.amount_satoshis,
+                inputs.clone(),
+                fee_millisatoshi_per_vbyte,
+                input_type,
+                &destination_types,
+            ),
+            None => Ok(utils::select_utxos(
+                params.strategy.unwrap_or_default(),
+                params.amount_satoshis,
+                &available_utxos,
+                fee_millisatoshi_per_vbyte,
+                input_type,
+                &destination_types,
+            )),
+        };
+        let selection = select_utxos(fee_millisatoshi_per_vbyte).map_err(|required_satoshis| {
+            SelectedUtxosFeeError::InsufficientInputs {
+                available_satoshis: inputs.iter().flatten().map(|utxo| utxo.value).sum(),
+                required_satoshis,
+            }
+        })?;
+
+        let fee_quotes = selection_fee_quotes(params.network, &tiers, &selection, select_utxos);
+
+        // No transaction is possible without selected UTXOs, so no output pays any fee either.
+        let outputs = output_fees(
+            outputs,
+            &destination_types,
+            (!selection.utxos.is_empty()).then_some(fee_millisatoshi_per_vbyte),
+        );
+
+        let amount_satoshis = if send_max {
+            selection.total_value() - selection.fee_satoshis
+        } else {
+            params.amount_satoshis
+        };
+
+        Ok(SelectedUtxosFeeResponse {
+            amount_satoshis,
+            utxos: selection.utxos,
+            fee_satoshis: selection.fee_satoshis,
+            algorithm: selection.algorithm,
+            change_output: selection.change_output,
+            outputs,
+            fee_millisatoshi_per_vbyte,
+            fee_quotes,
+        })
+    }
+    inner(params).await.into()
+}
 
-        if has_pending_transactions {
-            return Err(SelectedUtxosFeeError::PendingTransactions);
+/// Selects the UTXOs of a send-max payment: all the `inputs` if set, otherwise all the available
+/// UTXOs worth spending.
+fn send_max_utxos(
+    inputs: Option<&Vec<Utxo>>,
+    available_utxos: &[Utxo],
+    fee_millisatoshi_per_vbyte: u64,
+    input_type: InputType,
+    destination_types: &[ScriptType],
+) -> Result<UtxosSelection, u64> {
+    match inputs {
+        Some(inputs) => utils::send_max_selection(
+            inputs.clone(),
+            fee_millisatoshi_per_vbyte,
+            input_type,
+            destination_types,
+        ),
+        None => Ok(utils::select_all_utxos(
+            available_utxos,
+            fee_millisatoshi_per_vbyte,
+            input_type,
+            destination_types,
+        )),
+    }
+}
+
+/// Returns the script types of the destinations of a payment.  Without explicit outputs, the
+/// payment has a single destination.
+fn destination_types(
+    outputs: &[BtcTxOutput],
+    destination_address: Option<&str>,
+) -> Vec<ScriptType> {
+    if outputs.is_empty() {
+        vec![destination_address.map_or_else(ScriptType::default, ScriptType::of_address)]
+    } else {
+        outputs
+            .iter()
+            .map(|output| ScriptType::of_address(&output.destination_address))
+            .collect()
+    }
+}
+
+/// Returns the share of the fee paid for each of the outputs, of the given script types, at the
+/// given fee rate, or no fee without fee rate.
+fn output_fees(
+    outputs: Vec<BtcTxOutput>,
+    output_types: &[ScriptType],
+    fee_millisatoshi_per_vbyte: Option<u64>,
+) -> Vec<SelectedUtxosFeeOutput> {
+    outputs
+        .into_iter()
+        .zip(output_types)
+        .map(|(output, output_type)| SelectedUtxosFeeOutput {
+            fee_satoshis: fee_millisatoshi_per_vbyte.map_or(0, |fee_millisatoshi_per_vbyte| {
+                utils::estimate_output_fee(*output_type, fee_millisatoshi_per_vbyte)
+            }),
+            destination_address: output.destination_address,
+            amount_satoshis: output.amount_satoshis,
+        })
+        .collect()
+}
+
+/// Returns the explicit inputs, if any, and the UTXOs available for selection otherwise, i.e. the
+/// UTXOs that are neither frozen by the user nor reserved by a pending transaction, nor dust unless
+/// `dust_threshold_satoshis` is `None`.
+///
+/// Next to the current UTXOs, the unconfirmed change of the user's pending transactions may be
+/// spent, whatever the requested number of confirmations, so that payments can follow each other
+/// without waiting for confirmations.
+///
+/// # Errors
+/// - If there are explicit inputs that are not current UTXOs, or that are frozen, reserved or dust.
+fn selectable_utxos(
+    principal: Principal,
+    source_address: &str,
+    all_utxos: Vec<Utxo>,
+    inputs: Option<Vec<Outpoint>>,
+    dust_threshold_satoshis: Option<u64>,
+) -> Result<(Option<Vec<Utxo>>, Vec<Utxo>), SelectedUtxosFeeError> {
+    let frozen_outpoints =
+        read_state(|state| frozen_utxos::get_frozen_utxos(&state.btc_frozen_utxos, principal));
+    let (unreserved_utxos, reserved_utxos) = spendable_utxos(principal, source_address, all_utxos);
+    let inputs = if let Some(inputs) = inputs {
+        let utxos = current_utxos_of(&inputs, &[&unreserved_utxos[..], &reserved_utxos].concat())
+            .ok_or(SelectedUtxosFeeError::InvalidInputs)?;
+        if inputs
+            .iter()
+            .any(|outpoint| frozen_outpoints.contains(outpoint))
+        {
+            return Err(SelectedUtxosFeeError::FrozenInputs);
+        }
+        if utxos.iter().any(|utxo| reserved_utxos.contains(utxo)) {
+            return Err(SelectedUtxosFeeError::InputsAlreadyReserved);
+        }
+        if let Some(dust_threshold_satoshis) = dust_threshold_satoshis {
+            if utxos
+                .iter()
+                .any(|utxo| utxo.value < dust_threshold_satoshis)
+            {
+                return Err(SelectedUtxosFeeError::DustInputs {
+                    dust_threshold_satoshis,
+                });
+            }
         }
+        Some(utxos)
+    } else {
+        None
+    };
+    let available_utxos = match dust_threshold_satoshis {
+        Some(dust_threshold_satoshis) => {
+            utils::partition_dust(unreserved_utxos, dust_threshold_satoshis).0
+        }
+        None => unreserved_utxos,
+    };
+    Ok((
+        inputs,
+        frozen_utxos::without_frozen_utxos(available_utxos, &frozen_outpoints),
+    ))
+}
 
-        let median_fee_millisatoshi_per_vbyte = api::get_fee_per_byte(params.network);
-        // We support sending to one destination only.
-        // Therefore, the outputs are the destination and the source address for the change.
-        let output_count = 2;
-        let mut available_utxos = all_utxos.clone();
-        let selected_utxos =
-            utils::utxos_selection(params.amount_satoshis, &mut available_utxos, output_count);
-
-        // Fee calculation might still take into account default tx size and expected output.
-        // But if there are no selected utxos, no tx is possible. Therefore, no fee should be
-        // present.
-        if selected_utxos.is_empty() {
-            return Ok(SelectedUtxosFeeResponse {
-                utxos: selected_utxos,
-                fee_satoshis: 0,
-            });
+/// Prunes the pending transactions of the user, given their current UTXOs, and returns whether any
+/// transaction from `source_address` is still pending.
+fn has_pending_transactions(
+    principal: Principal,
+    source_address: &str,
+    all_utxos: &[Utxo],
+) -> bool {
+    let now_ns = time();
+    let ttls = read_config(|config| config.btc_pending_transaction_ttls);
+    mutate_state(|state| {
+        let mut model = BtcUserPendingTransactionsModel::new(
+            &mut state.btc_user_pending_transactions,
+            &mut state.btc_outpoint_reservations,
+            None,
+            None,
+            ttls,
+        );
+        model.prune_pending_transactions(principal, source_address, all_utxos, now_ns);
+        model
+            .get_pending_transactions(&principal, source_address)
+            .iter()
+            .any(StoredPendingTransaction::is_pending)
+    })
+}
+
+/// Prunes the pending transactions of the user, given their current UTXOs, and returns the UTXOs
+/// of `source_address` that are not reserved by any pending transaction, and those that are.
+///
+/// The UTXOs are the current UTXOs and the unconfirmed change of the user's pending transactions.
+fn spendable_utxos(
+    principal: Principal,
+    source_address: &str,
+    all_utxos: Vec<Utxo>,
+) -> (Vec<Utxo>, Vec<Utxo>) {
+    let now_ns = time();
+    let ttls = read_config(|config| config.btc_pending_transaction_ttls);
+    mutate_state(|state| {
+        let mut model = BtcUserPendingTransactionsModel::new(
+            &mut state.btc_user_pending_transactions,
+            &mut state.btc_outpoint_reservations,
+            None,
+            None,
+            ttls,
+        );
+        model.prune_pending_transactions(principal, source_address, &all_utxos, now_ns);
+        let change_utxos = model.change_utxos(&principal, source_address, now_ns);
+        all_utxos.into_iter().chain(change_utxos).partition(|utxo| {
+            !model.has_intersecting_pending_utxos(std::slice::from_ref(utxo), now_ns)
+        })
+    })
+}
+
+/// Quotes the fee of a selection at every priority tier, selecting the UTXOs again at the fee rate
+/// of each tier.  A selection without UTXOs has no quotes.
+fn selection_fee_quotes(
+    network: BitcoinNetwork,
+    tiers: &BtcFeeTiersConfig,
+    selection: &UtxosSelection,
+    select_utxos: impl Fn(u64) -> Result<UtxosSelection, u64>,
+) -> Vec<BtcFeeQuote> {
+    if selection.utxos.is_empty() {
+        return vec![];
+    }
+    // The explicit inputs may not pay for the fee at every tier, which is quoted as 0 then.
+    fee_quotes(network, tiers, |fee_millisatoshi_per_vbyte| {
+        select_utxos(fee_millisatoshi_per_vbyte).map_or(0, |selection| selection.fee_satoshis)
+    })
+}
+
+/// Quotes the fee, in satoshi, computed by `fee` from the fee rate of every priority tier.
+fn fee_quotes(
+    network: BitcoinNetwork,
+    tiers: &BtcFeeTiersConfig,
+    fee: impl Fn(u64) -> u64,
+) -> Vec<BtcFeeQuote> {
+    [
+        BtcFeePriority::Slow,
+        BtcFeePriority::Normal,
+        BtcFeePriority::Fast,
+    ]
+    .into_iter()
+    .map(|priority| {
+        let fee_millisatoshi_per_vbyte = api::get_fee_per_byte(network, priority, tiers);
+        BtcFeeQuote {
+            priority,
+            fee_millisatoshi_per_vbyte,
+            fee_satoshis: fee(fee_millisatoshi_per_vbyte),
         }
+    })
+    .collect()
+}
+
+/// Builds the unsigned Bitcoin transaction of a payment from the caller's address, as a BIP-174
+/// PSBT ready for signing.
+///
+/// The UTXOs, including the unconfirmed change of pending transactions, are selected and the fee
+/// is computed as in `btc_select_user_utxos_fee`, leaving dust out unless `include_dust` is set.
+/// Change is returned to the caller's address, unless it is dust, in which case it is added to the
+/// fee.
+///
+/// # Errors
+/// Errors are enumerated by: `BtcBuildPsbtError`.
+#[update(guard = "caller_is_not_anonymous")]
+pub async fn btc_build_psbt(params: BtcBuildPsbtRequest) -> BtcBuildPsbtResult {
+    async fn inner(params: BtcBuildPsbtRequest) -> Result<BtcBuildPsbtResponse, BtcBuildPsbtError> {
+        BTC_BUILD_PSBT_RATE_LIMITER
+            .with(rate_limiter::RateLimiter::check_caller)
+            .map_err(BtcBuildPsbtError::RateLimited)?;
 
-        let fee_satoshis = utils::estimate_fee(
-            selected_utxos.len() as u64,
-            median_fee_millisatoshi_per_vbyte,
-            output_count as u64,
+        let destinations = params
+            .outputs
+            .iter()
+            .map(|output| {
+                psbt::parse_address(&output.destination_address, params.network)
+                    .map(|address| (address, output.amount_satoshis))
+                    .ok_or_else(|| BtcBuildPsbtError::InvalidDestinationAddress {
+                        address: output.destination_address.clone(),
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let principal = ic_cdk::caller();
+        let (source_address, input_type) =
+            caller_btc_address(principal, params.network, params.address_type)
+                .await
+                .map_err(|msg| BtcBuildPsbtError::InternalError { msg })?;
+        let (source, tap_internal_key) =
+            psbt_source(principal, params.network, &source_address, input_type)
+                .await
+                .map_err(|msg| BtcBuildPsbtError::InternalError { msg })?;
+        let all_utxos = api::get_all_utxos(
+            params.network,
+            source_address.clone(),
+            Some(
+                params
+                    .min_confirmations
+                    .unwrap_or(MIN_CONFIRMATIONS_ACCEPTED_BTC_TX),
+            ),
+        )
+        .await
+        .map_err(|msg| BtcBuildPsbtError::InternalError { msg })?;
+
+        let (unreserved_utxos, _) = spendable_utxos(principal, &source_address, all_utxos);
+        let available_utxos = without_caller_frozen_utxos(principal, unreserved_utxos);
+
+        let tiers = read_config(|config| config.btc_fee_tiers.clone().unwrap_or_default());
+        let fee_millisatoshi_per_vbyte = api::get_fee_rate_per_byte(
+            params.network,
+            params
+                .fee_rate
+                .unwrap_or(BtcFeeRate::Priority(BtcFeePriority::Normal)),
+            &tiers,
         );
+        let available_utxos = if params.include_dust.unwrap_or_default() {
+            available_utxos
+        } else {
+            let dust_threshold_satoshis =
+                utils::dust_threshold(input_type, fee_millisatoshi_per_vbyte);
+            utils::partition_dust(available_utxos, dust_threshold_satoshis).0
+        };
+        let destination_types: Vec<ScriptType> = params
+            .outputs
+            .iter()
+            .map(|output| ScriptType::of_address(&output.destination_address))
+            .collect();
+        // The request validation ensures that the total does not overflow.
+        let amount_satoshis: u64 = params
+            .outputs
+            .iter()
+            .map(|output| output.amount_satoshis)
+            .sum();
+        let selection = utils::select_utxos(
+            params.strategy.unwrap_or_default(),
+            amount_satoshis,
+            &available_utxos,
+            fee_millisatoshi_per_vbyte,
+            input_type,
+            &destination_types,
+        );
+        if selection.utxos.is_empty() {
+            return Err(BtcBuildPsbtError::InsufficientFunds);
+        }
 
-        Ok(SelectedUtxosFeeResponse {
-            utxos: selected_utxos,
-            fee_satoshis,
+        let total_satoshis: u64 = selection.utxos.iter().map(|utxo| utxo.value).sum();
+        let change_satoshis = Some(total_satoshis - amount_satoshis - selection.fee_satoshis)
+            .filter(|change| {
+                selection.change_output && *change >= psbt::min_change_satoshis(&source)
+            })
+            .unwrap_or(0);
+        let psbt = psbt::build_psbt(
+            &source,
+            tap_internal_key,
+            &selection.utxos,
+            &destinations,
+            change_satoshis,
+        )
+        .map_err(|msg| BtcBuildPsbtError::InternalError { msg })?;
+
+        Ok(BtcBuildPsbtResponse {
+            psbt: psbt.serialize(),
+            fee_satoshis: total_satoshis - amount_satoshis - change_satoshis,
+            change_satoshis,
+            utxos: selection.utxos,
+        })
+    }
+    inner(params).await.into()
+}
+
+/// Advises whether the caller should consolidate the UTXOs of their address now, and builds the
+/// consolidation transaction on request.
+///
+/// The smallest UTXOs are consolidated into a single output of the caller's address.  The
+/// consolidation is recommended if there are at least `MIN_CONSOLIDATION_UTXOS` of them, and if
+/// spending them now saves fees compared to spending them later at the median fee rate of the fee
+/// history, i.e. when fees are low.  Dust UTXOs are never consolidated.
+///
+/// # Errors
+/// Errors are enumerated by: `BtcGetConsolidationAdviceError`.
+#[update(guard = "caller_is_not_anonymous")]
+pub async fn btc_get_consolidation_advice(
+    params: BtcGetConsolidationAdviceRequest,
+) -> BtcGetConsolidationAdviceResult {
+    async fn inner(
+        params: BtcGetConsolidationAdviceRequest,
+    ) -> Result<BtcGetConsolidationAdviceResponse, BtcGetConsolidationAdviceError> {
+        BTC_CONSOLIDATION_ADVICE_RATE_LIMITER
+            .with(rate_limiter::RateLimiter::check_caller)
+            .map_err(BtcGetConsolidationAdviceError::RateLimited)?;
+
+        let principal = ic_cdk::caller();
+        let (source_address, input_type) =
+            caller_btc_address(principal, params.network, params.address_type)
+                .await
+                .map_err(|msg| BtcGetConsolidationAdviceError::InternalError { msg })?;
+        let (source, tap_internal_key) =
+            psbt_source(principal, params.network, &source_address, input_type)
+                .await
+                .map_err(|msg| BtcGetConsolidationAdviceError::InternalError { msg })?;
+        let all_utxos = api::get_all_utxos(
+            params.network,
+            source_address.clone(),
+            Some(
+                params
+                    .min_confirmations
+                    .unwrap_or(MIN_CONFIRMATIONS_ACCEPTED_BTC_TX),
+            ),
+        )
+        .await
+        .map_err(|msg| BtcGetConsolidationAdviceError::InternalError { msg })?;
+
+        if has_pending_transactions(principal, &source_address, &all_utxos) {
+            return Err(BtcGetConsolidationAdviceError::PendingTransactions);
+        }
+        let available_utxos = without_caller_frozen_utxos(principal, all_utxos);
+
+        let tiers = read_config(|config| config.btc_fee_tiers.clone().unwrap_or_default());
+        let fee_millisatoshi_per_vbyte = api::get_fee_rate_per_byte(
+            params.network,
+            params
+                .fee_rate
+                .unwrap_or(BtcFeeRate::Priority(BtcFeePriority::Slow)),
+            &tiers,
+        );
+        // Consolidating dust would link the addresses that sent it to the caller's other UTXOs.
+        let (available_utxos, _) = utils::partition_dust(
+            available_utxos,
+            utils::dust_threshold(input_type, fee_millisatoshi_per_vbyte),
+        );
+        let reference_fee_millisatoshi_per_vbyte = read_state(|state| {
+            let snapshots = fee_history::get_fee_history(&state.btc_fee_history, params.network, 0);
+            fee_history::fee_history_stats(&snapshots)
+                .map_or(utils::LONG_TERM_FEE_MILLISATOSHI_PER_VBYTE, |stats| {
+                    stats.median_fee_millisatoshi_per_vbyte
+                })
+        });
+        let plan = utils::plan_consolidation(
+            &available_utxos,
+            fee_millisatoshi_per_vbyte,
+            reference_fee_millisatoshi_per_vbyte,
+            input_type,
+        );
+
+        let total_satoshis: u64 = plan.utxos.iter().map(|utxo| utxo.value).sum();
+        // The consolidated output must not be dust.
+        let consolidated_satoshis = Some(total_satoshis.saturating_sub(plan.fee_satoshis))
+            .filter(|amount| *amount >= psbt::min_change_satoshis(&source));
+        let recommended = plan.utxos.len() >= MIN_CONSOLIDATION_UTXOS
+            && plan.savings_satoshis > 0
+            && consolidated_satoshis.is_some();
+
+        let psbt = match consolidated_satoshis {
+            Some(consolidated_satoshis) if recommended && params.build_psbt => Some(
+                psbt::build_psbt(
+                    &source,
+                    tap_internal_key,
+                    &plan.utxos,
+                    &[],
+                    consolidated_satoshis,
+                )
+                .map_err(|msg| BtcGetConsolidationAdviceError::InternalError { msg })?
+                .serialize(),
+            ),
+            _ => None,
+        };
+
+        Ok(BtcGetConsolidationAdviceResponse {
+            recommended,
+            utxos: plan.utxos,
+            fee_satoshis: plan.fee_satoshis,
+            fee_millisatoshi_per_vbyte,
+            reference_fee_millisatoshi_per_vbyte,
+            savings_satoshis: plan.savings_satoshis,
+            psbt,
         })
     }
     inner(params).await.into()
@@ -134,6 +734,9 @@ pub async fn btc_select_user_utxos_fee(
 
 /// Adds a pending Bitcoin transaction for the caller.
 ///
+/// The transaction may spend the unconfirmed change of the caller's other pending transactions,
+/// and its own `change_output` can be spent by the following ones.
+///
 /// # Errors
 /// Errors are enumerated by: `BtcAddPendingTransactionError`.
 #[update(guard = "caller_is_not_anonymous")]
@@ -147,21 +750,16 @@ pub async fn btc_add_pending_transaction(
             return Err(BtcAddPendingTransactionError::EmptyUtxos);
         }
 
-        let unique_keys: HashSet<(&[u8], u32)> = params
-            .utxos
-            .iter()
-            .map(|u| (u.outpoint.txid.as_slice(), u.outpoint.vout))
-            .collect();
-
-        if unique_keys.len() != params.utxos.len() {
+        if has_duplicate_utxos(&params.utxos) {
             return Err(BtcAddPendingTransactionError::DuplicateUtxos);
         }
 
         let principal = ic_cdk::caller();
 
-        let source_address = signer::btc_principal_to_p2wpkh_address(params.network, &principal)
-            .await
-            .map_err(|msg| BtcAddPendingTransactionError::InternalError { msg })?;
+        let (source_address, _) =
+            caller_btc_address(principal, params.network, params.address_type)
+                .await
+                .map_err(|msg| BtcAddPendingTransactionError::InternalError { msg })?;
 
         let current_utxos = api::get_all_utxos(
             params.network,
@@ -172,48 +770,549 @@ pub async fn btc_add_pending_transaction(
         .map_err(|msg| BtcAddPendingTransactionError::InternalError { msg })?;
 
         let now_ns = time();
+        let ttls = read_config(|config| config.btc_pending_transaction_ttls);
+        mutate_state(|state| {
+            let mut model = BtcUserPendingTransactionsModel::new(
+                &mut state.btc_user_pending_transactions,
+                &mut state.btc_outpoint_reservations,
+                None,
+                None,
+                ttls,
+            );
+            model.prune_pending_transactions(principal, &source_address, &current_utxos, now_ns);
 
-        let current_keys: HashSet<(&[u8], u32)> = current_utxos
-            .iter()
-            .map(|u| (u.outpoint.txid.as_slice(), u.outpoint.vout))
-            .collect();
+            let change_utxos = model.change_utxos(&principal, &source_address, now_ns);
+            if !are_current_utxos(&params.utxos, &[&current_utxos[..], &change_utxos].concat()) {
+                return Err(BtcAddPendingTransactionError::InvalidUtxos);
+            }
 
-        let all_param_utxos_are_current = params
-            .utxos
-            .iter()
-            .all(|u| current_keys.contains(&(u.outpoint.txid.as_slice(), u.outpoint.vout)));
+            if model.has_intersecting_pending_utxos(&params.utxos, now_ns) {
+                return Err(BtcAddPendingTransactionError::UtxosAlreadyReserved);
+            }
+
+            let payment = sent_payment(
+                params.network,
+                params.destination_address.as_deref(),
+                params.amount_satoshis,
+                now_ns,
+            )
+            .map_err(|msg| BtcAddPendingTransactionError::InternalError { msg })?;
+            let current_pending_transaction = StoredPendingTransaction {
+                txid: params.txid.clone(),
+                utxos: params.utxos,
+                created_at_timestamp_ns: now_ns,
+                fee_satoshis: params.fee_satoshis,
+                replaced_txids: None,
+                destination_address: params.destination_address,
+                amount_satoshis: params.amount_satoshis,
+                network: Some(params.network),
+                status: Some(BtcPendingTransactionStatus::Pending),
+                finalized_at_timestamp_ns: None,
+                change_output: params.change_output,
+                parent_txids: None,
+            };
+            model
+                .add_pending_transaction(
+                    principal,
+                    source_address.clone(),
+                    current_pending_transaction,
+                )
+                .map_err(|msg| BtcAddPendingTransactionError::InternalError { msg })?;
+
+            if let Some(payment) = payment {
+                send_history::record_sent_transaction(
+                    &mut state.btc_send_history,
+                    principal,
+                    params.txid,
+                    payment,
+                    params.fee_satoshis,
+                );
+            }
+            Ok(())
+        })?;
 
-        if !all_param_utxos_are_current {
-            return Err(BtcAddPendingTransactionError::InvalidUtxos);
+        invalidate_cached_utxos(params.network, &source_address);
+        Ok(())
+    }
+    inner(params).await.into()
+}
+
+/// Replaces a pending Bitcoin transaction of the caller by one paying a higher fee.
+///
+/// The replacement must spend at least one UTXO of the replaced transaction, and its fee must
+/// exceed the fee of the replaced transaction as required by BIP-125.  The pending transaction
+/// keeps the txids of the transactions it replaced, and records the recipient and the amount of
+/// the replacement, which may pay less than the replaced transaction to fund the higher fee.
+///
+/// # Errors
+/// Errors are enumerated by: `BtcReplacePendingTransactionError`.
+#[update(guard = "caller_is_not_anonymous")]
+pub async fn btc_replace_pending_transaction(
+    params: BtcReplacePendingTransactionRequest,
+) -> BtcReplacePendingTransactionResult {
+    async fn inner(
+        params: BtcReplacePendingTransactionRequest,
+    ) -> Result<(), BtcReplacePendingTransactionError> {
+        if params.utxos.is_empty() {
+            return Err(BtcReplacePendingTransactionError::EmptyUtxos);
         }
 
+        if has_duplicate_utxos(&params.utxos) {
+            return Err(BtcReplacePendingTransactionError::DuplicateUtxos);
+        }
+
+        let principal = ic_cdk::caller();
+
+        let (source_address, input_type) =
+            caller_btc_address(principal, params.network, params.address_type)
+                .await
+                .map_err(|msg| BtcReplacePendingTransactionError::InternalError { msg })?;
+
+        let current_utxos = api::get_all_utxos(
+            params.network,
+            source_address.clone(),
+            Some(MIN_CONFIRMATIONS_ACCEPTED_BTC_TX),
+        )
+        .await
+        .map_err(|msg| BtcReplacePendingTransactionError::InternalError { msg })?;
+
+        let now_ns = time();
+        let ttls = read_config(|config| config.btc_pending_transaction_ttls);
         mutate_state(|state| {
             let mut model = BtcUserPendingTransactionsModel::new(
                 &mut state.btc_user_pending_transactions,
+                &mut state.btc_outpoint_reservations,
                 None,
                 None,
+                ttls,
             );
-            model.prune_pending_transactions(principal, &current_utxos, now_ns);
+            model.prune_pending_transactions(principal, &source_address, &current_utxos, now_ns);
 
-            if model.has_intersecting_pending_utxos(principal, &params.utxos) {
-                return Err(BtcAddPendingTransactionError::UtxosAlreadyReserved);
+            let change_utxos = model.change_utxos(&principal, &source_address, now_ns);
+            if !are_current_utxos(&params.utxos, &[&current_utxos[..], &change_utxos].concat()) {
+                return Err(BtcReplacePendingTransactionError::InvalidUtxos);
             }
 
-            let current_pending_transaction = StoredPendingTransaction {
-                txid: params.txid,
+            let replaced = model
+                .get_pending_transaction(&principal, &source_address, &params.replaced_txid)
+                .filter(StoredPendingTransaction::is_pending)
+                .ok_or(BtcReplacePendingTransactionError::ReplacedTransactionNotFound)?;
+            let replaced_fee_satoshis = replaced
+                .fee_satoshis
+                .ok_or(BtcReplacePendingTransactionError::ReplacedTransactionFeeUnknown)?;
+            let min_fee_satoshis = utils::min_replacement_fee(
+                replaced_fee_satoshis,
+                input_type,
+                params.utxos.len() as u64,
+                &pending_tx_output_types(input_type),
+            );
+            if params.fee_satoshis < min_fee_satoshis {
+                return Err(BtcReplacePendingTransactionError::InsufficientFee {
+                    min_fee_satoshis,
+                });
+            }
+
+            let payment = sent_payment(
+                params.network,
+                params.destination_address.as_deref(),
+                params.amount_satoshis,
+                now_ns,
+            )
+            .map_err(|msg| BtcReplacePendingTransactionError::InternalError { msg })?;
+            let replacement = StoredPendingTransaction {
+                txid: params.txid.clone(),
                 utxos: params.utxos,
                 created_at_timestamp_ns: now_ns,
+                fee_satoshis: Some(params.fee_satoshis),
+                replaced_txids: None,
+                destination_address: params.destination_address,
+                amount_satoshis: params.amount_satoshis,
+                network: replaced.network,
+                status: Some(BtcPendingTransactionStatus::Pending),
+                finalized_at_timestamp_ns: None,
+                change_output: params.change_output,
+                parent_txids: None,
             };
-            model
-                .add_pending_transaction(principal, source_address, current_pending_transaction)
-                .map_err(|msg| BtcAddPendingTransactionError::InternalError { msg })
+            model.replace_pending_transaction(
+                principal,
+                &source_address,
+                &params.replaced_txid,
+                replacement,
+            )?;
+
+            send_history::record_replacement(
+                &mut state.btc_send_history,
+                principal,
+                &params.replaced_txid,
+                &params.txid,
+                payment.as_ref(),
+                Some(params.fee_satoshis),
+                now_ns,
+            );
+            Ok(())
+        })?;
+
+        invalidate_cached_utxos(params.network, &source_address);
+        Ok(())
+    }
+    inner(params).await.into()
+}
+
+/// Broadcasts a signed Bitcoin transaction of the caller, and adds it to their pending
+/// transactions.
+///
+/// The transaction is checked first: it must spend current UTXOs of the caller's address, or the
+/// unconfirmed change of their pending transactions, that no pending transaction reserves, and pay
+/// exactly the quoted `outputs` plus at most one change output to the caller's address.  Its UTXOs
+/// are reserved before the broadcast, so that concurrent calls can't spend them, and released if
+/// the broadcast fails.
+///
+/// # Errors
+/// Errors are enumerated by: `BtcSendTransactionError`.
+#[update(guard = "caller_is_not_anonymous")]
+pub async fn btc_send_transaction(params: BtcSendTransactionRequest) -> BtcSendTransactionResult {
+    async fn inner(
+        params: BtcSendTransactionRequest,
+    ) -> Result<BtcSendTransactionResponse, BtcSendTransactionError> {
+        BTC_SEND_TRANSACTION_RATE_LIMITER
+            .with(rate_limiter::RateLimiter::check_caller)
+            .map_err(BtcSendTransactionError::RateLimited)?;
+
+        let transaction = signed_tx::decode_transaction(&params.transaction)
+            .map_err(|msg| BtcSendTransactionError::InvalidTransaction { msg })?;
+        let outpoints = signed_tx::input_outpoints(&transaction);
+        if outpoints.iter().collect::<HashSet<_>>().len() != outpoints.len() {
+            return Err(BtcSendTransactionError::DuplicateUtxos);
+        }
+        let destinations = params
+            .outputs
+            .iter()
+            .map(|output| {
+                psbt::parse_address(&output.destination_address, params.network)
+                    .map(|address| (address, output.amount_satoshis))
+                    .ok_or_else(|| BtcSendTransactionError::InvalidDestinationAddress {
+                        address: output.destination_address.clone(),
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let principal = ic_cdk::caller();
+        let (source_address, _) =
+            caller_btc_address(principal, params.network, params.address_type)
+                .await
+                .map_err(|msg| BtcSendTransactionError::InternalError { msg })?;
+        let source = psbt::parse_address(&source_address, params.network).ok_or_else(|| {
+            BtcSendTransactionError::InternalError {
+                msg: "Invalid source address".to_string(),
+            }
+        })?;
+        let change_output = signed_tx::change_output(&transaction, &source, &destinations)
+            .ok_or(BtcSendTransactionError::OutputsMismatch)?;
+
+        let current_utxos = api::get_all_utxos(
+            params.network,
+            source_address.clone(),
+            Some(MIN_CONFIRMATIONS_ACCEPTED_BTC_TX),
+        )
+        .await
+        .map_err(|msg| BtcSendTransactionError::InternalError { msg })?;
+
+        let now_ns = time();
+        // The payments are resolved before the broadcast, so that all of them are recorded.
+        let payments = params
+            .outputs
+            .iter()
+            .filter_map(|output| {
+                sent_payment(
+                    params.network,
+                    Some(&output.destination_address),
+                    Some(output.amount_satoshis),
+                    now_ns,
+                )
+                .map_err(|_| BtcSendTransactionError::InvalidDestinationAddress {
+                    address: output.destination_address.clone(),
+                })
+                .transpose()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let pending_transaction = add_sent_transaction(
+            principal,
+            &source_address,
+            params.network,
+            &transaction,
+            &params.outputs,
+            change_output,
+            &current_utxos,
+            now_ns,
+        )?;
+
+        if let Err(msg) = api::send_transaction(params.network, params.transaction).await {
+            mutate_state(|state| {
+                BtcUserPendingTransactionsModel::new(
+                    &mut state.btc_user_pending_transactions,
+                    &mut state.btc_outpoint_reservations,
+                    None,
+                    None,
+                    None,
+                )
+                .remove_pending_transaction(
+                    principal,
+                    &source_address,
+                    &pending_transaction.txid,
+                )
+            });
+            return Err(BtcSendTransactionError::BroadcastFailed { msg });
+        }
+
+        let fee_satoshis = pending_transaction.fee_satoshis;
+        record_sent_outputs(principal, &pending_transaction.txid, payments, fee_satoshis);
+        invalidate_cached_utxos(params.network, &source_address);
+        Ok(BtcSendTransactionResponse {
+            txid: pending_transaction.txid,
+            utxos: pending_transaction.utxos,
+            fee_satoshis: fee_satoshis.unwrap_or_default(),
+            change_output: pending_transaction.change_output,
+        })
+    }
+    inner(params).await.into()
+}
+
+/// Adds a signed transaction paying `outputs` from the caller's address to their pending
+/// transactions, once its inputs are checked: they must be current UTXOs of the address, or the
+/// unconfirmed change of pending transactions, and no pending transaction may reserve them.
+///
+/// Returns the stored pending transaction.
+#[expect(clippy::too_many_arguments)]
+fn add_sent_transaction(
+    principal: Principal,
+    source_address: &str,
+    network: BitcoinNetwork,
+    transaction: &bitcoin::Transaction,
+    outputs: &[BtcTxOutput],
+    change_output: Option<BtcChangeOutput>,
+    current_utxos: &[Utxo],
+    now_ns: u64,
+) -> Result<StoredPendingTransaction, BtcSendTransactionError> {
+    let ttls = read_config(|config| config.btc_pending_transaction_ttls);
+    mutate_state(|state| {
+        let mut model = BtcUserPendingTransactionsModel::new(
+            &mut state.btc_user_pending_transactions,
+            &mut state.btc_outpoint_reservations,
+            None,
+            None,
+            ttls,
+        );
+        model.prune_pending_transactions(principal, source_address, current_utxos, now_ns);
+
+        let change_utxos = model.change_utxos(&principal, source_address, now_ns);
+        let utxos = current_utxos_of(
+            &signed_tx::input_outpoints(transaction),
+            &[current_utxos, &change_utxos].concat(),
+        )
+        .ok_or(BtcSendTransactionError::InvalidUtxos)?;
+        if model.has_intersecting_pending_utxos(&utxos, now_ns) {
+            return Err(BtcSendTransactionError::UtxosAlreadyReserved);
+        }
+        let fee_satoshis = signed_tx::fee_satoshis(transaction, &utxos).ok_or_else(|| {
+            BtcSendTransactionError::InvalidTransaction {
+                msg: "Outputs are worth more than inputs".to_string(),
+            }
+        })?;
+
+        // A batch payment has no single recipient.
+        let payment = match outputs {
+            [output] => Some(output),
+            _ => None,
+        };
+        let pending_transaction = StoredPendingTransaction {
+            txid: signed_tx::txid(transaction),
+            utxos,
+            created_at_timestamp_ns: now_ns,
+            fee_satoshis: Some(fee_satoshis),
+            replaced_txids: None,
+            destination_address: payment.map(|output| output.destination_address.clone()),
+            amount_satoshis: payment.map(|output| output.amount_satoshis),
+            network: Some(network),
+            status: Some(BtcPendingTransactionStatus::Pending),
+            finalized_at_timestamp_ns: None,
+            change_output,
+            parent_txids: None,
+        };
+        model
+            .add_pending_transaction(
+                principal,
+                source_address.to_string(),
+                pending_transaction.clone(),
+            )
+            .map_err(|msg| BtcSendTransactionError::InternalError { msg })?;
+        Ok(pending_transaction)
+    })
+}
+
+/// Records every payment of a sent transaction in the send history of the caller.  The fee of a
+/// batch payment is recorded with its first output.
+fn record_sent_outputs(
+    principal: Principal,
+    txid: &[u8],
+    payments: Vec<Transaction<BitcoinNetwork, BtcAddress>>,
+    fee_satoshis: Option<u64>,
+) {
+    mutate_state(|state| {
+        for (index, payment) in payments.into_iter().enumerate() {
+            send_history::record_sent_transaction(
+                &mut state.btc_send_history,
+                principal,
+                txid.to_vec(),
+                payment,
+                fee_satoshis.filter(|_| index == 0),
+            );
+        }
+    });
+}
+
+/// Quotes the fee of a transaction replacing a pending Bitcoin transaction of the caller, at the
+/// fee rate of every priority tier.
+///
+/// The replacement is assumed to spend the same UTXOs.  The quotes never go below the minimum fee
+/// accepted by `btc_replace_pending_transaction`.
+///
+/// # Errors
+/// Errors are enumerated by: `BtcGetFeeBumpQuotesError`.
+#[update(guard = "caller_is_not_anonymous")]
+pub async fn btc_get_fee_bump_quotes(
+    params: BtcGetFeeBumpQuotesRequest,
+) -> BtcGetFeeBumpQuotesResult {
+    async fn inner(
+        params: BtcGetFeeBumpQuotesRequest,
+    ) -> Result<BtcGetFeeBumpQuotesResponse, BtcGetFeeBumpQuotesError> {
+        let principal = ic_cdk::caller();
+
+        let (source_address, input_type) =
+            caller_btc_address(principal, params.network, params.address_type)
+                .await
+                .map_err(|msg| BtcGetFeeBumpQuotesError::InternalError { msg })?;
+
+        let replaced = mutate_state(|state| {
+            BtcUserPendingTransactionsModel::new(
+                &mut state.btc_user_pending_transactions,
+                &mut state.btc_outpoint_reservations,
+                None,
+                None,
+                None,
+            )
+            .get_pending_transaction(&principal, &source_address, &params.txid)
+        })
+        .filter(StoredPendingTransaction::is_pending)
+        .ok_or(BtcGetFeeBumpQuotesError::TransactionNotFound)?;
+        let replaced_fee_satoshis = replaced
+            .fee_satoshis
+            .ok_or(BtcGetFeeBumpQuotesError::TransactionFeeUnknown)?;
+
+        let input_count = replaced.utxos.len() as u64;
+        let min_fee_satoshis = utils::min_replacement_fee(
+            replaced_fee_satoshis,
+            input_type,
+            input_count,
+            &pending_tx_output_types(input_type),
+        );
+        let tiers = read_config(|config| config.btc_fee_tiers.clone().unwrap_or_default());
+        let fee_quotes = fee_quotes(params.network, &tiers, |fee_millisatoshi_per_vbyte| {
+            utils::estimate_fee(
+                input_type,
+                input_count,
+                fee_millisatoshi_per_vbyte,
+                &pending_tx_output_types(input_type),
+            )
+            .max(min_fee_satoshis)
+        });
+
+        Ok(BtcGetFeeBumpQuotesResponse {
+            min_fee_satoshis,
+            fee_quotes,
         })
     }
     inner(params).await.into()
 }
 
+/// Returns the UTXOs that the user did not freeze.
+fn without_caller_frozen_utxos(principal: Principal, utxos: Vec<Utxo>) -> Vec<Utxo> {
+    let frozen_outpoints =
+        read_state(|state| frozen_utxos::get_frozen_utxos(&state.btc_frozen_utxos, principal));
+    frozen_utxos::without_frozen_utxos(utxos, &frozen_outpoints)
+}
+
+/// Returns the current UTXOs of the given outpoints, or `None` if there are no outpoints, if some
+/// of them are duplicates or if some of them are not current UTXOs.
+fn current_utxos_of(outpoints: &[Outpoint], current_utxos: &[Utxo]) -> Option<Vec<Utxo>> {
+    let utxos = outpoints
+        .iter()
+        .map(|outpoint| {
+            current_utxos
+                .iter()
+                .find(|utxo| utxo.outpoint == *outpoint)
+                .cloned()
+        })
+        .collect::<Option<Vec<Utxo>>>()?;
+    (!utxos.is_empty() && !has_duplicate_utxos(&utxos)).then_some(utxos)
+}
+
+/// Removes the cached UTXO sets of an address that has just spent some of its UTXOs.
+fn invalidate_cached_utxos(network: BitcoinNetwork, address: &str) {
+    utxo_cache::with_utxo_cache(|cache| cache.invalidate_address(network, address));
+}
+
+/// Returns the payment of an outgoing transaction for the send history, or `None` if its recipient
+/// or amount are unknown.
+///
+/// # Errors
+/// - If the recipient is not a valid address on `network`.
+fn sent_payment(
+    network: BitcoinNetwork,
+    destination_address: Option<&str>,
+    amount_satoshis: Option<u64>,
+    now_ns: u64,
+) -> Result<Option<Transaction<BitcoinNetwork, BtcAddress>>, String> {
+    let (Some(destination_address), Some(amount_satoshis)) = (destination_address, amount_satoshis)
+    else {
+        return Ok(None);
+    };
+    let counterparty = BtcAddress::parse_for_network(destination_address, network)
+        .map_err(|err| format!("Invalid destination address {destination_address}: {err:?}"))?;
+    Ok(Some(Transaction {
+        network,
+        transaction_type: TransactionType::Send,
+        amount: amount_satoshis,
+        timestamp: now_ns,
+        counterparty,
+    }))
+}
+
+/// Returns whether some of the UTXOs share the same outpoint.
+fn has_duplicate_utxos(utxos: &[Utxo]) -> bool {
+    let unique_keys: HashSet<(&[u8], u32)> = utxos
+        .iter()
+        .map(|u| (u.outpoint.txid.as_slice(), u.outpoint.vout))
+        .collect();
+    unique_keys.len() != utxos.len()
+}
+
+/// Returns whether all the UTXOs are among the current UTXOs of the address, which may include
+/// the unconfirmed change of pending transactions.
+fn are_current_utxos(utxos: &[Utxo], current_utxos: &[Utxo]) -> bool {
+    let current_keys: HashSet<(&[u8], u32)> = current_utxos
+        .iter()
+        .map(|u| (u.outpoint.txid.as_slice(), u.outpoint.vout))
+        .collect();
+    utxos
+        .iter()
+        .all(|u| current_keys.contains(&(u.outpoint.txid.as_slice(), u.outpoint.vout)))
+}
+
 /// Returns the pending Bitcoin transactions for the caller.
 ///
+/// Transactions that were confirmed or expired in the last day are returned as well, with their
+/// status.
+///
 /// # Errors
 /// Errors are enumerated by: `BtcGetPendingTransactionsError`.
 #[update(guard = "caller_is_not_anonymous")]
@@ -234,22 +1333,22 @@ pub async fn btc_get_pending_transactions(
         .await
         .map_err(|msg| BtcGetPendingTransactionsError::InternalError { msg })?;
 
+        let ttls = read_config(|config| config.btc_pending_transaction_ttls);
         let stored_transactions = mutate_state(|state| {
             let mut model = BtcUserPendingTransactionsModel::new(
                 &mut state.btc_user_pending_transactions,
+                &mut state.btc_outpoint_reservations,
                 None,
                 None,
+                ttls,
             );
-            model.prune_pending_transactions(principal, &current_utxos, now_ns);
+            model.prune_pending_transactions(principal, &params.address, &current_utxos, now_ns);
             model.get_pending_transactions(&principal, &params.address)
         });
 
         let pending_transactions = stored_transactions
             .iter()
-            .map(|tx| PendingTransaction {
-                txid: tx.txid.clone(),
-                utxos: tx.utxos.clone(),
-            })
+            .map(PendingTransaction::from)
             .collect();
 
         Ok(BtcGetPendingTransactionsReponse {
@@ -258,3 +1357,302 @@ pub async fn btc_get_pending_transactions(
     }
     inner(params).await.into()
 }
+
+/// Returns the balance of the caller's address.
+///
+/// UTXOs count as confirmed from `min_confirmations` confirmations.  The UTXOs spent by pending
+/// transactions, of the caller or of other users, are reserved as in `btc_select_user_utxos_fee`,
+/// and the spendable balance is the value of the confirmed UTXOs that are neither reserved nor
+/// frozen by the caller.
+///
+/// # Errors
+/// Errors are enumerated by: `BtcGetBalanceError`.
+#[update(guard = "caller_is_not_anonymous")]
+pub async fn btc_get_balance(params: BtcGetBalanceRequest) -> BtcGetBalanceResult {
+    async fn inner(
+        params: BtcGetBalanceRequest,
+    ) -> Result<BtcGetBalanceResponse, BtcGetBalanceError> {
+        BTC_GET_BALANCE_RATE_LIMITER
+            .with(rate_limiter::RateLimiter::check_caller)
+            .map_err(BtcGetBalanceError::RateLimited)?;
+
+        let principal = ic_cdk::caller();
+
+        let (source_address, _) =
+            caller_btc_address(principal, params.network, params.address_type)
+                .await
+                .map_err(|msg| BtcGetBalanceError::InternalError { msg })?;
+
+        let (all_utxos, tip_height) =
+            api::get_all_utxos_at_tip(params.network, source_address.clone(), None)
+                .await
+                .map_err(|msg| BtcGetBalanceError::InternalError { msg })?;
+
+        let now_ns = time();
+        let ttls = read_config(|config| config.btc_pending_transaction_ttls);
+        let frozen_outpoints =
+            read_state(|state| frozen_utxos::get_frozen_utxos(&state.btc_frozen_utxos, principal));
+        let reserved_utxos: Vec<Utxo> = mutate_state(|state| {
+            let mut model = BtcUserPendingTransactionsModel::new(
+                &mut state.btc_user_pending_transactions,
+                &mut state.btc_outpoint_reservations,
+                None,
+                None,
+                ttls,
+            );
+            model.prune_pending_transactions(principal, &source_address, &all_utxos, now_ns);
+            all_utxos
+                .iter()
+                .filter(|utxo| {
+                    model.has_intersecting_pending_utxos(std::slice::from_ref(utxo), now_ns)
+                })
+                .cloned()
+                .collect()
+        });
+
+        Ok(utils::balance(
+            &all_utxos,
+            tip_height,
+            params
+                .min_confirmations
+                .unwrap_or(MIN_CONFIRMATIONS_ACCEPTED_BTC_TX),
+            &reserved_utxos,
+            &frozen_outpoints,
+        ))
+    }
+    inner(params).await.into()
+}
+
+/// Reports the dust UTXOs of the caller's address, worth less than `utils::dust_threshold` at the
+/// requested fee rate, which are excluded from the automatic selection.
+///
+/// Dust that was not created by one of the caller's own transactions, whether in their send
+/// history or pending, is flagged as a suspected dust attack: spending it together with other UTXOs
+/// would reveal that they belong to the same owner.  Unconfirmed UTXOs are reported as well.
+///
+/// # Errors
+/// Errors are enumerated by: `BtcGetDustReportError`.
+#[update(guard = "caller_is_not_anonymous")]
+pub async fn btc_get_dust_report(params: BtcGetDustReportRequest) -> BtcGetDustReportResult {
+    async fn inner(
+        params: BtcGetDustReportRequest,
+    ) -> Result<BtcGetDustReportResponse, BtcGetDustReportError> {
+        BTC_GET_DUST_REPORT_RATE_LIMITER
+            .with(rate_limiter::RateLimiter::check_caller)
+            .map_err(BtcGetDustReportError::RateLimited)?;
+
+        let principal = ic_cdk::caller();
+        let (source_address, input_type) =
+            caller_btc_address(principal, params.network, params.address_type)
+                .await
+                .map_err(|msg| BtcGetDustReportError::InternalError { msg })?;
+        let all_utxos = api::get_all_utxos(params.network, source_address.clone(), None)
+            .await
+            .map_err(|msg| BtcGetDustReportError::InternalError { msg })?;
+
+        let tiers = read_config(|config| config.btc_fee_tiers.clone().unwrap_or_default());
+        let fee_millisatoshi_per_vbyte = api::get_fee_rate_per_byte(
+            params.network,
+            params
+                .fee_rate
+                .unwrap_or(BtcFeeRate::Priority(BtcFeePriority::Normal)),
+            &tiers,
+        );
+        let dust_threshold_satoshis = utils::dust_threshold(input_type, fee_millisatoshi_per_vbyte);
+        let (_, dust_utxos) = utils::partition_dust(all_utxos, dust_threshold_satoshis);
+
+        let ttls = read_config(|config| config.btc_pending_transaction_ttls);
+        let (own_txids, frozen_outpoints) = mutate_state(|state| {
+            let model = BtcUserPendingTransactionsModel::new(
+                &mut state.btc_user_pending_transactions,
+                &mut state.btc_outpoint_reservations,
+                None,
+                None,
+                ttls,
+            );
+            let own_txids: HashSet<Vec<u8>> = model
+                .get_pending_transactions(&principal, &source_address)
+                .into_iter()
+                .map(|transaction| transaction.txid)
+                .chain(send_history::sent_txids(&state.btc_send_history, principal))
+                .collect();
+            let frozen_outpoints =
+                frozen_utxos::get_frozen_utxos(&state.btc_frozen_utxos, principal);
+            (own_txids, frozen_outpoints)
+        });
+
+        Ok(BtcGetDustReportResponse {
+            dust_threshold_satoshis,
+            total_satoshis: dust_utxos.iter().map(|utxo| utxo.value).sum(),
+            utxos: dust_utxos
+                .into_iter()
+                .map(|utxo| BtcDustUtxo {
+                    suspected_attack: !own_txids.contains(&utxo.outpoint.txid),
+                    frozen: frozen_outpoints.contains(&utxo.outpoint),
+                    utxo,
+                })
+                .collect(),
+        })
+    }
+    inner(params).await.into()
+}
+
+/// Freezes and unfreezes UTXOs of the caller, and returns all their frozen UTXOs.
+///
+/// Frozen UTXOs are never selected to be spent by `btc_select_user_utxos_fee`, `btc_build_psbt`
+/// or `btc_get_consolidation_advice`, and are not part of the spendable balance.
+///
+/// # Errors
+/// Errors are enumerated by: `BtcUpdateFrozenUtxosError`.
+#[update(guard = "caller_is_not_anonymous")]
+#[expect(clippy::needless_pass_by_value)]
+#[must_use]
+pub fn btc_update_frozen_utxos(params: BtcUpdateFrozenUtxosRequest) -> BtcUpdateFrozenUtxosResult {
+    let principal = ic_cdk::caller();
+    mutate_state(|state| {
+        frozen_utxos::update_frozen_utxos(
+            &mut state.btc_frozen_utxos,
+            principal,
+            params.freeze.clone(),
+            &params.unfreeze,
+        )
+    })
+    .into()
+}
+
+/// Returns the UTXOs frozen by the caller, in the order they were frozen.
+#[query(guard = "caller_is_not_anonymous")]
+#[must_use]
+pub fn btc_list_frozen_utxos() -> Vec<Outpoint> {
+    read_state(|state| frozen_utxos::get_frozen_utxos(&state.btc_frozen_utxos, ic_cdk::caller()))
+}
+
+/// Returns a page of the outgoing Bitcoin transactions of the caller, newest first.
+///
+/// A transaction is recorded in the history when its pending transaction is added with a
+/// recipient and an amount, and is updated when it is replaced.  The history keeps the
+/// `MAX_SEND_HISTORY_LEN` most recent transactions of the caller.
+#[query(guard = "caller_is_not_anonymous")]
+#[expect(clippy::needless_pass_by_value)]
+#[must_use]
+pub fn btc_get_send_history(params: BtcGetSendHistoryRequest) -> BtcGetSendHistoryResponse {
+    read_state(|state| {
+        send_history::get_send_history(
+            &state.btc_send_history,
+            ic_cdk::caller(),
+            params.network,
+            params.before_id,
+            params.limit,
+        )
+    })
+}
+
+/// Subscribes the caller's address to deposit detection: the address is then polled periodically,
+/// and its incoming payments are recorded in the deposit feed of the caller.
+///
+/// Subscribing an address again does nothing, not even reading the tip of the chain.  The UTXOs
+/// that the address has when it is subscribed are not deposits.
+///
+/// # Returns
+/// All the subscribed addresses of the caller on success.
+///
+/// # Errors
+/// Errors are enumerated by: `BtcSubscribeDepositsError`.
+#[update(guard = "caller_is_not_anonymous")]
+pub async fn btc_subscribe_deposits(
+    params: BtcSubscribeDepositsRequest,
+) -> BtcSubscribeDepositsResult {
+    async fn inner(
+        params: BtcSubscribeDepositsRequest,
+    ) -> Result<Vec<BtcDepositSubscription>, BtcSubscribeDepositsError> {
+        BTC_SUBSCRIBE_DEPOSITS_RATE_LIMITER
+            .with(rate_limiter::RateLimiter::check_caller)
+            .map_err(BtcSubscribeDepositsError::RateLimited)?;
+
+        let principal = ic_cdk::caller();
+        let address_type = params
+            .address_type
+            .unwrap_or_else(|| caller_default_address_type(principal));
+        let subscriptions = read_state(|state| {
+            deposits::get_subscriptions(&state.btc_deposit_subscriptions, principal)
+        });
+        if deposits::is_subscribed(&subscriptions, params.network, address_type) {
+            return Ok(subscriptions);
+        }
+
+        let (address, _) = caller_btc_address(principal, params.network, Some(address_type))
+            .await
+            .map_err(|msg| BtcSubscribeDepositsError::InternalError { msg })?;
+        // The deposits are the UTXOs above the tip of the chain at subscription, so that the first
+        // poll already reports those received in the meantime.
+        let (_, tip_height) = api::get_all_utxos_at_tip(params.network, address.clone(), None)
+            .await
+            .map_err(|msg| BtcSubscribeDepositsError::InternalError { msg })?;
+
+        let now_ns = time();
+        Ok(mutate_state(|state| {
+            deposits::subscribe(
+                &mut state.btc_deposit_subscriptions,
+                principal,
+                params.network,
+                address_type,
+                address,
+                tip_height,
+                now_ns,
+            )
+        }))
+    }
+    inner(params).await.into()
+}
+
+/// Unsubscribes the caller's address from deposit detection.  The deposits already recorded are
+/// kept in the feed.
+///
+/// # Returns
+/// All the remaining subscribed addresses of the caller.
+#[update(guard = "caller_is_not_anonymous")]
+#[expect(clippy::needless_pass_by_value)]
+#[must_use]
+pub fn btc_unsubscribe_deposits(
+    params: BtcUnsubscribeDepositsRequest,
+) -> Vec<BtcDepositSubscription> {
+    let principal = ic_cdk::caller();
+    let address_type = params
+        .address_type
+        .unwrap_or_else(|| caller_default_address_type(principal));
+    mutate_state(|state| {
+        deposits::unsubscribe(
+            &mut state.btc_deposit_subscriptions,
+            principal,
+            params.network,
+            address_type,
+        )
+    })
+}
+
+/// Returns the addresses of the caller that are subscribed to deposit detection.
+#[query(guard = "caller_is_not_anonymous")]
+#[must_use]
+pub fn btc_get_deposit_subscriptions() -> Vec<BtcDepositSubscription> {
+    read_state(|state| {
+        deposits::get_subscriptions(&state.btc_deposit_subscriptions, ic_cdk::caller())
+    })
+}
+
+/// Returns a page of the deposits to the subscribed addresses of the caller, newest first.
+///
+/// The feed keeps the `MAX_DEPOSIT_EVENTS_LEN` most recent deposits of the caller.
+#[query(guard = "caller_is_not_anonymous")]
+#[expect(clippy::needless_pass_by_value)]
+#[must_use]
+pub fn btc_get_deposit_events(params: BtcGetDepositEventsRequest) -> BtcGetDepositEventsResponse {
+    read_state(|state| {
+        deposits::get_deposit_events(
+            &state.btc_deposit_events,
+            ic_cdk::caller(),
+            params.network,
+            params.before_id,
+            params.limit,
+        )
+    })
+}
@@ -4,3 +4,4 @@ pub mod contacts;
 pub mod custom_tokens;
 pub mod signer;
 pub mod user_profile;
+pub mod watch_only;
@@ -2,13 +2,14 @@ use ic_cdk::{api::time, query, update};
 use ic_verifiable_credentials::validate_ii_presentation_and_claims;
 use shared::types::{
     agreement::UpdateUserAgreementsRequest,
+    bitcoin::UpdateUserBtcSettingsRequest,
     dapp::{AddDappSettingsError, AddHiddenDappIdRequest},
     experimental_feature::UpdateExperimentalFeaturesSettingsRequest,
     network::{SaveNetworksSettingsRequest, SetShowTestnetsRequest},
     result_types::{
         AddUserCredentialResult, AddUserHiddenDappIdResult, GetUserProfileResult,
         SetUserShowTestnetsResult, UpdateExperimentalFeaturesSettingsResult,
-        UpdateUserAgreementsResult, UpdateUserNetworkSettingsResult,
+        UpdateUserAgreementsResult, UpdateUserBtcSettingsResult, UpdateUserNetworkSettingsResult,
     },
     user_profile::{
         AddUserCredentialError, AddUserCredentialRequest, HasUserProfileResponse, UserProfile,
@@ -214,6 +215,36 @@ pub fn update_user_experimental_feature_settings(
     .into()
 }
 
+/// Updates the user's bitcoin settings, such as the default type of their bitcoin address.
+///
+/// # Returns
+/// - Returns `Ok(())` if the bitcoin settings were updated successfully, or if they were already
+///   set to the same value.
+///
+/// # Errors
+/// - Returns `Err` if the user profile is not found, or the user profile version is not up-to-date.
+#[update(guard = "caller_is_not_anonymous")]
+#[expect(clippy::needless_pass_by_value)]
+#[must_use]
+pub fn update_user_btc_settings(
+    request: UpdateUserBtcSettingsRequest,
+) -> UpdateUserBtcSettingsResult {
+    let user_principal = ic_cdk::caller();
+    let stored_principal = StoredPrincipal(user_principal);
+
+    mutate_state(|s| {
+        let mut user_profile_model =
+            UserProfileModel::new(&mut s.user_profile, &mut s.user_profile_updated);
+        service::update_btc_settings(
+            stored_principal,
+            request.current_user_version,
+            request.btc,
+            &mut user_profile_model,
+        )
+    })
+    .into()
+}
+
 /// It creates a new user profile for the caller.
 /// If the user has already a profile, it will return that profile.
 #[update(guard = "caller_is_not_anonymous")]
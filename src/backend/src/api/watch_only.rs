@@ -0,0 +1,126 @@
+use ic_cdk::{api::time, query, update};
+use shared::types::{
+    account::{BtcAddress, TokenAccountId},
+    result_types::{AddWatchOnlyAddressResult, GetWatchOnlyBtcBalanceResult},
+    watch_only::{
+        AddWatchOnlyAddressRequest, GetWatchOnlyBtcBalanceError, GetWatchOnlyBtcBalanceRequest,
+        GetWatchOnlyBtcBalanceResponse, RemoveWatchOnlyAddressRequest, WatchOnlyAddress,
+        WatchOnlyAddressError,
+    },
+};
+
+use crate::{
+    bitcoin::api,
+    state::{mutate_state, read_state},
+    utils::{
+        guards::caller_is_not_anonymous, housekeeping::WATCH_ONLY_BTC_BALANCE_RATE_LIMITER,
+        rate_limiter,
+    },
+    watch_only,
+};
+
+/// Adds a BTC, ETH, SOL or Kaspa address that the caller watches without holding its keys, or
+/// updates its label if the caller already watches it.
+///
+/// # Returns
+/// All watch-only addresses of the caller on success.
+///
+/// # Errors
+/// Errors are enumerated by: `WatchOnlyAddressError`.
+#[update(guard = "caller_is_not_anonymous")]
+#[must_use]
+pub fn add_watch_only_address(request: AddWatchOnlyAddressRequest) -> AddWatchOnlyAddressResult {
+    fn inner(
+        request: AddWatchOnlyAddressRequest,
+    ) -> Result<Vec<WatchOnlyAddress>, WatchOnlyAddressError> {
+        let token_account_id = watch_only::parse_watch_only_address(&request.address)?;
+        let principal = ic_cdk::caller();
+        let now_ns = time();
+        mutate_state(|state| {
+            watch_only::add_watch_only_address(
+                &mut state.watch_only_addresses,
+                principal,
+                token_account_id,
+                request.label,
+                now_ns,
+            )
+        })
+    }
+    inner(request).into()
+}
+
+/// Removes a watch-only address of the caller.  Removing an address that the caller does not watch
+/// does nothing.
+///
+/// # Returns
+/// All remaining watch-only addresses of the caller.
+#[update(guard = "caller_is_not_anonymous")]
+#[expect(clippy::needless_pass_by_value)]
+#[must_use]
+pub fn remove_watch_only_address(request: RemoveWatchOnlyAddressRequest) -> Vec<WatchOnlyAddress> {
+    let principal = ic_cdk::caller();
+    match watch_only::parse_watch_only_address(&request.address) {
+        Ok(token_account_id) => mutate_state(|state| {
+            watch_only::remove_watch_only_address(
+                &mut state.watch_only_addresses,
+                principal,
+                &token_account_id,
+            )
+        }),
+        Err(_) => read_state(|state| {
+            watch_only::get_watch_only_addresses(&state.watch_only_addresses, principal)
+        }),
+    }
+}
+
+/// Returns the watch-only addresses of the caller, in the order they were added.
+#[query(guard = "caller_is_not_anonymous")]
+#[must_use]
+pub fn list_watch_only_addresses() -> Vec<WatchOnlyAddress> {
+    let principal = ic_cdk::caller();
+    read_state(|state| watch_only::get_watch_only_addresses(&state.watch_only_addresses, principal))
+}
+
+/// Returns the balance of a bitcoin address watched by the caller, summed over its UTXOs.
+///
+/// The network is given by the prefix of the address.
+///
+/// # Errors
+/// Errors are enumerated by: `GetWatchOnlyBtcBalanceError`.
+#[update(guard = "caller_is_not_anonymous")]
+pub async fn get_watch_only_btc_balance(
+    request: GetWatchOnlyBtcBalanceRequest,
+) -> GetWatchOnlyBtcBalanceResult {
+    async fn inner(
+        request: GetWatchOnlyBtcBalanceRequest,
+    ) -> Result<GetWatchOnlyBtcBalanceResponse, GetWatchOnlyBtcBalanceError> {
+        WATCH_ONLY_BTC_BALANCE_RATE_LIMITER
+            .with(rate_limiter::RateLimiter::check_caller)
+            .map_err(GetWatchOnlyBtcBalanceError::RateLimited)?;
+
+        let principal = ic_cdk::caller();
+        let (address, network) = BtcAddress::parse_with_network(&request.address)
+            .map_err(|_| GetWatchOnlyBtcBalanceError::AddressNotWatched)?;
+        let token_account_id = TokenAccountId::Btc(address.clone());
+        if !read_state(|state| {
+            watch_only::is_watched(&state.watch_only_addresses, principal, &token_account_id)
+        }) {
+            return Err(GetWatchOnlyBtcBalanceError::AddressNotWatched);
+        }
+
+        let utxos = api::get_all_utxos(
+            network,
+            address.as_str().to_string(),
+            request.min_confirmations,
+        )
+        .await
+        .map_err(|msg| GetWatchOnlyBtcBalanceError::InternalError { msg })?;
+
+        Ok(GetWatchOnlyBtcBalanceResponse {
+            network,
+            balance_satoshis: utxos.iter().map(|utxo| utxo.value).sum(),
+            utxo_count: utxos.len() as u64,
+        })
+    }
+    inner(request).await.into()
+}
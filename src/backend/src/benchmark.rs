@@ -120,8 +120,13 @@ fn with_btc_pending_model<R>(
     state: &mut State,
     f: impl FnOnce(&mut BtcUserPendingTransactionsModel<'_>) -> R,
 ) -> R {
-    let mut model =
-        BtcUserPendingTransactionsModel::new(&mut state.btc_user_pending_transactions, None, None);
+    let mut model = BtcUserPendingTransactionsModel::new(
+        &mut state.btc_user_pending_transactions,
+        &mut state.btc_outpoint_reservations,
+        None,
+        None,
+        None,
+    );
     f(&mut model)
 }
 
@@ -549,6 +554,15 @@ fn bench_btc_add_pending_transaction_with_count(existing_count: u8) -> BenchResu
                     txid: vec![i; 32],
                     utxos: vec![utxo.clone()],
                     created_at_timestamp_ns: 1_000_000_000,
+                    fee_satoshis: None,
+                    replaced_txids: None,
+                    destination_address: None,
+                    amount_satoshis: None,
+                    network: None,
+                    status: None,
+                    finalized_at_timestamp_ns: None,
+                    change_output: None,
+                    parent_txids: None,
                 };
                 model
                     .add_pending_transaction(principal, address.clone(), tx)
@@ -574,10 +588,10 @@ fn bench_btc_add_pending_transaction_with_count(existing_count: u8) -> BenchResu
     bench_fn(|| {
         mutate_state(|state| {
             with_btc_pending_model(state, |model| {
-                model.prune_pending_transactions(principal, &current_utxos, now_ns);
+                model.prune_pending_transactions(principal, &address, &current_utxos, now_ns);
 
                 assert!(
-                    !model.has_intersecting_pending_utxos(principal, &new_utxos),
+                    !model.has_intersecting_pending_utxos(&new_utxos, now_ns),
                     "unexpected intersection"
                 );
 
@@ -585,6 +599,15 @@ fn bench_btc_add_pending_transaction_with_count(existing_count: u8) -> BenchResu
                     txid: txid.clone(),
                     utxos: new_utxos.clone(),
                     created_at_timestamp_ns: now_ns,
+                    fee_satoshis: None,
+                    replaced_txids: None,
+                    destination_address: None,
+                    amount_satoshis: None,
+                    network: None,
+                    status: None,
+                    finalized_at_timestamp_ns: None,
+                    change_output: None,
+                    parent_txids: None,
                 };
 
                 model
@@ -618,6 +641,15 @@ fn bench_btc_get_pending_transactions_with_count(count: u8) -> BenchResult {
                     txid: vec![i; 32],
                     utxos: vec![utxo.clone()],
                     created_at_timestamp_ns: 1_000_000_000,
+                    fee_satoshis: None,
+                    replaced_txids: None,
+                    destination_address: None,
+                    amount_satoshis: None,
+                    network: None,
+                    status: None,
+                    finalized_at_timestamp_ns: None,
+                    change_output: None,
+                    parent_txids: None,
                 };
 
                 model
@@ -632,18 +664,13 @@ fn bench_btc_get_pending_transactions_with_count(count: u8) -> BenchResult {
     bench_fn(|| {
         let stored = mutate_state(|state| {
             with_btc_pending_model(state, |model| {
-                model.prune_pending_transactions(principal, &utxos, now_ns);
+                model.prune_pending_transactions(principal, &address, &utxos, now_ns);
                 model.get_pending_transactions(&principal, &address)
             })
         });
 
-        let pending: Vec<PendingTransaction> = stored
-            .iter()
-            .map(|tx| PendingTransaction {
-                txid: tx.txid.clone(),
-                utxos: tx.utxos.clone(),
-            })
-            .collect();
+        let pending: Vec<PendingTransaction> =
+            stored.iter().map(PendingTransaction::from).collect();
 
         std::hint::black_box(pending);
     })
@@ -1,13 +1,30 @@
-use std::{cell::RefCell, collections::HashMap};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+};
+
+use candid::Principal;
 
 use ic_cdk::api::management_canister::bitcoin::{
-    bitcoin_get_current_fee_percentiles, bitcoin_get_utxos, BitcoinNetwork,
-    GetCurrentFeePercentilesRequest, GetUtxosRequest, GetUtxosResponse, MillisatoshiPerByte, Utxo,
-    UtxoFilter,
+    bitcoin_get_current_fee_percentiles, bitcoin_get_utxos, bitcoin_send_transaction,
+    BitcoinNetwork, GetCurrentFeePercentilesRequest, GetUtxosRequest, GetUtxosResponse,
+    MillisatoshiPerByte, SendTransactionRequest, Utxo, UtxoFilter,
 };
 use ic_cdk_timers::{set_timer, set_timer_interval};
 use shared::types::bitcoin::{
-    FEE_PERCENTILES_INITIAL_DELAY, FEE_PERCENTILES_UPDATE_INTERVAL, FEE_UPDATE_TIMEOUT_NS,
+    BtcFeePriority, BtcFeeRate, BtcFeeTiersConfig, DEPOSIT_POLL_BATCH_LEN, DEPOSIT_POLL_INTERVAL,
+    DEPOSIT_POLL_TIMEOUT_NS, FEE_PERCENTILES_INITIAL_DELAY, FEE_PERCENTILES_UPDATE_INTERVAL,
+    FEE_UPDATE_TIMEOUT_NS,
+};
+
+use crate::{
+    bitcoin::{
+        deposits, fee_history,
+        pending_tx_model::BtcUserPendingTransactionsModel,
+        send_history,
+        utxo_cache::{with_utxo_cache, ChainTip, UtxoCacheKey},
+    },
+    state::{mutate_state, read_state},
 };
 
 // Default fee values for different networks when API fails
@@ -22,6 +39,11 @@ thread_local! {
     static FEE_PERCENTILES_CACHE: RefCell<HashMap<BitcoinNetwork, Vec<MillisatoshiPerByte>>> = RefCell::new(HashMap::new());
     /// `None` = idle; `Some(timestamp_ns)` = update started at that IC time.
     static FEE_UPDATE_STARTED_AT: RefCell<Option<u64>> = const { RefCell::new(None) };
+    /// `None` = idle; `Some(timestamp_ns)` = deposit poll started at that IC time.
+    static DEPOSIT_POLL_STARTED_AT: RefCell<Option<u64>> = const { RefCell::new(None) };
+    /// The last user polled for deposits, after whom the next poll starts.  `None` = the first
+    /// user.
+    static DEPOSIT_POLL_CURSOR: RefCell<Option<Principal>> = const { RefCell::new(None) };
 }
 
 /// Returns the UTXOs of the given bitcoin address.
@@ -50,6 +72,21 @@ pub async fn get_all_utxos(
     address: String,
     min_confirmations: Option<u32>,
 ) -> Result<Vec<Utxo>, String> {
+    get_all_utxos_at_tip(network, address, min_confirmations)
+        .await
+        .map(|(utxos, _tip_height)| utxos)
+}
+
+/// Returns all the UTXOs of a specific address, with the height of the tip of the chain that they
+/// were read at.
+///
+/// The UTXO sets that span several pages are cached until the tip of the chain moves, so only
+/// their first page is read again while the tip stays the same.
+pub async fn get_all_utxos_at_tip(
+    network: BitcoinNetwork,
+    address: String,
+    min_confirmations: Option<u32>,
+) -> Result<(Vec<Utxo>, u32), String> {
     let final_min_confirmations = if network == BitcoinNetwork::Regtest {
         // Tests with Regtest fail if min_confirmations is higher than 1.
         Some(1)
@@ -59,8 +96,25 @@ pub async fn get_all_utxos(
     let filter = final_min_confirmations.map(UtxoFilter::MinConfirmations);
     let mut utxos_response = get_utxos(network, address.clone(), filter).await?;
 
+    let tip = ChainTip {
+        height: utxos_response.tip_height,
+        block_hash: utxos_response.tip_block_hash,
+    };
+    let tip_height = tip.height;
     let mut all_utxos: Vec<Utxo> = utxos_response.utxos;
     let mut next_page: Option<Vec<u8>> = utxos_response.next_page;
+    // A UTXO set that fits in the first page is not worth caching.
+    if next_page.is_none() {
+        return Ok((all_utxos, tip_height));
+    }
+    let key = UtxoCacheKey {
+        network,
+        address: address.clone(),
+        min_confirmations: final_min_confirmations,
+    };
+    if let Some(cached_utxos) = with_utxo_cache(|cache| cache.get(&key, &tip)) {
+        return Ok((cached_utxos, tip_height));
+    }
     while next_page.is_some() {
         utxos_response =
             get_utxos(network, address.clone(), next_page.map(UtxoFilter::Page)).await?;
@@ -68,7 +122,22 @@ pub async fn get_all_utxos(
         next_page = utxos_response.next_page;
     }
 
-    Ok(all_utxos)
+    with_utxo_cache(|cache| cache.insert(key, tip, all_utxos.clone()));
+    Ok((all_utxos, tip_height))
+}
+
+/// Broadcasts a signed transaction to the bitcoin network.
+///
+/// NOTE: Relies on the `bitcoin_send_transaction` endpoint, which checks the transaction before
+/// forwarding it to the bitcoin peers.
+/// See [IC Interface](https://internetcomputer.org/docs/current/references/ic-interface-spec/#ic-bitcoin_send_transaction)
+pub async fn send_transaction(network: BitcoinNetwork, transaction: Vec<u8>) -> Result<(), String> {
+    bitcoin_send_transaction(SendTransactionRequest {
+        transaction,
+        network,
+    })
+    .await
+    .map_err(|err| err.1)
 }
 
 /// Spawns a fee-cache update only if no previous update is still in flight.
@@ -111,6 +180,24 @@ fn spawn_fee_update_if_idle() {
     });
 }
 
+/// Indexes the outpoints reserved by the pending transactions stored before the outpoint
+/// reservation index existed.  Does nothing once the index has entries.
+pub fn init_outpoint_reservations() {
+    mutate_state(|state| {
+        if !state.btc_outpoint_reservations.is_empty() {
+            return;
+        }
+        BtcUserPendingTransactionsModel::new(
+            &mut state.btc_user_pending_transactions,
+            &mut state.btc_outpoint_reservations,
+            None,
+            None,
+            None,
+        )
+        .index_pending_transactions();
+    });
+}
+
 /// Sets up periodic refreshing of Bitcoin transaction fee data.
 /// Pre-populates the cache synchronously with defaults so callers never see an empty cache,
 /// then schedules async updates to replace them with live data.
@@ -136,9 +223,105 @@ pub fn init_fee_percentiles_cache() {
     });
 }
 
+/// Sets up the periodic polling of the addresses subscribed to deposit detection.  Every poll
+/// covers the next `DEPOSIT_POLL_BATCH_LEN` users, see `poll_deposits`.
+pub fn init_deposit_polling() {
+    set_timer_interval(DEPOSIT_POLL_INTERVAL, spawn_deposit_poll_if_idle);
+}
+
+/// Spawns a deposit poll only if no previous poll is still in flight.
+/// If a previous poll appears stuck (older than `DEPOSIT_POLL_TIMEOUT_NS`),
+/// the stale lock is cleared and a new poll is allowed to proceed.
+fn spawn_deposit_poll_if_idle() {
+    let now = ic_cdk::api::time();
+
+    let poll_in_progress = DEPOSIT_POLL_STARTED_AT.with(|cell| {
+        cell.borrow()
+            .is_some_and(|started| now.saturating_sub(started) <= DEPOSIT_POLL_TIMEOUT_NS)
+    });
+    if poll_in_progress {
+        return;
+    }
+
+    DEPOSIT_POLL_STARTED_AT.with(|cell| *cell.borrow_mut() = Some(now));
+
+    ic_cdk::spawn(async {
+        poll_deposits().await;
+        DEPOSIT_POLL_STARTED_AT.with(|cell| *cell.borrow_mut() = None);
+    });
+}
+
+/// Polls the subscribed addresses of the next `DEPOSIT_POLL_BATCH_LEN` users, and records their
+/// deposits.  Once the last user is polled, the next poll starts over from the first one.
+///
+/// Addresses are polled one at a time, for the same reason as the fee percentiles.
+async fn poll_deposits() {
+    let start_after = DEPOSIT_POLL_CURSOR.with(|cell| *cell.borrow());
+    let batch = read_state(|state| {
+        deposits::subscriptions_after(
+            &state.btc_deposit_subscriptions,
+            start_after,
+            DEPOSIT_POLL_BATCH_LEN,
+        )
+    });
+    let cursor = batch
+        .last()
+        .map(|(principal, _)| *principal)
+        .filter(|_| batch.len() == DEPOSIT_POLL_BATCH_LEN);
+    DEPOSIT_POLL_CURSOR.with(|cell| *cell.borrow_mut() = cursor);
+
+    for (principal, subscriptions) in batch {
+        for subscription in subscriptions {
+            let (utxos, tip_height) = match get_all_utxos_at_tip(
+                subscription.network,
+                subscription.address.clone(),
+                None,
+            )
+            .await
+            {
+                Ok(utxos_at_tip) => utxos_at_tip,
+                Err(err) => {
+                    ic_cdk::eprintln!(
+                        "Failed to poll deposits of user {principal} on network {:?}: {err}",
+                        subscription.network
+                    );
+                    continue;
+                }
+            };
+            let now_ns = ic_cdk::api::time();
+            mutate_state(|state| {
+                let own_txids: HashSet<Vec<u8>> = BtcUserPendingTransactionsModel::new(
+                    &mut state.btc_user_pending_transactions,
+                    &mut state.btc_outpoint_reservations,
+                    None,
+                    None,
+                    None,
+                )
+                .get_pending_transactions(&principal, &subscription.address)
+                .into_iter()
+                .map(|transaction| transaction.txid)
+                .chain(send_history::sent_txids(&state.btc_send_history, principal))
+                .collect();
+                deposits::record_deposits(
+                    &mut state.btc_deposit_subscriptions,
+                    &mut state.btc_deposit_events,
+                    principal,
+                    subscription.network,
+                    &subscription.address,
+                    &utxos,
+                    tip_height,
+                    &own_txids,
+                    now_ns,
+                );
+            });
+        }
+    }
+}
+
 /// Updates the Bitcoin transaction fee percentiles cache for all networks (Mainnet, Testnet,
 /// Regtest) sequentially. Fetches current fee data from the bitcoin canister and stores it
-/// in the thread-local cache for quick access by other functions.
+/// in the thread-local cache for quick access by other functions.  The median is recorded in the
+/// fee history as well.
 ///
 /// Networks are fetched one at a time to avoid concurrent inter-canister callbacks: if one
 /// call traps (e.g. Regtest on staging), sequential execution prevents it from corrupting
@@ -150,6 +333,17 @@ async fn update_fee_percentiles_cache() -> Result<(), String> {
     for network in networks {
         match fetch_current_fee_percentiles(network).await {
             Ok(percentiles) => {
+                if let Some(&median) = percentiles.get(percentiles.len() / 2) {
+                    let now_ns = ic_cdk::api::time();
+                    mutate_state(|state| {
+                        fee_history::record_fee_snapshot(
+                            &mut state.btc_fee_history,
+                            network,
+                            now_ns,
+                            median,
+                        )
+                    });
+                }
                 FEE_PERCENTILES_CACHE.with(|cache| {
                     cache.borrow_mut().insert(network, percentiles);
                 });
@@ -263,17 +457,108 @@ fn initialize_default_fee_percentiles(network: BitcoinNetwork) -> Vec<u64> {
     percentiles
 }
 
-/// Returns the 50th percentile for sending fees.
-pub fn get_fee_per_byte(network: BitcoinNetwork) -> u64 {
+/// Returns the fee rate, in millisatoshi per vbyte, of the given priority tier.
+///
+/// The rate is the configured percentile of the fee percentiles, clamped to the configured bounds.
+pub fn get_fee_per_byte(
+    network: BitcoinNetwork,
+    priority: BtcFeePriority,
+    tiers: &BtcFeeTiersConfig,
+) -> u64 {
     // Get fee percentiles from previous transactions to estimate our own fee.
     let fee_percentiles = get_current_fee_percentiles(network);
 
-    if fee_percentiles.is_empty() {
+    fee_per_byte_from_percentiles(&fee_percentiles, priority, tiers)
         // This case should rarely happen due to default values,
         // but keeping as a fallback
-        get_default_fee_for_network(network)
-    } else {
-        let middle = fee_percentiles.len() / 2;
-        fee_percentiles[middle]
+        .unwrap_or_else(|| clamp_fee_per_byte(get_default_fee_for_network(network), tiers))
+}
+
+/// Returns the given fee rate in millisatoshi per vbyte, clamped to the configured bounds.
+pub fn get_fee_rate_per_byte(
+    network: BitcoinNetwork,
+    fee_rate: BtcFeeRate,
+    tiers: &BtcFeeTiersConfig,
+) -> u64 {
+    match fee_rate {
+        BtcFeeRate::Priority(priority) => get_fee_per_byte(network, priority, tiers),
+        BtcFeeRate::SatoshiPerVbyte(rate) => clamp_fee_per_byte(rate.saturating_mul(1_000), tiers),
+    }
+}
+
+/// Picks the fee rate of the given priority tier from the fee percentiles, or `None` if there are
+/// no percentiles.
+fn fee_per_byte_from_percentiles(
+    fee_percentiles: &[MillisatoshiPerByte],
+    priority: BtcFeePriority,
+    tiers: &BtcFeeTiersConfig,
+) -> Option<u64> {
+    let percentile = match priority {
+        BtcFeePriority::Slow => tiers.slow_percentile,
+        BtcFeePriority::Normal => tiers.normal_percentile,
+        BtcFeePriority::Fast => tiers.fast_percentile,
+    };
+    // The management canister returns 100 percentiles, but we can't rely on that.
+    let index = (usize::from(percentile) * fee_percentiles.len() / 100)
+        .min(fee_percentiles.len().checked_sub(1)?);
+    Some(clamp_fee_per_byte(fee_percentiles[index], tiers))
+}
+
+/// Clamps the given fee rate, in millisatoshi per vbyte, to the configured bounds.
+fn clamp_fee_per_byte(fee_millisatoshi_per_vbyte: u64, tiers: &BtcFeeTiersConfig) -> u64 {
+    fee_millisatoshi_per_vbyte.clamp(
+        tiers.min_fee_millisatoshi_per_vbyte,
+        tiers.max_fee_millisatoshi_per_vbyte,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn fee_per_byte_from_percentiles_picks_configured_percentiles() {
+        let fee_percentiles: Vec<u64> = (1..=100).map(|i| i * 1_000).collect();
+        let tiers = BtcFeeTiersConfig::default();
+
+        let fee = |priority| fee_per_byte_from_percentiles(&fee_percentiles, priority, &tiers);
+
+        assert_eq!(fee(BtcFeePriority::Slow), Some(26_000));
+        assert_eq!(fee(BtcFeePriority::Normal), Some(51_000));
+        assert_eq!(fee(BtcFeePriority::Fast), Some(76_000));
+    }
+
+    #[test]
+    fn fee_per_byte_from_percentiles_clamps_absurd_fees() {
+        let tiers = BtcFeeTiersConfig::default();
+
+        assert_eq!(
+            fee_per_byte_from_percentiles(&[0; 100], BtcFeePriority::Slow, &tiers),
+            Some(tiers.min_fee_millisatoshi_per_vbyte)
+        );
+        assert_eq!(
+            fee_per_byte_from_percentiles(&[u64::MAX; 100], BtcFeePriority::Fast, &tiers),
+            Some(tiers.max_fee_millisatoshi_per_vbyte)
+        );
+    }
+
+    #[test]
+    fn fee_per_byte_from_percentiles_handles_short_vectors() {
+        let tiers = BtcFeeTiersConfig::default();
+
+        assert_eq!(
+            fee_per_byte_from_percentiles(&[], BtcFeePriority::Normal, &tiers),
+            None
+        );
+        assert_eq!(
+            fee_per_byte_from_percentiles(&[3_000], BtcFeePriority::Fast, &tiers),
+            Some(3_000)
+        );
+        assert_eq!(
+            fee_per_byte_from_percentiles(&[3_000, 5_000], BtcFeePriority::Normal, &tiers),
+            Some(5_000)
+        );
     }
 }
@@ -0,0 +1,563 @@
+//! Detection of the incoming bitcoin deposits of the users who opted in.
+//!
+//! A user subscribes their addresses, which are then polled in batches by a timer, see
+//! `api::init_deposit_polling`.  The UTXOs above the scanned height, i.e. the tip of the chain at
+//! the previous poll or at subscription, are new, and those that were not created by one of the
+//! user's own transactions are recorded in the deposit feed of the user.  The last
+//! `DEPOSIT_REORG_DEPTH` scanned blocks are scanned again, in case a reorganization of the chain
+//! mined a deposit there, and the deposits are told apart by their outpoint.  Every user has at
+//! most `MAX_DEPOSIT_EVENTS_LEN` deposits: recording a deposit into a full feed evicts the oldest
+//! one.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    ops::Bound,
+};
+
+use candid::Principal;
+use ic_cdk::api::management_canister::bitcoin::{BitcoinNetwork, Utxo};
+use shared::types::{
+    bitcoin::{
+        BtcAddressType, BtcDepositEvent, BtcDepositSubscription, BtcGetDepositEventsResponse,
+        DEPOSIT_REORG_DEPTH, MAX_DEPOSIT_EVENTS_LEN, MAX_DEPOSIT_EVENTS_PAGE_LEN,
+    },
+    Timestamp,
+};
+
+use crate::types::{BtcDepositEventMap, BtcDepositSubscriptionMap, Candid, StoredPrincipal};
+
+/// Returns the subscribed addresses of the user, in the order they were subscribed.
+pub fn get_subscriptions(
+    subscriptions: &BtcDepositSubscriptionMap,
+    principal: Principal,
+) -> Vec<BtcDepositSubscription> {
+    subscriptions
+        .get(&StoredPrincipal(principal))
+        .map(|subscriptions| subscriptions.0)
+        .unwrap_or_default()
+}
+
+/// Returns whether the address of the given network and type is among the subscribed addresses of
+/// a user.
+pub fn is_subscribed(
+    user_subscriptions: &[BtcDepositSubscription],
+    network: BitcoinNetwork,
+    address_type: BtcAddressType,
+) -> bool {
+    user_subscriptions.iter().any(|subscription| {
+        subscription.network == network && subscription.address_type == address_type
+    })
+}
+
+/// Subscribes the address of the user of the given network and type, and returns all the
+/// subscribed addresses of the user.
+///
+/// The `tip_height` is the height of the tip of the chain at subscription: the UTXOs above it are
+/// deposits.  Subscribing an address again does nothing.
+pub fn subscribe(
+    subscriptions: &mut BtcDepositSubscriptionMap,
+    principal: Principal,
+    network: BitcoinNetwork,
+    address_type: BtcAddressType,
+    address: String,
+    tip_height: u32,
+    now_ns: Timestamp,
+) -> Vec<BtcDepositSubscription> {
+    let mut user_subscriptions = get_subscriptions(subscriptions, principal);
+    if is_subscribed(&user_subscriptions, network, address_type) {
+        return user_subscriptions;
+    }
+
+    user_subscriptions.push(BtcDepositSubscription {
+        network,
+        address_type,
+        address,
+        subscribed_at_timestamp_ns: now_ns,
+        subscribed_at_height: Some(tip_height),
+        scanned_height: Some(tip_height),
+    });
+    subscriptions.insert(
+        StoredPrincipal(principal),
+        Candid(user_subscriptions.clone()),
+    );
+    user_subscriptions
+}
+
+/// Unsubscribes the address of the user of the given network and type, and returns all the
+/// remaining subscribed addresses of the user.  The deposits already recorded are kept.
+pub fn unsubscribe(
+    subscriptions: &mut BtcDepositSubscriptionMap,
+    principal: Principal,
+    network: BitcoinNetwork,
+    address_type: BtcAddressType,
+) -> Vec<BtcDepositSubscription> {
+    let mut user_subscriptions = get_subscriptions(subscriptions, principal);
+    let initial_len = user_subscriptions.len();
+    user_subscriptions.retain(|subscription| {
+        subscription.network != network || subscription.address_type != address_type
+    });
+    if user_subscriptions.len() == initial_len {
+        return user_subscriptions;
+    }
+
+    let key = StoredPrincipal(principal);
+    if user_subscriptions.is_empty() {
+        subscriptions.remove(&key);
+    } else {
+        subscriptions.insert(key, Candid(user_subscriptions.clone()));
+    }
+    user_subscriptions
+}
+
+/// Returns the subscribed addresses of at most `limit` users, starting after `start_after`.
+pub fn subscriptions_after(
+    subscriptions: &BtcDepositSubscriptionMap,
+    start_after: Option<Principal>,
+    limit: usize,
+) -> Vec<(Principal, Vec<BtcDepositSubscription>)> {
+    let start = start_after.map_or(Bound::Unbounded, |principal| {
+        Bound::Excluded(StoredPrincipal(principal))
+    });
+    subscriptions
+        .range((start, Bound::Unbounded))
+        .take(limit)
+        .map(|entry| (entry.key().0, entry.value().0))
+        .collect()
+}
+
+/// Records the deposits to a subscribed address of the user, given its UTXOs at the tip of the
+/// chain, and returns how many were recorded.
+///
+/// The UTXOs above the scanned height, less `DEPOSIT_REORG_DEPTH` blocks but not below the height
+/// at subscription, are deposits unless they were already recorded or were created by one of the
+/// `own_txids` of the user, e.g. as change.  An address without a scanned height only gets it set:
+/// the UTXOs that the address already had are not deposits.  Does nothing if the user
+/// unsubscribed the address in the meantime.
+#[expect(clippy::too_many_arguments)]
+pub fn record_deposits(
+    subscriptions: &mut BtcDepositSubscriptionMap,
+    events: &mut BtcDepositEventMap,
+    principal: Principal,
+    network: BitcoinNetwork,
+    address: &str,
+    utxos: &[Utxo],
+    tip_height: u32,
+    own_txids: &HashSet<Vec<u8>>,
+    now_ns: Timestamp,
+) -> usize {
+    let key = StoredPrincipal(principal);
+    let mut user_subscriptions = get_subscriptions(subscriptions, principal);
+    let Some(subscription) = user_subscriptions
+        .iter_mut()
+        .find(|subscription| subscription.network == network && subscription.address == address)
+    else {
+        return 0;
+    };
+    let scanned_height = subscription.scanned_height.replace(tip_height);
+    // The UTXOs below the scanned height of an address subscribed without a height were all seen.
+    let subscribed_at_height = *subscription
+        .subscribed_at_height
+        .get_or_insert(scanned_height.unwrap_or(tip_height));
+    subscriptions.insert(key, Candid(user_subscriptions));
+    let Some(scanned_height) = scanned_height else {
+        return 0;
+    };
+
+    let from_height = scanned_height
+        .saturating_sub(DEPOSIT_REORG_DEPTH)
+        .max(subscribed_at_height);
+    let candidates: Vec<&Utxo> = utxos
+        .iter()
+        .filter(|utxo| {
+            utxo.height > from_height
+                && utxo.height <= tip_height
+                && !own_txids.contains(&utxo.outpoint.txid)
+        })
+        .collect();
+    if candidates.is_empty() {
+        return 0;
+    }
+
+    let mut feed = DepositFeed::read(events, key);
+    let mut recorded_count = 0;
+    for utxo in candidates {
+        if feed
+            .recorded
+            .contains(&(utxo.outpoint.txid.clone(), utxo.outpoint.vout))
+        {
+            continue;
+        }
+        feed.record(
+            events,
+            BtcDepositEvent {
+                id: 0,
+                network,
+                address: address.to_string(),
+                txid: utxo.outpoint.txid.clone(),
+                vout: utxo.outpoint.vout,
+                amount_satoshis: utxo.value,
+                height: utxo.height,
+                confirmations: tip_height - utxo.height + 1,
+                detected_at_timestamp_ns: now_ns,
+            },
+        );
+        recorded_count += 1;
+    }
+    recorded_count
+}
+
+/// The deposit feed of a user, read once per poll to record its new deposits.
+struct DepositFeed {
+    key: StoredPrincipal,
+    /// The ids of the deposits in the feed, oldest first.
+    ids: VecDeque<u64>,
+    /// The outpoints of the deposits in the feed, as `(txid, vout)`.
+    recorded: HashSet<(Vec<u8>, u32)>,
+}
+
+impl DepositFeed {
+    fn read(events: &BtcDepositEventMap, key: StoredPrincipal) -> Self {
+        let mut ids = VecDeque::new();
+        let mut recorded = HashSet::new();
+        for entry in events.range((key, 0)..=(key, u64::MAX)) {
+            let event = entry.value().0;
+            ids.push_back(event.id);
+            recorded.insert((event.txid, event.vout));
+        }
+        DepositFeed { key, ids, recorded }
+    }
+
+    /// Records a deposit with the next `id` of the feed, evicting the oldest deposit of a full
+    /// feed.
+    fn record(&mut self, events: &mut BtcDepositEventMap, mut event: BtcDepositEvent) {
+        if self.ids.len() >= MAX_DEPOSIT_EVENTS_LEN {
+            if let Some(oldest) = self.ids.pop_front() {
+                events.remove(&(self.key, oldest));
+            }
+        }
+        event.id = self.ids.back().map_or(0, |latest| latest + 1);
+        self.ids.push_back(event.id);
+        self.recorded.insert((event.txid.clone(), event.vout));
+        events.insert((self.key, event.id), Candid(event));
+    }
+}
+
+/// Returns a page of the deposits of the user, newest first.
+///
+/// The page holds at most `limit` deposits of the given network, older than `before_id`.
+pub fn get_deposit_events(
+    events: &BtcDepositEventMap,
+    principal: Principal,
+    network: Option<BitcoinNetwork>,
+    before_id: Option<u64>,
+    limit: Option<u32>,
+) -> BtcGetDepositEventsResponse {
+    let key = StoredPrincipal(principal);
+    let limit = limit.unwrap_or(MAX_DEPOSIT_EVENTS_PAGE_LEN) as usize;
+    let end = match before_id {
+        Some(0) => {
+            return BtcGetDepositEventsResponse {
+                events: vec![],
+                next_before_id: None,
+            }
+        }
+        Some(before_id) => before_id - 1,
+        None => u64::MAX,
+    };
+
+    let mut matching = events
+        .range((key, 0)..=(key, end))
+        .rev()
+        .map(|entry| entry.value().0)
+        .filter(|event| network.is_none_or(|network| event.network == network));
+    let events: Vec<BtcDepositEvent> = matching.by_ref().take(limit).collect();
+    let next_before_id = events
+        .last()
+        .map(|last| last.id)
+        .filter(|_| matching.next().is_some());
+
+    BtcGetDepositEventsResponse {
+        events,
+        next_before_id,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use ic_cdk::api::management_canister::bitcoin::Outpoint;
+    use ic_stable_structures::{
+        memory_manager::{MemoryId, MemoryManager},
+        DefaultMemoryImpl,
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    const ADDRESS: &str = "bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7c";
+
+    struct Setup {
+        subscriptions: BtcDepositSubscriptionMap,
+        events: BtcDepositEventMap,
+        _memory_manager: RefCell<MemoryManager<DefaultMemoryImpl>>,
+    }
+
+    fn setup() -> Setup {
+        let memory_manager = RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+        let subscriptions =
+            BtcDepositSubscriptionMap::init(memory_manager.borrow().get(MemoryId::new(0)));
+        let events = BtcDepositEventMap::init(memory_manager.borrow().get(MemoryId::new(1)));
+        Setup {
+            subscriptions,
+            events,
+            _memory_manager: memory_manager,
+        }
+    }
+
+    fn utxo(txid: u8, height: u32) -> Utxo {
+        Utxo {
+            outpoint: Outpoint {
+                txid: vec![txid; 32],
+                vout: 0,
+            },
+            value: 1_000 * u64::from(txid),
+            height,
+        }
+    }
+
+    fn subscribe_mainnet(setup: &mut Setup, principal: Principal) {
+        subscribe(
+            &mut setup.subscriptions,
+            principal,
+            BitcoinNetwork::Mainnet,
+            BtcAddressType::P2wpkh,
+            ADDRESS.to_string(),
+            100,
+            1,
+        );
+    }
+
+    fn poll(setup: &mut Setup, principal: Principal, utxos: &[Utxo], tip_height: u32) -> usize {
+        record_deposits(
+            &mut setup.subscriptions,
+            &mut setup.events,
+            principal,
+            BitcoinNetwork::Mainnet,
+            ADDRESS,
+            utxos,
+            tip_height,
+            &HashSet::from([vec![9; 32]]),
+            2,
+        )
+    }
+
+    fn txids(response: &BtcGetDepositEventsResponse) -> Vec<u8> {
+        response.events.iter().map(|event| event.txid[0]).collect()
+    }
+
+    #[test]
+    fn test_subscribe_and_unsubscribe() {
+        let mut setup = setup();
+        let principal = Principal::from_slice(&[1]);
+        subscribe_mainnet(&mut setup, principal);
+        subscribe_mainnet(&mut setup, principal);
+        let subscribed = subscribe(
+            &mut setup.subscriptions,
+            principal,
+            BitcoinNetwork::Testnet,
+            BtcAddressType::P2tr,
+            "tb1p".to_string(),
+            100,
+            2,
+        );
+
+        assert_eq!(subscribed.len(), 2);
+        assert_eq!(
+            unsubscribe(
+                &mut setup.subscriptions,
+                principal,
+                BitcoinNetwork::Mainnet,
+                BtcAddressType::P2tr,
+            ),
+            subscribed
+        );
+        assert_eq!(
+            unsubscribe(
+                &mut setup.subscriptions,
+                principal,
+                BitcoinNetwork::Mainnet,
+                BtcAddressType::P2wpkh,
+            ),
+            subscribed[1..].to_vec()
+        );
+        unsubscribe(
+            &mut setup.subscriptions,
+            principal,
+            BitcoinNetwork::Testnet,
+            BtcAddressType::P2tr,
+        );
+        assert!(setup.subscriptions.is_empty());
+    }
+
+    #[test]
+    fn test_subscriptions_after_are_batched() {
+        let mut setup = setup();
+        for i in 1..=5 {
+            subscribe_mainnet(&mut setup, Principal::from_slice(&[i]));
+        }
+
+        let batch = subscriptions_after(&setup.subscriptions, None, 2);
+        let principals: Vec<_> = batch.iter().map(|(principal, _)| *principal).collect();
+        assert_eq!(
+            principals,
+            vec![Principal::from_slice(&[1]), Principal::from_slice(&[2])]
+        );
+        let batch = subscriptions_after(&setup.subscriptions, Some(principals[1]), 10);
+        assert_eq!(batch.len(), 3);
+        assert_eq!(batch[0].0, Principal::from_slice(&[3]));
+    }
+
+    #[test]
+    fn test_record_deposits_records_new_utxos_only() {
+        let mut setup = setup();
+        let principal = Principal::from_slice(&[1]);
+        subscribe_mainnet(&mut setup, principal);
+
+        // The UTXO below the tip at subscription was already there, and the UTXO of the user's own
+        // transaction is not a deposit.
+        let utxos = [utxo(1, 100), utxo(2, 101), utxo(3, 102), utxo(9, 102)];
+        assert_eq!(poll(&mut setup, principal, &utxos, 103), 2);
+        // The UTXOs below the scanned height were seen by the previous poll.
+        assert_eq!(poll(&mut setup, principal, &utxos, 104), 0);
+
+        let page = get_deposit_events(&setup.events, principal, None, None, None);
+        assert_eq!(txids(&page), vec![3, 2]);
+        assert_eq!(
+            page.events[1],
+            BtcDepositEvent {
+                id: 0,
+                network: BitcoinNetwork::Mainnet,
+                address: ADDRESS.to_string(),
+                txid: vec![2; 32],
+                vout: 0,
+                amount_satoshis: 2_000,
+                height: 101,
+                confirmations: 3,
+                detected_at_timestamp_ns: 2,
+            }
+        );
+        assert_eq!(
+            get_subscriptions(&setup.subscriptions, principal)[0].scanned_height,
+            Some(104)
+        );
+    }
+
+    #[test]
+    fn test_record_deposits_without_scanned_height_only_sets_it() {
+        let mut setup = setup();
+        let principal = Principal::from_slice(&[1]);
+        subscribe_mainnet(&mut setup, principal);
+        let mut subscriptions = get_subscriptions(&setup.subscriptions, principal);
+        subscriptions[0].scanned_height = None;
+        setup
+            .subscriptions
+            .insert(StoredPrincipal(principal), Candid(subscriptions));
+
+        assert_eq!(poll(&mut setup, principal, &[utxo(1, 101)], 101), 0);
+        assert_eq!(
+            get_subscriptions(&setup.subscriptions, principal)[0].scanned_height,
+            Some(101)
+        );
+    }
+
+    #[test]
+    fn test_record_deposits_ignores_recorded_and_unsubscribed_addresses() {
+        let mut setup = setup();
+        let principal = Principal::from_slice(&[1]);
+        assert_eq!(poll(&mut setup, principal, &[utxo(1, 100)], 100), 0);
+
+        subscribe_mainnet(&mut setup, principal);
+        assert_eq!(poll(&mut setup, principal, &[utxo(1, 101)], 101), 1);
+        // After a reorganization, the deposit is included again at the same height.
+        poll(&mut setup, principal, &[], 100);
+        assert_eq!(poll(&mut setup, principal, &[utxo(1, 101)], 101), 0);
+        assert_eq!(setup.events.len(), 1);
+    }
+
+    #[test]
+    fn test_record_deposits_records_deposits_mined_again_below_the_scanned_height() {
+        let mut setup = setup();
+        let principal = Principal::from_slice(&[1]);
+        subscribe_mainnet(&mut setup, principal);
+        assert_eq!(poll(&mut setup, principal, &[utxo(1, 102)], 105), 1);
+
+        // A reorganization mined a deposit in a block that the previous poll scanned, whereas the
+        // UTXOs at subscription are still not deposits.
+        let utxos = [utxo(1, 102), utxo(2, 104), utxo(3, 100)];
+        assert_eq!(poll(&mut setup, principal, &utxos, 106), 1);
+        // Blocks deeper than `DEPOSIT_REORG_DEPTH` below the scanned height are not scanned again.
+        assert_eq!(poll(&mut setup, principal, &[], 120), 0);
+        assert_eq!(poll(&mut setup, principal, &[utxo(4, 110)], 121), 0);
+
+        let page = get_deposit_events(&setup.events, principal, None, None, None);
+        assert_eq!(txids(&page), vec![2, 1]);
+    }
+
+    #[test]
+    fn test_record_deposits_without_subscription_height_sets_it() {
+        let mut setup = setup();
+        let principal = Principal::from_slice(&[1]);
+        subscribe_mainnet(&mut setup, principal);
+        let mut subscriptions = get_subscriptions(&setup.subscriptions, principal);
+        subscriptions[0].subscribed_at_height = None;
+        setup
+            .subscriptions
+            .insert(StoredPrincipal(principal), Candid(subscriptions));
+
+        // The UTXOs below the scanned height were seen by the previous polls.
+        assert_eq!(poll(&mut setup, principal, &[utxo(1, 98)], 101), 0);
+        assert_eq!(
+            get_subscriptions(&setup.subscriptions, principal)[0].subscribed_at_height,
+            Some(100)
+        );
+    }
+
+    #[test]
+    fn test_deposit_events_are_paginated_and_bounded() {
+        let mut setup = setup();
+        let principal = Principal::from_slice(&[1]);
+        subscribe_mainnet(&mut setup, principal);
+        let tip_height = 100 + u32::try_from(MAX_DEPOSIT_EVENTS_LEN).unwrap() + 1;
+        let utxos: Vec<Utxo> = (101..=tip_height)
+            .map(|height| Utxo {
+                outpoint: Outpoint {
+                    txid: vec![1; 32],
+                    vout: height,
+                },
+                value: 1_000,
+                height,
+            })
+            .collect();
+        poll(&mut setup, principal, &utxos, tip_height);
+
+        assert_eq!(setup.events.len(), MAX_DEPOSIT_EVENTS_LEN as u64);
+        // The oldest deposit was evicted.
+        assert_eq!(
+            setup.events.first_key_value().map(|(key, _)| key.1),
+            Some(1)
+        );
+
+        let page = get_deposit_events(&setup.events, principal, None, None, Some(2));
+        assert_eq!(page.events.len(), 2);
+        assert_eq!(page.next_before_id, Some(MAX_DEPOSIT_EVENTS_LEN as u64 - 1));
+        let page = get_deposit_events(
+            &setup.events,
+            principal,
+            Some(BitcoinNetwork::Testnet),
+            None,
+            None,
+        );
+        assert_eq!(page.events, vec![]);
+    }
+}
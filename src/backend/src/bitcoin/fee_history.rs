@@ -0,0 +1,226 @@
+//! Bounded history of the fee rates of each bitcoin network.
+//!
+//! Every network has a ring buffer of at most `MAX_FEE_HISTORY_LEN` snapshots, taken at most once
+//! every `FEE_HISTORY_SNAPSHOT_INTERVAL_NS`: recording a snapshot into a full buffer evicts the
+//! oldest one.
+
+use ic_cdk::api::management_canister::bitcoin::{BitcoinNetwork, MillisatoshiPerByte};
+use shared::types::{
+    bitcoin::{
+        BtcFeeHistoryStats, BtcFeeSnapshot, FEE_HISTORY_SNAPSHOT_INTERVAL_NS, MAX_FEE_HISTORY_LEN,
+    },
+    Timestamp,
+};
+
+use crate::types::BtcFeeHistoryMap;
+
+/// Returns the key under which the snapshots of the given network are stored.
+///
+/// The keys must not change, as they are persisted in stable memory.
+fn network_key(network: BitcoinNetwork) -> u8 {
+    match network {
+        BitcoinNetwork::Mainnet => 0,
+        BitcoinNetwork::Testnet => 1,
+        BitcoinNetwork::Regtest => 2,
+    }
+}
+
+/// Returns the snapshots of the given network taken at or after `since_ns`, oldest first.
+pub fn get_fee_history(
+    fee_history: &BtcFeeHistoryMap,
+    network: BitcoinNetwork,
+    since_ns: Timestamp,
+) -> Vec<BtcFeeSnapshot> {
+    let key = network_key(network);
+    fee_history
+        .range((key, since_ns)..=(key, Timestamp::MAX))
+        .map(|entry| BtcFeeSnapshot {
+            timestamp_ns: entry.key().1,
+            fee_millisatoshi_per_vbyte: entry.value(),
+        })
+        .collect()
+}
+
+/// Records the fee rate of the given network at `now_ns`.
+///
+/// Does nothing if the previous snapshot of the network was taken less than
+/// `FEE_HISTORY_SNAPSHOT_INTERVAL_NS` ago.  Returns whether a snapshot was recorded.
+pub fn record_fee_snapshot(
+    fee_history: &mut BtcFeeHistoryMap,
+    network: BitcoinNetwork,
+    now_ns: Timestamp,
+    fee_millisatoshi_per_vbyte: MillisatoshiPerByte,
+) -> bool {
+    let key = network_key(network);
+    let mut snapshot_count = 0;
+    let mut oldest = None;
+    let mut latest = None;
+    for entry in fee_history.range((key, 0)..=(key, Timestamp::MAX)) {
+        let timestamp_ns = entry.key().1;
+        oldest.get_or_insert(timestamp_ns);
+        latest = Some(timestamp_ns);
+        snapshot_count += 1;
+    }
+
+    if latest.is_some_and(|latest| now_ns < latest.saturating_add(FEE_HISTORY_SNAPSHOT_INTERVAL_NS))
+    {
+        return false;
+    }
+
+    if snapshot_count >= MAX_FEE_HISTORY_LEN {
+        if let Some(oldest) = oldest {
+            fee_history.remove(&(key, oldest));
+        }
+    }
+    fee_history.insert((key, now_ns), fee_millisatoshi_per_vbyte);
+    true
+}
+
+/// Aggregates the fee rates of the given snapshots, or `None` if there are none.
+pub fn fee_history_stats(snapshots: &[BtcFeeSnapshot]) -> Option<BtcFeeHistoryStats> {
+    let mut fees: Vec<MillisatoshiPerByte> = snapshots
+        .iter()
+        .map(|snapshot| snapshot.fee_millisatoshi_per_vbyte)
+        .collect();
+    fees.sort_unstable();
+
+    Some(BtcFeeHistoryStats {
+        min_fee_millisatoshi_per_vbyte: *fees.first()?,
+        median_fee_millisatoshi_per_vbyte: fees[fees.len() / 2],
+        max_fee_millisatoshi_per_vbyte: *fees.last()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use ic_stable_structures::{
+        memory_manager::{MemoryId, MemoryManager},
+        DefaultMemoryImpl,
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn setup() -> (BtcFeeHistoryMap, RefCell<MemoryManager<DefaultMemoryImpl>>) {
+        let memory_manager = RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+        let map = BtcFeeHistoryMap::init(memory_manager.borrow().get(MemoryId::new(0)));
+        (map, memory_manager)
+    }
+
+    fn snapshot(timestamp_ns: Timestamp, fee_millisatoshi_per_vbyte: u64) -> BtcFeeSnapshot {
+        BtcFeeSnapshot {
+            timestamp_ns,
+            fee_millisatoshi_per_vbyte,
+        }
+    }
+
+    #[test]
+    fn test_record_fee_snapshot_per_network() {
+        let (mut map, _mm) = setup();
+
+        assert!(record_fee_snapshot(
+            &mut map,
+            BitcoinNetwork::Mainnet,
+            1,
+            10_000
+        ));
+        assert!(record_fee_snapshot(
+            &mut map,
+            BitcoinNetwork::Testnet,
+            2,
+            5_000
+        ));
+
+        assert_eq!(
+            get_fee_history(&map, BitcoinNetwork::Mainnet, 0),
+            vec![snapshot(1, 10_000)]
+        );
+        assert_eq!(
+            get_fee_history(&map, BitcoinNetwork::Testnet, 0),
+            vec![snapshot(2, 5_000)]
+        );
+        assert!(get_fee_history(&map, BitcoinNetwork::Regtest, 0).is_empty());
+    }
+
+    #[test]
+    fn test_record_fee_snapshot_respects_interval() {
+        let (mut map, _mm) = setup();
+        let network = BitcoinNetwork::Mainnet;
+
+        assert!(record_fee_snapshot(&mut map, network, 1, 10_000));
+        assert!(!record_fee_snapshot(
+            &mut map,
+            network,
+            FEE_HISTORY_SNAPSHOT_INTERVAL_NS,
+            20_000
+        ));
+        assert!(record_fee_snapshot(
+            &mut map,
+            network,
+            1 + FEE_HISTORY_SNAPSHOT_INTERVAL_NS,
+            30_000
+        ));
+
+        assert_eq!(
+            get_fee_history(&map, network, 0),
+            vec![
+                snapshot(1, 10_000),
+                snapshot(1 + FEE_HISTORY_SNAPSHOT_INTERVAL_NS, 30_000)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_record_fee_snapshot_evicts_oldest_when_full() {
+        let (mut map, _mm) = setup();
+        let network = BitcoinNetwork::Mainnet;
+
+        for i in 0..=MAX_FEE_HISTORY_LEN as u64 {
+            assert!(record_fee_snapshot(
+                &mut map,
+                network,
+                i * FEE_HISTORY_SNAPSHOT_INTERVAL_NS,
+                i
+            ));
+        }
+
+        let history = get_fee_history(&map, network, 0);
+        assert_eq!(history.len(), MAX_FEE_HISTORY_LEN);
+        assert_eq!(
+            history.first(),
+            Some(&snapshot(FEE_HISTORY_SNAPSHOT_INTERVAL_NS, 1))
+        );
+    }
+
+    #[test]
+    fn test_get_fee_history_filters_window() {
+        let (mut map, _mm) = setup();
+        let network = BitcoinNetwork::Mainnet;
+        for i in 0..3 {
+            record_fee_snapshot(&mut map, network, i * FEE_HISTORY_SNAPSHOT_INTERVAL_NS, i);
+        }
+
+        assert_eq!(
+            get_fee_history(&map, network, FEE_HISTORY_SNAPSHOT_INTERVAL_NS),
+            vec![
+                snapshot(FEE_HISTORY_SNAPSHOT_INTERVAL_NS, 1),
+                snapshot(2 * FEE_HISTORY_SNAPSHOT_INTERVAL_NS, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fee_history_stats() {
+        assert_eq!(fee_history_stats(&[]), None);
+        assert_eq!(
+            fee_history_stats(&[snapshot(1, 30), snapshot(2, 10), snapshot(3, 20)]),
+            Some(BtcFeeHistoryStats {
+                min_fee_millisatoshi_per_vbyte: 10,
+                median_fee_millisatoshi_per_vbyte: 20,
+                max_fee_millisatoshi_per_vbyte: 30,
+            })
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! UTXOs frozen by their owner.
+//!
+//! A user can freeze some of their UTXOs, e.g. to keep them apart from other coins for privacy, so
+//! that they are never selected to be spent.  Every user has at most `MAX_FROZEN_UTXOS` of them.
+//! Frozen outpoints are kept until they are unfrozen, even once spent.
+
+use candid::Principal;
+use ic_cdk::api::management_canister::bitcoin::{Outpoint, Utxo};
+use shared::types::bitcoin::{BtcUpdateFrozenUtxosError, MAX_FROZEN_UTXOS};
+
+use crate::types::{BtcFrozenUtxosMap, Candid, StoredPrincipal};
+
+/// Returns the outpoints frozen by the user, in the order they were frozen.
+pub fn get_frozen_utxos(frozen_utxos: &BtcFrozenUtxosMap, principal: Principal) -> Vec<Outpoint> {
+    frozen_utxos
+        .get(&StoredPrincipal(principal))
+        .map(|outpoints| outpoints.0)
+        .unwrap_or_default()
+}
+
+/// Freezes, then unfreezes, the given outpoints of the user, and returns all their frozen
+/// outpoints.
+///
+/// Freezing an outpoint that is already frozen, or unfreezing one that is not, does nothing.
+///
+/// # Errors
+/// - If the user would have more than `MAX_FROZEN_UTXOS` frozen outpoints.  Nothing is updated
+///   then.
+pub fn update_frozen_utxos(
+    frozen_utxos: &mut BtcFrozenUtxosMap,
+    principal: Principal,
+    freeze: Vec<Outpoint>,
+    unfreeze: &[Outpoint],
+) -> Result<Vec<Outpoint>, BtcUpdateFrozenUtxosError> {
+    let mut outpoints = get_frozen_utxos(frozen_utxos, principal);
+    for outpoint in freeze {
+        if !outpoints.contains(&outpoint) {
+            outpoints.push(outpoint);
+        }
+    }
+    outpoints.retain(|outpoint| !unfreeze.contains(outpoint));
+
+    if outpoints.len() > MAX_FROZEN_UTXOS {
+        return Err(BtcUpdateFrozenUtxosError::TooManyFrozenUtxos {
+            max: MAX_FROZEN_UTXOS as u64,
+        });
+    }
+
+    let key = StoredPrincipal(principal);
+    if outpoints.is_empty() {
+        frozen_utxos.remove(&key);
+    } else {
+        frozen_utxos.insert(key, Candid(outpoints.clone()));
+    }
+    Ok(outpoints)
+}
+
+/// Returns the UTXOs that are not frozen.
+pub fn without_frozen_utxos(utxos: Vec<Utxo>, frozen_outpoints: &[Outpoint]) -> Vec<Utxo> {
+    utxos
+        .into_iter()
+        .filter(|utxo| !frozen_outpoints.contains(&utxo.outpoint))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use ic_stable_structures::{
+        memory_manager::{MemoryId, MemoryManager},
+        DefaultMemoryImpl,
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn setup() -> (BtcFrozenUtxosMap, RefCell<MemoryManager<DefaultMemoryImpl>>) {
+        let memory_manager = RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+        let map = BtcFrozenUtxosMap::init(memory_manager.borrow().get(MemoryId::new(0)));
+        (map, memory_manager)
+    }
+
+    fn outpoint(vout: u32) -> Outpoint {
+        Outpoint {
+            txid: vec![0xAA; 32],
+            vout,
+        }
+    }
+
+    #[test]
+    fn test_update_frozen_utxos_freezes_then_unfreezes() {
+        let (mut map, _mm) = setup();
+        let principal = Principal::from_slice(&[1]);
+
+        assert_eq!(
+            update_frozen_utxos(
+                &mut map,
+                principal,
+                vec![outpoint(0), outpoint(1), outpoint(0)],
+                &[]
+            ),
+            Ok(vec![outpoint(0), outpoint(1)])
+        );
+        assert_eq!(
+            update_frozen_utxos(&mut map, principal, vec![outpoint(2)], &[outpoint(0)]),
+            Ok(vec![outpoint(1), outpoint(2)])
+        );
+        assert_eq!(
+            get_frozen_utxos(&map, principal),
+            vec![outpoint(1), outpoint(2)]
+        );
+        assert_eq!(get_frozen_utxos(&map, Principal::from_slice(&[2])), vec![]);
+    }
+
+    #[test]
+    fn test_update_frozen_utxos_removes_the_user_without_frozen_utxos() {
+        let (mut map, _mm) = setup();
+        let principal = Principal::from_slice(&[1]);
+
+        update_frozen_utxos(&mut map, principal, vec![outpoint(0)], &[]).unwrap();
+        assert_eq!(
+            update_frozen_utxos(&mut map, principal, vec![], &[outpoint(0)]),
+            Ok(vec![])
+        );
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_update_frozen_utxos_is_bounded() {
+        let (mut map, _mm) = setup();
+        let principal = Principal::from_slice(&[1]);
+        let outpoints: Vec<Outpoint> = (0..u32::try_from(MAX_FROZEN_UTXOS).unwrap())
+            .map(outpoint)
+            .collect();
+        update_frozen_utxos(&mut map, principal, outpoints.clone(), &[]).unwrap();
+
+        assert_eq!(
+            update_frozen_utxos(&mut map, principal, vec![outpoint(u32::MAX)], &[]),
+            Err(BtcUpdateFrozenUtxosError::TooManyFrozenUtxos {
+                max: MAX_FROZEN_UTXOS as u64
+            })
+        );
+        assert_eq!(get_frozen_utxos(&map, principal), outpoints);
+        // Room can be made in the same update.
+        assert!(update_frozen_utxos(
+            &mut map,
+            principal,
+            vec![outpoint(u32::MAX)],
+            &[outpoint(0)]
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_without_frozen_utxos() {
+        let utxo = |vout| Utxo {
+            outpoint: outpoint(vout),
+            value: 1_000,
+            height: 100,
+        };
+
+        assert_eq!(
+            without_frozen_utxos(vec![utxo(0), utxo(1), utxo(2)], &[outpoint(1), outpoint(3)]),
+            vec![utxo(0), utxo(2)]
+        );
+    }
+}
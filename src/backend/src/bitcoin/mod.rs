@@ -1,3 +1,10 @@
 pub(crate) mod api;
+pub(crate) mod deposits;
+pub(crate) mod fee_history;
+pub(crate) mod frozen_utxos;
 pub(crate) mod pending_tx_model;
+pub(crate) mod psbt;
+pub(crate) mod send_history;
+pub(crate) mod signed_tx;
 pub(crate) mod utils;
+pub(crate) mod utxo_cache;
@@ -1,10 +1,17 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, ops::Bound};
 
 use candid::Principal;
 use ic_cdk::api::management_canister::bitcoin::Utxo;
-use shared::types::bitcoin::StoredPendingTransaction;
+use shared::types::bitcoin::{
+    BtcPendingTransactionStatus, BtcPendingTransactionTtlConfig, BtcReplacePendingTransactionError,
+    StoredOutpointReservation, StoredPendingTransaction, MAX_REPLACEMENTS,
+    MAX_UNCONFIRMED_CHAIN_DEPTH, UNCONFIRMED_UTXO_HEIGHT,
+};
 
-use crate::types::{BtcUserPendingTransactionsMap, Candid, StoredPrincipal};
+use crate::types::{
+    BtcOutpointReservationMap, BtcUserPendingTransactionsMap, Candid, StoredOutpoint,
+    StoredPrincipal,
+};
 
 const MAX_PENDING_TRANSACTIONS: usize = 1000;
 
@@ -12,33 +19,47 @@ const MAX_ADDRESS_COUNT_PER_USER: usize = 20;
 
 const HOUR_IN_NS: u64 = 60 * 60 * 1_000_000_000;
 
+/// How long confirmed and expired transactions are kept before being pruned.
+const FINALIZED_TRANSACTION_RETENTION_NS: u64 = 24 * HOUR_IN_NS;
+
 // With this structure, if multiple users share the same address
 // they wouldn't share the pending transactions.
 // This is not possible with the current implementation of the addresses in CFS.
 // But something to have in mind for the future.
+// The reserved outpoints are indexed across principals, though, so that no UTXO can be reserved
+// twice.
 pub struct BtcUserPendingTransactionsModel<'a> {
     /// Map of `user_principal` to `PendingTransactionsMap`;
     pending_transactions_map: &'a mut BtcUserPendingTransactionsMap,
+    /// Map of `outpoint` to the pending transaction that reserves it.
+    outpoint_reservations: &'a mut BtcOutpointReservationMap,
     /// Maximum number of transactions that will be stored per `(principal, address)` tuple.
     max_pending_transactions: usize,
     /// Maximum number of addresses per user.
     max_addresses_per_user: usize,
+    /// How long pending transactions reserve their UTXOs, per network.
+    ttls: BtcPendingTransactionTtlConfig,
 }
 
 impl<'a> BtcUserPendingTransactionsModel<'a> {
     pub fn new(
         pending_transactions_map: &'a mut BtcUserPendingTransactionsMap,
+        outpoint_reservations: &'a mut BtcOutpointReservationMap,
         max_pending_txs: Option<usize>,
         max_addresses_per_user: Option<usize>,
+        ttls: Option<BtcPendingTransactionTtlConfig>,
     ) -> Self {
         Self {
             pending_transactions_map,
+            outpoint_reservations,
             max_pending_transactions: max_pending_txs.unwrap_or(MAX_PENDING_TRANSACTIONS),
             max_addresses_per_user: max_addresses_per_user.unwrap_or(MAX_ADDRESS_COUNT_PER_USER),
+            ttls: ttls.unwrap_or_default(),
         }
     }
 
-    /// Returns the pending transactions of a specific principal per address.
+    /// Returns the pending transactions of a specific principal per address, including the
+    /// recently confirmed and expired ones.
     pub fn get_pending_transactions(
         &self,
         principal: &Principal,
@@ -51,13 +72,48 @@ impl<'a> BtcUserPendingTransactionsModel<'a> {
             .unwrap_or_default()
     }
 
+    /// Returns the pending transaction with the given `txid` of a specific principal and address.
+    pub fn get_pending_transaction(
+        &self,
+        principal: &Principal,
+        address: &str,
+        txid: &[u8],
+    ) -> Option<StoredPendingTransaction> {
+        self.get_pending_transactions(principal, address)
+            .into_iter()
+            .find(|tx| tx.txid == txid)
+    }
+
+    /// Returns the unconfirmed change of the pending transactions of a specific principal and
+    /// address, which can be spent by follow-up transactions.
+    ///
+    /// The change of a transaction that already has `MAX_UNCONFIRMED_CHAIN_DEPTH` unconfirmed
+    /// ancestors, or that is older than its time to live, is not offered.  The change may be
+    /// reserved by a follow-up transaction already.
+    pub fn change_utxos(&self, principal: &Principal, address: &str, now_ns: u64) -> Vec<Utxo> {
+        let transactions = self.get_pending_transactions(principal, address);
+        transactions
+            .iter()
+            .filter(|tx| {
+                tx.is_pending()
+                    && tx.created_at_timestamp_ns + self.ttls.ttl_ns(tx.network) >= now_ns
+                    && chain_depth(&transactions, tx, 0) < MAX_UNCONFIRMED_CHAIN_DEPTH
+            })
+            .filter_map(StoredPendingTransaction::change_utxo)
+            .collect()
+    }
+
     /// Adds a pending transaction for a specific principal and address.
-    /// It has a limit of storable transactions set on init.
+    /// It has a limit of pending transactions set on init.  The confirmed and expired transactions
+    /// don't count towards it: when the list is full, the oldest of them is dropped to make room.
+    ///
+    /// The transaction is linked to the pending transactions of the same principal and address
+    /// whose change it spends.
     pub fn add_pending_transaction(
         &mut self,
         principal: Principal,
         address: String,
-        new_transaction: StoredPendingTransaction,
+        mut new_transaction: StoredPendingTransaction,
     ) -> Result<(), String> {
         let stored_principal = StoredPrincipal(principal);
         let mut address_map = self
@@ -67,36 +123,174 @@ impl<'a> BtcUserPendingTransactionsModel<'a> {
             .unwrap_or_default();
 
         if let Some(list) = address_map.get_mut(&address) {
-            if list.len() >= self.max_pending_transactions {
+            if list.iter().filter(|tx| tx.is_pending()).count() >= self.max_pending_transactions {
                 return Err("Maximum pending transactions reached".to_string());
             }
-            list.push(new_transaction);
-        } else {
-            if address_map.keys().len() >= self.max_addresses_per_user {
-                return Err("Maximum address per user reached".to_string());
+            if list.len() >= self.max_pending_transactions {
+                if let Some(index) = list.iter().position(|tx| !tx.is_pending()) {
+                    list.remove(index);
+                }
             }
-            let list: Vec<StoredPendingTransaction> = vec![new_transaction];
-            address_map.insert(address, list);
+        } else if address_map.keys().len() >= self.max_addresses_per_user {
+            return Err("Maximum address per user reached".to_string());
         }
 
+        new_transaction.parent_txids = parent_txids(
+            address_map.get(&address).unwrap_or(&vec![]),
+            &new_transaction,
+        );
+        if new_transaction.is_pending() {
+            self.reserve_outpoints(principal, &address, &new_transaction);
+        }
+        address_map
+            .entry(address)
+            .or_default()
+            .push(new_transaction);
+
         self.pending_transactions_map
             .insert(stored_principal, Candid(address_map));
         Ok(())
     }
 
-    /// Prunes pending transactions for a specific principal.
-    /// A pending transaction can be pruned for two reasons:
-    /// - Transaction is older than 1 hour. We consider that if a pending transaction is older than
-    ///   one hour it means it failed, and we can free to utxos to be used again.
-    /// - None of the transaction's utxos are present in the current utxos list. We use the pending
-    ///   transactions to avoid double spending. Once we know that an utxos is not available, we can
-    ///   remove the pending transaction. Normally, all utxos of a pending transaction should be
-    ///   present or not. Partial presence could happen if the utxos of a pending transaction were
-    ///   not really used in the transaction. We don't remove in partial presence because, in the
-    ///   end, partial presence will be temporary for one hour.
+    /// Replaces the pending transaction `replaced_txid` of a specific principal and address by
+    /// `new_transaction`, which keeps the replacement chain: the txids of all the transactions it
+    /// supersedes, oldest first.
+    ///
+    /// Only transactions with the `Pending` status can be replaced.  The replacement must spend at
+    /// least one UTXO of the replaced transaction, and none of the UTXOs reserved by the other
+    /// pending transactions of the principal.  Its fee is checked by
+    /// the caller.  A transaction whose change is spent by other pending transactions can't be
+    /// replaced, as the replacement would invalidate them.
+    pub fn replace_pending_transaction(
+        &mut self,
+        principal: Principal,
+        address: &str,
+        replaced_txid: &[u8],
+        mut new_transaction: StoredPendingTransaction,
+    ) -> Result<(), BtcReplacePendingTransactionError> {
+        let stored_principal = StoredPrincipal(principal);
+        let mut address_map = self
+            .pending_transactions_map
+            .get(&stored_principal)
+            .map(|c| c.0)
+            .unwrap_or_default();
+
+        let Some(index) = address_map.get(address).and_then(|txs| {
+            txs.iter()
+                .position(|tx| tx.txid == replaced_txid && tx.is_pending())
+        }) else {
+            return Err(BtcReplacePendingTransactionError::ReplacedTransactionNotFound);
+        };
+        let has_pending_children = address_map.get(address).is_some_and(|txs| {
+            txs.iter().any(|tx| {
+                tx.is_pending() && tx.parent_txids().iter().any(|txid| txid == replaced_txid)
+            })
+        });
+        if has_pending_children {
+            return Err(BtcReplacePendingTransactionError::ChangeSpentByPendingTransactions);
+        }
+
+        let reserved_by_others = new_transaction.utxos.iter().any(|utxo| {
+            self.live_reservation(utxo, new_transaction.created_at_timestamp_ns)
+                .is_some_and(|reservation| {
+                    !(reservation.principal == principal
+                        && reservation.address == address
+                        && reservation.txid == replaced_txid)
+                })
+        });
+        if reserved_by_others {
+            return Err(BtcReplacePendingTransactionError::UtxosAlreadyReserved);
+        }
+
+        let new_keys: HashSet<(&[u8], u32)> = new_transaction
+            .utxos
+            .iter()
+            .map(|u| (u.outpoint.txid.as_slice(), u.outpoint.vout))
+            .collect();
+
+        let transactions = address_map
+            .get_mut(address)
+            .expect("bug: the replaced transaction was found at this address");
+        let replaced = &transactions[index];
+        if !replaced
+            .utxos
+            .iter()
+            .any(|u| new_keys.contains(&(u.outpoint.txid.as_slice(), u.outpoint.vout)))
+        {
+            return Err(BtcReplacePendingTransactionError::NoReplacedUtxos);
+        }
+
+        let mut replaced_txids = replaced.replaced_txids.clone().unwrap_or_default();
+        if replaced_txids.len() >= MAX_REPLACEMENTS {
+            return Err(BtcReplacePendingTransactionError::MaxReplacementsReached);
+        }
+        replaced_txids.push(replaced.txid.clone());
+        new_transaction.replaced_txids = Some(replaced_txids);
+        new_transaction.parent_txids = parent_txids(transactions, &new_transaction);
+        self.release_outpoints(principal, address, &transactions[index]);
+        self.reserve_outpoints(principal, address, &new_transaction);
+        transactions[index] = new_transaction;
+
+        self.pending_transactions_map
+            .insert(stored_principal, Candid(address_map));
+        Ok(())
+    }
+
+    /// Removes the pending transaction `txid` of a specific principal and address, and releases
+    /// the UTXOs that it reserved.
+    ///
+    /// Used to undo the addition of a transaction that could not be broadcast.  Returns the removed
+    /// transaction, if any.
+    pub fn remove_pending_transaction(
+        &mut self,
+        principal: Principal,
+        address: &str,
+        txid: &[u8],
+    ) -> Option<StoredPendingTransaction> {
+        let stored_principal = StoredPrincipal(principal);
+        let mut address_map = self.pending_transactions_map.get(&stored_principal)?.0;
+        let transactions = address_map.get_mut(address)?;
+        let index = transactions.iter().position(|tx| tx.txid == txid)?;
+        let removed = transactions.remove(index);
+        self.release_outpoints(principal, address, &removed);
+
+        if transactions.is_empty() {
+            address_map.remove(address);
+        }
+        if address_map.is_empty() {
+            self.pending_transactions_map.remove(&stored_principal);
+        } else {
+            self.pending_transactions_map
+                .insert(stored_principal, Candid(address_map));
+        }
+        Some(removed)
+    }
+
+    /// Updates the status of the pending transactions of a specific principal and address, given
+    /// the current utxos of the address.
+    ///
+    /// A pending transaction is finalized for two reasons:
+    /// - None of the transaction's utxos are present in the current utxos list: it is `Confirmed`.
+    ///   Normally, all utxos of a pending transaction should be present or not. Partial presence
+    ///   could happen if the utxos of a pending transaction were not really used in the
+    ///   transaction. We don't finalize in partial presence because, in the end, partial presence
+    ///   will be temporary for the time to live of the transaction.
+    /// - Transaction is older than the time to live of its network, one hour by default: it is
+    ///   `Expired`. We consider that if a pending transaction is that old it means it failed, and
+    ///   we can free to utxos to be used again.
+    ///
+    /// The unconfirmed change of a parent transaction counts as present while the parent is
+    /// pending, and as any other utxo afterwards.  A transaction whose parent expired has failed as
+    /// well, and is `Expired`.  As the current utxos may only count from a few confirmations, a
+    /// transaction that spends nothing but change may be reported as confirmed along with its
+    /// parent.
+    ///
+    /// Finalized transactions don't reserve their utxos any more. They are kept for a day, so that
+    /// users can tell what happened to them, and then pruned.
     pub fn prune_pending_transactions(
         &mut self,
         principal: Principal,
+        address: &str,
         current_utxos: &[Utxo],
         now_ns: u64,
     ) {
@@ -108,34 +302,20 @@ impl<'a> BtcUserPendingTransactionsModel<'a> {
         else {
             return;
         };
+        let Some(transactions) = address_map.get_mut(address) else {
+            return;
+        };
 
-        let mut changed = false;
-        let mut addresses_to_remove = Vec::new();
-        for (address, transactions) in &mut address_map {
-            let initial_len = transactions.len();
-            transactions.retain(|pending_transaction| {
-                let is_old = pending_transaction.created_at_timestamp_ns + HOUR_IN_NS < now_ns;
-
-                let none_of_tx_utxos_are_still_present = pending_transaction
-                    .utxos
-                    .iter()
-                    .all(|utxo| !current_utxos.contains(utxo));
-
-                !is_old && !none_of_tx_utxos_are_still_present
-            });
-
-            if transactions.len() != initial_len {
-                changed = true;
-            }
-
-            if transactions.is_empty() {
-                addresses_to_remove.push(address.clone());
-            }
-        }
+        let (changed, _) = self.finalize_transactions(
+            principal,
+            address,
+            transactions,
+            Some(current_utxos),
+            now_ns,
+        );
 
-        for address in &addresses_to_remove {
-            address_map.remove(address.as_str());
-            changed = true;
+        if transactions.is_empty() {
+            address_map.remove(address);
         }
 
         if changed {
@@ -148,48 +328,291 @@ impl<'a> BtcUserPendingTransactionsModel<'a> {
         }
     }
 
-    /// Checks whether the provided UTXOs intersect with any existing pending
-    /// transactions for a specific principal.
+    /// Expires the stale pending transactions of all principals, and removes the finalized
+    /// transactions that are past their retention, as `prune_pending_transactions` does.  As the
+    /// current UTXOs are not known, no transaction is confirmed.
     ///
-    /// This method is used to prevent double reservation of the same UTXOs.
-    /// A conflict occurs if at least one UTXO in `new_utxos` is already present
-    /// in the UTXO set of any stored pending transaction for the given principal.
+    /// The principals are swept in order, starting after `start_after`.  `has_budget` is called
+    /// after every principal, and the sweep stops when it returns `false`.
+    ///
+    /// Returns the number of removed transactions, and the principal to resume the sweep after,
+    /// or `None` if the sweep is complete.
+    pub fn sweep_stale_transactions(
+        &mut self,
+        start_after: Option<Principal>,
+        now_ns: u64,
+        mut has_budget: impl FnMut() -> bool,
+    ) -> (usize, Option<Principal>) {
+        let mut removed = 0;
+        let mut cursor = start_after.map(StoredPrincipal);
+        loop {
+            let lower_bound = cursor.map_or(Bound::Unbounded, Bound::Excluded);
+            let Some((stored_principal, mut address_map)) = self
+                .pending_transactions_map
+                .range((lower_bound, Bound::Unbounded))
+                .next()
+                .map(|entry| (*entry.key(), entry.value().0))
+            else {
+                return (removed, None);
+            };
+
+            let mut changed = false;
+            for (address, transactions) in &mut address_map {
+                let (address_changed, address_removed) = self.finalize_transactions(
+                    stored_principal.0,
+                    address,
+                    transactions,
+                    None,
+                    now_ns,
+                );
+                changed |= address_changed;
+                removed += address_removed;
+            }
+            if changed {
+                address_map.retain(|_, transactions| !transactions.is_empty());
+                if address_map.is_empty() {
+                    self.pending_transactions_map.remove(&stored_principal);
+                } else {
+                    self.pending_transactions_map
+                        .insert(stored_principal, Candid(address_map));
+                }
+            }
+
+            cursor = Some(stored_principal);
+            if !has_budget() {
+                return (removed, Some(stored_principal.0));
+            }
+        }
+    }
+
+    /// Finalizes the pending transactions that are confirmed, if `current_utxos` are given, or
+    /// expired, and removes the finalized transactions that are past their retention.
+    ///
+    /// Returns whether any transaction changed, and the number of removed transactions.
+    fn finalize_transactions(
+        &mut self,
+        principal: Principal,
+        address: &str,
+        transactions: &mut Vec<StoredPendingTransaction>,
+        current_utxos: Option<&[Utxo]>,
+        now_ns: u64,
+    ) -> (bool, usize) {
+        let mut changed = false;
+        // Parents are added before their children, so that they are finalized first.
+        for index in 0..transactions.len() {
+            let transaction = &transactions[index];
+            if !transaction.is_pending() {
+                continue;
+            }
+            let parent_status = |txid: &[u8]| {
+                transactions
+                    .iter()
+                    .find(|tx| tx.txid == txid)
+                    .map(StoredPendingTransaction::status)
+            };
+            let has_expired_parent = transaction
+                .parent_txids()
+                .iter()
+                .any(|txid| parent_status(txid) == Some(BtcPendingTransactionStatus::Expired));
+            let none_of_tx_utxos_are_still_present = current_utxos.is_some_and(|current_utxos| {
+                transaction.utxos.iter().all(|utxo| {
+                    if utxo.height == UNCONFIRMED_UTXO_HEIGHT
+                        && parent_status(&utxo.outpoint.txid)
+                            == Some(BtcPendingTransactionStatus::Pending)
+                    {
+                        return false;
+                    }
+                    !current_utxos.iter().any(|current| {
+                        current == utxo
+                            || (utxo.height == UNCONFIRMED_UTXO_HEIGHT
+                                && current.outpoint == utxo.outpoint)
+                    })
+                })
+            });
+            let is_old = transaction.created_at_timestamp_ns
+                + self.ttls.ttl_ns(transaction.network)
+                < now_ns;
+
+            let status = if has_expired_parent {
+                BtcPendingTransactionStatus::Expired
+            } else if none_of_tx_utxos_are_still_present {
+                BtcPendingTransactionStatus::Confirmed
+            } else if is_old {
+                BtcPendingTransactionStatus::Expired
+            } else {
+                continue;
+            };
+            transactions[index].status = Some(status);
+            transactions[index].finalized_at_timestamp_ns = Some(now_ns);
+            self.release_outpoints(principal, address, &transactions[index]);
+            changed = true;
+        }
+
+        let initial_len = transactions.len();
+        transactions.retain(|transaction| {
+            transaction
+                .finalized_at_timestamp_ns
+                .is_none_or(|finalized_at| {
+                    finalized_at + FINALIZED_TRANSACTION_RETENTION_NS >= now_ns
+                })
+        });
+        let removed = initial_len - transactions.len();
+        (changed || removed > 0, removed)
+    }
+
+    /// Checks whether any of the provided UTXOs is reserved by a pending transaction, of any
+    /// principal.  Confirmed and expired transactions are ignored.
     ///
-    /// The check is performed across all addresses associated with the principal.
-    /// If the principal has no pending transactions stored, the method returns `false`.
+    /// This method is used to prevent double reservation of the same UTXOs.  It looks up every
+    /// UTXO in the outpoint index instead of scanning the stored transactions.  Reservations of
+    /// transactions older than their time to live are ignored even if they have not been pruned
+    /// yet, as these transactions are expired.
     ///
     /// Returns:
-    /// - `true` if there is at least one overlapping UTXO.
-    /// - `false` if no overlap is found.
-    pub fn has_intersecting_pending_utxos(&self, principal: Principal, new_utxos: &[Utxo]) -> bool {
-        let new_keys: HashSet<(&[u8], u32)> = new_utxos
+    /// - `true` if there is at least one reserved UTXO.
+    /// - `false` if no UTXO is reserved.
+    pub fn has_intersecting_pending_utxos(&self, new_utxos: &[Utxo], now_ns: u64) -> bool {
+        new_utxos
             .iter()
-            .map(|u| (u.outpoint.txid.as_slice(), u.outpoint.vout))
+            .any(|utxo| self.live_reservation(utxo, now_ns).is_some())
+    }
+
+    /// Indexes the outpoints of all the stored pending transactions.
+    ///
+    /// Used to build the index for the transactions stored before it existed.  Returns the number
+    /// of indexed transactions.
+    pub fn index_pending_transactions(&mut self) -> usize {
+        let pending_transactions: Vec<_> = self
+            .pending_transactions_map
+            .iter()
+            .flat_map(|entry| {
+                let principal = entry.key().0;
+                entry.value().0.into_iter().flat_map(move |(address, txs)| {
+                    txs.into_iter()
+                        .filter(StoredPendingTransaction::is_pending)
+                        .map(move |tx| (principal, address.clone(), tx))
+                })
+            })
             .collect();
+        for (principal, address, transaction) in &pending_transactions {
+            self.reserve_outpoints(*principal, address, transaction);
+        }
+        pending_transactions.len()
+    }
 
-        let stored_principal = StoredPrincipal(principal);
-        let Some(address_map) = self.pending_transactions_map.get(&stored_principal) else {
-            return false;
-        };
+    /// Returns the reservation of the outpoint of the given UTXO, unless its transaction is
+    /// expired at `now_ns`.
+    fn live_reservation(&self, utxo: &Utxo, now_ns: u64) -> Option<StoredOutpointReservation> {
+        self.outpoint_reservations
+            .get(&StoredOutpoint(utxo.outpoint.clone()))
+            .map(|reservation| reservation.0)
+            .filter(|reservation| {
+                reservation.created_at_timestamp_ns + self.ttls.ttl_ns(reservation.network)
+                    >= now_ns
+            })
+    }
 
-        address_map
-            .0
-            .values()
-            .flat_map(|txs| txs.iter())
-            .flat_map(|tx| tx.utxos.iter())
-            .any(|u| new_keys.contains(&(u.outpoint.txid.as_slice(), u.outpoint.vout)))
+    /// Reserves the outpoints of the given transaction.
+    fn reserve_outpoints(
+        &mut self,
+        principal: Principal,
+        address: &str,
+        transaction: &StoredPendingTransaction,
+    ) {
+        for utxo in &transaction.utxos {
+            self.outpoint_reservations.insert(
+                StoredOutpoint(utxo.outpoint.clone()),
+                Candid(StoredOutpointReservation {
+                    principal,
+                    address: address.to_string(),
+                    txid: transaction.txid.clone(),
+                    created_at_timestamp_ns: transaction.created_at_timestamp_ns,
+                    network: transaction.network,
+                }),
+            );
+        }
+    }
+
+    /// Releases the outpoints reserved by the given transaction.  Outpoints reserved by another
+    /// transaction in the meantime are left untouched.
+    fn release_outpoints(
+        &mut self,
+        principal: Principal,
+        address: &str,
+        transaction: &StoredPendingTransaction,
+    ) {
+        for utxo in &transaction.utxos {
+            let key = StoredOutpoint(utxo.outpoint.clone());
+            let reserved_by_transaction =
+                self.outpoint_reservations
+                    .get(&key)
+                    .is_some_and(|reservation| {
+                        reservation.principal == principal
+                            && reservation.address == address
+                            && reservation.txid == transaction.txid
+                    });
+            if reserved_by_transaction {
+                self.outpoint_reservations.remove(&key);
+            }
+        }
     }
 }
 
+/// Returns the txids of the pending transactions whose change the given transaction spends, or
+/// `None` if it spends no change.
+fn parent_txids(
+    transactions: &[StoredPendingTransaction],
+    transaction: &StoredPendingTransaction,
+) -> Option<Vec<Vec<u8>>> {
+    let parent_txids: Vec<Vec<u8>> = transactions
+        .iter()
+        .filter(|tx| {
+            tx.is_pending()
+                && tx.change_utxo().is_some_and(|change| {
+                    transaction
+                        .utxos
+                        .iter()
+                        .any(|utxo| utxo.outpoint == change.outpoint)
+                })
+        })
+        .map(|tx| tx.txid.clone())
+        .collect();
+    (!parent_txids.is_empty()).then_some(parent_txids)
+}
+
+/// Returns the number of pending ancestors of the given transaction, along its longest chain of
+/// parents, counting from `depth`.  The count stops past `MAX_UNCONFIRMED_CHAIN_DEPTH`.
+fn chain_depth(
+    transactions: &[StoredPendingTransaction],
+    transaction: &StoredPendingTransaction,
+    depth: usize,
+) -> usize {
+    if depth > MAX_UNCONFIRMED_CHAIN_DEPTH {
+        return depth;
+    }
+    transaction
+        .parent_txids()
+        .iter()
+        .filter_map(|txid| {
+            transactions
+                .iter()
+                .find(|tx| tx.txid == *txid && tx.is_pending())
+        })
+        .map(|parent| chain_depth(transactions, parent, depth + 1))
+        .max()
+        .unwrap_or(depth)
+}
+
 #[cfg(test)]
 mod tests {
     use std::{cell::RefCell, collections::HashMap, sync::LazyLock};
 
-    use ic_cdk::api::management_canister::bitcoin::Outpoint;
+    use ic_cdk::api::management_canister::bitcoin::{BitcoinNetwork, Outpoint};
     use ic_stable_structures::{
         memory_manager::{MemoryId, MemoryManager},
         DefaultMemoryImpl,
     };
+    use shared::types::bitcoin::BtcChangeOutput;
 
     use super::*;
 
@@ -249,18 +672,22 @@ mod tests {
 
     fn setup() -> (
         BtcUserPendingTransactionsMap,
+        BtcOutpointReservationMap,
         RefCell<MemoryManager<DefaultMemoryImpl>>,
     ) {
         let memory_manager = RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
         let map =
             BtcUserPendingTransactionsMap::init(memory_manager.borrow().get(MemoryId::new(0)));
-        (map, memory_manager)
+        let reservations =
+            BtcOutpointReservationMap::init(memory_manager.borrow().get(MemoryId::new(1)));
+        (map, reservations, memory_manager)
     }
 
     #[test]
     fn test_get_pending_transactions_empty() {
-        let (mut map, _mm) = setup();
-        let model = BtcUserPendingTransactionsModel::new(&mut map, None, None);
+        let (mut map, mut reservations, _mm) = setup();
+        let model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
         let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
 
         let pending_txs = model.get_pending_transactions(&principal, ADDRESS_1);
@@ -269,13 +696,23 @@ mod tests {
 
     #[test]
     fn test_add_pending_transaction_per_address() {
-        let (mut map, _mm) = setup();
-        let mut model = BtcUserPendingTransactionsModel::new(&mut map, None, None);
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
         let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
         let tx = StoredPendingTransaction {
             txid: vec![],
             utxos: vec![(*UTXO_1).clone()],
             created_at_timestamp_ns: 1_000_000,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
 
         // Add the pending transaction
@@ -294,14 +731,24 @@ mod tests {
 
     #[test]
     fn test_add_pending_transaction_does_not_add_other_principal() {
-        let (mut map, _mm) = setup();
-        let mut model = BtcUserPendingTransactionsModel::new(&mut map, None, None);
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
         let principal1 = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
         let principal2 = Principal::from_text(PRINCIPAL_TEXT_2).unwrap();
         let tx = StoredPendingTransaction {
             txid: vec![],
             utxos: vec![(*UTXO_1).clone()],
             created_at_timestamp_ns: 1_000_000,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
 
         let result = model.add_pending_transaction(principal1, ADDRESS_1.to_string(), tx.clone());
@@ -314,29 +761,66 @@ mod tests {
     // Test for add_pending_transaction when max_pending_transactions is reached
     #[test]
     fn test_add_pending_transaction_max_limit() {
-        let (mut map, _mm) = setup();
-        let mut model = BtcUserPendingTransactionsModel::new(&mut map, Some(3), None);
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, Some(3), None, None);
         let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
 
         let tx1 = StoredPendingTransaction {
             txid: vec![1, 2, 3],
             utxos: vec![(*UTXO_1).clone()],
             created_at_timestamp_ns: 1_000_000,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
         let tx2 = StoredPendingTransaction {
             txid: vec![4, 5, 6],
             utxos: vec![(*UTXO_2).clone()],
             created_at_timestamp_ns: 2_000_000,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
         let tx3 = StoredPendingTransaction {
             txid: vec![7, 8, 9],
             utxos: vec![(*UTXO_3).clone()],
             created_at_timestamp_ns: 3_000_000,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
         let tx4 = StoredPendingTransaction {
             txid: vec![10, 11, 12],
             utxos: vec![(*UTXO_4).clone()],
             created_at_timestamp_ns: 4_000_000,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
 
         // Add 3 transactions (max_pending_transactions = 3)
@@ -356,32 +840,140 @@ mod tests {
         assert_eq!(result.unwrap_err(), "Maximum pending transactions reached");
     }
 
+    #[test]
+    fn test_add_pending_transaction_max_limit_ignores_finalized_transactions() {
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, Some(2), None, None);
+        let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
+        let transaction = |txid: u8, utxo: &Utxo, status| StoredPendingTransaction {
+            txid: vec![txid],
+            utxos: vec![utxo.clone()],
+            created_at_timestamp_ns: u64::from(txid),
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status,
+            finalized_at_timestamp_ns: status.map(|_| u64::from(txid)),
+            change_output: None,
+            parent_txids: None,
+        };
+
+        // Fill the list with finalized transactions.
+        model
+            .add_pending_transaction(
+                principal,
+                ADDRESS_1.to_string(),
+                transaction(1, &UTXO_1, Some(BtcPendingTransactionStatus::Confirmed)),
+            )
+            .unwrap();
+        model
+            .add_pending_transaction(
+                principal,
+                ADDRESS_1.to_string(),
+                transaction(2, &UTXO_2, Some(BtcPendingTransactionStatus::Expired)),
+            )
+            .unwrap();
+
+        // New payments are still accepted, and the oldest finalized transactions make room.
+        model
+            .add_pending_transaction(
+                principal,
+                ADDRESS_1.to_string(),
+                transaction(3, &UTXO_3, None),
+            )
+            .unwrap();
+        model
+            .add_pending_transaction(
+                principal,
+                ADDRESS_1.to_string(),
+                transaction(4, &UTXO_4, None),
+            )
+            .unwrap();
+        let txids: Vec<Vec<u8>> = model
+            .get_pending_transactions(&principal, ADDRESS_1)
+            .into_iter()
+            .map(|tx| tx.txid)
+            .collect();
+        assert_eq!(txids, vec![vec![3], vec![4]]);
+
+        // The limit still applies to the pending transactions.
+        let result = model.add_pending_transaction(
+            principal,
+            ADDRESS_1.to_string(),
+            transaction(5, &UTXO_5, None),
+        );
+        assert_eq!(
+            result,
+            Err("Maximum pending transactions reached".to_string())
+        );
+    }
+
     // Test for add_pending_transaction when max_addresses_per_user is reached
     #[test]
     fn test_add_pending_transaction_max_address_limit() {
-        let (mut map, _mm) = setup();
-        let mut model = BtcUserPendingTransactionsModel::new(&mut map, None, Some(3));
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, Some(3), None);
         let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
 
         let tx1 = StoredPendingTransaction {
             txid: vec![1, 2, 3],
             utxos: vec![(*UTXO_1).clone()],
             created_at_timestamp_ns: 1_000_000,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
         let tx2 = StoredPendingTransaction {
             txid: vec![4, 5, 6],
             utxos: vec![(*UTXO_2).clone()],
             created_at_timestamp_ns: 2_000_000,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
         let tx3 = StoredPendingTransaction {
             txid: vec![7, 8, 9],
             utxos: vec![(*UTXO_3).clone()],
             created_at_timestamp_ns: 3_000_000,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
         let tx4 = StoredPendingTransaction {
             txid: vec![10, 11, 12],
             utxos: vec![(*UTXO_4).clone()],
             created_at_timestamp_ns: 4_000_000,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
 
         // Add 3 transactions (max_addresses_per_user = 3)
@@ -403,8 +995,9 @@ mod tests {
 
     #[test]
     fn test_prune_old_pending_transactions() {
-        let (mut map, _mm) = setup();
-        let mut model = BtcUserPendingTransactionsModel::new(&mut map, None, None);
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
         let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
 
         let yesterday_ns = 1_000_000;
@@ -414,6 +1007,15 @@ mod tests {
             txid: vec![1, 2, 3],
             utxos: vec![(*UTXO_1).clone()],
             created_at_timestamp_ns: yesterday_ns,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
         model
             .add_pending_transaction(principal, ADDRESS_1.to_string(), old_transaction.clone())
@@ -423,6 +1025,15 @@ mod tests {
             txid: vec![4, 5, 6],
             utxos: vec![(*UTXO_2).clone()],
             created_at_timestamp_ns: now_ns,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
         model
             .add_pending_transaction(principal, ADDRESS_1.to_string(), valid_transaction.clone())
@@ -433,17 +1044,45 @@ mod tests {
 
         let all_utxos = &[(*UTXO_1).clone(), (*UTXO_2).clone()];
 
-        model.prune_pending_transactions(principal, all_utxos, now_ns + 1);
+        model.prune_pending_transactions(principal, ADDRESS_1, all_utxos, now_ns + 1);
+
+        let pending_txs = model.get_pending_transactions(&principal, ADDRESS_1);
+        assert_eq!(
+            pending_txs,
+            vec![
+                StoredPendingTransaction {
+                    status: Some(BtcPendingTransactionStatus::Expired),
+                    finalized_at_timestamp_ns: Some(now_ns + 1),
+                    ..old_transaction
+                },
+                valid_transaction.clone()
+            ]
+        );
+        // The UTXOs of the expired transaction are available again.
+        assert!(!model.has_intersecting_pending_utxos(&[(*UTXO_1).clone()], now_ns + 1));
+
+        // The expired transaction is pruned once it is old enough.
+        model.prune_pending_transactions(
+            principal,
+            ADDRESS_1,
+            all_utxos,
+            now_ns + 2 + FINALIZED_TRANSACTION_RETENTION_NS,
+        );
 
         let pending_txs = model.get_pending_transactions(&principal, ADDRESS_1);
         assert_eq!(pending_txs.len(), 1);
-        assert_eq!(pending_txs[0], valid_transaction);
+        assert_eq!(pending_txs[0].txid, valid_transaction.txid);
+        assert_eq!(
+            pending_txs[0].status,
+            Some(BtcPendingTransactionStatus::Expired)
+        );
     }
 
     #[test]
     fn test_prune_with_available_utxos() {
-        let (mut map, _mm) = setup();
-        let mut model = BtcUserPendingTransactionsModel::new(&mut map, None, None);
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
         let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
 
         let now_ns = 1_000_000_000_000;
@@ -452,11 +1091,29 @@ mod tests {
             txid: vec![1, 2, 3],
             utxos: vec![(*UTXO_1).clone()],
             created_at_timestamp_ns: now_ns,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
         let transaction_2 = StoredPendingTransaction {
             txid: vec![4, 5, 6],
             utxos: vec![(*UTXO_2).clone()],
             created_at_timestamp_ns: now_ns,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
 
         model
@@ -470,17 +1127,48 @@ mod tests {
         assert_eq!(pending_txs.len(), 2);
 
         let available_utxos = &[(*UTXO_1).clone()];
-        model.prune_pending_transactions(principal, available_utxos, now_ns);
+        model.prune_pending_transactions(principal, ADDRESS_1, available_utxos, now_ns);
 
         let pending_txs = model.get_pending_transactions(&principal, ADDRESS_1);
-        assert_eq!(pending_txs.len(), 1);
-        assert_eq!(pending_txs[0], transaction_1);
+        assert_eq!(
+            pending_txs,
+            vec![
+                transaction_1,
+                StoredPendingTransaction {
+                    status: Some(BtcPendingTransactionStatus::Confirmed),
+                    finalized_at_timestamp_ns: Some(now_ns),
+                    ..transaction_2
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_prune_only_updates_the_given_address() {
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
+        let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
+
+        let now_ns = 1_000_000_000_000;
+        let transaction = pending_transaction(vec![1], vec![(*UTXO_1).clone()], 1_000);
+
+        model
+            .add_pending_transaction(principal, ADDRESS_2.to_string(), transaction.clone())
+            .unwrap();
+
+        // The UTXOs of another address say nothing about this transaction.
+        model.prune_pending_transactions(principal, ADDRESS_1, &[], now_ns);
+
+        let pending_txs = model.get_pending_transactions(&principal, ADDRESS_2);
+        assert_eq!(pending_txs, vec![transaction]);
     }
 
     #[test]
     fn test_does_not_prune_with_partial_available_utxos() {
-        let (mut map, _mm) = setup();
-        let mut model = BtcUserPendingTransactionsModel::new(&mut map, None, None);
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
         let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
 
         let now_ns = 1_000_000_000_000;
@@ -489,11 +1177,29 @@ mod tests {
             txid: vec![1, 2, 3],
             utxos: vec![(*UTXO_1).clone()],
             created_at_timestamp_ns: now_ns,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
         let transaction_2 = StoredPendingTransaction {
             txid: vec![4, 5, 6],
             utxos: vec![(*UTXO_2).clone(), (*UTXO_3).clone()],
             created_at_timestamp_ns: now_ns,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
 
         model
@@ -507,27 +1213,47 @@ mod tests {
         assert_eq!(pending_txs.len(), 2);
 
         let available_utxos = &[(*UTXO_1).clone(), (*UTXO_3).clone()];
-        model.prune_pending_transactions(principal, available_utxos, now_ns);
+        model.prune_pending_transactions(principal, ADDRESS_1, available_utxos, now_ns);
 
         let pending_txs = model.get_pending_transactions(&principal, ADDRESS_1);
         assert_eq!(pending_txs.len(), 2);
+        assert!(pending_txs.iter().all(StoredPendingTransaction::is_pending));
     }
 
     #[test]
     fn test_has_intersecting_pending_utxos_true_across_addresses() {
-        let (mut map, _mm) = setup();
-        let mut model = BtcUserPendingTransactionsModel::new(&mut map, None, None);
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
         let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
 
         let existing_1 = StoredPendingTransaction {
             txid: vec![1],
             utxos: vec![(*UTXO_1).clone()],
             created_at_timestamp_ns: 1_000_000,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
         let existing_2 = StoredPendingTransaction {
             txid: vec![2],
             utxos: vec![(*UTXO_2).clone()],
             created_at_timestamp_ns: 1_000_000,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
 
         model
@@ -537,18 +1263,27 @@ mod tests {
             .add_pending_transaction(principal, ADDRESS_2.to_string(), existing_2)
             .unwrap();
 
-        assert!(model.has_intersecting_pending_utxos(principal, &[(*UTXO_2).clone()]));
+        assert!(model.has_intersecting_pending_utxos(&[(*UTXO_2).clone()], 1_000_000));
     }
 
     #[test]
     fn test_has_intersecting_pending_utxos_false_when_disjoint() {
-        let (mut map, _mm) = setup();
+        let (mut map, mut reservations, _mm) = setup();
         let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
 
         let existing = StoredPendingTransaction {
             txid: vec![1],
             utxos: vec![(*UTXO_1).clone()],
             created_at_timestamp_ns: 1_000_000,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
 
         map.insert(
@@ -556,44 +1291,325 @@ mod tests {
             Candid(HashMap::from([(ADDRESS_1.to_string(), vec![existing])])),
         );
 
-        let model = BtcUserPendingTransactionsModel::new(&mut map, None, None);
-        assert!(!model.has_intersecting_pending_utxos(principal, &[(*UTXO_5).clone()]));
+        let model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
+        assert!(!model.has_intersecting_pending_utxos(&[(*UTXO_5).clone()], 1_000_000));
     }
 
     #[test]
-    fn test_has_intersecting_pending_utxos_false_other_principal() {
-        let (mut map, _mm) = setup();
-        let mut model = BtcUserPendingTransactionsModel::new(&mut map, None, None);
+    fn test_has_intersecting_pending_utxos_true_other_principal() {
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
         let principal_1 = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
-        let principal_2 = Principal::from_text(PRINCIPAL_TEXT_2).unwrap();
 
         let existing = StoredPendingTransaction {
             txid: vec![1],
             utxos: vec![(*UTXO_1).clone()],
             created_at_timestamp_ns: 1_000_000,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
 
         model
             .add_pending_transaction(principal_1, ADDRESS_1.to_string(), existing)
             .unwrap();
 
-        assert!(!model.has_intersecting_pending_utxos(principal_2, &[(*UTXO_1).clone()]));
+        // The reservations are not scoped to a principal: nobody else can reserve the UTXO.
+        assert!(model.has_intersecting_pending_utxos(&[(*UTXO_1).clone()], 1_000_000));
     }
 
     #[test]
     fn test_has_intersecting_pending_utxos_false_for_self_duplicates_when_no_pending_exists() {
-        let (mut map, _mm) = setup();
-        let model = BtcUserPendingTransactionsModel::new(&mut map, None, None);
-        let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
+        let (mut map, mut reservations, _mm) = setup();
+        let model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
 
         assert!(!model
-            .has_intersecting_pending_utxos(principal, &[(*UTXO_1).clone(), (*UTXO_1).clone()]));
+            .has_intersecting_pending_utxos(&[(*UTXO_1).clone(), (*UTXO_1).clone()], 1_000_000));
+    }
+
+    #[test]
+    fn test_has_intersecting_pending_utxos_false_when_reservation_expired() {
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
+        let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
+
+        model
+            .add_pending_transaction(
+                principal,
+                ADDRESS_1.to_string(),
+                pending_transaction(vec![1], vec![(*UTXO_1).clone()], 1_000),
+            )
+            .unwrap();
+
+        // The transaction was not pruned, but it is expired.
+        assert!(model.has_intersecting_pending_utxos(&[(*UTXO_1).clone()], 1_000_000 + HOUR_IN_NS));
+        assert!(!model.has_intersecting_pending_utxos(&[(*UTXO_1).clone()], 1_000_001 + HOUR_IN_NS));
+    }
+
+    #[test]
+    fn test_prune_pending_transactions_releases_outpoint_reservations() {
+        let (mut map, mut reservations, _mm) = setup();
+        let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
+        {
+            let mut model =
+                BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
+            model
+                .add_pending_transaction(
+                    principal,
+                    ADDRESS_1.to_string(),
+                    pending_transaction(vec![1], vec![(*UTXO_1).clone()], 1_000),
+                )
+                .unwrap();
+            model
+                .add_pending_transaction(
+                    principal,
+                    ADDRESS_1.to_string(),
+                    pending_transaction(vec![2], vec![(*UTXO_2).clone()], 1_000),
+                )
+                .unwrap();
+
+            // The first transaction is confirmed: its UTXO is spent.
+            model.prune_pending_transactions(principal, ADDRESS_1, &[(*UTXO_2).clone()], 1_000_001);
+        }
+
+        assert_eq!(reservations.len(), 1);
+        assert!(reservations
+            .get(&StoredOutpoint(UTXO_2.outpoint.clone()))
+            .is_some_and(|reservation| reservation.txid == vec![2]));
+    }
+
+    #[test]
+    fn test_remove_pending_transaction_releases_outpoint_reservations() {
+        let (mut map, mut reservations, _mm) = setup();
+        let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
+        {
+            let mut model =
+                BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
+            model
+                .add_pending_transaction(
+                    principal,
+                    ADDRESS_1.to_string(),
+                    pending_transaction(vec![1], vec![(*UTXO_1).clone()], 1_000),
+                )
+                .unwrap();
+
+            assert!(model
+                .remove_pending_transaction(principal, ADDRESS_1, &[2])
+                .is_none());
+            let removed = model
+                .remove_pending_transaction(principal, ADDRESS_1, &[1])
+                .unwrap();
+            assert_eq!(removed.txid, vec![1]);
+            assert!(!model.has_intersecting_pending_utxos(&[(*UTXO_1).clone()], 1_000));
+        }
+
+        assert!(map.is_empty());
+        assert!(reservations.is_empty());
+    }
+
+    #[test]
+    fn test_prune_pending_transactions_uses_network_ttl() {
+        let (mut map, mut reservations, _mm) = setup();
+        let ttls = BtcPendingTransactionTtlConfig {
+            mainnet_ns: 2 * HOUR_IN_NS,
+            testnet_ns: HOUR_IN_NS / 2,
+            regtest_ns: HOUR_IN_NS,
+        };
+        let mut model = BtcUserPendingTransactionsModel::new(
+            &mut map,
+            &mut reservations,
+            None,
+            None,
+            Some(ttls),
+        );
+        let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
+
+        for (txid, network, utxo) in [
+            (1, BitcoinNetwork::Mainnet, (*UTXO_1).clone()),
+            (2, BitcoinNetwork::Testnet, (*UTXO_2).clone()),
+        ] {
+            model
+                .add_pending_transaction(
+                    principal,
+                    ADDRESS_1.to_string(),
+                    StoredPendingTransaction {
+                        network: Some(network),
+                        ..pending_transaction(vec![txid], vec![utxo], 1_000)
+                    },
+                )
+                .unwrap();
+        }
+
+        let all_utxos = &[(*UTXO_1).clone(), (*UTXO_2).clone()];
+        model.prune_pending_transactions(principal, ADDRESS_1, all_utxos, 1_000_001 + HOUR_IN_NS);
+
+        let statuses: Vec<_> = model
+            .get_pending_transactions(&principal, ADDRESS_1)
+            .into_iter()
+            .map(|tx| tx.status)
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![None, Some(BtcPendingTransactionStatus::Expired)]
+        );
+        assert!(model.has_intersecting_pending_utxos(&[(*UTXO_1).clone()], 1_000_001 + HOUR_IN_NS));
+        assert!(!model.has_intersecting_pending_utxos(&[(*UTXO_2).clone()], 1_000_001 + HOUR_IN_NS));
+    }
+
+    #[test]
+    fn test_sweep_stale_transactions() {
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
+        let principal_1 = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
+        let principal_2 = Principal::from_text(PRINCIPAL_TEXT_2).unwrap();
+
+        model
+            .add_pending_transaction(
+                principal_1,
+                ADDRESS_1.to_string(),
+                pending_transaction(vec![1], vec![(*UTXO_1).clone()], 1_000),
+            )
+            .unwrap();
+        model
+            .add_pending_transaction(
+                principal_2,
+                ADDRESS_2.to_string(),
+                StoredPendingTransaction {
+                    created_at_timestamp_ns: 1_000_000 + HOUR_IN_NS,
+                    ..pending_transaction(vec![2], vec![(*UTXO_2).clone()], 1_000)
+                },
+            )
+            .unwrap();
+
+        // The first transaction expires, but the UTXOs are unknown so none is confirmed.
+        let expired_at = 1_000_001 + HOUR_IN_NS;
+        assert_eq!(
+            model.sweep_stale_transactions(None, expired_at, || true),
+            (0, None)
+        );
+        let pending_txs = model.get_pending_transactions(&principal_1, ADDRESS_1);
+        assert_eq!(
+            pending_txs[0].status,
+            Some(BtcPendingTransactionStatus::Expired)
+        );
+        assert!(!model.has_intersecting_pending_utxos(&[(*UTXO_1).clone()], 1_000_000));
+        assert!(model.has_intersecting_pending_utxos(&[(*UTXO_2).clone()], expired_at));
+
+        // Each transaction is removed once expired for longer than the retention.
+        let first_removed_at = expired_at + FINALIZED_TRANSACTION_RETENTION_NS + 1;
+        assert_eq!(
+            model.sweep_stale_transactions(None, first_removed_at, || true),
+            (1, None)
+        );
+        let second_removed_at = first_removed_at + FINALIZED_TRANSACTION_RETENTION_NS + 1;
+        assert_eq!(
+            model.sweep_stale_transactions(None, second_removed_at, || true),
+            (1, None)
+        );
+        assert!(model
+            .get_pending_transactions(&principal_1, ADDRESS_1)
+            .is_empty());
+        assert!(model
+            .get_pending_transactions(&principal_2, ADDRESS_2)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_sweep_stale_transactions_resumes_when_out_of_budget() {
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
+        let mut principals = [
+            Principal::from_text(PRINCIPAL_TEXT_1).unwrap(),
+            Principal::from_text(PRINCIPAL_TEXT_2).unwrap(),
+        ];
+        principals.sort_by_key(|principal| StoredPrincipal(*principal));
+        for principal in principals {
+            model
+                .add_pending_transaction(
+                    principal,
+                    ADDRESS_1.to_string(),
+                    StoredPendingTransaction {
+                        status: Some(BtcPendingTransactionStatus::Confirmed),
+                        finalized_at_timestamp_ns: Some(1_000_000),
+                        ..pending_transaction(vec![1], vec![], 1_000)
+                    },
+                )
+                .unwrap();
+        }
+
+        let now_ns = 1_000_001 + FINALIZED_TRANSACTION_RETENTION_NS;
+        assert_eq!(
+            model.sweep_stale_transactions(None, now_ns, || false),
+            (1, Some(principals[0]))
+        );
+        assert_eq!(
+            model.sweep_stale_transactions(Some(principals[0]), now_ns, || false),
+            (1, Some(principals[1]))
+        );
+        assert_eq!(
+            model.sweep_stale_transactions(Some(principals[1]), now_ns, || false),
+            (0, None)
+        );
+    }
+
+    #[test]
+    fn test_index_pending_transactions() {
+        let (mut map, mut reservations, _mm) = setup();
+        let principal_1 = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
+        let principal_2 = Principal::from_text(PRINCIPAL_TEXT_2).unwrap();
+
+        // Transactions stored before the index existed.
+        map.insert(
+            StoredPrincipal(principal_1),
+            Candid(HashMap::from([
+                (
+                    ADDRESS_1.to_string(),
+                    vec![pending_transaction(vec![1], vec![(*UTXO_1).clone()], 1_000)],
+                ),
+                (
+                    ADDRESS_2.to_string(),
+                    vec![StoredPendingTransaction {
+                        status: Some(BtcPendingTransactionStatus::Confirmed),
+                        finalized_at_timestamp_ns: Some(1_000_000),
+                        ..pending_transaction(vec![2], vec![(*UTXO_2).clone()], 1_000)
+                    }],
+                ),
+            ])),
+        );
+        map.insert(
+            StoredPrincipal(principal_2),
+            Candid(HashMap::from([(
+                ADDRESS_3.to_string(),
+                vec![pending_transaction(vec![3], vec![(*UTXO_3).clone()], 1_000)],
+            )])),
+        );
+
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
+        assert_eq!(model.index_pending_transactions(), 2);
+
+        assert!(model.has_intersecting_pending_utxos(&[(*UTXO_1).clone()], 1_000_000));
+        assert!(!model.has_intersecting_pending_utxos(&[(*UTXO_2).clone()], 1_000_000));
+        assert!(model.has_intersecting_pending_utxos(&[(*UTXO_3).clone()], 1_000_000));
     }
 
     #[test]
     fn test_has_intersecting_pending_utxos_true_when_second_call_reuses_same_utxos() {
-        let (mut map, _mm) = setup();
-        let mut model = BtcUserPendingTransactionsModel::new(&mut map, None, None);
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
         let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
 
         // First call reserves UTXO_1 and UTXO_2
@@ -601,6 +1617,15 @@ mod tests {
             txid: vec![1],
             utxos: vec![(*UTXO_1).clone(), (*UTXO_2).clone()],
             created_at_timestamp_ns: 1_000_000,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
         model
             .add_pending_transaction(principal, ADDRESS_1.to_string(), first)
@@ -608,13 +1633,14 @@ mod tests {
 
         // Second call attempts to reuse the exact same set => must intersect
         assert!(model
-            .has_intersecting_pending_utxos(principal, &[(*UTXO_1).clone(), (*UTXO_2).clone()]));
+            .has_intersecting_pending_utxos(&[(*UTXO_1).clone(), (*UTXO_2).clone()], 1_000_000));
     }
 
     #[test]
     fn test_has_intersecting_pending_utxos_true_when_second_call_partially_overlaps() {
-        let (mut map, _mm) = setup();
-        let mut model = BtcUserPendingTransactionsModel::new(&mut map, None, None);
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
         let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
 
         // First call reserves UTXO_1 and UTXO_2
@@ -622,6 +1648,15 @@ mod tests {
             txid: vec![1],
             utxos: vec![(*UTXO_1).clone(), (*UTXO_2).clone()],
             created_at_timestamp_ns: 1_000_000,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
         model
             .add_pending_transaction(principal, ADDRESS_1.to_string(), first)
@@ -629,14 +1664,15 @@ mod tests {
 
         // Second call overlaps on UTXO_2 only => still must intersect
         assert!(model
-            .has_intersecting_pending_utxos(principal, &[(*UTXO_2).clone(), (*UTXO_5).clone()]));
+            .has_intersecting_pending_utxos(&[(*UTXO_2).clone(), (*UTXO_5).clone()], 1_000_000));
     }
 
     #[test]
     fn test_has_intersecting_pending_utxos_true_when_overlap_is_same_outpoint_even_if_fields_differ(
     ) {
-        let (mut map, _mm) = setup();
-        let mut model = BtcUserPendingTransactionsModel::new(&mut map, None, None);
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
         let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
 
         // UTXO_3 and UTXO_4 share the same outpoint (txid=[], vout=2) but differ in value/height.
@@ -645,18 +1681,28 @@ mod tests {
             txid: vec![1],
             utxos: vec![(*UTXO_3).clone()],
             created_at_timestamp_ns: 1_000_000,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
         model
             .add_pending_transaction(principal, ADDRESS_1.to_string(), first)
             .unwrap();
 
-        assert!(model.has_intersecting_pending_utxos(principal, &[(*UTXO_4).clone()]));
+        assert!(model.has_intersecting_pending_utxos(&[(*UTXO_4).clone()], 1_000_000));
     }
 
     #[test]
     fn test_has_intersecting_pending_utxos_false_when_vout_matches_but_txid_differs() {
-        let (mut map, _mm) = setup();
-        let mut model = BtcUserPendingTransactionsModel::new(&mut map, None, None);
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
         let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
 
         // Existing pending uses TXID_A, vout=7
@@ -671,6 +1717,15 @@ mod tests {
                 height: 1,
             }],
             created_at_timestamp_ns: 1_000_000,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
 
         model
@@ -687,7 +1742,375 @@ mod tests {
             height: 2,
         };
 
-        assert!(!model.has_intersecting_pending_utxos(principal, &[candidate]));
+        assert!(!model.has_intersecting_pending_utxos(&[candidate], 1_000_000));
+    }
+
+    fn pending_transaction(txid: Vec<u8>, utxos: Vec<Utxo>, fee: u64) -> StoredPendingTransaction {
+        StoredPendingTransaction {
+            txid,
+            utxos,
+            created_at_timestamp_ns: 1_000_000,
+            fee_satoshis: Some(fee),
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
+        }
+    }
+
+    #[test]
+    fn test_replace_pending_transaction_keeps_replacement_chain() {
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
+        let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
+
+        let original = pending_transaction(vec![1], vec![(*UTXO_1).clone()], 1_000);
+        let first_replacement = pending_transaction(vec![2], vec![(*UTXO_1).clone()], 2_000);
+        let second_replacement =
+            pending_transaction(vec![3], vec![(*UTXO_1).clone(), (*UTXO_2).clone()], 3_000);
+
+        model
+            .add_pending_transaction(principal, ADDRESS_1.to_string(), original)
+            .unwrap();
+        model
+            .replace_pending_transaction(principal, ADDRESS_1, &[1], first_replacement)
+            .unwrap();
+        model
+            .replace_pending_transaction(principal, ADDRESS_1, &[2], second_replacement.clone())
+            .unwrap();
+
+        let pending_txs = model.get_pending_transactions(&principal, ADDRESS_1);
+        assert_eq!(
+            pending_txs,
+            vec![StoredPendingTransaction {
+                replaced_txids: Some(vec![vec![1], vec![2]]),
+                ..second_replacement
+            }]
+        );
+        // The replaced UTXOs are now reserved by the replacement.
+        assert!(model.has_intersecting_pending_utxos(&[(*UTXO_2).clone()], 1_000_000));
+    }
+
+    #[test]
+    fn test_replace_pending_transaction_rejects_utxos_reserved_by_other_principal() {
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
+        let principal_1 = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
+        let principal_2 = Principal::from_text(PRINCIPAL_TEXT_2).unwrap();
+
+        model
+            .add_pending_transaction(
+                principal_1,
+                ADDRESS_1.to_string(),
+                pending_transaction(vec![1], vec![(*UTXO_1).clone()], 1_000),
+            )
+            .unwrap();
+        model
+            .add_pending_transaction(
+                principal_2,
+                ADDRESS_2.to_string(),
+                pending_transaction(vec![2], vec![(*UTXO_2).clone()], 1_000),
+            )
+            .unwrap();
+
+        let replacement =
+            pending_transaction(vec![3], vec![(*UTXO_2).clone(), (*UTXO_1).clone()], 2_000);
+        assert_eq!(
+            model.replace_pending_transaction(principal_2, ADDRESS_2, &[2], replacement),
+            Err(BtcReplacePendingTransactionError::UtxosAlreadyReserved)
+        );
+    }
+
+    #[test]
+    fn test_replace_pending_transaction_not_found() {
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
+        let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
+
+        model
+            .add_pending_transaction(
+                principal,
+                ADDRESS_1.to_string(),
+                pending_transaction(vec![1], vec![(*UTXO_1).clone()], 1_000),
+            )
+            .unwrap();
+
+        let replacement = pending_transaction(vec![2], vec![(*UTXO_1).clone()], 2_000);
+        assert_eq!(
+            model.replace_pending_transaction(principal, ADDRESS_1, &[9], replacement.clone()),
+            Err(BtcReplacePendingTransactionError::ReplacedTransactionNotFound)
+        );
+        assert_eq!(
+            model.replace_pending_transaction(principal, ADDRESS_2, &[1], replacement),
+            Err(BtcReplacePendingTransactionError::ReplacedTransactionNotFound)
+        );
+    }
+
+    #[test]
+    fn test_replace_pending_transaction_requires_replaced_utxos() {
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
+        let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
+
+        model
+            .add_pending_transaction(
+                principal,
+                ADDRESS_1.to_string(),
+                pending_transaction(vec![1], vec![(*UTXO_1).clone()], 1_000),
+            )
+            .unwrap();
+
+        let replacement = pending_transaction(vec![2], vec![(*UTXO_5).clone()], 2_000);
+        assert_eq!(
+            model.replace_pending_transaction(principal, ADDRESS_1, &[1], replacement),
+            Err(BtcReplacePendingTransactionError::NoReplacedUtxos)
+        );
+    }
+
+    #[test]
+    fn test_replace_pending_transaction_rejects_utxos_reserved_by_other_transactions() {
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
+        let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
+
+        model
+            .add_pending_transaction(
+                principal,
+                ADDRESS_1.to_string(),
+                pending_transaction(vec![1], vec![(*UTXO_1).clone()], 1_000),
+            )
+            .unwrap();
+        model
+            .add_pending_transaction(
+                principal,
+                ADDRESS_2.to_string(),
+                pending_transaction(vec![2], vec![(*UTXO_2).clone()], 1_000),
+            )
+            .unwrap();
+
+        let replacement =
+            pending_transaction(vec![3], vec![(*UTXO_1).clone(), (*UTXO_2).clone()], 2_000);
+        assert_eq!(
+            model.replace_pending_transaction(principal, ADDRESS_1, &[1], replacement),
+            Err(BtcReplacePendingTransactionError::UtxosAlreadyReserved)
+        );
+    }
+
+    #[test]
+    fn test_replace_pending_transaction_max_replacements() {
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
+        let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
+
+        model
+            .add_pending_transaction(
+                principal,
+                ADDRESS_1.to_string(),
+                pending_transaction(vec![0], vec![(*UTXO_1).clone()], 1_000),
+            )
+            .unwrap();
+        let last = u8::try_from(MAX_REPLACEMENTS).unwrap();
+        for i in 1..=last {
+            let replacement = pending_transaction(vec![i], vec![(*UTXO_1).clone()], 1_000);
+            model
+                .replace_pending_transaction(principal, ADDRESS_1, &[i - 1], replacement)
+                .unwrap();
+        }
+
+        let replacement = pending_transaction(vec![last + 1], vec![(*UTXO_1).clone()], 1_000);
+        assert_eq!(
+            model.replace_pending_transaction(principal, ADDRESS_1, &[last], replacement),
+            Err(BtcReplacePendingTransactionError::MaxReplacementsReached)
+        );
+    }
+
+    /// Returns a pending transaction with the given change output, of 500 satoshi at vout 1.
+    fn with_change(transaction: StoredPendingTransaction) -> StoredPendingTransaction {
+        StoredPendingTransaction {
+            change_output: Some(BtcChangeOutput {
+                vout: 1,
+                amount_satoshis: 500,
+            }),
+            ..transaction
+        }
+    }
+
+    fn change_utxo(txid: &[u8]) -> Utxo {
+        Utxo {
+            outpoint: Outpoint {
+                txid: txid.to_vec(),
+                vout: 1,
+            },
+            value: 500,
+            height: UNCONFIRMED_UTXO_HEIGHT,
+        }
+    }
+
+    #[test]
+    fn test_change_utxos_are_offered_and_spent_by_children() {
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
+        let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
+
+        let parent = with_change(pending_transaction(vec![1], vec![(*UTXO_1).clone()], 1_000));
+        model
+            .add_pending_transaction(principal, ADDRESS_1.to_string(), parent)
+            .unwrap();
+        assert_eq!(
+            model.change_utxos(&principal, ADDRESS_1, 1_000_000),
+            vec![change_utxo(&[1])]
+        );
+        assert!(model
+            .change_utxos(&principal, ADDRESS_2, 1_000_000)
+            .is_empty());
+
+        let child = pending_transaction(vec![2], vec![change_utxo(&[1])], 1_000);
+        model
+            .add_pending_transaction(principal, ADDRESS_1.to_string(), child)
+            .unwrap();
+
+        let child = model
+            .get_pending_transaction(&principal, ADDRESS_1, &[2])
+            .unwrap();
+        assert_eq!(child.parent_txids, Some(vec![vec![1]]));
+        assert!(model.has_intersecting_pending_utxos(&[change_utxo(&[1])], 1_000_000));
+    }
+
+    #[test]
+    fn test_change_utxos_respect_max_chain_depth() {
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
+        let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
+
+        let mut utxo = (*UTXO_1).clone();
+        for txid in 0..=MAX_UNCONFIRMED_CHAIN_DEPTH {
+            let txid = vec![u8::try_from(txid).unwrap()];
+            assert_eq!(
+                model.change_utxos(&principal, ADDRESS_1, 1_000_000).last(),
+                (txid[0] > 0).then_some(&utxo)
+            );
+            let transaction = with_change(pending_transaction(txid.clone(), vec![utxo], 1_000));
+            model
+                .add_pending_transaction(principal, ADDRESS_1.to_string(), transaction)
+                .unwrap();
+            utxo = change_utxo(&txid);
+        }
+
+        // The last transaction has `MAX_UNCONFIRMED_CHAIN_DEPTH` unconfirmed ancestors.
+        assert!(!model
+            .change_utxos(&principal, ADDRESS_1, 1_000_000)
+            .contains(&utxo));
+    }
+
+    #[test]
+    fn test_prune_keeps_children_pending_while_their_parent_is() {
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
+        let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
+
+        let parent = with_change(pending_transaction(vec![1], vec![(*UTXO_1).clone()], 1_000));
+        let child = pending_transaction(vec![2], vec![change_utxo(&[1])], 1_000);
+        for transaction in [parent, child] {
+            model
+                .add_pending_transaction(principal, ADDRESS_1.to_string(), transaction)
+                .unwrap();
+        }
+
+        // The change is not a current UTXO, but the parent is still pending.
+        model.prune_pending_transactions(principal, ADDRESS_1, &[(*UTXO_1).clone()], 1_000_001);
+        assert!(model
+            .get_pending_transactions(&principal, ADDRESS_1)
+            .iter()
+            .all(StoredPendingTransaction::is_pending));
+
+        // The parent is confirmed, and its change is a current UTXO that the child still spends.
+        let confirmed_change = Utxo {
+            height: 200,
+            ..change_utxo(&[1])
+        };
+        model.prune_pending_transactions(principal, ADDRESS_1, &[confirmed_change], 1_000_002);
+        let statuses: Vec<_> = model
+            .get_pending_transactions(&principal, ADDRESS_1)
+            .into_iter()
+            .map(|tx| tx.status)
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![Some(BtcPendingTransactionStatus::Confirmed), None]
+        );
+    }
+
+    #[test]
+    fn test_prune_expires_children_of_expired_parents() {
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
+        let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
+
+        let parent = with_change(pending_transaction(vec![1], vec![(*UTXO_1).clone()], 1_000));
+        let child = StoredPendingTransaction {
+            created_at_timestamp_ns: 2_000_000,
+            ..pending_transaction(vec![2], vec![change_utxo(&[1])], 1_000)
+        };
+        for transaction in [parent, child] {
+            model
+                .add_pending_transaction(principal, ADDRESS_1.to_string(), transaction)
+                .unwrap();
+        }
+
+        // Only the parent is older than its time to live.
+        model.prune_pending_transactions(
+            principal,
+            ADDRESS_1,
+            &[(*UTXO_1).clone()],
+            1_000_001 + HOUR_IN_NS,
+        );
+
+        let statuses: Vec<_> = model
+            .get_pending_transactions(&principal, ADDRESS_1)
+            .into_iter()
+            .map(|tx| tx.status)
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![Some(BtcPendingTransactionStatus::Expired); 2]
+        );
+        assert!(!model.has_intersecting_pending_utxos(&[change_utxo(&[1])], 1_000_001));
+    }
+
+    #[test]
+    fn test_replace_pending_transaction_rejects_parents_of_pending_transactions() {
+        let (mut map, mut reservations, _mm) = setup();
+        let mut model =
+            BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
+        let principal = Principal::from_text(PRINCIPAL_TEXT_1).unwrap();
+
+        let parent = with_change(pending_transaction(vec![1], vec![(*UTXO_1).clone()], 1_000));
+        let child = pending_transaction(vec![2], vec![change_utxo(&[1])], 1_000);
+        for transaction in [parent, child] {
+            model
+                .add_pending_transaction(principal, ADDRESS_1.to_string(), transaction)
+                .unwrap();
+        }
+
+        let replacement = pending_transaction(vec![3], vec![(*UTXO_1).clone()], 2_000);
+        assert_eq!(
+            model.replace_pending_transaction(principal, ADDRESS_1, &[1], replacement),
+            Err(BtcReplacePendingTransactionError::ChangeSpentByPendingTransactions)
+        );
     }
 
     #[test]
@@ -704,12 +2127,24 @@ mod tests {
             txid: vec![1, 2, 3],
             utxos: vec![(*UTXO_1).clone()],
             created_at_timestamp_ns: 1_234_567,
+            fee_satoshis: None,
+            replaced_txids: None,
+            destination_address: None,
+            amount_satoshis: None,
+            network: None,
+            status: None,
+            finalized_at_timestamp_ns: None,
+            change_output: None,
+            parent_txids: None,
         };
 
         {
             let memory = memory_manager.borrow().get(MemoryId::new(0));
             let mut map = BtcUserPendingTransactionsMap::init(memory);
-            let mut model = BtcUserPendingTransactionsModel::new(&mut map, None, None);
+            let mut reservations =
+                BtcOutpointReservationMap::init(memory_manager.borrow().get(MemoryId::new(1)));
+            let mut model =
+                BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
             model
                 .add_pending_transaction(principal, address.clone(), tx.clone())
                 .unwrap();
@@ -719,7 +2154,10 @@ mod tests {
         {
             let memory = memory_manager.borrow().get(MemoryId::new(0));
             let mut map = BtcUserPendingTransactionsMap::init(memory);
-            let model = BtcUserPendingTransactionsModel::new(&mut map, None, None);
+            let mut reservations =
+                BtcOutpointReservationMap::init(memory_manager.borrow().get(MemoryId::new(1)));
+            let model =
+                BtcUserPendingTransactionsModel::new(&mut map, &mut reservations, None, None, None);
             let pending_txs = model.get_pending_transactions(&principal, &address);
             assert_eq!(pending_txs.len(), 1);
             assert_eq!(pending_txs[0], tx);
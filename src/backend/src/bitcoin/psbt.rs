@@ -0,0 +1,243 @@
+//! Construction of unsigned transactions as [BIP-174](https://github.com/bitcoin/bips/blob/master/bip-0174.mediawiki)
+//! partially signed bitcoin transactions (PSBTs).
+
+use std::str::FromStr;
+
+use bitcoin::{
+    absolute::LockTime, hashes::Hash, transaction::Version, Address, Amount, OutPoint, Psbt,
+    ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness, XOnlyPublicKey,
+};
+use ic_cdk::api::management_canister::bitcoin::{BitcoinNetwork, Utxo};
+
+use crate::signer::transform_network;
+
+/// Parses the given address, or returns `None` if it is not a valid address on `network`.
+pub fn parse_address(address: &str, network: BitcoinNetwork) -> Option<Address> {
+    Address::from_str(address)
+        .ok()?
+        .require_network(transform_network(network))
+        .ok()
+}
+
+/// Returns the smallest change, in satoshi, worth returning to `change_address`.  Smaller change is
+/// dust and is better left to the miners.
+pub fn min_change_satoshis(change_address: &Address) -> u64 {
+    change_address.script_pubkey().minimal_non_dust().to_sat()
+}
+
+/// Builds an unsigned transaction spending `utxos` from `source_address` to pay `outputs`, as a
+/// PSBT.
+///
+/// Unless `change_satoshis` is 0, the change is returned to `source_address` in the last output.
+/// Every input signals replaceability (BIP-125) and carries the UTXO it spends as witness UTXO, so
+/// that signers can check the amounts.  The inputs of a P2TR `source_address` also carry its
+/// `tap_internal_key`, which key-path signers need.
+///
+/// # Errors
+/// - If the txid of a UTXO is malformed.
+pub fn build_psbt(
+    source_address: &Address,
+    tap_internal_key: Option<XOnlyPublicKey>,
+    utxos: &[Utxo],
+    outputs: &[(Address, u64)],
+    change_satoshis: u64,
+) -> Result<Psbt, String> {
+    let source_script = source_address.script_pubkey();
+
+    let input = utxos
+        .iter()
+        .map(|utxo| {
+            let txid = <[u8; 32]>::try_from(utxo.outpoint.txid.as_slice())
+                .map_err(|_| "Invalid UTXO transaction ID".to_string())?;
+            Ok(TxIn {
+                previous_output: OutPoint {
+                    txid: Txid::from_byte_array(txid),
+                    vout: utxo.outpoint.vout,
+                },
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::default(),
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let mut output: Vec<TxOut> = outputs
+        .iter()
+        .map(|(address, amount_satoshis)| TxOut {
+            value: Amount::from_sat(*amount_satoshis),
+            script_pubkey: address.script_pubkey(),
+        })
+        .collect();
+    if change_satoshis > 0 {
+        output.push(TxOut {
+            value: Amount::from_sat(change_satoshis),
+            script_pubkey: source_script.clone(),
+        });
+    }
+
+    let unsigned_tx = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input,
+        output,
+    };
+    let mut psbt = Psbt::from_unsigned_tx(unsigned_tx).map_err(|err| err.to_string())?;
+    for (psbt_input, utxo) in psbt.inputs.iter_mut().zip(utxos) {
+        psbt_input.witness_utxo = Some(TxOut {
+            value: Amount::from_sat(utxo.value),
+            script_pubkey: source_script.clone(),
+        });
+        psbt_input.tap_internal_key = tap_internal_key;
+    }
+
+    Ok(psbt)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::key::Secp256k1;
+    use ic_cdk::api::management_canister::bitcoin::Outpoint;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    const SOURCE_ADDRESS: &str = "bcrt1qpg7udjvq7gx2fp480pgt4hnhj3qc4nhrkstc33";
+
+    fn destination() -> Address {
+        Address::p2wsh(&ScriptBuf::new(), bitcoin::Network::Regtest)
+    }
+
+    fn utxo(vout: u32, value: u64) -> Utxo {
+        Utxo {
+            outpoint: Outpoint {
+                txid: vec![0xAA; 32],
+                vout,
+            },
+            value,
+            height: 100,
+        }
+    }
+
+    #[test]
+    fn parse_address_checks_network() {
+        assert!(parse_address(SOURCE_ADDRESS, BitcoinNetwork::Regtest).is_some());
+        assert!(parse_address(SOURCE_ADDRESS, BitcoinNetwork::Mainnet).is_none());
+        assert!(parse_address("not an address", BitcoinNetwork::Regtest).is_none());
+    }
+
+    #[test]
+    fn build_psbt_includes_change_and_witness_utxos() {
+        let source = parse_address(SOURCE_ADDRESS, BitcoinNetwork::Regtest).unwrap();
+        let destination = destination();
+        let utxos = vec![utxo(0, 10_000), utxo(1, 20_000)];
+
+        let psbt = build_psbt(
+            &source,
+            None,
+            &utxos,
+            &[(destination.clone(), 25_000)],
+            4_000,
+        )
+        .unwrap();
+
+        let tx = &psbt.unsigned_tx;
+        assert_eq!(tx.input.len(), 2);
+        assert_eq!(tx.input[1].previous_output.vout, 1);
+        assert!(tx.input.iter().all(|input| input.sequence.is_rbf()));
+        assert_eq!(
+            tx.output,
+            vec![
+                TxOut {
+                    value: Amount::from_sat(25_000),
+                    script_pubkey: destination.script_pubkey(),
+                },
+                TxOut {
+                    value: Amount::from_sat(4_000),
+                    script_pubkey: source.script_pubkey(),
+                },
+            ]
+        );
+        assert_eq!(
+            psbt.inputs
+                .iter()
+                .map(|input| input.witness_utxo.as_ref().unwrap().value.to_sat())
+                .collect::<Vec<_>>(),
+            vec![10_000, 20_000]
+        );
+        assert_eq!(psbt.fee().unwrap().to_sat(), 1_000);
+    }
+
+    #[test]
+    fn build_psbt_omits_zero_change() {
+        let source = parse_address(SOURCE_ADDRESS, BitcoinNetwork::Regtest).unwrap();
+        let destination = destination();
+
+        let psbt = build_psbt(
+            &source,
+            None,
+            &[utxo(0, 10_000)],
+            &[(destination, 9_000)],
+            0,
+        )
+        .unwrap();
+
+        assert_eq!(psbt.unsigned_tx.output.len(), 1);
+    }
+
+    #[test]
+    fn build_psbt_serialization_round_trips() {
+        let source = parse_address(SOURCE_ADDRESS, BitcoinNetwork::Regtest).unwrap();
+        let destination = destination();
+        let psbt = build_psbt(
+            &source,
+            None,
+            &[utxo(0, 10_000)],
+            &[(destination, 9_000)],
+            0,
+        )
+        .unwrap();
+
+        assert_eq!(Psbt::deserialize(&psbt.serialize()).unwrap(), psbt);
+    }
+
+    #[test]
+    fn build_psbt_rejects_malformed_txid() {
+        let source = parse_address(SOURCE_ADDRESS, BitcoinNetwork::Regtest).unwrap();
+        let mut malformed = utxo(0, 10_000);
+        malformed.outpoint.txid = vec![0xAA; 31];
+
+        assert!(build_psbt(&source, None, &[malformed], &[], 0).is_err());
+    }
+
+    #[test]
+    fn build_psbt_sets_tap_internal_key_of_p2tr_inputs() {
+        let secp = Secp256k1::verification_only();
+        let internal_key = XOnlyPublicKey::from_str(
+            "cc8a4bc64d897bddc5fbc2f670f7a8ba0b386779106cf1223c6fc5d7cd6fc115",
+        )
+        .unwrap();
+        let source = Address::p2tr(&secp, internal_key, None, bitcoin::Network::Regtest);
+        let utxos = vec![utxo(0, 10_000), utxo(1, 20_000)];
+
+        let psbt = build_psbt(
+            &source,
+            Some(internal_key),
+            &utxos,
+            &[(destination(), 25_000)],
+            4_000,
+        )
+        .unwrap();
+
+        assert!(psbt
+            .inputs
+            .iter()
+            .all(|input| input.tap_internal_key == Some(internal_key)));
+    }
+
+    #[test]
+    fn min_change_satoshis_is_p2wpkh_dust_limit() {
+        let source = parse_address(SOURCE_ADDRESS, BitcoinNetwork::Regtest).unwrap();
+
+        assert_eq!(min_change_satoshis(&source), 294);
+    }
+}
@@ -0,0 +1,468 @@
+//! Bounded history of the outgoing bitcoin transactions of each user.
+//!
+//! A transaction is recorded when its pending transaction is registered, and kept after the
+//! pending transaction is pruned, so that the history survives device switches.  Every user has at
+//! most `MAX_SEND_HISTORY_LEN` transactions: recording a transaction into a full history evicts the
+//! oldest one.
+
+use candid::Principal;
+use ic_cdk::api::management_canister::bitcoin::BitcoinNetwork;
+use shared::types::{
+    account::BtcAddress,
+    bitcoin::{
+        BtcGetSendHistoryResponse, BtcSentTransaction, MAX_REPLACEMENTS, MAX_SEND_HISTORY_LEN,
+        MAX_SEND_HISTORY_PAGE_LEN,
+    },
+    transaction::Transaction,
+    Timestamp,
+};
+
+use crate::types::{BtcSendHistoryMap, Candid, StoredPrincipal};
+
+/// Records an outgoing transaction of the user, and returns its `id`.
+pub fn record_sent_transaction(
+    send_history: &mut BtcSendHistoryMap,
+    principal: Principal,
+    txid: Vec<u8>,
+    transaction: Transaction<BitcoinNetwork, BtcAddress>,
+    fee_satoshis: Option<u64>,
+) -> u64 {
+    let key = StoredPrincipal(principal);
+    let mut transaction_count = 0;
+    let mut oldest = None;
+    let mut latest = None;
+    for entry in send_history.range((key, 0)..=(key, u64::MAX)) {
+        let id = entry.key().1;
+        oldest.get_or_insert(id);
+        latest = Some(id);
+        transaction_count += 1;
+    }
+
+    if transaction_count >= MAX_SEND_HISTORY_LEN {
+        if let Some(oldest) = oldest {
+            send_history.remove(&(key, oldest));
+        }
+    }
+    let id = latest.map_or(0, |latest| latest + 1);
+    let sent_transaction = BtcSentTransaction {
+        id,
+        txid,
+        updated_at_timestamp_ns: transaction.timestamp,
+        transaction,
+        fee_satoshis,
+        replaced_txids: None,
+    };
+    send_history.insert((key, id), Candid(sent_transaction));
+    id
+}
+
+/// Records that an outgoing transaction of the user was replaced, e.g. to bump its fee, by one
+/// making the given `payment`, if known.
+///
+/// A batch payment has an entry per output, which are all updated: the fee goes to the first one,
+/// as when it was sent, and the `payment` to the one paying the same recipient.  As for pending
+/// transactions, a transaction is replaced at most `MAX_REPLACEMENTS` times.  Returns whether the
+/// replacement was recorded, i.e. the replaced transaction is in the history and below that limit.
+pub fn record_replacement(
+    send_history: &mut BtcSendHistoryMap,
+    principal: Principal,
+    replaced_txid: &[u8],
+    txid: &[u8],
+    payment: Option<&Transaction<BitcoinNetwork, BtcAddress>>,
+    fee_satoshis: Option<u64>,
+    now_ns: Timestamp,
+) -> bool {
+    let key = StoredPrincipal(principal);
+    let sent_transactions: Vec<BtcSentTransaction> = send_history
+        .range((key, 0)..=(key, u64::MAX))
+        .map(|entry| entry.value().0)
+        .filter(|sent_transaction| sent_transaction.txid == replaced_txid)
+        .collect();
+    let Some(first) = sent_transactions.first() else {
+        return false;
+    };
+    if first
+        .replaced_txids
+        .as_ref()
+        .is_some_and(|replaced_txids| replaced_txids.len() >= MAX_REPLACEMENTS)
+    {
+        return false;
+    }
+
+    // A single payment may change its recipient, whereas a batch payment is matched by recipient.
+    let is_single = sent_transactions.len() == 1;
+    for (index, mut sent_transaction) in sent_transactions.into_iter().enumerate() {
+        let mut replaced_txids = sent_transaction.replaced_txids.take().unwrap_or_default();
+        replaced_txids.push(std::mem::replace(&mut sent_transaction.txid, txid.to_vec()));
+        sent_transaction.replaced_txids = Some(replaced_txids);
+        // The payment keeps the time it was first sent.
+        if let Some(payment) = payment {
+            if is_single || sent_transaction.transaction.counterparty == payment.counterparty {
+                sent_transaction.transaction.amount = payment.amount;
+                sent_transaction.transaction.counterparty = payment.counterparty.clone();
+            }
+        }
+        sent_transaction.fee_satoshis = fee_satoshis.filter(|_| index == 0);
+        sent_transaction.updated_at_timestamp_ns = now_ns;
+        send_history.insert((key, sent_transaction.id), Candid(sent_transaction));
+    }
+    true
+}
+
+/// Returns a page of the outgoing transactions of the user, newest first.
+///
+/// The page holds at most `limit` transactions of the given network, older than `before_id`.
+pub fn get_send_history(
+    send_history: &BtcSendHistoryMap,
+    principal: Principal,
+    network: Option<BitcoinNetwork>,
+    before_id: Option<u64>,
+    limit: Option<u32>,
+) -> BtcGetSendHistoryResponse {
+    let key = StoredPrincipal(principal);
+    let limit = limit.unwrap_or(MAX_SEND_HISTORY_PAGE_LEN) as usize;
+    let end = match before_id {
+        Some(0) => {
+            return BtcGetSendHistoryResponse {
+                transactions: vec![],
+                next_before_id: None,
+            }
+        }
+        Some(before_id) => before_id - 1,
+        None => u64::MAX,
+    };
+
+    let mut matching = send_history
+        .range((key, 0)..=(key, end))
+        .rev()
+        .map(|entry| entry.value().0)
+        .filter(|sent_transaction| {
+            network.is_none_or(|network| sent_transaction.transaction.network == network)
+        });
+    let transactions: Vec<BtcSentTransaction> = matching.by_ref().take(limit).collect();
+    let next_before_id = transactions
+        .last()
+        .map(|last| last.id)
+        .filter(|_| matching.next().is_some());
+
+    BtcGetSendHistoryResponse {
+        transactions,
+        next_before_id,
+    }
+}
+
+/// Returns the txids of all the outgoing transactions of the user, including the replaced ones.
+pub fn sent_txids(send_history: &BtcSendHistoryMap, principal: Principal) -> Vec<Vec<u8>> {
+    let key = StoredPrincipal(principal);
+    send_history
+        .range((key, 0)..=(key, u64::MAX))
+        .flat_map(|entry| {
+            let sent_transaction = entry.value().0;
+            sent_transaction
+                .replaced_txids
+                .unwrap_or_default()
+                .into_iter()
+                .chain([sent_transaction.txid])
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use ic_stable_structures::{
+        memory_manager::{MemoryId, MemoryManager},
+        DefaultMemoryImpl,
+    };
+    use pretty_assertions::assert_eq;
+    use shared::types::transaction::TransactionType;
+
+    use super::*;
+
+    fn setup() -> (BtcSendHistoryMap, RefCell<MemoryManager<DefaultMemoryImpl>>) {
+        let memory_manager = RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+        let map = BtcSendHistoryMap::init(memory_manager.borrow().get(MemoryId::new(0)));
+        (map, memory_manager)
+    }
+
+    fn payment(network: BitcoinNetwork, timestamp: u64) -> Transaction<BitcoinNetwork, BtcAddress> {
+        Transaction {
+            network,
+            transaction_type: TransactionType::Send,
+            amount: 1_000,
+            timestamp,
+            counterparty: BtcAddress::P2WPKH(
+                "bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7c".to_string(),
+            ),
+        }
+    }
+
+    fn record(
+        map: &mut BtcSendHistoryMap,
+        principal: Principal,
+        txid: u8,
+        network: BitcoinNetwork,
+    ) -> u64 {
+        record_sent_transaction(
+            map,
+            principal,
+            vec![txid; 32],
+            payment(network, u64::from(txid)),
+            Some(100),
+        )
+    }
+
+    fn txids(response: &BtcGetSendHistoryResponse) -> Vec<u8> {
+        response
+            .transactions
+            .iter()
+            .map(|sent_transaction| sent_transaction.txid[0])
+            .collect()
+    }
+
+    #[test]
+    fn test_send_history_is_paginated_newest_first() {
+        let (mut map, _mm) = setup();
+        let principal = Principal::from_slice(&[1]);
+        for txid in 0..5 {
+            record(&mut map, principal, txid, BitcoinNetwork::Mainnet);
+        }
+        record(
+            &mut map,
+            Principal::from_slice(&[2]),
+            9,
+            BitcoinNetwork::Mainnet,
+        );
+
+        let page = get_send_history(&map, principal, None, None, Some(2));
+        assert_eq!(txids(&page), vec![4, 3]);
+        assert_eq!(page.next_before_id, Some(3));
+
+        let page = get_send_history(&map, principal, None, page.next_before_id, Some(2));
+        assert_eq!(txids(&page), vec![2, 1]);
+
+        let page = get_send_history(&map, principal, None, page.next_before_id, Some(2));
+        assert_eq!(txids(&page), vec![0]);
+        assert_eq!(page.next_before_id, None);
+    }
+
+    #[test]
+    fn test_send_history_filters_by_network() {
+        let (mut map, _mm) = setup();
+        let principal = Principal::from_slice(&[1]);
+        record(&mut map, principal, 0, BitcoinNetwork::Mainnet);
+        record(&mut map, principal, 1, BitcoinNetwork::Testnet);
+        record(&mut map, principal, 2, BitcoinNetwork::Mainnet);
+
+        let page = get_send_history(&map, principal, Some(BitcoinNetwork::Mainnet), None, None);
+        assert_eq!(txids(&page), vec![2, 0]);
+        assert_eq!(page.next_before_id, None);
+    }
+
+    #[test]
+    fn test_send_history_is_bounded() {
+        let (mut map, _mm) = setup();
+        let principal = Principal::from_slice(&[1]);
+        for txid in 0..=MAX_SEND_HISTORY_LEN {
+            record(
+                &mut map,
+                principal,
+                u8::try_from(txid % 256).unwrap(),
+                BitcoinNetwork::Mainnet,
+            );
+        }
+
+        assert_eq!(map.len(), MAX_SEND_HISTORY_LEN as u64);
+        // The oldest transaction was evicted.
+        assert_eq!(map.first_key_value().map(|(key, _)| key.1), Some(1));
+        assert_eq!(
+            map.last_key_value().map(|(key, _)| key.1),
+            Some(MAX_SEND_HISTORY_LEN as u64)
+        );
+    }
+
+    #[test]
+    fn test_record_replacement() {
+        let (mut map, _mm) = setup();
+        let principal = Principal::from_slice(&[1]);
+        let id = record(&mut map, principal, 1, BitcoinNetwork::Mainnet);
+
+        assert!(record_replacement(
+            &mut map,
+            principal,
+            &[1; 32],
+            &[2; 32],
+            None,
+            Some(200),
+            10
+        ));
+        assert!(!record_replacement(
+            &mut map,
+            principal,
+            &[1; 32],
+            &[3; 32],
+            None,
+            Some(300),
+            20
+        ));
+
+        let page = get_send_history(&map, principal, None, None, None);
+        assert_eq!(
+            page.transactions,
+            vec![BtcSentTransaction {
+                id,
+                txid: vec![2; 32],
+                transaction: payment(BitcoinNetwork::Mainnet, 1),
+                fee_satoshis: Some(200),
+                replaced_txids: Some(vec![vec![1; 32]]),
+                updated_at_timestamp_ns: 10,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_record_replacement_updates_the_payment() {
+        let (mut map, _mm) = setup();
+        let principal = Principal::from_slice(&[1]);
+        record(&mut map, principal, 1, BitcoinNetwork::Mainnet);
+        let lowered_payment = Transaction {
+            amount: 900,
+            ..payment(BitcoinNetwork::Mainnet, 10)
+        };
+
+        assert!(record_replacement(
+            &mut map,
+            principal,
+            &[1; 32],
+            &[2; 32],
+            Some(&lowered_payment),
+            Some(200),
+            10
+        ));
+
+        let page = get_send_history(&map, principal, None, None, None);
+        assert_eq!(
+            page.transactions[0].transaction,
+            Transaction {
+                amount: 900,
+                ..payment(BitcoinNetwork::Mainnet, 1)
+            }
+        );
+    }
+
+    #[test]
+    fn test_record_replacement_updates_all_outputs_of_a_batch_payment() {
+        let (mut map, _mm) = setup();
+        let principal = Principal::from_slice(&[1]);
+        let other_recipient = Transaction {
+            counterparty: BtcAddress::P2WPKH(
+                "bc1qqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5fcj4z3".to_string(),
+            ),
+            ..payment(BitcoinNetwork::Mainnet, 1)
+        };
+        let first_id = record(&mut map, principal, 1, BitcoinNetwork::Mainnet);
+        let second_id = record_sent_transaction(
+            &mut map,
+            principal,
+            vec![1; 32],
+            other_recipient.clone(),
+            None,
+        );
+        let lowered_payment = Transaction {
+            amount: 900,
+            ..other_recipient.clone()
+        };
+
+        assert!(record_replacement(
+            &mut map,
+            principal,
+            &[1; 32],
+            &[2; 32],
+            Some(&lowered_payment),
+            Some(200),
+            10
+        ));
+
+        let page = get_send_history(&map, principal, None, None, None);
+        assert_eq!(
+            page.transactions,
+            vec![
+                BtcSentTransaction {
+                    id: second_id,
+                    txid: vec![2; 32],
+                    transaction: Transaction {
+                        amount: 900,
+                        ..other_recipient
+                    },
+                    fee_satoshis: None,
+                    replaced_txids: Some(vec![vec![1; 32]]),
+                    updated_at_timestamp_ns: 10,
+                },
+                BtcSentTransaction {
+                    id: first_id,
+                    txid: vec![2; 32],
+                    transaction: payment(BitcoinNetwork::Mainnet, 1),
+                    fee_satoshis: Some(200),
+                    replaced_txids: Some(vec![vec![1; 32]]),
+                    updated_at_timestamp_ns: 10,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_record_replacement_stops_at_max_replacements() {
+        let (mut map, _mm) = setup();
+        let principal = Principal::from_slice(&[1]);
+        record(&mut map, principal, 0, BitcoinNetwork::Mainnet);
+        let last = u8::try_from(MAX_REPLACEMENTS).unwrap();
+        for txid in 1..=last {
+            assert!(record_replacement(
+                &mut map,
+                principal,
+                &[txid - 1; 32],
+                &[txid; 32],
+                None,
+                Some(100),
+                10
+            ));
+        }
+
+        assert!(!record_replacement(
+            &mut map,
+            principal,
+            &[last; 32],
+            &[last + 1; 32],
+            None,
+            Some(100),
+            20
+        ));
+        let page = get_send_history(&map, principal, None, None, None);
+        assert_eq!(page.transactions[0].txid, vec![last; 32]);
+        assert_eq!(
+            page.transactions[0].replaced_txids.as_ref().map(Vec::len),
+            Some(MAX_REPLACEMENTS)
+        );
+    }
+
+    #[test]
+    fn test_sent_txids_include_replaced_transactions() {
+        let (mut map, _mm) = setup();
+        let principal = Principal::from_slice(&[1]);
+        record(&mut map, principal, 1, BitcoinNetwork::Mainnet);
+        record(&mut map, principal, 2, BitcoinNetwork::Testnet);
+        record(
+            &mut map,
+            Principal::from_slice(&[2]),
+            9,
+            BitcoinNetwork::Mainnet,
+        );
+        record_replacement(&mut map, principal, &[1; 32], &[3; 32], None, Some(200), 10);
+
+        assert_eq!(
+            sent_txids(&map, principal),
+            vec![vec![1; 32], vec![3; 32], vec![2; 32]]
+        );
+    }
+}
@@ -0,0 +1,198 @@
+//! Checks of signed transactions before they are broadcast.
+
+use bitcoin::{consensus, hashes::Hash, Address, Transaction};
+use ic_cdk::api::management_canister::bitcoin::{Outpoint, Utxo};
+use shared::types::bitcoin::BtcChangeOutput;
+
+/// Decodes a transaction serialized in the consensus format.
+///
+/// # Errors
+/// - If the bytes are not exactly one transaction.
+pub fn decode_transaction(bytes: &[u8]) -> Result<Transaction, String> {
+    consensus::deserialize(bytes).map_err(|err| err.to_string())
+}
+
+/// Returns the txid of the transaction, in the byte order of the outpoints of UTXOs.
+pub fn txid(transaction: &Transaction) -> Vec<u8> {
+    transaction.compute_txid().to_byte_array().to_vec()
+}
+
+/// Returns the outpoints spent by the transaction, in the order of its inputs.
+pub fn input_outpoints(transaction: &Transaction) -> Vec<Outpoint> {
+    transaction
+        .input
+        .iter()
+        .map(|input| Outpoint {
+            txid: input.previous_output.txid.to_byte_array().to_vec(),
+            vout: input.previous_output.vout,
+        })
+        .collect()
+}
+
+/// Matches the outputs of the transaction with the quoted `outputs`, in any order.
+///
+/// Returns the change output, or `Some(None)` if there is none, if every quoted output is paid
+/// exactly once and the only other output, if any, pays `source_address`.  Returns `None`
+/// otherwise.
+#[expect(clippy::option_option)]
+pub fn change_output(
+    transaction: &Transaction,
+    source_address: &Address,
+    outputs: &[(Address, u64)],
+) -> Option<Option<BtcChangeOutput>> {
+    let source_script = source_address.script_pubkey();
+    let mut unpaid: Vec<_> = outputs
+        .iter()
+        .map(|(address, amount_satoshis)| (address.script_pubkey(), *amount_satoshis))
+        .collect();
+    let mut change = None;
+    for (vout, output) in (0..).zip(&transaction.output) {
+        let amount_satoshis = output.value.to_sat();
+        if let Some(index) = unpaid.iter().position(|(script, amount)| {
+            *script == output.script_pubkey && *amount == amount_satoshis
+        }) {
+            unpaid.swap_remove(index);
+        } else if output.script_pubkey == source_script && change.is_none() {
+            change = Some(BtcChangeOutput {
+                vout,
+                amount_satoshis,
+            });
+        } else {
+            return None;
+        }
+    }
+    unpaid.is_empty().then_some(change)
+}
+
+/// Returns the fee paid by the transaction spending `utxos`, or `None` if its outputs are worth
+/// more than the UTXOs.
+pub fn fee_satoshis(transaction: &Transaction, utxos: &[Utxo]) -> Option<u64> {
+    let inputs_satoshis = utxos
+        .iter()
+        .try_fold(0_u64, |total, utxo| total.checked_add(utxo.value))?;
+    let outputs_satoshis = transaction.output.iter().try_fold(0_u64, |total, output| {
+        total.checked_add(output.value.to_sat())
+    })?;
+    inputs_satoshis.checked_sub(outputs_satoshis)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Amount, Network, ScriptBuf, TxOut};
+    use ic_cdk::api::management_canister::bitcoin::BitcoinNetwork;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::bitcoin::psbt::{build_psbt, parse_address};
+
+    const SOURCE_ADDRESS: &str = "bcrt1qpg7udjvq7gx2fp480pgt4hnhj3qc4nhrkstc33";
+
+    fn source() -> Address {
+        parse_address(SOURCE_ADDRESS, BitcoinNetwork::Regtest).unwrap()
+    }
+
+    fn destination() -> Address {
+        Address::p2wsh(&ScriptBuf::new(), Network::Regtest)
+    }
+
+    fn utxo(vout: u32, value: u64) -> Utxo {
+        Utxo {
+            outpoint: Outpoint {
+                txid: vec![0xAA; 32],
+                vout,
+            },
+            value,
+            height: 100,
+        }
+    }
+
+    fn transaction(utxos: &[Utxo], outputs: &[(Address, u64)], change: u64) -> Transaction {
+        build_psbt(&source(), None, utxos, outputs, change)
+            .unwrap()
+            .unsigned_tx
+    }
+
+    #[test]
+    fn decode_transaction_round_trips() {
+        let tx = transaction(&[utxo(0, 10_000)], &[(destination(), 9_000)], 0);
+
+        let decoded = decode_transaction(&consensus::serialize(&tx)).unwrap();
+
+        assert_eq!(decoded, tx);
+        assert_eq!(txid(&decoded), tx.compute_txid().to_byte_array().to_vec());
+    }
+
+    #[test]
+    fn decode_transaction_rejects_garbage_and_trailing_bytes() {
+        let tx = transaction(&[utxo(0, 10_000)], &[(destination(), 9_000)], 0);
+        let mut bytes = consensus::serialize(&tx);
+        bytes.push(0);
+
+        assert!(decode_transaction(&bytes).is_err());
+        assert!(decode_transaction(&[1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn input_outpoints_match_spent_utxos() {
+        let utxos = vec![utxo(0, 10_000), utxo(3, 20_000)];
+        let tx = transaction(&utxos, &[(destination(), 25_000)], 0);
+
+        assert_eq!(
+            input_outpoints(&tx),
+            utxos
+                .into_iter()
+                .map(|utxo| utxo.outpoint)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn change_output_finds_change_to_source_address() {
+        let outputs = [(destination(), 25_000)];
+        let tx = transaction(&[utxo(0, 30_000)], &outputs, 4_000);
+
+        assert_eq!(
+            change_output(&tx, &source(), &outputs),
+            Some(Some(BtcChangeOutput {
+                vout: 1,
+                amount_satoshis: 4_000,
+            }))
+        );
+        assert_eq!(
+            change_output(&tx, &source(), &[(destination(), 26_000)]),
+            None
+        );
+    }
+
+    #[test]
+    fn change_output_rejects_missing_and_extra_outputs() {
+        let outputs = [(destination(), 25_000), (source(), 1_000)];
+        let tx = transaction(&[utxo(0, 30_000)], &outputs, 0);
+
+        assert_eq!(change_output(&tx, &source(), &outputs), Some(None));
+        // The output to the source address is taken for change, but the quoted one is unpaid.
+        assert_eq!(
+            change_output(
+                &tx,
+                &source(),
+                &[(destination(), 25_000), (destination(), 1)]
+            ),
+            None
+        );
+        let mut extra = tx.clone();
+        extra.output.push(TxOut {
+            value: Amount::from_sat(500),
+            script_pubkey: destination().script_pubkey(),
+        });
+        assert_eq!(change_output(&extra, &source(), &outputs), None);
+    }
+
+    #[test]
+    fn fee_satoshis_is_inputs_minus_outputs() {
+        let utxos = [utxo(0, 30_000)];
+        let tx = transaction(&utxos, &[(destination(), 25_000)], 4_000);
+
+        assert_eq!(fee_satoshis(&tx, &utxos), Some(1_000));
+        assert_eq!(fee_satoshis(&tx, &[utxo(0, 20_000)]), None);
+    }
+}
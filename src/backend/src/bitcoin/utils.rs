@@ -1,6 +1,15 @@
 //! Functions [inspired by ckBTC Minter](https://github.com/dfinity/ic/blob/285a5db07da50a4e350ec43bf3b488cc6fe36102/rs/bitcoin/ckbtc/minter/src/lib.rs#L1258)
 
-use ic_cdk::api::management_canister::bitcoin::Utxo;
+use std::str::FromStr;
+
+use ic_cdk::api::management_canister::bitcoin::{Outpoint, Utxo};
+use shared::types::{
+    account::BtcAddress,
+    bitcoin::{
+        BtcAddressType, BtcGetBalanceResponse, UtxosSelectionAlgorithm, UtxosSelectionStrategy,
+        MAX_CONSOLIDATION_UTXOS,
+    },
+};
 
 /// Selects a subset of UTXOs with the specified total target value and removes
 /// the selected UTXOs from the available set.
@@ -87,35 +96,674 @@ pub fn utxos_selection(
     input_utxos
 }
 
-/// Estimates the size of transaction (in vbytes) with the given number of inputs and outputs.
+/// The script type of a transaction output, which determines its size.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScriptType {
+    P2pkh,
+    P2sh,
+    #[default]
+    P2wpkh,
+    P2wsh,
+    P2tr,
+}
+
+impl ScriptType {
+    /// Returns the size, in vbytes, of an output paying to this script type.
+    ///
+    /// An output is made of an 8 byte amount, a 1 byte script length and the script itself.
+    const fn output_vbytes(self) -> u64 {
+        match self {
+            ScriptType::P2pkh => 34,
+            ScriptType::P2sh => 32,
+            ScriptType::P2wpkh => 31,
+            ScriptType::P2wsh | ScriptType::P2tr => 43,
+        }
+    }
+
+    /// Returns the script type of the given destination address.
+    ///
+    /// Addresses that cannot be parsed are assumed to take the largest output, so that the fee is
+    /// never underestimated.
+    pub fn of_address(address: &str) -> Self {
+        BtcAddress::from_str(address).map_or(ScriptType::P2tr, |address| Self::from(&address))
+    }
+}
+
+impl From<&BtcAddress> for ScriptType {
+    fn from(address: &BtcAddress) -> Self {
+        match address {
+            BtcAddress::P2PKH(_) => ScriptType::P2pkh,
+            BtcAddress::P2SH(_) => ScriptType::P2sh,
+            BtcAddress::P2WPKH(_) => ScriptType::P2wpkh,
+            BtcAddress::P2WSH(_) => ScriptType::P2wsh,
+            BtcAddress::P2TR(_) => ScriptType::P2tr,
+        }
+    }
+}
+
+/// How the inputs of a transaction are spent, which determines their size.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputType {
+    /// Spends a P2WPKH output with an ECDSA signature and a public key.
+    #[default]
+    P2wpkh,
+    /// Spends a P2TR output through its key path, with a single Schnorr signature.
+    P2trKeyPath,
+}
+
+impl From<BtcAddressType> for InputType {
+    fn from(address_type: BtcAddressType) -> Self {
+        match address_type {
+            BtcAddressType::P2wpkh => InputType::P2wpkh,
+            BtcAddressType::P2tr => InputType::P2trKeyPath,
+        }
+    }
+}
+
+impl InputType {
+    /// Returns the size, in vbytes, of an input of this type.
+    ///
+    /// An input is made of a 36 byte outpoint, an empty script and a 4 byte sequence number, plus
+    /// the witness at a quarter of its size, rounded up.
+    const fn vbytes(self) -> u64 {
+        match self {
+            InputType::P2wpkh => 68,
+            InputType::P2trKeyPath => 58,
+        }
+    }
+
+    /// Returns the script type of the outputs spent by inputs of this type.  Change is returned
+    /// to an output of that same type.
+    pub const fn script_type(self) -> ScriptType {
+        match self {
+            InputType::P2wpkh => ScriptType::P2wpkh,
+            InputType::P2trKeyPath => ScriptType::P2tr,
+        }
+    }
+}
+
+/// Estimates the size of transaction (in vbytes) with the given inputs and outputs.
 // See [MediaWiki](https://github.com/bitcoin/bips/blob/master/bip-0141.mediawiki)
 // for the transaction structure and
 // [Stack Exchange](https://bitcoin.stackexchange.com/questions/92587/calculate-transaction-fee-for-external-addresses-which-doesnt-belong-to-my-loca/92600#92600)
 // for transaction size estimate.
-const INPUT_SIZE_VBYTES: u64 = 68;
-const OUTPUT_SIZE_VBYTES: u64 = 31;
 const TX_OVERHEAD_VBYTES: u64 = 11;
-fn tx_vsize_estimate(input_count: u64, output_count: u64) -> u64 {
-    input_count * INPUT_SIZE_VBYTES + output_count * OUTPUT_SIZE_VBYTES + TX_OVERHEAD_VBYTES
+fn tx_vsize_estimate(input_type: InputType, input_count: u64, output_types: &[ScriptType]) -> u64 {
+    input_count * input_type.vbytes()
+        + output_types
+            .iter()
+            .map(|output_type| output_type.output_vbytes())
+            .sum::<u64>()
+        + TX_OVERHEAD_VBYTES
 }
 
-/// Estimates the transaction fee, in satoshi, based on the number of utxos and outputs
+/// Estimates the transaction fee, in satoshi, based on its inputs and outputs
 ///
 /// Arguments:
+///   * `input_type` - how the UTXOs used for the transaction are spent.
 ///   * `selected_utxos_count` - the number of UTXOs used for the transaction.
 ///   * `median_fee_millisatoshi_per_vbyte` - the median network fee, in millisatoshi per vbyte.
-///   * `output_count` - the number of outputs of the bitcoin transaction.
+///   * `output_types` - the script types of the outputs of the bitcoin transaction.
 pub fn estimate_fee(
+    input_type: InputType,
     selected_utxos_count: u64,
     median_fee_millisatoshi_per_vbyte: u64,
-    output_count: u64,
+    output_types: &[ScriptType],
 ) -> u64 {
-    tx_vsize_estimate(selected_utxos_count, output_count) * median_fee_millisatoshi_per_vbyte / 1000
+    tx_vsize_estimate(input_type, selected_utxos_count, output_types)
+        * median_fee_millisatoshi_per_vbyte
+        / 1000
+}
+
+/// Estimates the part of the transaction fee, in satoshi, due to a single output.
+pub fn estimate_output_fee(output_type: ScriptType, fee_millisatoshi_per_vbyte: u64) -> u64 {
+    vbytes_fee(output_type.output_vbytes(), fee_millisatoshi_per_vbyte)
+}
+
+/// The fee rate, in millisatoshi per vbyte, by which a replacement transaction must at least pay
+/// for its own size.  Same value as Bitcoin Core's `-incrementalrelayfee`.
+const INCREMENTAL_RELAY_FEE_MILLISATOSHI_PER_VBYTE: u64 = 1_000;
+
+/// Returns the minimum fee, in satoshi, of a transaction replacing one that paid
+/// `replaced_fee_satoshis`.
+///
+/// Per BIP-125, the replacement pays more than the replaced transaction, plus its own size at the
+/// incremental relay fee rate.
+pub fn min_replacement_fee(
+    replaced_fee_satoshis: u64,
+    input_type: InputType,
+    input_count: u64,
+    output_types: &[ScriptType],
+) -> u64 {
+    replaced_fee_satoshis
+        + vbytes_fee(
+            tx_vsize_estimate(input_type, input_count, output_types),
+            INCREMENTAL_RELAY_FEE_MILLISATOSHI_PER_VBYTE,
+        )
+}
+
+/// The fee rate, in millisatoshi per vbyte, at which UTXOs are expected to be spent in the long
+/// run.
+///
+/// Used by the waste metric: spending an input now is wasteful when the current fee rate is above
+/// this rate, and saves fees when it is below.  Same value as Bitcoin Core's `-consolidatefeerate`.
+pub const LONG_TERM_FEE_MILLISATOSHI_PER_VBYTE: u64 = 10_000;
+
+/// Maximum number of steps taken by the branch-and-bound search, as in Bitcoin Core.
+const BNB_MAX_TRIES: usize = 100_000;
+
+/// The fee rate, in millisatoshi per vbyte, of the dust threshold at low fee rates.  Same value as
+/// Bitcoin Core's `-dustrelayfee`.
+const DUST_RELAY_FEE_MILLISATOSHI_PER_VBYTE: u64 = 3_000;
+
+/// Returns the value, in satoshi, below which a UTXO spent as `input_type` is dust at the given fee
+/// rate, i.e. worth less than the fee of creating and spending it.
+///
+/// At low fee rates, the threshold is the dust limit of Bitcoin Core's relay policy.
+pub fn dust_threshold(input_type: InputType, fee_millisatoshi_per_vbyte: u64) -> u64 {
+    vbytes_fee(
+        input_type.vbytes() + input_type.script_type().output_vbytes(),
+        fee_millisatoshi_per_vbyte.max(DUST_RELAY_FEE_MILLISATOSHI_PER_VBYTE),
+    )
+}
+
+/// Splits the UTXOs into those worth at least `dust_threshold_satoshis`, and the dust.
+pub fn partition_dust(utxos: Vec<Utxo>, dust_threshold_satoshis: u64) -> (Vec<Utxo>, Vec<Utxo>) {
+    utxos
+        .into_iter()
+        .partition(|utxo| utxo.value >= dust_threshold_satoshis)
+}
+
+/// Returns the fee, in satoshi, of the given number of vbytes, rounded up.
+fn vbytes_fee(vbytes: u64, fee_millisatoshi_per_vbyte: u64) -> u64 {
+    (vbytes * fee_millisatoshi_per_vbyte).div_ceil(1000)
+}
+
+/// Returns the cost, in satoshi, of creating a change output now and spending it later.
+fn cost_of_change(input_type: InputType, fee_millisatoshi_per_vbyte: u64) -> u64 {
+    estimate_output_fee(input_type.script_type(), fee_millisatoshi_per_vbyte)
+        + vbytes_fee(input_type.vbytes(), LONG_TERM_FEE_MILLISATOSHI_PER_VBYTE)
+}
+
+/// Computes the waste metric of a selection, [as defined by Bitcoin Core](https://github.com/bitcoin/bitcoin/blob/v27.0/src/wallet/coinselection.cpp#L803).
+///
+/// The waste is the fee paid for the inputs on top of what they would cost at the long-term fee
+/// rate, plus either the cost of the change output or, without change, the excess value that is
+/// given away to the miners.  A lower waste is better; it can be negative when fees are low.
+fn selection_waste(
+    input_type: InputType,
+    input_count: u64,
+    fee_millisatoshi_per_vbyte: u64,
+    change_output: bool,
+    excess: u64,
+) -> i128 {
+    let input_timing_cost = i128::from(vbytes_fee(input_type.vbytes(), fee_millisatoshi_per_vbyte))
+        - i128::from(vbytes_fee(
+            input_type.vbytes(),
+            LONG_TERM_FEE_MILLISATOSHI_PER_VBYTE,
+        ));
+    let change_cost = if change_output {
+        cost_of_change(input_type, fee_millisatoshi_per_vbyte)
+    } else {
+        excess
+    };
+    input_timing_cost * i128::from(input_count) + i128::from(change_cost)
+}
+
+/// Searches for a subset of the available UTXOs that pays for the `target` without needing a
+/// change output.
+///
+/// The `target` is the amount to send plus the fee of the transaction without its inputs. Each UTXO
+/// counts for its effective value, i.e. its value minus the fee of spending it.  A subset matches
+/// if its effective value is in `[target, target + cost_of_change]`: such an excess is cheaper to
+/// give to the miners than to return as change.
+///
+/// The search explores the UTXOs by decreasing effective value and gives up after
+/// `BNB_MAX_TRIES` steps.  Returns the match with the lowest waste, or `None` if no match was
+/// found.
+///
+/// See [Bitcoin Core](https://github.com/bitcoin/bitcoin/blob/v27.0/src/wallet/coinselection.cpp#L31)
+/// for the reference implementation.
+fn branch_and_bound(
+    target: u64,
+    available_utxos: &[Utxo],
+    fee_millisatoshi_per_vbyte: u64,
+    input_type: InputType,
+) -> Option<Vec<Utxo>> {
+    let input_fee = vbytes_fee(input_type.vbytes(), fee_millisatoshi_per_vbyte);
+    let upper_bound = target + cost_of_change(input_type, fee_millisatoshi_per_vbyte);
+
+    // UTXOs that cost more to spend than they are worth can never help.
+    let mut pool: Vec<(u64, &Utxo)> = available_utxos
+        .iter()
+        .filter(|u| u.value > input_fee)
+        .map(|u| (u.value - input_fee, u))
+        .collect();
+    pool.sort_by(|a, b| b.0.cmp(&a.0));
+
+    // `remaining[i]` is the total effective value of `pool[i..]`.
+    let mut remaining = vec![0u64; pool.len() + 1];
+    for i in (0..pool.len()).rev() {
+        remaining[i] = remaining[i + 1] + pool[i].0;
+    }
+
+    let mut selection: Vec<usize> = Vec::new();
+    let mut value = 0u64;
+    let mut next = 0usize;
+    let mut best: Option<(i128, Vec<usize>)> = None;
+    for _ in 0..BNB_MAX_TRIES {
+        let backtrack = if value > upper_bound || value + remaining[next] < target {
+            true
+        } else if value >= target {
+            let waste = selection_waste(
+                input_type,
+                selection.len() as u64,
+                fee_millisatoshi_per_vbyte,
+                false,
+                value - target,
+            );
+            if best
+                .as_ref()
+                .is_none_or(|(best_waste, _)| waste < *best_waste)
+            {
+                best = Some((waste, selection.clone()));
+            }
+            true
+        } else {
+            false
+        };
+
+        if backtrack {
+            // Undo the last inclusion and explore the branch that omits that UTXO instead.
+            let Some(last) = selection.pop() else {
+                // The whole tree has been explored.
+                break;
+            };
+            value -= pool[last].0;
+            next = last + 1;
+        } else {
+            value += pool[next].0;
+            selection.push(next);
+            next += 1;
+        }
+    }
+
+    best.map(|(_, indices)| indices.into_iter().map(|i| pool[i].1.clone()).collect())
+}
+
+/// A selection of UTXOs together with the fee of the transaction spending them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UtxosSelection {
+    pub input_type: InputType,
+    pub utxos: Vec<Utxo>,
+    pub fee_satoshis: u64,
+    pub algorithm: UtxosSelectionAlgorithm,
+    pub change_output: bool,
+}
+
+impl UtxosSelection {
+    pub fn total_value(&self) -> u64 {
+        self.utxos.iter().map(|u| u.value).sum()
+    }
+
+    /// The waste metric of the selection when sending `target` satoshi.
+    fn waste(&self, target: u64, fee_millisatoshi_per_vbyte: u64) -> i128 {
+        let excess = self
+            .total_value()
+            .saturating_sub(target)
+            .saturating_sub(self.fee_satoshis);
+        selection_waste(
+            self.input_type,
+            self.utxos.len() as u64,
+            fee_millisatoshi_per_vbyte,
+            self.change_output,
+            excess,
+        )
+    }
+}
+
+/// Selects UTXOs with the greedy `utxos_selection`, assuming a change output.
+///
+/// The UTXOs are selected to pay for the target and the fee of spending them.  Since the fee grows
+/// with the number of inputs, they are selected again, for the target and the fee of the previous
+/// selection, until they pay for both.
+///
+/// If there are no UTXOs matching the criteria, returns an empty selection without fee.
+fn greedy_selection(
+    target: u64,
+    available_utxos: &[Utxo],
+    fee_millisatoshi_per_vbyte: u64,
+    input_type: InputType,
+    destination_types: &[ScriptType],
+) -> UtxosSelection {
+    let mut output_types = destination_types.to_vec();
+    output_types.push(input_type.script_type());
+    let mut fee_satoshis = 0;
+    // A selection with no more inputs than the previous one pays for the fee, so this ends once
+    // the UTXOs pay for the fee or run out.
+    loop {
+        let mut remaining_utxos = available_utxos.to_vec();
+        let utxos = utxos_selection(
+            target + fee_satoshis,
+            &mut remaining_utxos,
+            output_types.len(),
+        );
+        // If there are no selected utxos, no tx is possible. Therefore, no fee should be present.
+        if utxos.is_empty() {
+            return UtxosSelection {
+                input_type,
+                utxos,
+                fee_satoshis: 0,
+                algorithm: UtxosSelectionAlgorithm::Greedy,
+                change_output: false,
+            };
+        }
+
+        let total_value: u64 = utxos.iter().map(|u| u.value).sum();
+        let required_fee_satoshis = estimate_fee(
+            input_type,
+            utxos.len() as u64,
+            fee_millisatoshi_per_vbyte,
+            &output_types,
+        );
+        if total_value >= target + required_fee_satoshis {
+            return UtxosSelection {
+                input_type,
+                change_output: total_value > target + required_fee_satoshis,
+                utxos,
+                fee_satoshis: required_fee_satoshis,
+                algorithm: UtxosSelectionAlgorithm::Greedy,
+            };
+        }
+        fee_satoshis = required_fee_satoshis;
+    }
+}
+
+/// Selects the UTXOs, spent as `input_type`, to send `target` satoshi to destinations of the given
+/// script types.
+///
+/// Two candidates are computed: a changeless selection found by `branch_and_bound` and the
+/// `greedy_selection`, which usually needs a change output.  The `strategy` decides between them:
+/// - `MinimizeFee` takes the candidate with the lowest waste.
+/// - `MinimizeInputs` takes the candidate with the fewest inputs, then the lowest waste.
+/// - `AvoidChange` takes the changeless candidate.
+///
+/// Falls back to the greedy selection if there is no changeless candidate.  The fee of a changeless
+/// selection includes the excess value, since it goes to the miners.
+///
+/// If there are no UTXOs matching the criteria, returns an empty selection without fee.
+pub fn select_utxos(
+    strategy: UtxosSelectionStrategy,
+    target: u64,
+    available_utxos: &[Utxo],
+    fee_millisatoshi_per_vbyte: u64,
+    input_type: InputType,
+    destination_types: &[ScriptType],
+) -> UtxosSelection {
+    let greedy = greedy_selection(
+        target,
+        available_utxos,
+        fee_millisatoshi_per_vbyte,
+        input_type,
+        destination_types,
+    );
+
+    let changeless_target =
+        target + estimate_fee(input_type, 0, fee_millisatoshi_per_vbyte, destination_types);
+    let Some(changeless) = branch_and_bound(
+        changeless_target,
+        available_utxos,
+        fee_millisatoshi_per_vbyte,
+        input_type,
+    )
+    .map(|utxos| {
+        let total_value: u64 = utxos.iter().map(|u| u.value).sum();
+        UtxosSelection {
+            input_type,
+            fee_satoshis: total_value - target,
+            utxos,
+            algorithm: UtxosSelectionAlgorithm::BranchAndBound,
+            change_output: false,
+        }
+    }) else {
+        return greedy;
+    };
+
+    if greedy.utxos.is_empty() {
+        return changeless;
+    }
+
+    let greedy_waste = greedy.waste(target, fee_millisatoshi_per_vbyte);
+    let changeless_waste = changeless.waste(target, fee_millisatoshi_per_vbyte);
+    let changeless_wins = match strategy {
+        UtxosSelectionStrategy::MinimizeFee => changeless_waste <= greedy_waste,
+        UtxosSelectionStrategy::MinimizeInputs => {
+            (changeless.utxos.len(), changeless_waste) <= (greedy.utxos.len(), greedy_waste)
+        }
+        UtxosSelectionStrategy::AvoidChange => true,
+    };
+
+    if changeless_wins {
+        changeless
+    } else {
+        greedy
+    }
+}
+
+/// Computes the fee of a transaction spending exactly the given UTXOs, chosen by the user, to send
+/// `target` satoshi to destinations of the given script types.
+///
+/// The transaction has a change output if the UTXOs pay for the fee of one, otherwise the excess
+/// value goes to the miners.
+///
+/// # Errors
+/// - If the UTXOs do not even pay for a changeless transaction.  Returns the value they must have.
+pub fn manual_selection(
+    target: u64,
+    utxos: Vec<Utxo>,
+    fee_millisatoshi_per_vbyte: u64,
+    input_type: InputType,
+    destination_types: &[ScriptType],
+) -> Result<UtxosSelection, u64> {
+    let total_value: u64 = utxos.iter().map(|u| u.value).sum();
+    let input_count = utxos.len() as u64;
+    let mut output_types = destination_types.to_vec();
+    output_types.push(input_type.script_type());
+    let fee_with_change = estimate_fee(
+        input_type,
+        input_count,
+        fee_millisatoshi_per_vbyte,
+        &output_types,
+    );
+    let fee_without_change = estimate_fee(
+        input_type,
+        input_count,
+        fee_millisatoshi_per_vbyte,
+        destination_types,
+    );
+    let (fee_satoshis, change_output) = if total_value > target + fee_with_change {
+        (fee_with_change, true)
+    } else if total_value >= target + fee_without_change {
+        (total_value - target, false)
+    } else {
+        return Err(target + fee_without_change);
+    };
+    Ok(UtxosSelection {
+        input_type,
+        utxos,
+        fee_satoshis,
+        algorithm: UtxosSelectionAlgorithm::Manual,
+        change_output,
+    })
+}
+
+/// Computes the fee of a transaction spending exactly the given UTXOs to destinations of the
+/// given script types, without change.  The destinations receive the value of the UTXOs net of the
+/// fee.
+///
+/// # Errors
+/// - If the UTXOs do not pay for more than the fee.  Returns the value they must have.
+pub fn send_max_selection(
+    utxos: Vec<Utxo>,
+    fee_millisatoshi_per_vbyte: u64,
+    input_type: InputType,
+    destination_types: &[ScriptType],
+) -> Result<UtxosSelection, u64> {
+    let total_value: u64 = utxos.iter().map(|u| u.value).sum();
+    let fee_satoshis = estimate_fee(
+        input_type,
+        utxos.len() as u64,
+        fee_millisatoshi_per_vbyte,
+        destination_types,
+    );
+    if utxos.is_empty() || total_value <= fee_satoshis {
+        return Err(fee_satoshis + 1);
+    }
+    Ok(UtxosSelection {
+        input_type,
+        utxos,
+        fee_satoshis,
+        algorithm: UtxosSelectionAlgorithm::SendMax,
+        change_output: false,
+    })
+}
+
+/// Selects all the available UTXOs that are worth more than the fee of spending them, to send
+/// as much as possible to destinations of the given script types.
+///
+/// If they do not pay for more than the fee, returns an empty selection without fee.
+pub fn select_all_utxos(
+    available_utxos: &[Utxo],
+    fee_millisatoshi_per_vbyte: u64,
+    input_type: InputType,
+    destination_types: &[ScriptType],
+) -> UtxosSelection {
+    send_max_selection(
+        economical_utxos(available_utxos, fee_millisatoshi_per_vbyte, input_type),
+        fee_millisatoshi_per_vbyte,
+        input_type,
+        destination_types,
+    )
+    .unwrap_or(UtxosSelection {
+        input_type,
+        utxos: vec![],
+        fee_satoshis: 0,
+        algorithm: UtxosSelectionAlgorithm::SendMax,
+        change_output: false,
+    })
+}
+
+/// Returns the UTXOs worth more than the fee of spending them as `input_type`.
+fn economical_utxos(
+    utxos: &[Utxo],
+    fee_millisatoshi_per_vbyte: u64,
+    input_type: InputType,
+) -> Vec<Utxo> {
+    let input_fee_satoshis = vbytes_fee(input_type.vbytes(), fee_millisatoshi_per_vbyte);
+    utxos
+        .iter()
+        .filter(|utxo| utxo.value > input_fee_satoshis)
+        .cloned()
+        .collect()
+}
+
+/// A transaction spending many UTXOs into a single output of the same address.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ConsolidationPlan {
+    /// The UTXOs to consolidate, smallest first.
+    pub utxos: Vec<Utxo>,
+    pub fee_satoshis: u64,
+    /// The fees saved by spending the consolidated output later instead of the UTXOs, net of
+    /// `fee_satoshis`.  0 if the consolidation costs more than it saves.
+    pub savings_satoshis: u64,
+}
+
+/// Plans the consolidation of the available UTXOs at `fee_millisatoshi_per_vbyte`, expecting them
+/// to be spent later at `reference_fee_millisatoshi_per_vbyte` otherwise.
+///
+/// The smallest UTXOs are consolidated first, since they cost the most to spend relative to their
+/// value, up to `MAX_CONSOLIDATION_UTXOS`.  UTXOs worth less than the fee of spending them are left
+/// out.  Returns an empty plan if fewer than two UTXOs are worth consolidating.
+pub fn plan_consolidation(
+    available_utxos: &[Utxo],
+    fee_millisatoshi_per_vbyte: u64,
+    reference_fee_millisatoshi_per_vbyte: u64,
+    input_type: InputType,
+) -> ConsolidationPlan {
+    let mut utxos = economical_utxos(available_utxos, fee_millisatoshi_per_vbyte, input_type);
+    utxos.sort_by_key(|utxo| utxo.value);
+    utxos.truncate(MAX_CONSOLIDATION_UTXOS);
+    if utxos.len() < 2 {
+        return ConsolidationPlan::default();
+    }
+
+    let input_count = utxos.len() as u64;
+    let fee_satoshis = estimate_fee(
+        input_type,
+        input_count,
+        fee_millisatoshi_per_vbyte,
+        &[input_type.script_type()],
+    );
+    let reference_input_fee_satoshis =
+        vbytes_fee(input_type.vbytes(), reference_fee_millisatoshi_per_vbyte);
+    let savings_satoshis = (input_count * reference_input_fee_satoshis)
+        .saturating_sub(fee_satoshis + reference_input_fee_satoshis);
+
+    ConsolidationPlan {
+        utxos,
+        fee_satoshis,
+        savings_satoshis,
+    }
+}
+
+/// Returns the number of confirmations of a UTXO, given the height of the tip of the chain.
+pub fn confirmations(utxo: &Utxo, tip_height: u32) -> u32 {
+    (tip_height + 1).saturating_sub(utxo.height)
+}
+
+/// Returns the balance of an address, given its UTXOs at `tip_height` and the UTXOs reserved by
+/// its pending transactions.
+///
+/// Reserved UTXOs that are no longer unspent are ignored.
+pub fn balance(
+    utxos: &[Utxo],
+    tip_height: u32,
+    min_confirmations: u32,
+    reserved_utxos: &[Utxo],
+    frozen_outpoints: &[Outpoint],
+) -> BtcGetBalanceResponse {
+    let is_reserved = |utxo: &Utxo| {
+        reserved_utxos
+            .iter()
+            .any(|reserved| reserved.outpoint == utxo.outpoint)
+    };
+    let is_frozen = |utxo: &Utxo| frozen_outpoints.contains(&utxo.outpoint);
+    utxos
+        .iter()
+        .fold(BtcGetBalanceResponse::default(), |mut balance, utxo| {
+            let confirmed = confirmations(utxo, tip_height) >= min_confirmations;
+            let reserved = is_reserved(utxo);
+            balance.total_satoshis += utxo.value;
+            if confirmed {
+                balance.confirmed_satoshis += utxo.value;
+            } else {
+                balance.unconfirmed_satoshis += utxo.value;
+            }
+            if reserved {
+                balance.reserved_satoshis += utxo.value;
+            } else if is_frozen(utxo) {
+                balance.frozen_satoshis += utxo.value;
+            } else if confirmed {
+                balance.spendable_satoshis += utxo.value;
+            }
+            balance
+        })
 }
 
 #[cfg(test)]
 mod tests {
     use ic_cdk::api::management_canister::bitcoin::Outpoint;
+    use pretty_assertions::assert_eq;
 
     // Import the outer scope
     use super::*;
@@ -241,18 +889,556 @@ mod tests {
 
     #[test]
     fn estimate_fee_returns_overhead_if_no_input_nor_output() {
-        assert_eq!(estimate_fee(0, 1000, 0), TX_OVERHEAD_VBYTES);
+        assert_eq!(
+            estimate_fee(InputType::P2wpkh, 0, 1000, &[]),
+            TX_OVERHEAD_VBYTES
+        );
     }
 
     #[test]
     fn estimate_fee_incrases_per_input_count() {
-        assert_eq!(estimate_fee(2, 1000, 2), 209);
-        assert_eq!(estimate_fee(4, 1000, 2), 345);
+        assert_eq!(
+            estimate_fee(InputType::P2wpkh, 2, 1000, &[ScriptType::P2wpkh; 2]),
+            209
+        );
+        assert_eq!(
+            estimate_fee(InputType::P2wpkh, 4, 1000, &[ScriptType::P2wpkh; 2]),
+            345
+        );
     }
 
     #[test]
     fn estimate_fee_incrases_per_output_count() {
-        assert_eq!(estimate_fee(2, 1000, 2), 209);
-        assert_eq!(estimate_fee(2, 1000, 4), 271);
+        assert_eq!(
+            estimate_fee(InputType::P2wpkh, 2, 1000, &[ScriptType::P2wpkh; 2]),
+            209
+        );
+        assert_eq!(
+            estimate_fee(InputType::P2wpkh, 2, 1000, &[ScriptType::P2wpkh; 4]),
+            271
+        );
+    }
+
+    #[test]
+    fn estimate_fee_depends_on_output_script_types() {
+        let fee = |output_type| estimate_fee(InputType::P2wpkh, 1, 1000, &[output_type]);
+
+        assert_eq!(fee(ScriptType::P2wpkh), 110);
+        assert_eq!(fee(ScriptType::P2sh), 111);
+        assert_eq!(fee(ScriptType::P2pkh), 113);
+        assert_eq!(fee(ScriptType::P2wsh), 122);
+        assert_eq!(fee(ScriptType::P2tr), 122);
+    }
+
+    #[test]
+    fn estimate_fee_depends_on_input_type() {
+        assert_eq!(
+            estimate_fee(InputType::P2trKeyPath, 2, 1000, &[ScriptType::P2tr; 2]),
+            213
+        );
+        assert_eq!(
+            estimate_fee(InputType::P2wpkh, 2, 1000, &[ScriptType::P2tr; 2]),
+            233
+        );
+    }
+
+    #[test]
+    fn script_type_of_address() {
+        let cases = [
+            ("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2", ScriptType::P2pkh),
+            ("3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy", ScriptType::P2sh),
+            (
+                "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq",
+                ScriptType::P2wpkh,
+            ),
+            (
+                "bc1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3qccfmv3",
+                ScriptType::P2wsh,
+            ),
+            (
+                "bc1p5d7rjq7g6rdk2yhzks9smlaqtedr4dekq08ge8ztwac72sfr9rusxg3297",
+                ScriptType::P2tr,
+            ),
+            ("not an address", ScriptType::P2tr),
+        ];
+
+        for (address, expected) in cases {
+            assert_eq!(ScriptType::of_address(address), expected, "{address}");
+        }
+    }
+
+    #[test]
+    fn input_type_of_address_type() {
+        assert_eq!(InputType::from(BtcAddressType::P2wpkh), InputType::P2wpkh);
+        assert_eq!(
+            InputType::from(BtcAddressType::P2tr),
+            InputType::P2trKeyPath
+        );
+        assert_eq!(
+            InputType::from(BtcAddressType::P2tr).script_type(),
+            ScriptType::P2tr
+        );
+    }
+
+    const FEE_RATE: u64 = 1_000;
+
+    fn utxo(vout: u32, value: u64) -> Utxo {
+        Utxo {
+            outpoint: Outpoint {
+                txid: vec![0xAA; 32],
+                vout,
+            },
+            value,
+            height: 100,
+        }
+    }
+
+    #[test]
+    fn branch_and_bound_finds_exact_match() {
+        // Effective values are 932, 1932 and 4932 with an input fee of 68.
+        let available_utxos = vec![utxo(0, 1_000), utxo(1, 2_000), utxo(2, 5_000)];
+
+        let selected =
+            branch_and_bound(2_864, &available_utxos, FEE_RATE, InputType::P2wpkh).unwrap();
+
+        assert_utxos_eq(selected, vec![utxo(1, 2_000), utxo(0, 1_000)]);
+    }
+
+    #[test]
+    fn branch_and_bound_prefers_lowest_waste() {
+        // At 20 sat/vB, above the long-term fee rate, the input fee is 1_360.  Both the single UTXO
+        // and the pair match, and the single UTXO wastes less despite giving away more excess.
+        let fee_rate = 20_000;
+        let available_utxos = vec![utxo(0, 2_360), utxo(1, 2_380), utxo(2, 3_410)];
+
+        let selected =
+            branch_and_bound(2_000, &available_utxos, fee_rate, InputType::P2wpkh).unwrap();
+
+        assert_utxos_eq(selected, vec![utxo(2, 3_410)]);
+    }
+
+    #[test]
+    fn branch_and_bound_spends_more_inputs_when_fees_are_low() {
+        // At 1 sat/vB, below the long-term fee rate, consolidating inputs now reduces the waste.
+        let available_utxos = vec![utxo(0, 1_000), utxo(1, 1_100), utxo(2, 2_100)];
+
+        let selected =
+            branch_and_bound(1_950, &available_utxos, FEE_RATE, InputType::P2wpkh).unwrap();
+
+        assert_utxos_eq(selected, vec![utxo(1, 1_100), utxo(0, 1_000)]);
+    }
+
+    #[test]
+    fn branch_and_bound_returns_none_without_match() {
+        // Any subset either falls short of the target or overshoots it by more than the cost of
+        // change.
+        let available_utxos = vec![utxo(0, 10_000), utxo(1, 20_000)];
+
+        assert_eq!(
+            branch_and_bound(5_000, &available_utxos, FEE_RATE, InputType::P2wpkh),
+            None
+        );
+    }
+
+    #[test]
+    fn branch_and_bound_ignores_utxos_worth_less_than_their_fee() {
+        let available_utxos = vec![utxo(0, 50), utxo(1, 68)];
+
+        assert_eq!(
+            branch_and_bound(1, &available_utxos, FEE_RATE, InputType::P2wpkh),
+            None
+        );
+    }
+
+    #[test]
+    fn select_utxos_returns_changeless_selection_when_available() {
+        // Sending 1_800 to one destination costs 42 without inputs and 68 per input.
+        let available_utxos = vec![utxo(0, 1_910), utxo(1, 5_000)];
+
+        let selection = select_utxos(
+            UtxosSelectionStrategy::MinimizeFee,
+            1_800,
+            &available_utxos,
+            FEE_RATE,
+            InputType::P2wpkh,
+            &[ScriptType::P2wpkh],
+        );
+
+        assert_eq!(
+            selection,
+            UtxosSelection {
+                input_type: InputType::P2wpkh,
+                utxos: vec![utxo(0, 1_910)],
+                fee_satoshis: 110,
+                algorithm: UtxosSelectionAlgorithm::BranchAndBound,
+                change_output: false,
+            }
+        );
+    }
+
+    #[test]
+    fn select_utxos_falls_back_to_greedy() {
+        let available_utxos = vec![utxo(0, 10_000), utxo(1, 20_000)];
+
+        let selection = select_utxos(
+            UtxosSelectionStrategy::AvoidChange,
+            5_000,
+            &available_utxos,
+            FEE_RATE,
+            InputType::P2wpkh,
+            &[ScriptType::P2wpkh],
+        );
+
+        assert_eq!(
+            selection,
+            UtxosSelection {
+                input_type: InputType::P2wpkh,
+                utxos: vec![utxo(0, 10_000)],
+                fee_satoshis: estimate_fee(
+                    InputType::P2wpkh,
+                    1,
+                    FEE_RATE,
+                    &[ScriptType::P2wpkh; 2]
+                ),
+                algorithm: UtxosSelectionAlgorithm::Greedy,
+                change_output: true,
+            }
+        );
+    }
+
+    #[test]
+    fn greedy_selection_pays_for_the_fee() {
+        let fee = estimate_fee(InputType::P2wpkh, 1, FEE_RATE, &[ScriptType::P2wpkh; 2]);
+
+        // The smallest UTXO covering the amount doesn't pay for the fee as well.
+        let selection = greedy_selection(
+            5_000,
+            &[utxo(0, 5_000 + fee - 1), utxo(1, 20_000)],
+            FEE_RATE,
+            InputType::P2wpkh,
+            &[ScriptType::P2wpkh],
+        );
+        assert_eq!(
+            selection,
+            UtxosSelection {
+                input_type: InputType::P2wpkh,
+                utxos: vec![utxo(1, 20_000)],
+                fee_satoshis: fee,
+                algorithm: UtxosSelectionAlgorithm::Greedy,
+                change_output: true,
+            }
+        );
+
+        let selection = greedy_selection(
+            5_000,
+            &[utxo(0, 5_000 + fee - 1)],
+            FEE_RATE,
+            InputType::P2wpkh,
+            &[ScriptType::P2wpkh],
+        );
+        assert!(selection.utxos.is_empty());
+        assert_eq!(selection.fee_satoshis, 0);
+    }
+
+    #[test]
+    fn select_utxos_returns_empty_selection_if_not_enough_funds() {
+        let available_utxos = vec![utxo(0, 100), utxo(1, 200)];
+
+        let selection = select_utxos(
+            UtxosSelectionStrategy::MinimizeFee,
+            1_000,
+            &available_utxos,
+            FEE_RATE,
+            InputType::P2wpkh,
+            &[ScriptType::P2wpkh],
+        );
+
+        assert!(selection.utxos.is_empty());
+        assert_eq!(selection.fee_satoshis, 0);
+        assert!(!selection.change_output);
+    }
+
+    #[test]
+    fn select_utxos_strategy_decides_between_candidates() {
+        // Greedy picks the single 10_000 UTXO with change, whereas the only changeless selection
+        // spends three UTXOs.
+        let available_utxos = vec![
+            utxo(0, 1_000),
+            utxo(1, 1_000),
+            utxo(2, 1_100),
+            utxo(3, 10_000),
+        ];
+        let target = 2_800;
+
+        let min_inputs = select_utxos(
+            UtxosSelectionStrategy::MinimizeInputs,
+            target,
+            &available_utxos,
+            FEE_RATE,
+            InputType::P2wpkh,
+            &[ScriptType::P2wpkh],
+        );
+        assert_eq!(min_inputs.algorithm, UtxosSelectionAlgorithm::Greedy);
+        assert_eq!(min_inputs.utxos.len(), 1);
+
+        let avoid_change = select_utxos(
+            UtxosSelectionStrategy::AvoidChange,
+            target,
+            &available_utxos,
+            FEE_RATE,
+            InputType::P2wpkh,
+            &[ScriptType::P2wpkh],
+        );
+        assert_eq!(
+            avoid_change.algorithm,
+            UtxosSelectionAlgorithm::BranchAndBound
+        );
+        assert_eq!(avoid_change.utxos.len(), 3);
+        assert!(!avoid_change.change_output);
+
+        // At a fee rate below the long-term rate, spending more inputs now reduces the waste.
+        let min_fee = select_utxos(
+            UtxosSelectionStrategy::MinimizeFee,
+            target,
+            &available_utxos,
+            FEE_RATE,
+            InputType::P2wpkh,
+            &[ScriptType::P2wpkh],
+        );
+        assert_eq!(min_fee.algorithm, UtxosSelectionAlgorithm::BranchAndBound);
+    }
+
+    #[test]
+    fn min_replacement_fee_pays_for_the_replacement_at_the_incremental_rate() {
+        // 1 input and 2 outputs: 68 + 2 * 31 + 11 = 141 vbytes at 1 sat/vB.
+        assert_eq!(
+            min_replacement_fee(
+                1_000,
+                InputType::P2wpkh,
+                1,
+                &[ScriptType::P2wpkh, ScriptType::P2wpkh]
+            ),
+            1_141
+        );
+        assert_eq!(
+            min_replacement_fee(1_000, InputType::P2wpkh, 3, &[ScriptType::P2tr]),
+            1_000 + 3 * 68 + 43 + 11
+        );
+    }
+
+    #[test]
+    fn balance_splits_utxos_by_confirmations_reservations_and_freezes() {
+        let utxo_at = |vout, value, height| Utxo {
+            height,
+            ..utxo(vout, value)
+        };
+        let confirmed = utxo_at(0, 1_000, 95);
+        let reserved = utxo_at(1, 2_000, 90);
+        let unconfirmed = utxo_at(2, 4_000, 100);
+        let spent = utxo_at(3, 8_000, 80);
+        let frozen = utxo_at(4, 16_000, 90);
+
+        let balance = balance(
+            &[confirmed, reserved.clone(), unconfirmed, frozen.clone()],
+            100,
+            6,
+            &[reserved, spent],
+            std::slice::from_ref(&frozen.outpoint),
+        );
+
+        assert_eq!(
+            balance,
+            BtcGetBalanceResponse {
+                total_satoshis: 23_000,
+                confirmed_satoshis: 19_000,
+                unconfirmed_satoshis: 4_000,
+                reserved_satoshis: 2_000,
+                frozen_satoshis: 16_000,
+                spendable_satoshis: 1_000,
+            }
+        );
+    }
+
+    #[test]
+    fn plan_consolidation_saves_fees_when_the_fee_rate_is_low() {
+        let mut available_utxos: Vec<Utxo> = (0..20).map(|vout| utxo(vout, 10_000)).collect();
+        // Worth less than the 68 sat of spending it.
+        available_utxos.push(utxo(20, 50));
+
+        let plan = plan_consolidation(&available_utxos, 1_000, 10_000, InputType::P2wpkh);
+
+        assert_eq!(plan.utxos.len(), 20);
+        // 20 inputs and 1 output: 20 * 68 + 31 + 11 vbytes at 1 sat/vB.
+        assert_eq!(plan.fee_satoshis, 1_402);
+        // Spending 20 inputs instead of 1 later, at 10 sat/vB.
+        assert_eq!(plan.savings_satoshis, 19 * 680 - 1_402);
+    }
+
+    #[test]
+    fn plan_consolidation_saves_nothing_when_the_fee_rate_is_high() {
+        let available_utxos: Vec<Utxo> = (0..20).map(|vout| utxo(vout, 10_000)).collect();
+
+        let plan = plan_consolidation(&available_utxos, 10_000, 1_000, InputType::P2wpkh);
+
+        assert_eq!(plan.utxos.len(), 20);
+        assert_eq!(plan.savings_satoshis, 0);
+    }
+
+    #[test]
+    fn plan_consolidation_spends_the_smallest_utxos_first() {
+        let available_utxos: Vec<Utxo> = (0..u32::try_from(MAX_CONSOLIDATION_UTXOS).unwrap() + 10)
+            .rev()
+            .map(|vout| utxo(vout, 1_000 + u64::from(vout)))
+            .collect();
+
+        let plan = plan_consolidation(&available_utxos, 1_000, 10_000, InputType::P2wpkh);
+
+        assert_eq!(plan.utxos.len(), MAX_CONSOLIDATION_UTXOS);
+        assert_eq!(plan.utxos[0].value, 1_000);
+        assert_eq!(
+            plan.utxos.last().map(|utxo| utxo.value),
+            Some(1_000 + MAX_CONSOLIDATION_UTXOS as u64 - 1)
+        );
+    }
+
+    #[test]
+    fn plan_consolidation_needs_two_utxos() {
+        let plan = plan_consolidation(&[utxo(0, 10_000)], 1_000, 10_000, InputType::P2wpkh);
+
+        assert_eq!(plan, ConsolidationPlan::default());
+    }
+
+    #[test]
+    fn manual_selection_spends_all_utxos_with_change() {
+        let utxos = vec![utxo(0, 10_000), utxo(1, 20_000)];
+
+        let selection = manual_selection(
+            5_000,
+            utxos.clone(),
+            1_000,
+            InputType::P2wpkh,
+            &[ScriptType::P2wpkh],
+        )
+        .unwrap();
+
+        assert_eq!(selection.utxos, utxos);
+        assert!(selection.change_output);
+        assert_eq!(
+            selection.fee_satoshis,
+            estimate_fee(
+                InputType::P2wpkh,
+                2,
+                1_000,
+                &[ScriptType::P2wpkh, ScriptType::P2wpkh]
+            )
+        );
+        assert_eq!(selection.algorithm, UtxosSelectionAlgorithm::Manual);
+    }
+
+    #[test]
+    fn manual_selection_pays_the_excess_as_fee_without_change() {
+        let fee_with_change = estimate_fee(
+            InputType::P2wpkh,
+            1,
+            1_000,
+            &[ScriptType::P2wpkh, ScriptType::P2wpkh],
+        );
+
+        let selection = manual_selection(
+            10_000 - fee_with_change,
+            vec![utxo(0, 10_000)],
+            1_000,
+            InputType::P2wpkh,
+            &[ScriptType::P2wpkh],
+        )
+        .unwrap();
+
+        assert!(!selection.change_output);
+        assert_eq!(selection.fee_satoshis, fee_with_change);
+    }
+
+    #[test]
+    fn manual_selection_fails_when_the_utxos_do_not_pay_for_the_fee() {
+        let selection = manual_selection(
+            10_000,
+            vec![utxo(0, 10_000)],
+            1_000,
+            InputType::P2wpkh,
+            &[ScriptType::P2wpkh],
+        );
+
+        let fee_without_change = estimate_fee(InputType::P2wpkh, 1, 1_000, &[ScriptType::P2wpkh]);
+        assert_eq!(selection, Err(10_000 + fee_without_change));
+    }
+
+    #[test]
+    fn send_max_selection_spends_all_utxos_without_change() {
+        let utxos = vec![utxo(0, 10_000), utxo(1, 20_000)];
+
+        let selection =
+            send_max_selection(utxos.clone(), 1_000, InputType::P2wpkh, &[ScriptType::P2tr])
+                .unwrap();
+
+        assert_eq!(selection.utxos, utxos);
+        assert!(!selection.change_output);
+        assert_eq!(
+            selection.fee_satoshis,
+            estimate_fee(InputType::P2wpkh, 2, 1_000, &[ScriptType::P2tr])
+        );
+        assert_eq!(selection.algorithm, UtxosSelectionAlgorithm::SendMax);
+    }
+
+    #[test]
+    fn send_max_selection_fails_when_the_utxos_do_not_pay_for_more_than_the_fee() {
+        let fee = estimate_fee(InputType::P2wpkh, 1, 1_000, &[ScriptType::P2wpkh]);
+
+        let selection = send_max_selection(
+            vec![utxo(0, fee)],
+            1_000,
+            InputType::P2wpkh,
+            &[ScriptType::P2wpkh],
+        );
+
+        assert_eq!(selection, Err(fee + 1));
+    }
+
+    #[test]
+    fn select_all_utxos_skips_utxos_not_worth_spending() {
+        let input_fee = vbytes_fee(InputType::P2wpkh.vbytes(), 10_000);
+        let utxos = vec![utxo(0, 50_000), utxo(1, input_fee), utxo(2, 30_000)];
+
+        let selection = select_all_utxos(&utxos, 10_000, InputType::P2wpkh, &[ScriptType::P2wpkh]);
+
+        assert_eq!(selection.utxos, vec![utxo(0, 50_000), utxo(2, 30_000)]);
+        assert!(!selection.change_output);
+    }
+
+    #[test]
+    fn select_all_utxos_returns_an_empty_selection_without_funds() {
+        let selection = select_all_utxos(&[], 1_000, InputType::P2wpkh, &[ScriptType::P2wpkh]);
+
+        assert!(selection.utxos.is_empty());
+        assert_eq!(selection.fee_satoshis, 0);
+    }
+
+    #[test]
+    fn dust_threshold_is_the_relay_dust_limit_at_low_fee_rates() {
+        assert_eq!(dust_threshold(InputType::P2wpkh, 1_000), 297);
+        assert_eq!(dust_threshold(InputType::P2trKeyPath, 1_000), 303);
+    }
+
+    #[test]
+    fn dust_threshold_grows_with_the_fee_rate() {
+        assert_eq!(dust_threshold(InputType::P2wpkh, 50_000), 99 * 50);
+    }
+
+    #[test]
+    fn partition_dust_keeps_utxos_worth_the_threshold() {
+        let utxos = vec![utxo(0, 296), utxo(1, 297), utxo(2, 1)];
+
+        let (spendable, dust) = partition_dust(utxos, 297);
+
+        assert_eq!(spendable, vec![utxo(1, 297)]);
+        assert_eq!(dust, vec![utxo(0, 296), utxo(2, 1)]);
     }
 }
@@ -0,0 +1,292 @@
+//! A heap cache of the UTXO sets of bitcoin addresses.
+//!
+//! Reading the UTXOs of an address pages through `bitcoin_get_utxos`, which is costly for
+//! addresses with many UTXOs.  The first page is always read, since it tells the tip of the chain,
+//! but the following pages are served from the cache as long as the tip has not moved.
+use std::{cell::RefCell, collections::HashMap};
+
+use ic_cdk::api::management_canister::bitcoin::{BitcoinNetwork, Utxo};
+
+/// The maximum number of UTXO sets in the cache.  The least recently used set is evicted to make
+/// room for a new one.
+pub const MAX_UTXO_CACHE_ENTRIES: usize = 1_000;
+
+/// The maximum number of UTXOs in the cache, summed over all its sets, which bounds its heap usage.
+/// The least recently used sets are evicted to make room for a new one, and a set larger than this
+/// is not cached at all.
+pub const MAX_UTXO_CACHE_UTXOS: usize = 100_000;
+
+thread_local! {
+    // Like the fee percentiles cache, the UTXO sets are quickly stale, so there is no need to keep
+    // them across canister upgrades.
+    static UTXO_CACHE: RefCell<UtxoCache> = RefCell::new(UtxoCache::default());
+}
+
+/// Identifies the UTXO set of an address, as filtered by its minimum number of confirmations.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UtxoCacheKey {
+    pub network: BitcoinNetwork,
+    pub address: String,
+    pub min_confirmations: Option<u32>,
+}
+
+/// The tip of the chain at which a UTXO set was read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChainTip {
+    pub height: u32,
+    pub block_hash: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct UtxoCacheEntry {
+    tip: ChainTip,
+    utxos: Vec<Utxo>,
+    /// When the entry was last used, in number of cache accesses.
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct UtxoCache {
+    entries: HashMap<UtxoCacheKey, UtxoCacheEntry>,
+    /// The number of UTXOs in the cache, summed over all its entries.
+    utxo_count: usize,
+    /// The number of cache accesses so far, used to find the least recently used entry.
+    accesses: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl UtxoCache {
+    /// Returns the cached UTXO set of the key, if it was read at the given tip.  A set read at
+    /// another tip is stale and removed.
+    pub fn get(&mut self, key: &UtxoCacheKey, tip: &ChainTip) -> Option<Vec<Utxo>> {
+        self.accesses += 1;
+        match self.entries.get_mut(key) {
+            Some(entry) if entry.tip == *tip => {
+                entry.last_used = self.accesses;
+                self.hits += 1;
+                Some(entry.utxos.clone())
+            }
+            Some(_) => {
+                self.remove(key);
+                self.misses += 1;
+                None
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Caches the UTXO set of the key, read at the given tip.  A set of more than
+    /// `MAX_UTXO_CACHE_UTXOS` UTXOs is not cached.
+    pub fn insert(&mut self, key: UtxoCacheKey, tip: ChainTip, utxos: Vec<Utxo>) {
+        self.accesses += 1;
+        self.remove(&key);
+        if utxos.len() > MAX_UTXO_CACHE_UTXOS {
+            return;
+        }
+        while self.entries.len() >= MAX_UTXO_CACHE_ENTRIES
+            || self.utxo_count + utxos.len() > MAX_UTXO_CACHE_UTXOS
+        {
+            let least_recently_used = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            match least_recently_used {
+                Some(least_recently_used) => self.remove(&least_recently_used),
+                None => break,
+            }
+        }
+        self.utxo_count += utxos.len();
+        self.entries.insert(
+            key,
+            UtxoCacheEntry {
+                tip,
+                utxos,
+                last_used: self.accesses,
+            },
+        );
+    }
+
+    /// Removes the cached UTXO sets of an address, whatever their minimum number of confirmations.
+    pub fn invalidate_address(&mut self, network: BitcoinNetwork, address: &str) {
+        self.entries
+            .retain(|key, _| key.network != network || key.address != address);
+        self.utxo_count = self.entries.values().map(|entry| entry.utxos.len()).sum();
+    }
+
+    fn remove(&mut self, key: &UtxoCacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.utxo_count -= entry.utxos.len();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn utxo_count(&self) -> usize {
+        self.utxo_count
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+}
+
+/// Calls the closure with the UTXO cache of the canister.
+pub fn with_utxo_cache<R>(f: impl FnOnce(&mut UtxoCache) -> R) -> R {
+    UTXO_CACHE.with(|cache| f(&mut cache.borrow_mut()))
+}
+
+#[cfg(test)]
+mod tests {
+    use ic_cdk::api::management_canister::bitcoin::Outpoint;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn key(address: &str, min_confirmations: Option<u32>) -> UtxoCacheKey {
+        UtxoCacheKey {
+            network: BitcoinNetwork::Mainnet,
+            address: address.to_string(),
+            min_confirmations,
+        }
+    }
+
+    fn tip(height: u32) -> ChainTip {
+        ChainTip {
+            height,
+            block_hash: vec![u8::try_from(height % 256).unwrap(); 32],
+        }
+    }
+
+    fn utxos(value: u64) -> Vec<Utxo> {
+        many_utxos(value, 1)
+    }
+
+    fn many_utxos(value: u64, count: usize) -> Vec<Utxo> {
+        (0..u32::try_from(count).unwrap())
+            .map(|vout| Utxo {
+                outpoint: Outpoint {
+                    txid: vec![0xAA; 32],
+                    vout,
+                },
+                value,
+                height: 100,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn cached_utxos_are_returned_at_the_same_tip() {
+        let mut cache = UtxoCache::default();
+        cache.insert(key("a", Some(1)), tip(100), utxos(1_000));
+
+        assert_eq!(cache.get(&key("a", Some(1)), &tip(100)), Some(utxos(1_000)));
+        assert_eq!(cache.get(&key("a", Some(6)), &tip(100)), None);
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+    }
+
+    #[test]
+    fn cached_utxos_are_removed_when_the_tip_moves() {
+        let mut cache = UtxoCache::default();
+        cache.insert(key("a", None), tip(100), utxos(1_000));
+
+        assert_eq!(cache.get(&key("a", None), &tip(101)), None);
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn cached_utxos_are_removed_on_a_reorg_at_the_same_height() {
+        let mut cache = UtxoCache::default();
+        cache.insert(key("a", None), tip(100), utxos(1_000));
+        let reorged_tip = ChainTip {
+            height: 100,
+            block_hash: vec![0xFF; 32],
+        };
+
+        assert_eq!(cache.get(&key("a", None), &reorged_tip), None);
+    }
+
+    #[test]
+    fn invalidating_an_address_removes_all_its_utxo_sets() {
+        let mut cache = UtxoCache::default();
+        cache.insert(key("a", None), tip(100), utxos(1_000));
+        cache.insert(key("a", Some(6)), tip(100), utxos(1_000));
+        cache.insert(key("b", None), tip(100), utxos(2_000));
+
+        cache.invalidate_address(BitcoinNetwork::Mainnet, "a");
+
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&key("b", None), &tip(100)), Some(utxos(2_000)));
+    }
+
+    #[test]
+    fn least_recently_used_entry_is_evicted_when_full() {
+        let mut cache = UtxoCache::default();
+        for i in 0..MAX_UTXO_CACHE_ENTRIES {
+            cache.insert(key(&i.to_string(), None), tip(100), utxos(1_000));
+        }
+        // Using the oldest entry makes the second oldest the least recently used.
+        assert!(cache.get(&key("0", None), &tip(100)).is_some());
+
+        cache.insert(key("new", None), tip(100), utxos(1_000));
+
+        assert_eq!(cache.len(), MAX_UTXO_CACHE_ENTRIES);
+        assert!(cache.get(&key("0", None), &tip(100)).is_some());
+        assert!(cache.get(&key("1", None), &tip(100)).is_none());
+        assert!(cache.get(&key("new", None), &tip(100)).is_some());
+    }
+    #[test]
+    fn least_recently_used_entries_are_evicted_to_make_room_for_large_sets() {
+        let mut cache = UtxoCache::default();
+        let half = MAX_UTXO_CACHE_UTXOS / 2;
+        cache.insert(key("a", None), tip(100), many_utxos(1_000, half));
+        cache.insert(key("b", None), tip(100), many_utxos(1_000, half));
+        assert_eq!(cache.utxo_count(), MAX_UTXO_CACHE_UTXOS);
+        // Using the oldest set makes the second oldest the least recently used.
+        assert!(cache.get(&key("a", None), &tip(100)).is_some());
+
+        cache.insert(key("c", None), tip(100), utxos(1_000));
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.utxo_count(), half + 1);
+        assert!(cache.get(&key("a", None), &tip(100)).is_some());
+        assert!(cache.get(&key("b", None), &tip(100)).is_none());
+        assert!(cache.get(&key("c", None), &tip(100)).is_some());
+    }
+
+    #[test]
+    fn sets_larger_than_the_cache_are_not_cached() {
+        let mut cache = UtxoCache::default();
+        cache.insert(key("a", None), tip(100), utxos(1_000));
+
+        cache.insert(
+            key("b", None),
+            tip(100),
+            many_utxos(1_000, MAX_UTXO_CACHE_UTXOS + 1),
+        );
+
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.utxo_count(), 1);
+        assert_eq!(cache.get(&key("b", None), &tip(100)), None);
+    }
+
+    #[test]
+    fn replacing_a_set_updates_the_utxo_count() {
+        let mut cache = UtxoCache::default();
+        cache.insert(key("a", None), tip(100), many_utxos(1_000, 3));
+        cache.insert(key("a", None), tip(101), utxos(1_000));
+
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.utxo_count(), 1);
+    }
+}
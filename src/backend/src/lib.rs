@@ -1,5 +1,5 @@
 use candid::Principal;
-use ic_cdk::{export_candid, init, post_upgrade};
+use ic_cdk::{api::management_canister::bitcoin::Outpoint, export_candid, init, post_upgrade};
 use shared::{
     http::{HttpRequest, HttpResponse},
     std_canister_status,
@@ -7,8 +7,14 @@ use shared::{
         agreement::UpdateUserAgreementsRequest,
         backend_config::{Arg, Config},
         bitcoin::{
-            BtcAddPendingTransactionRequest, BtcGetFeePercentilesRequest,
-            BtcGetPendingTransactionsRequest, SelectedUtxosFeeRequest,
+            BtcAddPendingTransactionRequest, BtcBuildPsbtRequest, BtcDepositSubscription,
+            BtcGetBalanceRequest, BtcGetConsolidationAdviceRequest, BtcGetDepositEventsRequest,
+            BtcGetDepositEventsResponse, BtcGetDustReportRequest, BtcGetFeeBumpQuotesRequest,
+            BtcGetFeeHistoryRequest, BtcGetFeeHistoryResponse, BtcGetFeePercentilesRequest,
+            BtcGetPendingTransactionsRequest, BtcGetSendHistoryRequest, BtcGetSendHistoryResponse,
+            BtcReplacePendingTransactionRequest, BtcSendTransactionRequest,
+            BtcSubscribeDepositsRequest, BtcUnsubscribeDepositsRequest,
+            BtcUpdateFrozenUtxosRequest, SelectedUtxosFeeRequest, UpdateUserBtcSettingsRequest,
         },
         contact::{CreateContactRequest, UpdateContactRequest},
         custom_token::CustomToken,
@@ -16,16 +22,24 @@ use shared::{
         experimental_feature::UpdateExperimentalFeaturesSettingsRequest,
         network::{SaveNetworksSettingsRequest, SetShowTestnetsRequest},
         result_types::{
-            AddUserCredentialResult, AddUserHiddenDappIdResult, AllowSigningResult,
-            BtcAddPendingTransactionResult, BtcGetFeePercentilesResult,
-            BtcGetPendingTransactionsResult, BtcSelectUserUtxosFeeResult, CreateContactResult,
-            DeleteContactResult, GetAllowedCyclesResult, GetContactResult, GetContactsResult,
-            GetUserProfileResult, SetUserShowTestnetsResult, UpdateContactResult,
+            AddUserCredentialResult, AddUserHiddenDappIdResult, AddWatchOnlyAddressResult,
+            AllowSigningResult, BtcAddPendingTransactionResult, BtcBuildPsbtResult,
+            BtcGetBalanceResult, BtcGetConsolidationAdviceResult, BtcGetDustReportResult,
+            BtcGetFeeBumpQuotesResult, BtcGetFeePercentilesResult, BtcGetPendingTransactionsResult,
+            BtcReplacePendingTransactionResult, BtcSelectUserUtxosFeeResult,
+            BtcSendTransactionResult, BtcSubscribeDepositsResult, BtcUpdateFrozenUtxosResult,
+            CreateContactResult, DeleteContactResult, GetAllowedCyclesResult, GetContactResult,
+            GetContactsResult, GetUserProfileResult, GetWatchOnlyBtcBalanceResult,
+            SetUserShowTestnetsResult, UpdateContactResult,
             UpdateExperimentalFeaturesSettingsResult, UpdateUserAgreementsResult,
-            UpdateUserNetworkSettingsResult,
+            UpdateUserBtcSettingsResult, UpdateUserNetworkSettingsResult,
         },
         signer::topup::{TopUpCyclesLedgerRequest, TopUpCyclesLedgerResult},
         user_profile::{AddUserCredentialRequest, HasUserProfileResponse, UserProfile},
+        watch_only::{
+            AddWatchOnlyAddressRequest, GetWatchOnlyBtcBalanceRequest,
+            RemoveWatchOnlyAddressRequest, WatchOnlyAddress,
+        },
         Stats, Timestamp,
     },
 };
@@ -41,6 +55,7 @@ mod token;
 mod types;
 mod user_profile;
 mod utils;
+mod watch_only;
 
 #[cfg(feature = "canbench-rs")]
 mod benchmark;
@@ -48,12 +63,14 @@ mod benchmark;
 #[init]
 pub fn init(arg: Arg) {
     match arg {
-        Arg::Init(arg) => set_config(arg),
+        Arg::Init(arg) => set_config(*arg),
         Arg::Upgrade => ic_cdk::trap("upgrade args in init"),
     }
 
     // Initialize the Bitcoin fee percentiles cache
     bitcoin::api::init_fee_percentiles_cache();
+    bitcoin::api::init_outpoint_reservations();
+    bitcoin::api::init_deposit_polling();
 
     utils::housekeeping::start_periodic_housekeeping_timers();
 }
@@ -66,7 +83,7 @@ pub fn init(arg: Arg) {
 #[post_upgrade]
 pub fn post_upgrade(arg: Option<Arg>) {
     match arg {
-        Some(Arg::Init(arg)) => set_config(arg),
+        Some(Arg::Init(arg)) => set_config(*arg),
         _ => {
             read_state(|s| {
                 let _ = s.config.get().as_ref().expect(
@@ -78,6 +95,8 @@ pub fn post_upgrade(arg: Option<Arg>) {
 
     // Initialize the Bitcoin fee percentiles cache
     bitcoin::api::init_fee_percentiles_cache();
+    bitcoin::api::init_outpoint_reservations();
+    bitcoin::api::init_deposit_polling();
 
     utils::housekeeping::start_periodic_housekeeping_timers();
 }
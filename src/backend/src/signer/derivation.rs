@@ -0,0 +1,160 @@
+//! Offline derivation of the public keys of the chain fusion signer.
+//!
+//! The threshold keys of the Internet Computer are derived from the master key of a canister with
+//! an extension of BIP-32 non-hardened derivation, where every component of the derivation path is
+//! an arbitrary byte string instead of a 31 bit index.  Given the master public key and chain code
+//! of the signer, the public key of any derivation path can therefore be computed locally, as in
+//! the [ckBTC minter](https://github.com/dfinity/ic/blob/35153c7cb7b9d1da60472ca7e94c693e418f87bd/rs/bitcoin/ckbtc/minter/src/address.rs#L101-L101).
+
+use bitcoin::{
+    hashes::{hmac, sha512, Hash, HashEngine},
+    secp256k1::{PublicKey, Scalar, Secp256k1},
+};
+
+/// A SEC1 compressed secp256k1 public key with its chain code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtendedPublicKey {
+    pub public_key: Vec<u8>,
+    pub chain_code: Vec<u8>,
+}
+
+impl ExtendedPublicKey {
+    /// Derives the public key of the given derivation path from this key.
+    ///
+    /// # Errors
+    /// - If this key is not a valid compressed secp256k1 public key with a 32 byte chain code.
+    pub fn derive(&self, derivation_path: &[Vec<u8>]) -> Result<ExtendedPublicKey, String> {
+        let secp = Secp256k1::verification_only();
+        let mut public_key = PublicKey::from_slice(&self.public_key)
+            .map_err(|_| "Invalid master public key".to_string())?;
+        let mut chain_code = <[u8; 32]>::try_from(self.chain_code.as_slice())
+            .map_err(|_| "Invalid master chain code".to_string())?;
+
+        for index in derivation_path {
+            let mut input = public_key.serialize().to_vec();
+            input.extend_from_slice(index);
+            // As in SLIP-10, if the tweak is out of range or the child key is the point at
+            // infinity, the derivation is retried from the right half of the HMAC output.
+            loop {
+                let (tweak, next_chain_code) = hmac_sha512(&chain_code, &input);
+                let child_key = Scalar::from_be_bytes(tweak)
+                    .ok()
+                    .and_then(|tweak| public_key.add_exp_tweak(&secp, &tweak).ok());
+                if let Some(child_key) = child_key {
+                    public_key = child_key;
+                    chain_code = next_chain_code;
+                    break;
+                }
+                input = [0x01].into_iter().chain(next_chain_code).collect();
+                input.extend_from_slice(index);
+            }
+        }
+
+        Ok(ExtendedPublicKey {
+            public_key: public_key.serialize().to_vec(),
+            chain_code: chain_code.to_vec(),
+        })
+    }
+}
+
+/// Returns the left and right halves of the HMAC-SHA512 of `data` with the given key.
+fn hmac_sha512(key: &[u8], data: &[u8]) -> ([u8; 32], [u8; 32]) {
+    let mut engine = hmac::HmacEngine::<sha512::Hash>::new(key);
+    engine.input(data);
+    let output = hmac::Hmac::<sha512::Hash>::from_engine(engine).to_byte_array();
+    let mut left = [0; 32];
+    let mut right = [0; 32];
+    left.copy_from_slice(&output[..32]);
+    right.copy_from_slice(&output[32..]);
+    (left, right)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::secp256k1::SecretKey;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    /// Derives the secret key of the given derivation path with the same scheme.
+    fn derive_secret_key(
+        secret_key: SecretKey,
+        chain_code: [u8; 32],
+        derivation_path: &[Vec<u8>],
+    ) -> SecretKey {
+        let secp = Secp256k1::new();
+        let mut secret_key = secret_key;
+        let mut chain_code = chain_code;
+        for index in derivation_path {
+            let mut input = secret_key.public_key(&secp).serialize().to_vec();
+            input.extend_from_slice(index);
+            let (tweak, next_chain_code) = hmac_sha512(&chain_code, &input);
+            secret_key = secret_key
+                .add_tweak(&Scalar::from_be_bytes(tweak).unwrap())
+                .unwrap();
+            chain_code = next_chain_code;
+        }
+        secret_key
+    }
+
+    fn master_key() -> (SecretKey, ExtendedPublicKey) {
+        let secret_key = SecretKey::from_slice(&[0x42; 32]).unwrap();
+        let public_key = ExtendedPublicKey {
+            public_key: secret_key
+                .public_key(&Secp256k1::new())
+                .serialize()
+                .to_vec(),
+            chain_code: vec![0x07; 32],
+        };
+        (secret_key, public_key)
+    }
+
+    #[test]
+    fn derive_matches_the_derived_secret_key() {
+        let (secret_key, master) = master_key();
+        let derivation_path = vec![vec![0_u8], vec![0xAB; 29]];
+
+        let derived = master.derive(&derivation_path).unwrap();
+
+        let expected = derive_secret_key(secret_key, [0x07; 32], &derivation_path);
+        assert_eq!(
+            derived.public_key,
+            expected.public_key(&Secp256k1::new()).serialize().to_vec()
+        );
+    }
+
+    #[test]
+    fn derive_depends_on_every_path_component() {
+        let (_, master) = master_key();
+
+        let derived = master.derive(&[vec![0], vec![1]]).unwrap();
+
+        assert_ne!(derived, master.derive(&[vec![0], vec![2]]).unwrap());
+        assert_ne!(derived, master.derive(&[vec![1], vec![1]]).unwrap());
+        assert_eq!(derived, master.derive(&[vec![0], vec![1]]).unwrap());
+    }
+
+    #[test]
+    fn derive_without_path_returns_the_master_key() {
+        let (_, master) = master_key();
+
+        assert_eq!(master.derive(&[]).unwrap(), master);
+    }
+
+    #[test]
+    fn derive_rejects_invalid_master_key() {
+        let (_, master) = master_key();
+
+        let invalid_key = ExtendedPublicKey {
+            public_key: vec![0x02; 12],
+            ..master.clone()
+        };
+        assert!(invalid_key.derive(&[vec![0]]).is_err());
+
+        let invalid_chain_code = ExtendedPublicKey {
+            chain_code: vec![0x07; 31],
+            ..master
+        };
+        assert!(invalid_chain_code.derive(&[vec![0]]).is_err());
+    }
+}
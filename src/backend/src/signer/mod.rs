@@ -1,7 +1,8 @@
 mod canister_ids;
+mod derivation;
 mod service;
 
 pub(crate) use service::{
-    allow_signing, approve_signing, btc_principal_to_p2wpkh_address, get_allowed_cycles,
-    has_sufficient_allowance, top_up_cycles_ledger,
+    allow_signing, approve_signing, btc_principal_to_address, btc_principal_to_p2tr_internal_key,
+    get_allowed_cycles, has_sufficient_allowance, top_up_cycles_ledger, transform_network,
 };
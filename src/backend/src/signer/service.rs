@@ -1,11 +1,14 @@
 //! Code for interacting with the chain fusion signer.
-use bitcoin::{Address, CompressedPublicKey, Network};
+use std::{cell::RefCell, collections::HashMap};
+
+use bitcoin::{key::Secp256k1, Address, CompressedPublicKey, Network, XOnlyPublicKey};
 use candid::{Nat, Principal};
 use ic_cdk::api::{
     call::call_with_payment128,
     management_canister::{
         bitcoin::BitcoinNetwork,
         ecdsa::{ecdsa_public_key, EcdsaCurve, EcdsaKeyId, EcdsaPublicKeyArgument},
+        schnorr::{schnorr_public_key, SchnorrAlgorithm, SchnorrKeyId, SchnorrPublicKeyArgument},
     },
 };
 use ic_cycles_ledger_client::{
@@ -13,15 +16,21 @@ use ic_cycles_ledger_client::{
 };
 use ic_ledger_types::Subaccount;
 use serde_bytes::ByteBuf;
-use shared::types::signer::{
-    topup::{
-        TopUpCyclesLedgerError, TopUpCyclesLedgerRequest, TopUpCyclesLedgerResponse,
-        TopUpCyclesLedgerResult,
+use shared::types::{
+    bitcoin::BtcAddressType,
+    signer::{
+        topup::{
+            TopUpCyclesLedgerError, TopUpCyclesLedgerRequest, TopUpCyclesLedgerResponse,
+            TopUpCyclesLedgerResult,
+        },
+        AllowSigningError, GetAllowedCyclesError,
     },
-    AllowSigningError, GetAllowedCyclesError,
 };
 
-use super::canister_ids::{CYCLES_LEDGER, SIGNER};
+use super::{
+    canister_ids::{CYCLES_LEDGER, SIGNER},
+    derivation::ExtendedPublicKey,
+};
 use crate::state::read_config;
 
 /// Current ledger fee in cycles.  Historically stable.
@@ -189,10 +198,69 @@ pub fn principal2account(principal: &Principal) -> ByteBuf {
         .into()
 }
 
+/// The threshold keys of the chain fusion signer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum SignerKey {
+    Ecdsa,
+    /// BIP-340 Schnorr.
+    Schnorr,
+}
+
+thread_local! {
+    // The master public keys of the chain fusion signer never change for a given key name and
+    // signer canister, so they are fetched once and kept on the heap.  They are keyed by the
+    // config values so that a change of configuration fetches new keys.
+    static CFS_MASTER_PUBKEYS: RefCell<HashMap<(SignerKey, String, Principal), ExtendedPublicKey>> = RefCell::new(HashMap::new());
+}
+
+/// Fetches the public key of the given derivation path of the chain fusion signer.
+async fn cfs_pubkey(
+    signer_key: SignerKey,
+    key_name: String,
+    cfs_canister_id: Principal,
+    derivation_path: Vec<Vec<u8>>,
+) -> Result<ExtendedPublicKey, String> {
+    match signer_key {
+        SignerKey::Ecdsa => ecdsa_public_key(EcdsaPublicKeyArgument {
+            canister_id: Some(cfs_canister_id),
+            derivation_path,
+            key_id: EcdsaKeyId {
+                curve: EcdsaCurve::Secp256k1,
+                name: key_name,
+            },
+        })
+        .await
+        .map(|(key,)| ExtendedPublicKey {
+            public_key: key.public_key,
+            chain_code: key.chain_code,
+        })
+        .map_err(|_| "Failed to get ecdsa public key".to_string()),
+        SignerKey::Schnorr => schnorr_public_key(SchnorrPublicKeyArgument {
+            canister_id: Some(cfs_canister_id),
+            derivation_path,
+            key_id: SchnorrKeyId {
+                algorithm: SchnorrAlgorithm::Bip340secp256k1,
+                name: key_name,
+            },
+        })
+        .await
+        .map(|(key,)| ExtendedPublicKey {
+            public_key: key.public_key,
+            chain_code: key.chain_code,
+        })
+        .map_err(|_| "Failed to get schnorr public key".to_string()),
+    }
+}
+
 /// Computes the public key of the specified principal.
-// TODO: Cache CFS pubkey and derive it offline as in [ckBTC minter](https://github.com/dfinity/ic/blob/35153c7cb7b9d1da60472ca7e94c693e418f87bd/rs/bitcoin/ckbtc/minter/src/address.rs#L101-L101)
-async fn cfs_ecdsa_pubkey_of(principal: &Principal) -> Result<Vec<u8>, String> {
-    let (ecdsa_key_name, maybe_cfs_canister_id) =
+///
+/// The key is derived locally from the cached master public key of the signer, as in the
+/// [ckBTC minter](https://github.com/dfinity/ic/blob/35153c7cb7b9d1da60472ca7e94c693e418f87bd/rs/bitcoin/ckbtc/minter/src/address.rs#L101-L101).
+/// When the master key is not cached yet, it is fetched together with the key of the principal,
+/// and only cached if the local derivation matches the key returned by the signer.
+async fn cfs_pubkey_of(signer_key: SignerKey, principal: &Principal) -> Result<Vec<u8>, String> {
+    // The threshold Schnorr keys have the same names as the threshold ECDSA keys.
+    let (key_name, maybe_cfs_canister_id) =
         read_config(|s| (s.ecdsa_key_name.clone(), s.cfs_canister_id));
     // As set in [CFS](https://github.com/dfinity/chain-fusion-signer/blob/26b683c6de9971fdbf7bd4cebc04d427d1753289/src/signer/canister/src/derivation_path.rs#L6)
     // 0 is for BTC
@@ -201,23 +269,33 @@ async fn cfs_ecdsa_pubkey_of(principal: &Principal) -> Result<Vec<u8>, String> {
     let btc_schema = vec![0_u8];
     let derivation_path = vec![btc_schema, principal.as_slice().to_vec()];
     let cfs_canister_id = maybe_cfs_canister_id.ok_or("Missing CFS canister id")?;
-    if let Ok((key,)) = ecdsa_public_key(EcdsaPublicKeyArgument {
-        canister_id: Some(cfs_canister_id),
-        derivation_path,
-        key_id: EcdsaKeyId {
-            curve: EcdsaCurve::Secp256k1,
-            name: ecdsa_key_name,
-        },
-    })
-    .await
+    let cache_key = (signer_key, key_name.clone(), cfs_canister_id);
+
+    if let Some(master_key) = CFS_MASTER_PUBKEYS.with(|keys| keys.borrow().get(&cache_key).cloned())
     {
-        Ok(key.public_key)
-    } else {
-        Err("Failed to get ecdsa public key".to_string())
+        return Ok(master_key.derive(&derivation_path)?.public_key);
+    }
+
+    let master_key = cfs_pubkey(signer_key, key_name.clone(), cfs_canister_id, vec![]).await?;
+    let live_key = cfs_pubkey(
+        signer_key,
+        key_name,
+        cfs_canister_id,
+        derivation_path.clone(),
+    )
+    .await?
+    .public_key;
+    // On a mismatch the live key is used and the master key is fetched again on the next call.
+    if master_key
+        .derive(&derivation_path)
+        .is_ok_and(|derived| derived.public_key == live_key)
+    {
+        CFS_MASTER_PUBKEYS.with(|keys| keys.borrow_mut().insert(cache_key, master_key));
     }
+    Ok(live_key)
 }
 
-fn transform_network(network: BitcoinNetwork) -> Network {
+pub fn transform_network(network: BitcoinNetwork) -> Network {
     match network {
         BitcoinNetwork::Mainnet => Network::Bitcoin,
         BitcoinNetwork::Testnet => Network::Testnet,
@@ -233,7 +311,7 @@ pub async fn btc_principal_to_p2wpkh_address(
     network: BitcoinNetwork,
     principal: &Principal,
 ) -> Result<String, String> {
-    let ecdsa_pubkey = cfs_ecdsa_pubkey_of(principal).await?;
+    let ecdsa_pubkey = cfs_pubkey_of(SignerKey::Ecdsa, principal).await?;
     if let Ok(compressed_public_key) = CompressedPublicKey::from_slice(&ecdsa_pubkey) {
         Ok(Address::p2wpkh(&compressed_public_key, transform_network(network)).to_string())
     } else {
@@ -241,6 +319,56 @@ pub async fn btc_principal_to_p2wpkh_address(
     }
 }
 
+/// Converts the BIP-340 Schnorr public key of the principal to a P2TR address without script
+/// path, as specified by BIP-86.
+///
+/// # Errors
+/// - It was not possible to get the P2TR from the public key.
+pub async fn btc_principal_to_p2tr_address(
+    network: BitcoinNetwork,
+    principal: &Principal,
+) -> Result<String, String> {
+    let internal_key = btc_principal_to_p2tr_internal_key(principal)
+        .await
+        .map_err(|_| "Error getting P2TR from public key".to_string())?;
+    Ok(Address::p2tr(
+        &Secp256k1::verification_only(),
+        internal_key,
+        None,
+        transform_network(network),
+    )
+    .to_string())
+}
+
+/// Returns the x-only internal key of the P2TR address of the principal, which key-path signers
+/// need to sign its inputs.
+///
+/// # Errors
+/// - It was not possible to get the x-only key from the public key.
+pub async fn btc_principal_to_p2tr_internal_key(
+    principal: &Principal,
+) -> Result<XOnlyPublicKey, String> {
+    let schnorr_pubkey = cfs_pubkey_of(SignerKey::Schnorr, principal).await?;
+    // The public key is SEC1 encoded: the x-only key follows the parity byte.
+    XOnlyPublicKey::from_slice(schnorr_pubkey.get(1..).unwrap_or_default())
+        .map_err(|_| "Error getting the x-only key from public key".to_string())
+}
+
+/// Converts the public key of the principal to an address of the given type.
+///
+/// # Errors
+/// - It was not possible to get the address from the public key.
+pub async fn btc_principal_to_address(
+    network: BitcoinNetwork,
+    principal: &Principal,
+    address_type: BtcAddressType,
+) -> Result<String, String> {
+    match address_type {
+        BtcAddressType::P2wpkh => btc_principal_to_p2wpkh_address(network, principal).await,
+        BtcAddressType::P2tr => btc_principal_to_p2tr_address(network, principal).await,
+    }
+}
+
 /// Tops up the backend canister account on the cycles ledger.
 ///
 /// # Context
@@ -14,6 +14,13 @@ pub(crate) const POW_CHALLENGE_MEMORY_ID: MemoryId = MemoryId::new(5);
 pub(crate) const CONTACT_MEMORY_ID: MemoryId = MemoryId::new(6);
 pub(crate) const BTC_USER_PENDING_TRANSACTIONS_MEMORY_ID: MemoryId = MemoryId::new(7);
 pub(crate) const TOKEN_ACTIVITY_MEMORY_ID: MemoryId = MemoryId::new(8);
+pub(crate) const BTC_FEE_HISTORY_MEMORY_ID: MemoryId = MemoryId::new(9);
+pub(crate) const BTC_OUTPOINT_RESERVATION_MEMORY_ID: MemoryId = MemoryId::new(10);
+pub(crate) const BTC_FROZEN_UTXOS_MEMORY_ID: MemoryId = MemoryId::new(11);
+pub(crate) const BTC_SEND_HISTORY_MEMORY_ID: MemoryId = MemoryId::new(12);
+pub(crate) const WATCH_ONLY_ADDRESS_MEMORY_ID: MemoryId = MemoryId::new(13);
+pub(crate) const BTC_DEPOSIT_SUBSCRIPTION_MEMORY_ID: MemoryId = MemoryId::new(14);
+pub(crate) const BTC_DEPOSIT_EVENT_MEMORY_ID: MemoryId = MemoryId::new(15);
 
 thread_local! {
     pub(crate) static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
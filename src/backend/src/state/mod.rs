@@ -7,14 +7,18 @@ use shared::types::{
 
 use crate::{
     state::memory::{
+        BTC_DEPOSIT_EVENT_MEMORY_ID, BTC_DEPOSIT_SUBSCRIPTION_MEMORY_ID, BTC_FEE_HISTORY_MEMORY_ID,
+        BTC_FROZEN_UTXOS_MEMORY_ID, BTC_OUTPOINT_RESERVATION_MEMORY_ID, BTC_SEND_HISTORY_MEMORY_ID,
         BTC_USER_PENDING_TRANSACTIONS_MEMORY_ID, CONFIG_MEMORY_ID, CONTACT_MEMORY_ID,
         MEMORY_MANAGER, POW_CHALLENGE_MEMORY_ID, TOKEN_ACTIVITY_MEMORY_ID,
         USER_CUSTOM_TOKEN_MEMORY_ID, USER_PROFILE_MEMORY_ID, USER_PROFILE_UPDATED_MEMORY_ID,
-        USER_TOKEN_MEMORY_ID,
+        USER_TOKEN_MEMORY_ID, WATCH_ONLY_ADDRESS_MEMORY_ID,
     },
     types::{
-        BtcUserPendingTransactionsMap, Candid, ConfigCell, ContactMap, CustomTokenMap,
-        PowChallengeMap, TokenActivityMap, UserProfileMap, UserProfileUpdatedMap, UserTokenMap,
+        BtcDepositEventMap, BtcDepositSubscriptionMap, BtcFeeHistoryMap, BtcFrozenUtxosMap,
+        BtcOutpointReservationMap, BtcSendHistoryMap, BtcUserPendingTransactionsMap, Candid,
+        ConfigCell, ContactMap, CustomTokenMap, PowChallengeMap, TokenActivityMap, UserProfileMap,
+        UserProfileUpdatedMap, UserTokenMap, WatchOnlyAddressMap,
     },
 };
 
@@ -36,9 +40,27 @@ pub(crate) struct State {
     pub(crate) pow_challenge: PowChallengeMap,
     pub(crate) contact: ContactMap,
     pub(crate) btc_user_pending_transactions: BtcUserPendingTransactionsMap,
+    /// Index of the outpoints reserved by the pending transactions of
+    /// `btc_user_pending_transactions`.  Use `BtcUserPendingTransactionsModel` to keep both
+    /// consistent.
+    pub(crate) btc_outpoint_reservations: BtcOutpointReservationMap,
     // TODO: implement a periodic cleanup of old entries
     // TODO: limit the map size with an eviction policy
     pub(crate) token_activity: TokenActivityMap,
+    /// Bounded history of the bitcoin fee rates, kept across upgrades unlike the fee percentiles
+    /// cache.
+    pub(crate) btc_fee_history: BtcFeeHistoryMap,
+    /// The UTXOs that each user froze, see `bitcoin::frozen_utxos`.
+    pub(crate) btc_frozen_utxos: BtcFrozenUtxosMap,
+    /// Bounded history of the outgoing bitcoin transactions of each user, kept after their pending
+    /// transactions are pruned.
+    pub(crate) btc_send_history: BtcSendHistoryMap,
+    /// The addresses that each user watches without holding their keys, see `watch_only`.
+    pub(crate) watch_only_addresses: WatchOnlyAddressMap,
+    /// The addresses of each user that are polled for incoming deposits, see `bitcoin::deposits`.
+    pub(crate) btc_deposit_subscriptions: BtcDepositSubscriptionMap,
+    /// Bounded feed of the incoming deposits to the subscribed addresses of each user.
+    pub(crate) btc_deposit_events: BtcDepositEventMap,
 }
 
 impl From<&State> for Stats {
@@ -67,7 +89,22 @@ thread_local! {
             btc_user_pending_transactions: BtcUserPendingTransactionsMap::init(
                 mm.borrow().get(BTC_USER_PENDING_TRANSACTIONS_MEMORY_ID),
             ),
+            btc_outpoint_reservations: BtcOutpointReservationMap::init(
+                mm.borrow().get(BTC_OUTPOINT_RESERVATION_MEMORY_ID),
+            ),
             token_activity: TokenActivityMap::init(mm.borrow().get(TOKEN_ACTIVITY_MEMORY_ID)),
+            btc_fee_history: BtcFeeHistoryMap::init(mm.borrow().get(BTC_FEE_HISTORY_MEMORY_ID)),
+            btc_frozen_utxos: BtcFrozenUtxosMap::init(mm.borrow().get(BTC_FROZEN_UTXOS_MEMORY_ID)),
+            btc_send_history: BtcSendHistoryMap::init(mm.borrow().get(BTC_SEND_HISTORY_MEMORY_ID)),
+            watch_only_addresses: WatchOnlyAddressMap::init(
+                mm.borrow().get(WATCH_ONLY_ADDRESS_MEMORY_ID),
+            ),
+            btc_deposit_subscriptions: BtcDepositSubscriptionMap::init(
+                mm.borrow().get(BTC_DEPOSIT_SUBSCRIPTION_MEMORY_ID),
+            ),
+            btc_deposit_events: BtcDepositEventMap::init(
+                mm.borrow().get(BTC_DEPOSIT_EVENT_MEMORY_ID),
+            ),
         })
     );
 }
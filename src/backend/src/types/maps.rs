@@ -1,15 +1,25 @@
 use std::collections::HashMap;
 
+use ic_cdk::api::management_canister::bitcoin::Outpoint;
 use ic_stable_structures::{
     memory_manager::VirtualMemory, DefaultMemoryImpl, StableBTreeMap, StableCell,
 };
 use shared::types::{
-    backend_config::Config, bitcoin::StoredPendingTransaction, contact::StoredContacts,
-    custom_token::CustomToken, pow::StoredChallenge, token::UserToken,
-    user_profile::StoredUserProfile, Timestamp,
+    backend_config::Config,
+    bitcoin::{
+        BtcDepositEvent, BtcDepositSubscription, BtcSentTransaction, StoredOutpointReservation,
+        StoredPendingTransaction,
+    },
+    contact::StoredContacts,
+    custom_token::CustomToken,
+    pow::StoredChallenge,
+    token::UserToken,
+    user_profile::StoredUserProfile,
+    watch_only::WatchOnlyAddress,
+    Timestamp,
 };
 
-use crate::types::storable::{Candid, StoredPrincipal, StoredTokenId};
+use crate::types::storable::{Candid, StoredOutpoint, StoredPrincipal, StoredTokenId};
 
 pub type VMem = VirtualMemory<DefaultMemoryImpl>;
 
@@ -36,4 +46,33 @@ pub type PendingTransactionsMap = HashMap<String, Vec<StoredPendingTransaction>>
 pub type BtcUserPendingTransactionsMap =
     StableBTreeMap<StoredPrincipal, Candid<PendingTransactionsMap>, VMem>;
 
+/// Map of `outpoint` to the pending transaction that reserves it, across all principals.
+pub type BtcOutpointReservationMap =
+    StableBTreeMap<StoredOutpoint, Candid<StoredOutpointReservation>, VMem>;
+
+/// Map of `user_principal` to the outpoints frozen by the user, which are never selected to be
+/// spent.
+pub type BtcFrozenUtxosMap = StableBTreeMap<StoredPrincipal, Candid<Vec<Outpoint>>, VMem>;
+
+/// Map of (`user_principal`, `id`) to an outgoing transaction of the user.  See
+/// `bitcoin::send_history`.
+pub type BtcSendHistoryMap =
+    StableBTreeMap<(StoredPrincipal, u64), Candid<BtcSentTransaction>, VMem>;
+
 pub type TokenActivityMap = StableBTreeMap<StoredTokenId, Timestamp, VMem>;
+
+/// Map of (`network`, `timestamp`) to the median fee rate of the network at that time, in
+/// millisatoshi per vbyte.  See `bitcoin::fee_history` for the network keys.
+pub type BtcFeeHistoryMap = StableBTreeMap<(u8, Timestamp), u64, VMem>;
+
+/// Map of `user_principal` to the addresses watched by the user, in the order they were added.
+/// See `watch_only`.
+pub type WatchOnlyAddressMap = StableBTreeMap<StoredPrincipal, Candid<Vec<WatchOnlyAddress>>, VMem>;
+
+/// Map of `user_principal` to the addresses of the user that are polled for incoming deposits.
+/// See `bitcoin::deposits`.
+pub type BtcDepositSubscriptionMap =
+    StableBTreeMap<StoredPrincipal, Candid<Vec<BtcDepositSubscription>>, VMem>;
+
+/// Map of (`user_principal`, `id`) to an incoming deposit of the user.  See `bitcoin::deposits`.
+pub type BtcDepositEventMap = StableBTreeMap<(StoredPrincipal, u64), Candid<BtcDepositEvent>, VMem>;
@@ -3,8 +3,10 @@ pub(crate) mod storable;
 
 pub(crate) use self::{
     maps::{
-        BtcUserPendingTransactionsMap, ConfigCell, ContactMap, CustomTokenMap, PowChallengeMap,
-        TokenActivityMap, UserProfileMap, UserProfileUpdatedMap, UserTokenMap, VMem,
+        BtcDepositEventMap, BtcDepositSubscriptionMap, BtcFeeHistoryMap, BtcFrozenUtxosMap,
+        BtcOutpointReservationMap, BtcSendHistoryMap, BtcUserPendingTransactionsMap, ConfigCell,
+        ContactMap, CustomTokenMap, PowChallengeMap, TokenActivityMap, UserProfileMap,
+        UserProfileUpdatedMap, UserTokenMap, VMem, WatchOnlyAddressMap,
     },
-    storable::{Candid, StoredPrincipal, StoredTokenId},
+    storable::{Candid, StoredOutpoint, StoredPrincipal, StoredTokenId},
 };
@@ -1,6 +1,7 @@
-use std::{borrow::Cow, ops::Deref};
+use std::{borrow::Cow, cmp::Ordering, ops::Deref};
 
 use candid::{decode_one, encode_one, CandidType, Deserialize, Principal};
+use ic_cdk::api::management_canister::bitcoin::Outpoint;
 use ic_stable_structures::storable::{Blob, Bound, Storable};
 use shared::types::custom_token::CustomTokenId;
 
@@ -86,3 +87,41 @@ impl Storable for StoredTokenId {
         Self(decode_one(bytes.as_ref()).expect("failed to candid-decode CustomTokenId"))
     }
 }
+
+/// A bitcoin outpoint, stored as its 32 byte txid followed by its output index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredOutpoint(pub Outpoint);
+
+impl Ord for StoredOutpoint {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (&self.0.txid, self.0.vout).cmp(&(&other.0.txid, other.0.vout))
+    }
+}
+
+impl PartialOrd for StoredOutpoint {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Storable for StoredOutpoint {
+    const BOUND: Bound = <(Blob<32>, u32)>::BOUND;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let txid = Blob::<32>::try_from(self.0.txid.as_slice())
+            .expect("txid length should not exceed 32 bytes");
+        Cow::Owned((txid, self.0.vout).into_bytes())
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        self.to_bytes().to_vec()
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        let (txid, vout) = <(Blob<32>, u32)>::from_bytes(bytes);
+        Self(Outpoint {
+            txid: txid.as_slice().to_vec(),
+            vout,
+        })
+    }
+}
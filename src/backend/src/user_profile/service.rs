@@ -3,6 +3,7 @@ use std::result::Result;
 use ic_cdk::api::time;
 use shared::types::{
     agreement::{UpdateAgreementsError, UserAgreements},
+    bitcoin::{BtcSettings, UpdateUserBtcSettingsError},
     dapp::AddDappSettingsError,
     experimental_feature::{
         ExperimentalFeatureSettingsMap, UpdateExperimentalFeaturesSettingsError,
@@ -211,3 +212,43 @@ pub fn update_experimental_feature_settings(
     user_profile_model.store_new(principal, now, &new_profile);
     Ok(())
 }
+
+/// Updates the user's bitcoin settings.
+///
+/// # Arguments
+/// * `principal` - The principal of the user.
+/// * `profile_version` - The version of the user's profile.
+/// * `btc` - The new bitcoin settings to save.
+/// * `user_profile_model` - The user profile model.
+///
+/// # Returns
+/// - Returns `Ok(())` if the settings were successfully updated or no change was needed.
+///
+/// # Errors
+/// - Returns `Err` if the user profile is not found, or the user profile version is not up-to-date.
+pub fn update_btc_settings(
+    principal: StoredPrincipal,
+    profile_version: Option<Version>,
+    btc: BtcSettings,
+    user_profile_model: &mut UserProfileModel,
+) -> Result<(), UpdateUserBtcSettingsError> {
+    let user_profile = find_profile(principal, user_profile_model)
+        .map_err(|_| UpdateUserBtcSettingsError::UserNotFound)?;
+    let now = time();
+    let new_profile = user_profile.with_btc_settings(profile_version, now, btc)?;
+    user_profile_model.store_new(principal, now, &new_profile);
+    Ok(())
+}
+
+/// Returns the user's bitcoin settings, or the default settings if the user has no profile or
+/// has never set them.
+pub fn find_btc_settings(
+    principal: StoredPrincipal,
+    user_profile_model: &UserProfileModel,
+) -> BtcSettings {
+    find_profile(principal, user_profile_model)
+        .ok()
+        .and_then(|profile| profile.settings)
+        .and_then(|settings| settings.btc)
+        .unwrap_or_default()
+}
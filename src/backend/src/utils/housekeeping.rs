@@ -1,16 +1,29 @@
 use std::{cell::RefCell, time::Duration};
 
-use ic_cdk::api::time;
+use candid::Principal;
+use ic_cdk::api::{instruction_counter, time};
 use ic_cdk_timers::{set_timer, set_timer_interval};
 use shared::types::signer::topup::TopUpCyclesLedgerResult;
 
 use super::rate_limiter;
-use crate::{api, signer, types::StoredPrincipal};
+use crate::{
+    api,
+    bitcoin::pending_tx_model::BtcUserPendingTransactionsModel,
+    signer,
+    state::{mutate_state, read_config},
+    types::StoredPrincipal,
+};
 
 thread_local! {
     /// `None` means idle; `Some(ns)` is the IC timestamp when the current run started.
     static HOUSEKEEPING_STARTED_AT: RefCell<Option<u64>> = const { RefCell::new(None) };
     static ALLOW_SIGNING_IN_PROGRESS: RefCell<u32> = const { RefCell::new(0) };
+    /// Number of stale pending bitcoin transactions removed by the sweep since the last upgrade.
+    static SWEPT_BTC_PENDING_TRANSACTIONS: RefCell<u64> = const { RefCell::new(0) };
+    /// `None` means idle; `Some(ns)` is the IC timestamp when the current sweep of the stale
+    /// pending bitcoin transactions started.
+    static BTC_PENDING_TRANSACTIONS_SWEEP_STARTED_AT: RefCell<Option<u64>> =
+        const { RefCell::new(None) };
 
     /// High-frequency guard rate limiter checked **before** any inter-canister
     /// call.  Designed to cheaply reject rapid-fire requests that would otherwise
@@ -27,6 +40,34 @@ thread_local! {
     /// Rate-limits `btc_select_user_utxos_fee`: max 10 calls per caller per minute.
     pub(crate) static BTC_SELECT_UTXOS_FEE_RATE_LIMITER: rate_limiter::RateLimiter =
         rate_limiter::RateLimiter::new(10, 60 * 1_000_000_000);
+
+    /// Rate-limits `btc_build_psbt`: max 10 calls per caller per minute.
+    pub(crate) static BTC_BUILD_PSBT_RATE_LIMITER: rate_limiter::RateLimiter =
+        rate_limiter::RateLimiter::new(10, 60 * 1_000_000_000);
+
+    /// Rate-limits `btc_get_consolidation_advice`: max 10 calls per caller per minute.
+    pub(crate) static BTC_CONSOLIDATION_ADVICE_RATE_LIMITER: rate_limiter::RateLimiter =
+        rate_limiter::RateLimiter::new(10, 60 * 1_000_000_000);
+
+    /// Rate-limits `btc_get_balance`: max 10 calls per caller per minute.
+    pub(crate) static BTC_GET_BALANCE_RATE_LIMITER: rate_limiter::RateLimiter =
+        rate_limiter::RateLimiter::new(10, 60 * 1_000_000_000);
+
+    /// Rate-limits `btc_get_dust_report`: max 10 calls per caller per minute.
+    pub(crate) static BTC_GET_DUST_REPORT_RATE_LIMITER: rate_limiter::RateLimiter =
+        rate_limiter::RateLimiter::new(10, 60 * 1_000_000_000);
+
+    /// Rate-limits `btc_send_transaction`: max 10 calls per caller per minute.
+    pub(crate) static BTC_SEND_TRANSACTION_RATE_LIMITER: rate_limiter::RateLimiter =
+        rate_limiter::RateLimiter::new(10, 60 * 1_000_000_000);
+
+    /// Rate-limits `btc_subscribe_deposits`: max 10 calls per caller per minute.
+    pub(crate) static BTC_SUBSCRIBE_DEPOSITS_RATE_LIMITER: rate_limiter::RateLimiter =
+        rate_limiter::RateLimiter::new(10, 60 * 1_000_000_000);
+
+    /// Rate-limits `get_watch_only_btc_balance`: max 10 calls per caller per minute.
+    pub(crate) static WATCH_ONLY_BTC_BALANCE_RATE_LIMITER: rate_limiter::RateLimiter =
+        rate_limiter::RateLimiter::new(10, 60 * 1_000_000_000);
 }
 
 /// 2 hours in nanoseconds — if a housekeeping run has been in progress for
@@ -36,6 +77,10 @@ const HOUSEKEEPING_TIMEOUT_NS: u64 = 2 * 60 * 60 * 1_000_000_000;
 
 pub(crate) const MAX_CONCURRENT_ALLOW_SIGNING: u32 = 50;
 
+/// Instructions that a chunk of the sweep of the stale pending bitcoin transactions may use before
+/// the sweep yields to the next message.  Well below the instruction limit of a message.
+const BTC_PENDING_TRANSACTIONS_SWEEP_CHUNK_INSTRUCTIONS: u64 = 1_000_000_000;
+
 /// Returns `true` if a housekeeping run is currently in flight and has not
 /// timed out.  If the lock has been held longer than `HOUSEKEEPING_TIMEOUT_NS`,
 /// logs a warning and returns `false` so the caller can force a new run.
@@ -149,9 +194,71 @@ pub(crate) fn start_periodic_housekeeping_timers() {
     let _ = set_timer_interval(hour, spawn_housekeeping_if_idle);
 }
 
+/// Returns the number of stale pending bitcoin transactions removed by the sweep since the last
+/// upgrade.
+pub(crate) fn swept_btc_pending_transactions() -> u64 {
+    SWEPT_BTC_PENDING_TRANSACTIONS.with(|cell| *cell.borrow())
+}
+
+/// Marks a sweep of the stale pending bitcoin transactions as started at `now_ns`, unless one is
+/// still in flight.  Returns `true` if the new sweep may proceed.
+///
+/// As for housekeeping, a sweep in flight for longer than `HOUSEKEEPING_TIMEOUT_NS` is considered
+/// stuck, e.g. because a chunk trapped, and a new one is allowed to proceed.
+fn try_start_btc_pending_transactions_sweep(now_ns: u64) -> bool {
+    BTC_PENDING_TRANSACTIONS_SWEEP_STARTED_AT.with(|cell| {
+        let mut started_at = cell.borrow_mut();
+        if started_at
+            .is_some_and(|started| now_ns.saturating_sub(started) <= HOUSEKEEPING_TIMEOUT_NS)
+        {
+            return false;
+        }
+        *started_at = Some(now_ns);
+        true
+    })
+}
+
+/// Sweeps the stale pending bitcoin transactions of all users, starting after `start_after`.
+///
+/// Pending transactions are otherwise only pruned when their user calls the bitcoin endpoints
+/// again.  Every chunk stops after `BTC_PENDING_TRANSACTIONS_SWEEP_CHUNK_INSTRUCTIONS` and
+/// schedules the next one, so that a large map never exceeds the instruction limit.
+fn sweep_stale_btc_pending_transactions(start_after: Option<Principal>) {
+    let now_ns = time();
+    let ttls = read_config(|config| config.btc_pending_transaction_ttls);
+    let (removed, resume_after) = mutate_state(|state| {
+        BtcUserPendingTransactionsModel::new(
+            &mut state.btc_user_pending_transactions,
+            &mut state.btc_outpoint_reservations,
+            None,
+            None,
+            ttls,
+        )
+        .sweep_stale_transactions(start_after, now_ns, || {
+            instruction_counter() < BTC_PENDING_TRANSACTIONS_SWEEP_CHUNK_INSTRUCTIONS
+        })
+    });
+    SWEPT_BTC_PENDING_TRANSACTIONS.with(|cell| *cell.borrow_mut() += removed as u64);
+
+    if let Some(resume_after) = resume_after {
+        set_timer(Duration::ZERO, move || {
+            sweep_stale_btc_pending_transactions(Some(resume_after));
+        });
+    } else {
+        BTC_PENDING_TRANSACTIONS_SWEEP_STARTED_AT.with(|cell| *cell.borrow_mut() = None);
+    }
+}
+
 /// Runs hourly housekeeping tasks:
+/// - Sweep the stale pending bitcoin transactions.
 /// - Top up the cycles ledger.
 async fn hourly_housekeeping_tasks() {
+    // Sweeps the stale pending bitcoin transactions, in chunks run by timers, unless the previous
+    // sweep is still running.
+    if try_start_btc_pending_transactions_sweep(time()) {
+        sweep_stale_btc_pending_transactions(None);
+    }
+
     // Tops up the account on the cycles ledger
     {
         let result = api::signer::top_up_cycles_ledger(None).await;
@@ -175,6 +282,10 @@ mod tests {
         HOUSEKEEPING_STARTED_AT.with(|cell| *cell.borrow_mut() = Some(ns));
     }
 
+    fn reset_btc_pending_transactions_sweep() {
+        BTC_PENDING_TRANSACTIONS_SWEEP_STARTED_AT.with(|cell| *cell.borrow_mut() = None);
+    }
+
     fn reset_allow_signing() {
         ALLOW_SIGNING_IN_PROGRESS.with(|cell| *cell.borrow_mut() = 0);
     }
@@ -227,6 +338,36 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_btc_pending_transactions_sweep_is_not_started_twice() {
+        reset_btc_pending_transactions_sweep();
+        let start = 1_000_000_000u64;
+
+        assert!(try_start_btc_pending_transactions_sweep(start));
+        assert!(
+            !try_start_btc_pending_transactions_sweep(start + HOUSEKEEPING_TIMEOUT_NS),
+            "should skip while the previous sweep is in flight"
+        );
+
+        reset_btc_pending_transactions_sweep();
+        assert!(
+            try_start_btc_pending_transactions_sweep(start + 1),
+            "should start once the previous sweep finished"
+        );
+    }
+
+    #[test]
+    fn test_btc_pending_transactions_sweep_restarts_after_timeout() {
+        reset_btc_pending_transactions_sweep();
+        let start = 1_000_000_000u64;
+
+        assert!(try_start_btc_pending_transactions_sweep(start));
+        assert!(
+            try_start_btc_pending_transactions_sweep(start + HOUSEKEEPING_TIMEOUT_NS + 1),
+            "should restart a sweep that appears stuck"
+        );
+    }
+
     #[test]
     fn test_allow_signing_acquire_and_release() {
         reset_allow_signing();
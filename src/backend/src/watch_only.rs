@@ -0,0 +1,227 @@
+//! Addresses watched by their owner without holding their keys.
+//!
+//! A user can watch BTC, ETH, SOL and Kaspa addresses, e.g. of cold storage, with a label.  Every
+//! user has at most `MAX_WATCH_ONLY_ADDRESSES` of them.
+
+use std::str::FromStr;
+
+use candid::Principal;
+use shared::types::{
+    account::TokenAccountId,
+    watch_only::{WatchOnlyAddress, WatchOnlyAddressError, MAX_WATCH_ONLY_ADDRESSES},
+};
+
+use crate::types::{Candid, StoredPrincipal, WatchOnlyAddressMap};
+
+/// Parses a BTC, ETH, SOL or Kaspa address.
+///
+/// # Errors
+/// - If the address is not one of these, e.g. if it is an ICRC account.
+pub fn parse_watch_only_address(address: &str) -> Result<TokenAccountId, WatchOnlyAddressError> {
+    match TokenAccountId::from_str(address) {
+        Ok(TokenAccountId::Icrcv2(_)) | Err(_) => Err(WatchOnlyAddressError::InvalidAddress),
+        Ok(token_account_id) => Ok(token_account_id),
+    }
+}
+
+/// Returns the addresses watched by the user, in the order they were added.
+pub fn get_watch_only_addresses(
+    watch_only_addresses: &WatchOnlyAddressMap,
+    principal: Principal,
+) -> Vec<WatchOnlyAddress> {
+    watch_only_addresses
+        .get(&StoredPrincipal(principal))
+        .map(|addresses| addresses.0)
+        .unwrap_or_default()
+}
+
+/// Returns whether the user watches the given address.
+pub fn is_watched(
+    watch_only_addresses: &WatchOnlyAddressMap,
+    principal: Principal,
+    token_account_id: &TokenAccountId,
+) -> bool {
+    get_watch_only_addresses(watch_only_addresses, principal)
+        .iter()
+        .any(|address| address.token_account_id == *token_account_id)
+}
+
+/// Adds a watch-only address of the user, or updates its label if the user already watches it, and
+/// returns all their watch-only addresses.
+///
+/// # Errors
+/// - If the user already watches `MAX_WATCH_ONLY_ADDRESSES` other addresses.
+pub fn add_watch_only_address(
+    watch_only_addresses: &mut WatchOnlyAddressMap,
+    principal: Principal,
+    token_account_id: TokenAccountId,
+    label: String,
+    now_ns: u64,
+) -> Result<Vec<WatchOnlyAddress>, WatchOnlyAddressError> {
+    let mut addresses = get_watch_only_addresses(watch_only_addresses, principal);
+    if let Some(address) = addresses
+        .iter_mut()
+        .find(|address| address.token_account_id == token_account_id)
+    {
+        address.label = label;
+    } else if addresses.len() >= MAX_WATCH_ONLY_ADDRESSES {
+        return Err(WatchOnlyAddressError::TooManyAddresses {
+            max: MAX_WATCH_ONLY_ADDRESSES as u64,
+        });
+    } else {
+        addresses.push(WatchOnlyAddress {
+            token_account_id,
+            label,
+            created_at_timestamp_ns: now_ns,
+        });
+    }
+
+    watch_only_addresses.insert(StoredPrincipal(principal), Candid(addresses.clone()));
+    Ok(addresses)
+}
+
+/// Removes a watch-only address of the user, and returns all their remaining watch-only addresses.
+///
+/// Removing an address that the user does not watch does nothing.
+pub fn remove_watch_only_address(
+    watch_only_addresses: &mut WatchOnlyAddressMap,
+    principal: Principal,
+    token_account_id: &TokenAccountId,
+) -> Vec<WatchOnlyAddress> {
+    let mut addresses = get_watch_only_addresses(watch_only_addresses, principal);
+    let initial_len = addresses.len();
+    addresses.retain(|address| address.token_account_id != *token_account_id);
+    if addresses.len() == initial_len {
+        return addresses;
+    }
+
+    let key = StoredPrincipal(principal);
+    if addresses.is_empty() {
+        watch_only_addresses.remove(&key);
+    } else {
+        watch_only_addresses.insert(key, Candid(addresses.clone()));
+    }
+    addresses
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use ic_stable_structures::{
+        memory_manager::{MemoryId, MemoryManager},
+        DefaultMemoryImpl,
+    };
+    use pretty_assertions::assert_eq;
+    use shared::types::account::{BtcAddress, EthAddress};
+
+    use super::*;
+
+    const BTC_ADDRESS: &str = "bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7c";
+    const ETH_ADDRESS: &str = "0x1D1479C185d32EB90533a08b36B3CFa5F84A0E6B";
+
+    fn setup() -> (
+        WatchOnlyAddressMap,
+        RefCell<MemoryManager<DefaultMemoryImpl>>,
+    ) {
+        let memory_manager = RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+        let map = WatchOnlyAddressMap::init(memory_manager.borrow().get(MemoryId::new(0)));
+        (map, memory_manager)
+    }
+
+    fn btc() -> TokenAccountId {
+        TokenAccountId::Btc(BtcAddress::from_str(BTC_ADDRESS).unwrap())
+    }
+
+    fn eth() -> TokenAccountId {
+        TokenAccountId::Eth(EthAddress::from_str(ETH_ADDRESS).unwrap())
+    }
+
+    #[test]
+    fn test_parse_watch_only_address() {
+        assert_eq!(parse_watch_only_address(BTC_ADDRESS), Ok(btc()));
+        assert_eq!(parse_watch_only_address(ETH_ADDRESS), Ok(eth()));
+        assert_eq!(
+            parse_watch_only_address("not an address"),
+            Err(WatchOnlyAddressError::InvalidAddress)
+        );
+        // ICRC accounts are not addresses of cold storage.
+        assert_eq!(
+            parse_watch_only_address(
+                "7blps-itamd-lzszp-7lbda-4nngn-fev5u-2jvpn-6y3ap-eunp7-kz57e-fqe"
+            ),
+            Err(WatchOnlyAddressError::InvalidAddress)
+        );
+    }
+
+    #[test]
+    fn test_add_watch_only_address_updates_the_label_of_watched_addresses() {
+        let (mut map, _mm) = setup();
+        let principal = Principal::from_slice(&[1]);
+
+        add_watch_only_address(&mut map, principal, btc(), "Vault".to_string(), 1).unwrap();
+        add_watch_only_address(&mut map, principal, eth(), "Treasury".to_string(), 2).unwrap();
+        let addresses =
+            add_watch_only_address(&mut map, principal, btc(), "Cold".to_string(), 3).unwrap();
+
+        assert_eq!(
+            addresses,
+            vec![
+                WatchOnlyAddress {
+                    token_account_id: btc(),
+                    label: "Cold".to_string(),
+                    created_at_timestamp_ns: 1,
+                },
+                WatchOnlyAddress {
+                    token_account_id: eth(),
+                    label: "Treasury".to_string(),
+                    created_at_timestamp_ns: 2,
+                },
+            ]
+        );
+        assert_eq!(get_watch_only_addresses(&map, principal), addresses);
+        assert!(is_watched(&map, principal, &eth()));
+        assert!(!is_watched(&map, Principal::from_slice(&[2]), &eth()));
+    }
+
+    #[test]
+    fn test_add_watch_only_address_is_bounded() {
+        let (mut map, _mm) = setup();
+        let principal = Principal::from_slice(&[1]);
+        for i in 0..MAX_WATCH_ONLY_ADDRESSES {
+            let address = TokenAccountId::Eth(EthAddress::Public(format!("0x{i:040x}")));
+            add_watch_only_address(&mut map, principal, address, i.to_string(), 1).unwrap();
+        }
+
+        assert_eq!(
+            add_watch_only_address(&mut map, principal, btc(), "Vault".to_string(), 2),
+            Err(WatchOnlyAddressError::TooManyAddresses {
+                max: MAX_WATCH_ONLY_ADDRESSES as u64
+            })
+        );
+        assert_eq!(
+            get_watch_only_addresses(&map, principal).len(),
+            MAX_WATCH_ONLY_ADDRESSES
+        );
+        // Watched addresses can still be relabelled.
+        let watched = TokenAccountId::Eth(EthAddress::Public(format!("0x{:040x}", 0)));
+        assert!(add_watch_only_address(&mut map, principal, watched, "New".to_string(), 2).is_ok());
+    }
+
+    #[test]
+    fn test_remove_watch_only_address() {
+        let (mut map, _mm) = setup();
+        let principal = Principal::from_slice(&[1]);
+        add_watch_only_address(&mut map, principal, btc(), "Vault".to_string(), 1).unwrap();
+
+        assert_eq!(
+            remove_watch_only_address(&mut map, principal, &eth()).len(),
+            1
+        );
+        assert_eq!(
+            remove_watch_only_address(&mut map, principal, &btc()),
+            vec![]
+        );
+        assert!(map.is_empty());
+    }
+}
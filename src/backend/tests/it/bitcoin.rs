@@ -2,18 +2,30 @@ use std::time::Duration;
 
 use candid::Principal;
 use ic_cdk::api::management_canister::bitcoin::{BitcoinNetwork, Outpoint, Utxo};
+use pretty_assertions::assert_eq;
 use shared::types::{
     bitcoin::{
-        BtcAddPendingTransactionError, BtcAddPendingTransactionRequest,
-        BtcGetPendingTransactionsError, BtcGetPendingTransactionsReponse,
-        BtcGetPendingTransactionsRequest, SelectedUtxosFeeError, SelectedUtxosFeeRequest,
-        SelectedUtxosFeeResponse,
+        BtcAddPendingTransactionError, BtcAddPendingTransactionRequest, BtcAddressType,
+        BtcBuildPsbtError, BtcBuildPsbtRequest, BtcBuildPsbtResponse, BtcDepositSubscription,
+        BtcGetBalanceError, BtcGetBalanceRequest, BtcGetBalanceResponse,
+        BtcGetConsolidationAdviceError, BtcGetConsolidationAdviceRequest,
+        BtcGetConsolidationAdviceResponse, BtcGetDepositEventsRequest, BtcGetDepositEventsResponse,
+        BtcGetDustReportError, BtcGetDustReportRequest, BtcGetDustReportResponse,
+        BtcGetFeeBumpQuotesError, BtcGetFeeBumpQuotesRequest, BtcGetFeeBumpQuotesResponse,
+        BtcGetFeeHistoryRequest, BtcGetFeeHistoryResponse, BtcGetPendingTransactionsError,
+        BtcGetPendingTransactionsReponse, BtcGetPendingTransactionsRequest,
+        BtcGetSendHistoryRequest, BtcGetSendHistoryResponse, BtcReplacePendingTransactionError,
+        BtcReplacePendingTransactionRequest, BtcSendTransactionError, BtcSendTransactionRequest,
+        BtcSendTransactionResponse, BtcSubscribeDepositsError, BtcSubscribeDepositsRequest,
+        BtcTxOutput, BtcUnsubscribeDepositsRequest, BtcUpdateFrozenUtxosError,
+        BtcUpdateFrozenUtxosRequest, SelectedUtxosFeeError, SelectedUtxosFeeOutput,
+        SelectedUtxosFeeRequest, SelectedUtxosFeeResponse, UtxosSelectionAlgorithm,
     },
     signer::RateLimitError,
 };
 
 use crate::utils::{
-    mock::CALLER,
+    mock::{CALLER, USER_1},
     pocketic::{setup, PicCanisterTrait},
 };
 
@@ -28,7 +40,15 @@ fn test_select_user_utxos_fee_returns_zero_when_user_has_insufficient_funds() {
     let request = SelectedUtxosFeeRequest {
         amount_satoshis: 100_000_000u64,
         network: BitcoinNetwork::Regtest,
+        address_type: None,
         min_confirmations: None,
+        strategy: None,
+        outputs: None,
+        destination_address: None,
+        fee_rate: None,
+        inputs: None,
+        send_max: None,
+        include_dust: None,
     };
     let response = pic_setup.update::<Result<SelectedUtxosFeeResponse, SelectedUtxosFeeError>>(
         caller,
@@ -44,6 +64,129 @@ fn test_select_user_utxos_fee_returns_zero_when_user_has_insufficient_funds() {
 
     assert_eq!(response.utxos.len(), 0);
     assert_eq!(response.fee_satoshis, 0);
+    assert!(!response.change_output);
+}
+
+#[test]
+fn test_select_user_utxos_fee_send_max_returns_zero_amount_without_funds() {
+    let pic_setup = setup();
+
+    let caller = Principal::from_text(CALLER).unwrap();
+
+    let request = SelectedUtxosFeeRequest {
+        amount_satoshis: 0,
+        network: BitcoinNetwork::Regtest,
+        address_type: None,
+        min_confirmations: None,
+        strategy: None,
+        outputs: None,
+        destination_address: Some(MOCK_ADDRESS.to_string()),
+        fee_rate: None,
+        inputs: None,
+        send_max: Some(true),
+        include_dust: None,
+    };
+    let response = pic_setup
+        .update::<Result<SelectedUtxosFeeResponse, SelectedUtxosFeeError>>(
+            caller,
+            "btc_select_user_utxos_fee",
+            request,
+        )
+        .expect("Call failed")
+        .expect("Request was not successful");
+
+    assert_eq!(response.utxos.len(), 0);
+    assert_eq!(response.fee_satoshis, 0);
+    assert_eq!(response.amount_satoshis, 0);
+    assert_eq!(response.algorithm, UtxosSelectionAlgorithm::SendMax);
+    assert!(!response.change_output);
+}
+
+#[test]
+fn test_select_user_utxos_fee_returns_output_breakdown_for_batch_payment() {
+    let pic_setup = setup();
+
+    let caller = Principal::from_text(CALLER).unwrap();
+
+    let outputs = vec![
+        BtcTxOutput {
+            destination_address: MOCK_ADDRESS.to_string(),
+            amount_satoshis: 60_000_000,
+        },
+        BtcTxOutput {
+            destination_address: MOCK_ADDRESS.to_string(),
+            amount_satoshis: 40_000_000,
+        },
+    ];
+    let request = SelectedUtxosFeeRequest {
+        amount_satoshis: 100_000_000u64,
+        network: BitcoinNetwork::Regtest,
+        address_type: None,
+        min_confirmations: None,
+        strategy: None,
+        outputs: Some(outputs.clone()),
+        destination_address: None,
+        fee_rate: None,
+        inputs: None,
+        send_max: None,
+        include_dust: None,
+    };
+    let response = pic_setup
+        .update::<Result<SelectedUtxosFeeResponse, SelectedUtxosFeeError>>(
+            caller,
+            "btc_select_user_utxos_fee",
+            request,
+        )
+        .expect("Call failed")
+        .expect("Request was not successful");
+
+    // The user has no funds, so no output pays any fee.
+    assert_eq!(response.fee_satoshis, 0);
+    assert!(response.fee_quotes.is_empty());
+    assert_eq!(
+        response.outputs,
+        outputs
+            .into_iter()
+            .map(|output| SelectedUtxosFeeOutput {
+                destination_address: output.destination_address,
+                amount_satoshis: output.amount_satoshis,
+                fee_satoshis: 0,
+            })
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_select_user_utxos_fee_rejects_outputs_not_matching_amount() {
+    let pic_setup = setup();
+
+    let caller = Principal::from_text(CALLER).unwrap();
+
+    let request = SelectedUtxosFeeRequest {
+        amount_satoshis: 100_000_000u64,
+        network: BitcoinNetwork::Regtest,
+        address_type: None,
+        min_confirmations: None,
+        strategy: None,
+        outputs: Some(vec![BtcTxOutput {
+            destination_address: MOCK_ADDRESS.to_string(),
+            amount_satoshis: 1_000,
+        }]),
+        destination_address: None,
+        fee_rate: None,
+        inputs: None,
+        send_max: None,
+        include_dust: None,
+    };
+    let response = pic_setup.update::<Result<SelectedUtxosFeeResponse, SelectedUtxosFeeError>>(
+        caller,
+        "btc_select_user_utxos_fee",
+        request,
+    );
+
+    assert!(response
+        .unwrap_err()
+        .contains("Amount does not match the total of the outputs"));
 }
 
 const UTXO_1: Utxo = Utxo {
@@ -56,7 +199,7 @@ const UTXO_1: Utxo = Utxo {
 };
 
 #[test]
-fn test_select_user_utxos_fee_pending_transaction_error() {
+fn test_select_user_utxos_fee_skips_pending_utxos() {
     let pic_setup = setup();
 
     let caller = Principal::from_text(CALLER).unwrap();
@@ -68,6 +211,11 @@ fn test_select_user_utxos_fee_pending_transaction_error() {
         txid: txid.clone(),
         utxos: utxos.clone(),
         network: BitcoinNetwork::Regtest,
+        address_type: None,
+        fee_satoshis: None,
+        destination_address: None,
+        amount_satoshis: None,
+        change_output: None,
     };
 
     let add_response = pic_setup.update::<Result<(), BtcAddPendingTransactionError>>(
@@ -84,7 +232,15 @@ fn test_select_user_utxos_fee_pending_transaction_error() {
     let request = SelectedUtxosFeeRequest {
         amount_satoshis: 100_000_000u64,
         network: BitcoinNetwork::Regtest,
+        address_type: None,
         min_confirmations: None,
+        strategy: None,
+        outputs: None,
+        destination_address: None,
+        fee_rate: None,
+        inputs: None,
+        send_max: None,
+        include_dust: None,
     };
     let select_response = pic_setup
         .update::<Result<SelectedUtxosFeeResponse, SelectedUtxosFeeError>>(
@@ -93,12 +249,8 @@ fn test_select_user_utxos_fee_pending_transaction_error() {
             request,
         );
 
-    // The following passes if we don't prune the pending transactions before checking them
-    // because the utxos added in the pending transactions are not present from the bitcoin api.
-    // match response.expect("Call failed") {
-    //     Ok(_) => panic!("Selecting utxos should fail with pending transctions"),
-    //     Err(err) => assert_eq!(err, SelectedUtxosFeeError::PendingTransactions),
-    // }
+    // Pending transactions don't prevent the selection: the other UTXOs, and the change of the
+    // pending transactions, can be spent.
 
     // Because utxos from bitcoin API is an empty string.
     // The selected utxos are empty and fee is 0.
@@ -110,6 +262,117 @@ fn test_select_user_utxos_fee_pending_transaction_error() {
     assert_eq!(select_data.fee_satoshis, 0);
 }
 
+#[test]
+fn test_btc_build_psbt_returns_insufficient_funds_when_user_has_no_utxos() {
+    let pic_setup = setup();
+
+    let caller = Principal::from_text(CALLER).unwrap();
+
+    let request = BtcBuildPsbtRequest {
+        network: BitcoinNetwork::Regtest,
+        address_type: None,
+        outputs: vec![BtcTxOutput {
+            destination_address: MOCK_ADDRESS.to_string(),
+            amount_satoshis: 10_000,
+        }],
+        fee_rate: None,
+        strategy: None,
+        min_confirmations: None,
+        include_dust: None,
+    };
+    let response = pic_setup
+        .update::<Result<BtcBuildPsbtResponse, BtcBuildPsbtError>>(
+            caller,
+            "btc_build_psbt",
+            request,
+        )
+        .expect("Call failed");
+
+    assert_eq!(response, Err(BtcBuildPsbtError::InsufficientFunds));
+}
+
+#[test]
+fn test_btc_build_psbt_rejects_destination_on_other_network() {
+    let pic_setup = setup();
+
+    let caller = Principal::from_text(CALLER).unwrap();
+
+    let request = BtcBuildPsbtRequest {
+        network: BitcoinNetwork::Mainnet,
+        address_type: None,
+        outputs: vec![BtcTxOutput {
+            destination_address: MOCK_ADDRESS.to_string(),
+            amount_satoshis: 10_000,
+        }],
+        fee_rate: None,
+        strategy: None,
+        min_confirmations: None,
+        include_dust: None,
+    };
+    let response = pic_setup
+        .update::<Result<BtcBuildPsbtResponse, BtcBuildPsbtError>>(
+            caller,
+            "btc_build_psbt",
+            request,
+        )
+        .expect("Call failed");
+
+    assert_eq!(
+        response,
+        Err(BtcBuildPsbtError::InvalidDestinationAddress {
+            address: MOCK_ADDRESS.to_string(),
+        })
+    );
+}
+
+#[test]
+fn test_btc_get_fee_history_is_empty_for_network_without_fee_updates() {
+    let pic_setup = setup();
+
+    let caller = Principal::from_text(CALLER).unwrap();
+
+    // The fee percentiles of regtest are never fetched, so it has no fee history.
+    let request = BtcGetFeeHistoryRequest {
+        network: BitcoinNetwork::Regtest,
+        window_ns: Some(60 * 60 * 1_000_000_000),
+    };
+    let response = pic_setup
+        .query::<BtcGetFeeHistoryResponse>(caller, "btc_get_fee_history", request)
+        .expect("Call failed");
+
+    assert_eq!(
+        response,
+        BtcGetFeeHistoryResponse {
+            snapshots: vec![],
+            stats: None,
+        }
+    );
+}
+
+#[test]
+fn test_btc_get_send_history_is_empty_without_sent_transactions() {
+    let pic_setup = setup();
+
+    let caller = Principal::from_text(CALLER).unwrap();
+
+    let request = BtcGetSendHistoryRequest {
+        network: Some(BitcoinNetwork::Regtest),
+        before_id: None,
+        limit: None,
+    };
+    let response = pic_setup
+        .query::<BtcGetSendHistoryResponse>(caller, "btc_get_send_history", request)
+        .expect("Call failed");
+
+    assert_eq!(
+        response,
+        BtcGetSendHistoryResponse {
+            transactions: vec![],
+            next_before_id: None,
+        }
+    );
+}
+
 #[test]
 fn test_add_and_read_pending_transactions() {
     let pic_setup = setup();
@@ -124,6 +387,11 @@ fn test_add_and_read_pending_transactions() {
         txid: txid.clone(),
         utxos: utxos.clone(),
         network: BitcoinNetwork::Regtest,
+        address_type: None,
+        fee_satoshis: None,
+        destination_address: None,
+        amount_satoshis: None,
+        change_output: None,
     };
 
     let add_response = pic_setup.update::<Result<(), BtcAddPendingTransactionError>>(
@@ -163,6 +431,303 @@ fn test_add_and_read_pending_transactions() {
     // and the pending transaction won't be pruned.
 }
 
+#[test]
+fn test_replace_pending_transaction_rejects_utxos_not_owned() {
+    let pic_setup = setup();
+
+    let caller = Principal::from_text(CALLER).unwrap();
+
+    let request = BtcReplacePendingTransactionRequest {
+        replaced_txid: vec![1],
+        txid: vec![2],
+        utxos: vec![UTXO_1],
+        network: BitcoinNetwork::Regtest,
+        address_type: None,
+        fee_satoshis: 1_000,
+        destination_address: None,
+        amount_satoshis: None,
+        change_output: None,
+    };
+    let response = pic_setup
+        .update::<Result<(), BtcReplacePendingTransactionError>>(
+            caller,
+            "btc_replace_pending_transaction",
+            request,
+        )
+        .expect("Call failed");
+
+    // The bitcoin API returns no UTXOs for the caller.
+    assert_eq!(
+        response,
+        Err(BtcReplacePendingTransactionError::InvalidUtxos)
+    );
+}
+
+#[test]
+fn test_get_fee_bump_quotes_of_unknown_transaction() {
+    let pic_setup = setup();
+
+    let caller = Principal::from_text(CALLER).unwrap();
+
+    let request = BtcGetFeeBumpQuotesRequest {
+        txid: vec![1],
+        network: BitcoinNetwork::Regtest,
+        address_type: None,
+    };
+    let response = pic_setup
+        .update::<Result<BtcGetFeeBumpQuotesResponse, BtcGetFeeBumpQuotesError>>(
+            caller,
+            "btc_get_fee_bump_quotes",
+            request,
+        )
+        .expect("Call failed");
+
+    assert_eq!(response, Err(BtcGetFeeBumpQuotesError::TransactionNotFound));
+}
+
+#[test]
+fn test_btc_subscribe_deposits_and_read_empty_feed() {
+    let pic_setup = setup();
+
+    let caller = Principal::from_text(CALLER).unwrap();
+
+    let request = BtcSubscribeDepositsRequest {
+        network: BitcoinNetwork::Regtest,
+        address_type: Some(BtcAddressType::P2wpkh),
+    };
+    let subscriptions = pic_setup
+        .update::<Result<Vec<BtcDepositSubscription>, BtcSubscribeDepositsError>>(
+            caller,
+            "btc_subscribe_deposits",
+            request,
+        )
+        .expect("Call failed")
+        .expect("Failed to subscribe");
+    assert_eq!(subscriptions.len(), 1);
+    assert_eq!(subscriptions[0].network, BitcoinNetwork::Regtest);
+    assert_eq!(subscriptions[0].address_type, BtcAddressType::P2wpkh);
+    assert!(subscriptions[0].scanned_height.is_some());
+
+    let listed = pic_setup
+        .query::<Vec<BtcDepositSubscription>>(caller, "btc_get_deposit_subscriptions", ())
+        .expect("Call failed");
+    assert_eq!(listed, subscriptions);
+
+    let request = BtcGetDepositEventsRequest {
+        network: None,
+        before_id: None,
+        limit: None,
+    };
+    let response = pic_setup
+        .query::<BtcGetDepositEventsResponse>(caller, "btc_get_deposit_events", request)
+        .expect("Call failed");
+    assert_eq!(
+        response,
+        BtcGetDepositEventsResponse {
+            events: vec![],
+            next_before_id: None,
+        }
+    );
+
+    let request = BtcUnsubscribeDepositsRequest {
+        network: BitcoinNetwork::Regtest,
+        address_type: None,
+    };
+    let remaining = pic_setup
+        .update::<Vec<BtcDepositSubscription>>(caller, "btc_unsubscribe_deposits", request)
+        .expect("Call failed");
+    assert_eq!(remaining, vec![]);
+}
+
+#[test]
+fn test_btc_get_balance_is_zero_when_user_has_no_utxos() {
+    let pic_setup = setup();
+
+    let caller = Principal::from_text(CALLER).unwrap();
+
+    let request = BtcGetBalanceRequest {
+        network: BitcoinNetwork::Regtest,
+        address_type: None,
+        min_confirmations: None,
+    };
+    let response = pic_setup
+        .update::<Result<BtcGetBalanceResponse, BtcGetBalanceError>>(
+            caller,
+            "btc_get_balance",
+            request,
+        )
+        .expect("Call failed");
+
+    assert_eq!(response, Ok(BtcGetBalanceResponse::default()));
+}
+
+#[test]
+fn test_btc_get_consolidation_advice_without_utxos_is_not_recommended() {
+    let pic_setup = setup();
+
+    let caller = Principal::from_text(CALLER).unwrap();
+
+    let request = BtcGetConsolidationAdviceRequest {
+        network: BitcoinNetwork::Regtest,
+        address_type: None,
+        fee_rate: None,
+        min_confirmations: None,
+        build_psbt: true,
+    };
+    let response = pic_setup
+        .update::<Result<BtcGetConsolidationAdviceResponse, BtcGetConsolidationAdviceError>>(
+            caller,
+            "btc_get_consolidation_advice",
+            request,
+        )
+        .expect("Call failed")
+        .expect("Request was not successful");
+
+    assert!(!response.recommended);
+    assert!(response.utxos.is_empty());
+    assert_eq!(response.savings_satoshis, 0);
+    assert_eq!(response.psbt, None);
+}
+
+#[test]
+fn test_btc_get_dust_report_is_empty_when_user_has_no_utxos() {
+    let pic_setup = setup();
+
+    let caller = Principal::from_text(CALLER).unwrap();
+
+    let request = BtcGetDustReportRequest {
+        network: BitcoinNetwork::Regtest,
+        address_type: None,
+        fee_rate: None,
+    };
+    let response = pic_setup
+        .update::<Result<BtcGetDustReportResponse, BtcGetDustReportError>>(
+            caller,
+            "btc_get_dust_report",
+            request,
+        )
+        .expect("Call failed")
+        .expect("Request was not successful");
+
+    assert!(response.utxos.is_empty());
+    assert_eq!(response.total_satoshis, 0);
+    // The threshold never goes below the relay dust limit of a P2WPKH input.
+    assert!(response.dust_threshold_satoshis >= 297);
+}
+
+#[test]
+fn test_btc_send_transaction_rejects_undecodable_transaction() {
+    let pic_setup = setup();
+
+    let caller = Principal::from_text(CALLER).unwrap();
+
+    let request = BtcSendTransactionRequest {
+        network: BitcoinNetwork::Regtest,
+        address_type: None,
+        transaction: vec![1, 2, 3],
+        outputs: vec![BtcTxOutput {
+            destination_address: MOCK_ADDRESS.to_string(),
+            amount_satoshis: 1_000,
+        }],
+    };
+    let response = pic_setup
+        .update::<Result<BtcSendTransactionResponse, BtcSendTransactionError>>(
+            caller,
+            "btc_send_transaction",
+            request,
+        )
+        .expect("Call failed");
+
+    assert!(matches!(
+        response,
+        Err(BtcSendTransactionError::InvalidTransaction { .. })
+    ));
+}
+
+#[test]
+fn test_btc_update_frozen_utxos_freezes_and_unfreezes() {
+    let pic_setup = setup();
+
+    let caller = Principal::from_text(CALLER).unwrap();
+    let outpoint = |vout| Outpoint {
+        txid: vec![0xAA; 32],
+        vout,
+    };
+
+    let request = BtcUpdateFrozenUtxosRequest {
+        freeze: vec![outpoint(0), outpoint(1)],
+        unfreeze: vec![],
+    };
+    let response = pic_setup
+        .update::<Result<Vec<Outpoint>, BtcUpdateFrozenUtxosError>>(
+            caller,
+            "btc_update_frozen_utxos",
+            request,
+        )
+        .expect("Call failed");
+    assert_eq!(response, Ok(vec![outpoint(0), outpoint(1)]));
+
+    let request = BtcUpdateFrozenUtxosRequest {
+        freeze: vec![],
+        unfreeze: vec![outpoint(0)],
+    };
+    pic_setup
+        .update::<Result<Vec<Outpoint>, BtcUpdateFrozenUtxosError>>(
+            caller,
+            "btc_update_frozen_utxos",
+            request,
+        )
+        .expect("Call failed")
+        .expect("Request was not successful");
+
+    let frozen_utxos = pic_setup
+        .query::<Vec<Outpoint>>(caller, "btc_list_frozen_utxos", ())
+        .expect("Call failed");
+    assert_eq!(frozen_utxos, vec![outpoint(1)]);
+
+    let frozen_utxos = pic_setup
+        .query::<Vec<Outpoint>>(
+            Principal::from_text(USER_1).unwrap(),
+            "btc_list_frozen_utxos",
+            (),
+        )
+        .expect("Call failed");
+    assert_eq!(frozen_utxos, vec![]);
+}
+
+#[test]
+fn test_select_user_utxos_fee_rejects_inputs_not_owned() {
+    let pic_setup = setup();
+
+    let caller = Principal::from_text(CALLER).unwrap();
+
+    let request = SelectedUtxosFeeRequest {
+        amount_satoshis: 1_000,
+        network: BitcoinNetwork::Regtest,
+        address_type: None,
+        min_confirmations: None,
+        strategy: None,
+        outputs: None,
+        destination_address: None,
+        fee_rate: None,
+        inputs: Some(vec![Outpoint {
+            txid: vec![0xAA; 32],
+            vout: 0,
+        }]),
+        send_max: None,
+        include_dust: None,
+    };
+    let response = pic_setup
+        .update::<Result<SelectedUtxosFeeResponse, SelectedUtxosFeeError>>(
+            caller,
+            "btc_select_user_utxos_fee",
+            request,
+        )
+        .expect("Call failed");
+
+    assert_eq!(response, Err(SelectedUtxosFeeError::InvalidInputs));
+}
+
 // -------------------------------------------------------------------------------------------------
 // - Rate-limit integration tests for btc_select_user_utxos_fee
 // -------------------------------------------------------------------------------------------------
@@ -174,7 +739,15 @@ fn call_btc_select_user_utxos_fee(
     let request = SelectedUtxosFeeRequest {
         amount_satoshis: 100_000_000u64,
         network: BitcoinNetwork::Regtest,
+        address_type: None,
         min_confirmations: None,
+        strategy: None,
+        outputs: None,
+        destination_address: None,
+        fee_rate: None,
+        inputs: None,
+        send_max: None,
+        include_dust: None,
     };
     pic_setup
         .update::<Result<SelectedUtxosFeeResponse, SelectedUtxosFeeError>>(
@@ -14,7 +14,7 @@ fn config_is_available_to_allowed_users_only() {
     } else {
         unreachable!("The init arg is definitely an init arg")
     };
-    let expected_config = Config::from(init_arg);
+    let expected_config = Config::from(*init_arg);
     // Try anonymous request
     assert!(
         pic_setup
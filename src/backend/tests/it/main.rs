@@ -9,3 +9,4 @@ mod stats;
 mod user_credentials;
 mod user_profile;
 mod utils;
+mod watch_only;
@@ -0,0 +1,118 @@
+use candid::Principal;
+use shared::types::{
+    bitcoin::{
+        BtcAddressType, BtcSettings, UpdateUserBtcSettingsError, UpdateUserBtcSettingsRequest,
+    },
+    user_profile::{GetUserProfileError, UserProfile},
+};
+
+use crate::utils::{
+    mock::CALLER,
+    pocketic::{setup, PicCanisterTrait},
+};
+
+#[test]
+fn test_update_user_btc_settings_saves_the_default_address_type() {
+    let pic_setup = setup();
+
+    let caller = Principal::from_text(CALLER).unwrap();
+
+    let create_profile_response =
+        pic_setup.update::<UserProfile>(caller, "create_user_profile", ());
+
+    let profile = create_profile_response.expect("Create failed");
+    assert_eq!(
+        profile.settings.unwrap().btc,
+        Some(BtcSettings {
+            default_address_type: BtcAddressType::P2wpkh,
+        })
+    );
+
+    let update_user_btc_settings_arg = UpdateUserBtcSettingsRequest {
+        btc: BtcSettings {
+            default_address_type: BtcAddressType::P2tr,
+        },
+        current_user_version: profile.version,
+    };
+
+    let update_user_btc_settings_response = pic_setup
+        .update::<Result<(), UpdateUserBtcSettingsError>>(
+            caller,
+            "update_user_btc_settings",
+            update_user_btc_settings_arg,
+        );
+
+    assert_eq!(update_user_btc_settings_response, Ok(Ok(())));
+
+    let get_profile_response = pic_setup.update::<Result<UserProfile, GetUserProfileError>>(
+        caller,
+        "get_user_profile",
+        (),
+    );
+
+    let user_profile = get_profile_response
+        .expect("Call to get profile failed")
+        .expect("Get profile failed");
+
+    assert_eq!(
+        user_profile.settings.unwrap().btc,
+        Some(BtcSettings {
+            default_address_type: BtcAddressType::P2tr,
+        })
+    );
+}
+
+#[test]
+fn test_update_user_btc_settings_cannot_update_wrong_version() {
+    let pic_setup = setup();
+
+    let caller = Principal::from_text(CALLER).unwrap();
+
+    let create_profile_response =
+        pic_setup.update::<UserProfile>(caller, "create_user_profile", ());
+
+    let profile = create_profile_response.expect("Create failed");
+
+    let update_user_btc_settings_arg = UpdateUserBtcSettingsRequest {
+        btc: BtcSettings {
+            default_address_type: BtcAddressType::P2tr,
+        },
+        current_user_version: profile.version.map(|version| version + 1),
+    };
+
+    let update_user_btc_settings_response = pic_setup
+        .update::<Result<(), UpdateUserBtcSettingsError>>(
+            caller,
+            "update_user_btc_settings",
+            update_user_btc_settings_arg,
+        );
+
+    assert_eq!(
+        update_user_btc_settings_response,
+        Ok(Err(UpdateUserBtcSettingsError::VersionMismatch))
+    );
+}
+
+#[test]
+fn test_update_user_btc_settings_requires_a_profile() {
+    let pic_setup = setup();
+
+    let caller = Principal::from_text(CALLER).unwrap();
+
+    let update_user_btc_settings_arg = UpdateUserBtcSettingsRequest {
+        btc: BtcSettings::default(),
+        current_user_version: None,
+    };
+
+    let update_user_btc_settings_response = pic_setup
+        .update::<Result<(), UpdateUserBtcSettingsError>>(
+            caller,
+            "update_user_btc_settings",
+            update_user_btc_settings_arg,
+        );
+
+    assert_eq!(
+        update_user_btc_settings_response,
+        Ok(Err(UpdateUserBtcSettingsError::UserNotFound))
+    );
+}
@@ -1,3 +1,4 @@
+mod btc_settings;
 mod dapp_settings;
 mod experimental_features_settings;
 mod networks_settings;
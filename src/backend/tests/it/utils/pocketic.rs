@@ -418,7 +418,7 @@ impl PicBackend {
 }
 
 pub(crate) fn init_arg() -> Arg {
-    Arg::Init(InitArg {
+    Arg::Init(Box::new(InitArg {
         ecdsa_key_name: "test_key_1".to_string(),
         allowed_callers: vec![Principal::from_text(CALLER).unwrap()],
         ic_root_key_der: None,
@@ -434,7 +434,9 @@ pub(crate) fn init_arg() -> Arg {
             Principal::from_text(SIGNER_CANISTER_ID).expect("wrong cfs canister id"),
         ),
         derivation_origin: Some(VC_DERIVATION_ORIGIN.to_string()),
-    })
+        btc_fee_tiers: None,
+        btc_pending_transaction_ttls: None,
+    }))
 }
 
 /// A test Oisy backend canister with a shared reference to the `PocketIc` instance it is installed
@@ -0,0 +1,98 @@
+use candid::Principal;
+use pretty_assertions::assert_eq;
+use shared::types::watch_only::{
+    AddWatchOnlyAddressRequest, GetWatchOnlyBtcBalanceError, GetWatchOnlyBtcBalanceRequest,
+    GetWatchOnlyBtcBalanceResponse, RemoveWatchOnlyAddressRequest, WatchOnlyAddress,
+    WatchOnlyAddressError,
+};
+
+use crate::utils::{
+    mock::CALLER,
+    pocketic::{setup, PicCanisterTrait},
+};
+
+const BTC_ADDRESS: &str = "bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7c";
+const KASPA_ADDRESS: &str = "kaspa:qpauqsvk7yf9unexwmxsnmg547mhyga37csh0kj53q6xxgl24ydxjsgzthw5j";
+
+#[test]
+fn test_watch_only_addresses_can_be_added_listed_and_removed() {
+    let pic_setup = setup();
+    let caller = Principal::from_text(CALLER).unwrap();
+
+    for (address, label) in [(BTC_ADDRESS, "Vault"), (KASPA_ADDRESS, "Savings")] {
+        let request = AddWatchOnlyAddressRequest {
+            address: address.to_string(),
+            label: label.to_string(),
+        };
+        pic_setup
+            .update::<Result<Vec<WatchOnlyAddress>, WatchOnlyAddressError>>(
+                caller,
+                "add_watch_only_address",
+                request,
+            )
+            .expect("Call failed")
+            .expect("Failed to add watch-only address");
+    }
+
+    let addresses = pic_setup
+        .query::<Vec<WatchOnlyAddress>>(caller, "list_watch_only_addresses", ())
+        .expect("Call failed");
+    assert_eq!(
+        addresses
+            .iter()
+            .map(|address| address.label.as_str())
+            .collect::<Vec<_>>(),
+        vec!["Vault", "Savings"]
+    );
+
+    let remaining = pic_setup
+        .update::<Vec<WatchOnlyAddress>>(
+            caller,
+            "remove_watch_only_address",
+            RemoveWatchOnlyAddressRequest {
+                address: BTC_ADDRESS.to_string(),
+            },
+        )
+        .expect("Call failed");
+    assert_eq!(remaining, addresses[1..].to_vec());
+}
+
+#[test]
+fn test_add_watch_only_address_rejects_icrc_accounts() {
+    let pic_setup = setup();
+    let caller = Principal::from_text(CALLER).unwrap();
+
+    let request = AddWatchOnlyAddressRequest {
+        address: CALLER.to_string(),
+        label: "Mine".to_string(),
+    };
+    let result = pic_setup
+        .update::<Result<Vec<WatchOnlyAddress>, WatchOnlyAddressError>>(
+            caller,
+            "add_watch_only_address",
+            request,
+        )
+        .expect("Call failed");
+
+    assert_eq!(result, Err(WatchOnlyAddressError::InvalidAddress));
+}
+
+#[test]
+fn test_get_watch_only_btc_balance_requires_a_watched_address() {
+    let pic_setup = setup();
+    let caller = Principal::from_text(CALLER).unwrap();
+
+    let request = GetWatchOnlyBtcBalanceRequest {
+        address: BTC_ADDRESS.to_string(),
+        min_confirmations: None,
+    };
+    let result = pic_setup
+        .update::<Result<GetWatchOnlyBtcBalanceResponse, GetWatchOnlyBtcBalanceError>>(
+            caller,
+            "get_watch_only_btc_balance",
+            request,
+        )
+        .expect("Call failed");
+
+    assert_eq!(result, Err(GetWatchOnlyBtcBalanceError::AddressNotWatched));
+}
@@ -8,6 +8,10 @@ use crate::{
     types::{
         agreement::{Agreements, UpdateAgreementsError, UserAgreements},
         backend_config::{Config, InitArg},
+        bitcoin::{
+            BtcFeeTiersConfig, BtcPendingTransactionTtlConfig, BtcSettings,
+            UpdateUserBtcSettingsError,
+        },
         contact::{
             Contact, ContactAddressData, ContactImage, CreateContactRequest, UpdateContactRequest,
         },
@@ -30,6 +34,10 @@ use crate::{
             AddUserCredentialError, OisyUser, StoredUserProfile, UserCredential, UserProfile,
         },
         verifiable_credential::CredentialType,
+        watch_only::{
+            AddWatchOnlyAddressRequest, GetWatchOnlyBtcBalanceRequest,
+            RemoveWatchOnlyAddressRequest, MAX_WATCH_ONLY_ADDRESS_LEN, MAX_WATCH_ONLY_LABEL_LEN,
+        },
         Timestamp, TokenVersion, Version, MAX_SYMBOL_LENGTH,
     },
     validate::{validate_on_deserialize, Validate},
@@ -141,6 +149,7 @@ impl From<InitArg> for Config {
     ///
     /// # Panics
     /// - If the root key cannot be parsed.
+    /// - If the bitcoin fee tiers are invalid.
     fn from(arg: InitArg) -> Self {
         let InitArg {
             ecdsa_key_name,
@@ -149,6 +158,8 @@ impl From<InitArg> for Config {
             ic_root_key_der,
             cfs_canister_id,
             derivation_origin,
+            btc_fee_tiers,
+            btc_pending_transaction_ttls,
         } = arg;
         let ic_root_key_raw = match extract_raw_root_pk_from_der(
             &ic_root_key_der.unwrap_or_else(|| IC_ROOT_PK_DER.to_vec()),
@@ -156,6 +167,18 @@ impl From<InitArg> for Config {
             Ok(root_key) => root_key,
             Err(msg) => panic!("{}", format!("Error parsing root key: {msg}")),
         };
+        if let Some(Err(msg)) = btc_fee_tiers.as_ref().map(BtcFeeTiersConfig::validate) {
+            panic!("{}", format!("Invalid bitcoin fee tiers: {msg}"));
+        }
+        if let Some(Err(msg)) = btc_pending_transaction_ttls
+            .as_ref()
+            .map(BtcPendingTransactionTtlConfig::validate)
+        {
+            panic!(
+                "{}",
+                format!("Invalid bitcoin pending transaction time to live: {msg}")
+            );
+        }
         Config {
             ecdsa_key_name,
             allowed_callers,
@@ -163,6 +186,8 @@ impl From<InitArg> for Config {
             supported_credentials,
             ic_root_key_raw: Some(ic_root_key_raw),
             derivation_origin,
+            btc_fee_tiers,
+            btc_pending_transaction_ttls,
         }
     }
 }
@@ -222,6 +247,7 @@ impl StoredUserProfile {
                 },
             },
             experimental_features: ExperimentalFeaturesSettings::default(),
+            btc: Some(BtcSettings::default()),
         };
         let agreements = Agreements::default();
         let credentials: BTreeMap<CredentialType, UserCredential> = BTreeMap::new();
@@ -468,6 +494,35 @@ impl StoredUserProfile {
         new_profile.updated_timestamp = now;
         Ok(new_profile)
     }
+
+    /// Returns a copy with the bitcoin settings set to the specified value.
+    ///
+    /// # Errors
+    ///
+    /// Will return Err if there is a version mismatch.
+    pub fn with_btc_settings(
+        &self,
+        profile_version: Option<Version>,
+        now: Timestamp,
+        btc: BtcSettings,
+    ) -> Result<StoredUserProfile, UpdateUserBtcSettingsError> {
+        if profile_version != self.version {
+            return Err(UpdateUserBtcSettingsError::VersionMismatch);
+        }
+
+        let settings = self.settings.clone().unwrap_or_default();
+        if settings.btc.unwrap_or_default() == btc {
+            return Ok(self.clone());
+        }
+
+        let mut new_profile = self.with_incremented_version();
+        new_profile.settings = Some(Settings {
+            btc: Some(btc),
+            ..settings
+        });
+        new_profile.updated_timestamp = now;
+        Ok(new_profile)
+    }
 }
 
 impl From<&StoredUserProfile> for UserProfile {
@@ -781,6 +836,42 @@ impl Validate for UpdateContactRequest {
     }
 }
 
+impl Validate for AddWatchOnlyAddressRequest {
+    fn validate(&self) -> Result<(), Error> {
+        validate_string_length(
+            &self.address,
+            MAX_WATCH_ONLY_ADDRESS_LEN,
+            "AddWatchOnlyAddressRequest.address",
+        )?;
+        validate_string_length(
+            &self.label,
+            MAX_WATCH_ONLY_LABEL_LEN,
+            "AddWatchOnlyAddressRequest.label",
+        )?;
+        validate_string_whitespace_padding(&self.label, "AddWatchOnlyAddressRequest.label")
+    }
+}
+
+impl Validate for RemoveWatchOnlyAddressRequest {
+    fn validate(&self) -> Result<(), Error> {
+        validate_string_length(
+            &self.address,
+            MAX_WATCH_ONLY_ADDRESS_LEN,
+            "RemoveWatchOnlyAddressRequest.address",
+        )
+    }
+}
+
+impl Validate for GetWatchOnlyBtcBalanceRequest {
+    fn validate(&self) -> Result<(), Error> {
+        validate_string_length(
+            &self.address,
+            MAX_WATCH_ONLY_ADDRESS_LEN,
+            "GetWatchOnlyBtcBalanceRequest.address",
+        )
+    }
+}
+
 // Apply the validation during deserialization for all types
 validate_on_deserialize!(Contact);
 validate_on_deserialize!(ContactAddressData);
@@ -798,3 +889,6 @@ validate_on_deserialize!(SplTokenId);
 validate_on_deserialize!(ErcToken);
 validate_on_deserialize!(ErcTokenId);
 validate_on_deserialize!(UserToken);
+validate_on_deserialize!(AddWatchOnlyAddressRequest);
+validate_on_deserialize!(RemoveWatchOnlyAddressRequest);
+validate_on_deserialize!(GetWatchOnlyBtcBalanceRequest);
@@ -15,15 +15,20 @@ const WASM_PAGE_SIZE: u64 = 65536;
 const GIBIBYTE: u32 = 1 << 30;
 
 /// Returns the metrics in the Prometheus format.
+///
+/// `encode_canister_metrics` encodes the metrics specific to the canister, after the health
+/// metrics.
 #[must_use]
-pub fn get_metrics() -> HttpResponse {
+pub fn get_metrics(
+    encode_canister_metrics: impl FnOnce(&mut MetricsEncoder<Vec<u8>>) -> std::io::Result<()>,
+) -> HttpResponse {
     let now = ic_cdk::api::time();
     let mut writer = MetricsEncoder::new(
         vec![],
         i64::try_from(now / 1_000_000)
             .unwrap_or_else(|_| unreachable!("u64::MAX / 1_000_000 is smaller than i64::MAX")),
     );
-    match encode_metrics(&mut writer) {
+    match encode_metrics(&mut writer).and_then(|()| encode_canister_metrics(&mut writer)) {
         Ok(()) => {
             let body = writer.into_inner();
             HttpResponse {
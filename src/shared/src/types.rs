@@ -24,6 +24,7 @@ pub mod token_standard;
 pub mod transaction;
 pub mod user_profile;
 pub mod verifiable_credential;
+pub mod watch_only;
 
 #[cfg(test)]
 mod tests;
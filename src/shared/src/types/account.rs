@@ -8,6 +8,7 @@
 //! also to all ERC20 tokens.
 
 use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::management_canister::bitcoin::BitcoinNetwork;
 use serde::Serialize;
 
 use super::token_id::TokenId;
@@ -182,6 +183,7 @@ pub enum BtcAddress {
 impl AccountId<BitcoinMainnet> for BtcAddress {}
 impl AccountId<BitcoinTestnet> for BtcAddress {}
 impl AccountId<BitcoinRegtest> for BtcAddress {}
+impl AccountId<BitcoinNetwork> for BtcAddress {}
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
 pub enum EthAddress {
     /// A public Ethereum address.
@@ -208,11 +210,12 @@ impl TokenId<EthereumSepolia> for EthAddress {}
 /// - Devnet: `kaspadev:`
 /// - Simnet: `kaspasim:`
 ///
-/// The address body starts with 'q' (P2PK) or 'p' (P2SH) followed by Bech32 characters.
+/// The address body starts with 'q' (P2PK) or 'p' (P2SH) followed by Bech32 characters.  Its
+/// checksum is verified when it is parsed, and it is stored in lower case.
 ///
 /// # Example
-/// - `kaspa:qyp9cat9eecq5f8tqjk4wp7w576vfm0ey8cx0j9qlyxg5q4u` (mainnet)
-/// - `kaspatest:qyp9cat9eecq5f8tqjk4wp7w576vfm0ey8cx0j9qlyxg5q4u` (testnet)
+/// - `kaspa:qpauqsvk7yf9unexwmxsnmg547mhyga37csh0kj53q6xxgl24ydxjsgzthw5j` (mainnet)
+/// - `kaspatest:qqnapngv3zxp305qf06w6hpzmyxtx2r99jjhs04lu980xdyd2ulwwmx9evrfz` (testnet)
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
 pub enum KaspaAddress {
 	/// A public Kaspa address.
@@ -221,7 +224,7 @@ pub enum KaspaAddress {
 	/// A string starting with network prefix (e.g., "kaspa:", "kaspatest:").
 	///
 	/// # Example
-	/// - `kaspa:qyp9cat9eecq5f8tqjk4wp7w576vfm0ey8cx0j9qlyxg5q4u`
+	/// - `kaspa:qpauqsvk7yf9unexwmxsnmg547mhyga37csh0kj53q6xxgl24ydxjsgzthw5j`
 	Public(String),
 }
 impl AccountId<KaspaMainnet> for KaspaAddress {}
@@ -2,11 +2,13 @@
 use std::str::FromStr;
 
 use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::management_canister::bitcoin::BitcoinNetwork;
 use serde::Serialize;
 use sha2::{Digest, Sha256};
 
 use super::{
-    BtcAddress, EthAddress, IcrcSubaccountId, Icrcv2AccountId, SolPrincipal, TokenAccountId,
+    BtcAddress, EthAddress, IcrcSubaccountId, Icrcv2AccountId, KaspaAddress, SolPrincipal,
+    TokenAccountId,
 };
 
 #[cfg(test)]
@@ -20,6 +22,8 @@ pub enum ParseError {
     InvalidPrefix,
     InvalidChecksum,
     InvalidEncoding,
+    /// The address is valid, but belongs to another network.
+    NetworkMismatch,
 }
 
 impl FromStr for TokenAccountId {
@@ -30,6 +34,7 @@ impl FromStr for TokenAccountId {
             .map(TokenAccountId::Sol)
             .or_else(|_| BtcAddress::from_str(s).map(TokenAccountId::Btc))
             .or_else(|_| EthAddress::from_str(s).map(TokenAccountId::Eth))
+            .or_else(|_| KaspaAddress::from_str(s).map(TokenAccountId::Kaspa))
             .or_else(|_| Icrcv2AccountId::from_str(s).map(TokenAccountId::Icrcv2))
             .map_err(|_| ParseError::UnsupportedFormat)
     }
@@ -53,6 +58,12 @@ impl From<EthAddress> for TokenAccountId {
     }
 }
 
+impl From<KaspaAddress> for TokenAccountId {
+    fn from(value: KaspaAddress) -> Self {
+        TokenAccountId::Kaspa(value)
+    }
+}
+
 impl From<Icrcv2AccountId> for TokenAccountId {
     fn from(value: Icrcv2AccountId) -> Self {
         TokenAccountId::Icrcv2(value)
@@ -111,6 +122,77 @@ impl FromStr for EthAddress {
     }
 }
 
+/// The prefixes of the Kaspa networks, in lower case.
+const KASPA_PREFIXES: [&str; 4] = ["kaspa:", "kaspatest:", "kaspadev:", "kaspasim:"];
+
+impl FromStr for KaspaAddress {
+    type Err = ParseError;
+
+    /// Parses a Kaspa address, verifying its checksum.
+    ///
+    /// The address body, after the network prefix, starts with `q` (P2PK) or `p` (P2SH) and is
+    /// 61 (Schnorr P2PK, P2SH) to 63 (ECDSA P2PK) Bech32 characters long.  As for segwit
+    /// addresses, the address is stored in lower case, its canonical form.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lowercase = s.to_ascii_lowercase();
+        let (prefix, body) = KASPA_PREFIXES
+            .iter()
+            .find_map(|prefix| Some((*prefix, lowercase.strip_prefix(prefix)?)))
+            .ok_or(ParseError::InvalidPrefix)?;
+        if !(61..=63).contains(&body.len()) {
+            return Err(ParseError::InvalidLength);
+        }
+        if !body.starts_with(['q', 'p']) {
+            return Err(ParseError::InvalidEncoding);
+        }
+        let values = body
+            .bytes()
+            .map(|c| {
+                BtcAddress::BECH32_CHARSET
+                    .iter()
+                    .position(|&d| d == c)
+                    .and_then(|value| u8::try_from(value).ok())
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or(ParseError::InvalidEncoding)?;
+        // The prefix is checksummed without its trailing colon, followed by a zero separator.
+        let prefix_values = prefix
+            .trim_end_matches(':')
+            .bytes()
+            .map(|c| c & 0x1f)
+            .chain([0]);
+        if Self::polymod(prefix_values.chain(values)) != 0 {
+            return Err(ParseError::InvalidChecksum);
+        }
+        Ok(KaspaAddress::Public(lowercase))
+    }
+}
+
+impl KaspaAddress {
+    /// Computes the 40-bit checksum polynomial of Kaspa addresses, as in `CashAddr`, over the
+    /// given 5-bit values.  It is zero for the values of a valid address.
+    fn polymod(values: impl IntoIterator<Item = u8>) -> u64 {
+        const GENERATOR: [u64; 5] = [
+            0x98_f2bc_8e61,
+            0x79_b76d_99e2,
+            0xf3_3e5f_b3c4,
+            0xae_2eab_e2a8,
+            0x1e_4f43_e470,
+        ];
+        let mut checksum: u64 = 1;
+        for value in values {
+            let top = checksum >> 35;
+            checksum = ((checksum & 0x07_ffff_ffff) << 5) ^ u64::from(value);
+            for (i, generator) in GENERATOR.iter().enumerate() {
+                if (top >> i) & 1 == 1 {
+                    checksum ^= generator;
+                }
+            }
+        }
+        checksum ^ 1
+    }
+}
+
 impl FromStr for IcrcSubaccountId {
     type Err = ParseError;
 
@@ -129,26 +211,84 @@ impl FromStr for BtcAddress {
     type Err = ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Self::from_p2pkh(s)
-            .or_else(|_| Self::from_p2sh(s))
-            .or_else(|_| Self::from_p2wpkh(s))
-            .or_else(|_| Self::from_p2wsh(s))
-            .or_else(|_| Self::from_p2tr(s))
+        Self::parse_with_network(s).map(|(address, _)| address)
     }
 }
 
 impl BtcAddress {
     const MAINNET_PREFIX: &str = "bc";
     const TESTNET_PREFIX: &str = "tb";
+    const REGTEST_PREFIX: &str = "bcrt";
+
+    const MAINNET_P2PKH_VERSION: u8 = 0x00;
+    const MAINNET_P2SH_VERSION: u8 = 0x05;
+    const TESTNET_P2PKH_VERSION: u8 = 0x6f;
+    const TESTNET_P2SH_VERSION: u8 = 0xc4;
+
+    /// The characters of the `Bech32` alphabet, indexed by their 5-bit value.
+    const BECH32_CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+    /// The checksum constant of `Bech32`, used by witness version 0.  See BIP-173.
+    const BECH32_CONST: u32 = 1;
+    /// The checksum constant of `Bech32m`, used by witness versions 1 and above.  See BIP-350.
+    const BECH32M_CONST: u32 = 0x2bc8_30a3;
+    /// The maximum length of a `Bech32` string.
+    const BECH32_MAX_LEN: usize = 90;
+
+    /// Returns the address in its string form.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        match self {
+            BtcAddress::P2PKH(address)
+            | BtcAddress::P2SH(address)
+            | BtcAddress::P2WPKH(address)
+            | BtcAddress::P2WSH(address)
+            | BtcAddress::P2TR(address) => address,
+        }
+    }
 
-    /// Removes the mainnet or testnet prefix from a Bitcoin address
+    /// Parses a Bitcoin address of any supported type and returns the network it belongs to.
+    ///
+    /// Note: Legacy (`Base58Check`) addresses use the same version bytes on testnet and regtest, so
+    /// they are reported as `Testnet`.  Use [`Self::parse_for_network`] to check an address
+    /// against a given network.
     ///
     /// # Errors
-    /// - If the address does not have either `MAINNET_PREFIX` or `TESTNET_PREFIX` as a prefix.
-    fn strip_prefix(s: &str) -> Result<&str, ParseError> {
-        s.strip_prefix(Self::MAINNET_PREFIX)
-            .or_else(|| s.strip_prefix(Self::TESTNET_PREFIX))
-            .ok_or(ParseError::InvalidPrefix)
+    /// - If the address is not a valid Bitcoin address.
+    pub fn parse_with_network(s: &str) -> Result<(Self, BitcoinNetwork), ParseError> {
+        if Self::network_of_prefix(s).is_some() {
+            Self::from_segwit(s)
+        } else {
+            Self::from_base58check(s)
+        }
+    }
+
+    /// Parses a Bitcoin address that must belong to the given network.
+    ///
+    /// # Errors
+    /// - If the address is not a valid Bitcoin address.
+    /// - `NetworkMismatch` if the address belongs to another network.
+    pub fn parse_for_network(s: &str, network: BitcoinNetwork) -> Result<Self, ParseError> {
+        let (address, detected) = Self::parse_with_network(s)?;
+        let is_legacy = matches!(address, BtcAddress::P2PKH(_) | BtcAddress::P2SH(_));
+        match (detected, network) {
+            (detected, network) if detected == network => Ok(address),
+            (BitcoinNetwork::Testnet, BitcoinNetwork::Regtest) if is_legacy => Ok(address),
+            _ => Err(ParseError::NetworkMismatch),
+        }
+    }
+
+    /// Returns the network of the human-readable part of a `Bech32` address, if it has one of the
+    /// known prefixes.
+    ///
+    /// Note: The regtest prefix `bcrt` must be checked before the mainnet prefix `bc`.
+    fn network_of_prefix(s: &str) -> Option<BitcoinNetwork> {
+        let hrp = s.rsplit_once('1')?.0.to_ascii_lowercase();
+        match hrp.as_str() {
+            Self::REGTEST_PREFIX => Some(BitcoinNetwork::Regtest),
+            Self::MAINNET_PREFIX => Some(BitcoinNetwork::Mainnet),
+            Self::TESTNET_PREFIX => Some(BitcoinNetwork::Testnet),
+            _ => None,
+        }
     }
 
     /// Calculates the checksum for a Bitcoin address
@@ -167,45 +307,172 @@ impl BtcAddress {
         checksum
     }
 
-    /// Parses a P2PKH Bitcoin address
+    /// Parses a legacy P2PKH or P2SH address, verifying its `Base58Check` checksum.
     ///
     /// # Errors
-    /// - If the address is not a valid P2PKH address.
-    pub fn from_p2pkh(s: &str) -> Result<Self, ParseError> {
-        if !(s.len() >= 27 && s.len() <= 34) {
+    /// - If the address is not a valid `Base58Check` address with a known version byte.
+    fn from_base58check(s: &str) -> Result<(Self, BitcoinNetwork), ParseError> {
+        if !(s.len() >= 26 && s.len() <= 35) {
             return Err(ParseError::InvalidLength);
         }
-        let body = s; // No prefix to strip
-        let bytes = bs58::decode(body)
+        let bytes = bs58::decode(s)
             .into_vec()
-            .map_err(|_| ParseError::InvalidChecksum)?;
+            .map_err(|_| ParseError::InvalidEncoding)?;
         if bytes.len() != 25 {
             return Err(ParseError::InvalidLength);
         }
-        if bytes[0] != 0x00 {
-            return Err(ParseError::InvalidPrefix);
-        }
         let checksum = &bytes[21..25];
         let expected_checksum = Self::address_checksum(&bytes[0..21]);
         if checksum != expected_checksum {
             return Err(ParseError::InvalidChecksum);
         }
-        Ok(BtcAddress::P2PKH(s.to_string()))
+        let address = s.to_string();
+        match bytes[0] {
+            Self::MAINNET_P2PKH_VERSION => {
+                Ok((BtcAddress::P2PKH(address), BitcoinNetwork::Mainnet))
+            }
+            Self::MAINNET_P2SH_VERSION => Ok((BtcAddress::P2SH(address), BitcoinNetwork::Mainnet)),
+            Self::TESTNET_P2PKH_VERSION => {
+                Ok((BtcAddress::P2PKH(address), BitcoinNetwork::Testnet))
+            }
+            Self::TESTNET_P2SH_VERSION => Ok((BtcAddress::P2SH(address), BitcoinNetwork::Testnet)),
+            _ => Err(ParseError::InvalidPrefix),
+        }
     }
 
-    /// Parses a P2SH Bitcoin address
+    /// Computes the `Bech32` checksum polynomial over the given 5-bit values.  See BIP-173.
+    fn bech32_polymod(values: impl IntoIterator<Item = u8>) -> u32 {
+        const GENERATOR: [u32; 5] = [
+            0x3b6a_57b2,
+            0x2650_8e6d,
+            0x1ea1_19fa,
+            0x3d42_33dd,
+            0x2a14_62b3,
+        ];
+        let mut checksum: u32 = 1;
+        for value in values {
+            let top = checksum >> 25;
+            checksum = ((checksum & 0x01ff_ffff) << 5) ^ u32::from(value);
+            for (i, generator) in GENERATOR.iter().enumerate() {
+                if (top >> i) & 1 == 1 {
+                    checksum ^= generator;
+                }
+            }
+        }
+        checksum
+    }
+
+    /// Regroups a sequence of 5-bit values into bytes, rejecting non-zero or excess padding.
+    fn bytes_from_5bit(values: &[u8]) -> Result<Vec<u8>, ParseError> {
+        let mut accumulator: u32 = 0;
+        let mut bits: u32 = 0;
+        let mut bytes = Vec::with_capacity(values.len() * 5 / 8);
+        for value in values {
+            accumulator = (accumulator << 5) | u32::from(*value);
+            bits += 5;
+            if bits >= 8 {
+                bits -= 8;
+                bytes.push(((accumulator >> bits) & 0xff) as u8);
+            }
+        }
+        if bits >= 5 || (accumulator << (8 - bits)) & 0xff != 0 {
+            return Err(ParseError::InvalidEncoding);
+        }
+        Ok(bytes)
+    }
+
+    /// Parses a segwit address, verifying its `Bech32` or `Bech32m` checksum.  See BIP-173 and
+    /// BIP-350.
+    ///
+    /// The address is stored in lower case, its canonical form, so that an address typed in upper
+    /// case equals the same address in lower case.
     ///
     /// # Errors
-    /// - If the address is not a valid P2SH address.
-    pub fn from_p2sh(s: &str) -> Result<Self, ParseError> {
-        let body = s; // No prefix to strip
-        if !body.starts_with('3') {
-            return Err(ParseError::InvalidPrefix);
+    /// - If the address is not a valid segwit address of a supported type.
+    fn from_segwit(s: &str) -> Result<(Self, BitcoinNetwork), ParseError> {
+        if s.len() > Self::BECH32_MAX_LEN {
+            return Err(ParseError::InvalidLength);
         }
-        if body.len() != 34 {
+        if s.chars().any(|c| c.is_ascii_lowercase()) && s.chars().any(|c| c.is_ascii_uppercase()) {
+            return Err(ParseError::InvalidEncoding);
+        }
+        let network = Self::network_of_prefix(s).ok_or(ParseError::InvalidPrefix)?;
+        let lowercase = s.to_ascii_lowercase();
+        let (hrp, data) = lowercase
+            .rsplit_once('1')
+            .ok_or(ParseError::InvalidPrefix)?;
+        // The witness version and the checksum take 1 and 6 characters respectively.
+        if data.len() < 7 {
             return Err(ParseError::InvalidLength);
         }
-        Ok(BtcAddress::P2SH(s.to_string()))
+        let values = data
+            .bytes()
+            .map(|c| {
+                Self::BECH32_CHARSET
+                    .iter()
+                    .position(|&d| d == c)
+                    .and_then(|value| u8::try_from(value).ok())
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or(ParseError::InvalidEncoding)?;
+        let hrp_values = hrp
+            .bytes()
+            .map(|c| c >> 5)
+            .chain([0])
+            .chain(hrp.bytes().map(|c| c & 0x1f));
+        let checksum = Self::bech32_polymod(hrp_values.chain(values.iter().copied()));
+        let witness_version = values[0];
+        let expected_checksum = if witness_version == 0 {
+            Self::BECH32_CONST
+        } else {
+            Self::BECH32M_CONST
+        };
+        if checksum != expected_checksum {
+            return Err(ParseError::InvalidChecksum);
+        }
+        let program = Self::bytes_from_5bit(&values[1..values.len() - 6])?;
+        let address = lowercase;
+        match (witness_version, program.len()) {
+            (0, 20) => Ok((BtcAddress::P2WPKH(address), network)),
+            (0, 32) => Ok((BtcAddress::P2WSH(address), network)),
+            (0, _) => Err(ParseError::InvalidLength),
+            (1, 32) => Ok((BtcAddress::P2TR(address), network)),
+            _ => Err(ParseError::UnsupportedFormat),
+        }
+    }
+
+    /// Parses an address with the given parser and checks that it has the expected type.
+    fn parse_as(
+        s: &str,
+        parse: fn(&str) -> Result<(Self, BitcoinNetwork), ParseError>,
+        is_expected_type: fn(&Self) -> bool,
+    ) -> Result<Self, ParseError> {
+        let (address, _) = parse(s)?;
+        if is_expected_type(&address) {
+            Ok(address)
+        } else {
+            Err(ParseError::InvalidPrefix)
+        }
+    }
+
+    /// Parses a P2PKH Bitcoin address
+    ///
+    /// # Errors
+    /// - If the address is not a valid P2PKH address.
+    pub fn from_p2pkh(s: &str) -> Result<Self, ParseError> {
+        Self::parse_as(s, Self::from_base58check, |address| {
+            matches!(address, BtcAddress::P2PKH(_))
+        })
+    }
+
+    /// Parses a P2SH Bitcoin address
+    ///
+    /// # Errors
+    /// - If the address is not a valid P2SH address.
+    pub fn from_p2sh(s: &str) -> Result<Self, ParseError> {
+        Self::parse_as(s, Self::from_base58check, |address| {
+            matches!(address, BtcAddress::P2SH(_))
+        })
     }
 
     /// Parses a P2WPKH Bitcoin address
@@ -213,14 +480,9 @@ impl BtcAddress {
     /// # Errors
     /// - If the address is not a valid P2WPKH address.
     pub fn from_p2wpkh(s: &str) -> Result<Self, ParseError> {
-        let body = Self::strip_prefix(s)?;
-        if !body.starts_with('1') {
-            return Err(ParseError::InvalidPrefix);
-        }
-        if s.len() != 42 {
-            return Err(ParseError::InvalidLength);
-        }
-        Ok(BtcAddress::P2WPKH(s.to_string()))
+        Self::parse_as(s, Self::from_segwit, |address| {
+            matches!(address, BtcAddress::P2WPKH(_))
+        })
     }
 
     /// Parses a P2WSH Bitcoin address
@@ -228,14 +490,9 @@ impl BtcAddress {
     /// # Errors
     /// - If the address is not a valid P2WSH address.
     pub fn from_p2wsh(s: &str) -> Result<Self, ParseError> {
-        let body = Self::strip_prefix(s)?;
-        if !body.starts_with("1q") {
-            return Err(ParseError::InvalidPrefix);
-        }
-        if s.len() != 62 {
-            return Err(ParseError::InvalidLength);
-        }
-        Ok(BtcAddress::P2WSH(s.to_string()))
+        Self::parse_as(s, Self::from_segwit, |address| {
+            matches!(address, BtcAddress::P2WSH(_))
+        })
     }
 
     /// Parses a P2TR Bitcoin address
@@ -243,13 +500,8 @@ impl BtcAddress {
     /// # Errors
     /// - If the address is not a valid P2TR address.
     pub fn from_p2tr(s: &str) -> Result<Self, ParseError> {
-        let body = Self::strip_prefix(s)?;
-        if !body.starts_with("1p") {
-            return Err(ParseError::InvalidPrefix);
-        }
-        if s.len() != 62 {
-            return Err(ParseError::InvalidLength);
-        }
-        Ok(BtcAddress::P2TR(s.to_string()))
+        Self::parse_as(s, Self::from_segwit, |address| {
+            matches!(address, BtcAddress::P2TR(_))
+        })
     }
 }
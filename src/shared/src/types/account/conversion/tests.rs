@@ -3,6 +3,7 @@
 use std::fmt::Debug;
 
 use candid::Principal;
+use pretty_assertions::assert_eq;
 
 use super::*;
 
@@ -130,6 +131,85 @@ fn eth_test_vectors() -> Vec<TestVector<EthAddress>> {
     ]
 }
 
+fn kaspa_test_vectors() -> Vec<TestVector<KaspaAddress>> {
+    vec![
+        TestVector {
+            name: "Kaspa: Mainnet P2PK",
+            input: "kaspa:qpauqsvk7yf9unexwmxsnmg547mhyga37csh0kj53q6xxgl24ydxjsgzthw5j",
+            expected: Ok(KaspaAddress::Public(
+                "kaspa:qpauqsvk7yf9unexwmxsnmg547mhyga37csh0kj53q6xxgl24ydxjsgzthw5j".to_string(),
+            )),
+        },
+        TestVector {
+            name: "Kaspa: Mainnet ECDSA P2PK",
+            input: "kaspa:qyp29ulp7lqmp4897cr3s2f6fdwx6l50jzsm9s75uhmqwxpf8f94cmgeaf4w9t3",
+            expected: Ok(KaspaAddress::Public(
+                "kaspa:qyp29ulp7lqmp4897cr3s2f6fdwx6l50jzsm9s75uhmqwxpf8f94cmgeaf4w9t3".to_string(),
+            )),
+        },
+        TestVector {
+            name: "Kaspa: Mainnet P2SH",
+            input: "kaspa:precqv0krj3r6uyyfa36ga7s0u9jct0v4wg8ctsfde2gkrsgwgw8jgxfzfc98",
+            expected: Ok(KaspaAddress::Public(
+                "kaspa:precqv0krj3r6uyyfa36ga7s0u9jct0v4wg8ctsfde2gkrsgwgw8jgxfzfc98".to_string(),
+            )),
+        },
+        TestVector {
+            name: "Kaspa: Testnet P2PK",
+            input: "kaspatest:qqnapngv3zxp305qf06w6hpzmyxtx2r99jjhs04lu980xdyd2ulwwmx9evrfz",
+            expected: Ok(KaspaAddress::Public(
+                "kaspatest:qqnapngv3zxp305qf06w6hpzmyxtx2r99jjhs04lu980xdyd2ulwwmx9evrfz"
+                    .to_string(),
+            )),
+        },
+        TestVector {
+            name: "Kaspa: Testnet P2SH",
+            input: "kaspatest:pz308c0hcxcdfe0kquvzjwjtt3kharus5xev84897cr3s2f6fdwx6cns0ugsp",
+            expected: Ok(KaspaAddress::Public(
+                "kaspatest:pz308c0hcxcdfe0kquvzjwjtt3kharus5xev84897cr3s2f6fdwx6cns0ugsp"
+                    .to_string(),
+            )),
+        },
+        TestVector {
+            name: "Kaspa: Upper case is stored in lower case",
+            input: "KASPA:QPAUQSVK7YF9UNEXWMXSNMG547MHYGA37CSH0KJ53Q6XXGL24YDXJSGZTHW5J",
+            expected: Ok(KaspaAddress::Public(
+                "kaspa:qpauqsvk7yf9unexwmxsnmg547mhyga37csh0kj53q6xxgl24ydxjsgzthw5j".to_string(),
+            )),
+        },
+        TestVector {
+            name: "Kaspa: Unknown prefix",
+            input: "kaspax:qpauqsvk7yf9unexwmxsnmg547mhyga37csh0kj53q6xxgl24ydxjsgzthw5j",
+            expected: Err(ParseError::InvalidPrefix),
+        },
+        TestVector {
+            name: "Kaspa: Prefix of another network",
+            input: "kaspatest:qpauqsvk7yf9unexwmxsnmg547mhyga37csh0kj53q6xxgl24ydxjsgzthw5j",
+            expected: Err(ParseError::InvalidChecksum),
+        },
+        TestVector {
+            name: "Kaspa: Too short",
+            input: "kaspa:qyp9cat9eecq5f8tqjk4wp7w576vfm0ey8cx0j9qlyxg5q4u",
+            expected: Err(ParseError::InvalidLength),
+        },
+        TestVector {
+            name: "Kaspa: Invalid version",
+            input: "kaspa:zpauqsvk7yf9unexwmxsnmg547mhyga37csh0kj53q6xxgl24ydxjsgzthw5j",
+            expected: Err(ParseError::InvalidEncoding),
+        },
+        TestVector {
+            name: "Kaspa: Invalid characters",
+            input: "kaspa:qpauqsvk7yf9unexwmxsnmg547mhyga37csh0kj53q6xxgl24ydxjsgzthw5b",
+            expected: Err(ParseError::InvalidEncoding),
+        },
+        TestVector {
+            name: "Kaspa: Invalid checksum",
+            input: "kaspa:qpauqsvk7yf9unexwmxsnmg547mhyga37csh0kj53q6xxgl24ydxjsgzthw5k",
+            expected: Err(ParseError::InvalidChecksum),
+        },
+    ]
+}
+
 fn btc_test_vectors() -> Vec<TestVector<BtcAddress>> {
     vec![
         TestVector {
@@ -167,6 +247,100 @@ fn btc_test_vectors() -> Vec<TestVector<BtcAddress>> {
                 "bc1pxwww0ct9ue7e8tdnlmug5m2tamfn7q06sahstg39ys4c9f3340qqxrdu9k".to_string(),
             )),
         },
+        TestVector {
+            name: "BTC: Uppercase P2WPKH",
+            input: "BC1QQYPQXPQ9QCRSSZG2PVXQ6RS0ZQG3YYC5FCJ4Z3",
+            expected: Ok(BtcAddress::P2WPKH(
+                "bc1qqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5fcj4z3".to_string(),
+            )),
+        },
+        TestVector {
+            name: "BTC: Testnet P2PKH",
+            input: "mfcHP2WMCVLsVZA8yrovmhMgxNFW9r98xw",
+            expected: Ok(BtcAddress::P2PKH(
+                "mfcHP2WMCVLsVZA8yrovmhMgxNFW9r98xw".to_string(),
+            )),
+        },
+        TestVector {
+            name: "BTC: Testnet P2SH",
+            input: "2MsLZ5FqqYpjM1Q1W4X81zMVZTF9gdbhVwd",
+            expected: Ok(BtcAddress::P2SH(
+                "2MsLZ5FqqYpjM1Q1W4X81zMVZTF9gdbhVwd".to_string(),
+            )),
+        },
+        TestVector {
+            name: "BTC: Testnet P2WPKH",
+            input: "tb1qqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5r7fxez",
+            expected: Ok(BtcAddress::P2WPKH(
+                "tb1qqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5r7fxez".to_string(),
+            )),
+        },
+        TestVector {
+            name: "BTC: Testnet P2TR",
+            input: "tb1pqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5z5tpwxqergd3c8g7rusqe7ea7u",
+            expected: Ok(BtcAddress::P2TR(
+                "tb1pqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5z5tpwxqergd3c8g7rusqe7ea7u".to_string(),
+            )),
+        },
+        TestVector {
+            name: "BTC: Regtest P2WPKH",
+            input: "bcrt1qqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5phstwt",
+            expected: Ok(BtcAddress::P2WPKH(
+                "bcrt1qqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5phstwt".to_string(),
+            )),
+        },
+        TestVector {
+            name: "BTC: Regtest P2WSH",
+            input: "bcrt1qqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5z5tpwxqergd3c8g7rusq7snjn6",
+            expected: Ok(BtcAddress::P2WSH(
+                "bcrt1qqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5z5tpwxqergd3c8g7rusq7snjn6".to_string(),
+            )),
+        },
+        TestVector {
+            name: "BTC: Regtest P2TR",
+            input: "bcrt1pqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5z5tpwxqergd3c8g7rusq58nmtx",
+            expected: Ok(BtcAddress::P2TR(
+                "bcrt1pqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5z5tpwxqergd3c8g7rusq58nmtx".to_string(),
+            )),
+        },
+    ]
+    .into_iter()
+    .chain(btc_invalid_test_vectors())
+    .collect()
+}
+
+fn btc_invalid_test_vectors() -> Vec<TestVector<BtcAddress>> {
+    vec![
+        TestVector {
+            name: "BTC: P2SH with a bad checksum",
+            input: "342ftSRCvFHfCeFFBuz4xwbeqnDw6BGUez",
+            expected: Err(ParseError::InvalidChecksum),
+        },
+        TestVector {
+            name: "BTC: P2WPKH with a bad checksum",
+            input: "bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7d",
+            expected: Err(ParseError::InvalidChecksum),
+        },
+        TestVector {
+            name: "BTC: P2TR with a Bech32 checksum",
+            input: "bc1pqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5z5tpwxqergd3c8g7rusqm2l7p3",
+            expected: Err(ParseError::InvalidChecksum),
+        },
+        TestVector {
+            name: "BTC: P2WPKH with mixed case",
+            input: "bc1qQYPQXPQ9QCRSSZG2PVXQ6RS0ZQG3YYC5FCJ4Z3",
+            expected: Err(ParseError::InvalidEncoding),
+        },
+        TestVector {
+            name: "BTC: Unsupported witness version",
+            input: "bc1zqypqxpq9qcrsszg2pvxq6rs0zqg3yyc52390a9",
+            expected: Err(ParseError::UnsupportedFormat),
+        },
+        TestVector {
+            name: "BTC: Litecoin P2PKH",
+            input: "LKKHMBjCU89fyFNgSRprDoD8Jb25N8uWvd",
+            expected: Err(ParseError::InvalidPrefix),
+        },
     ]
 }
 
@@ -221,6 +395,23 @@ impl From<TestVector<EthAddress>> for TestVector<TokenAccountId> {
     }
 }
 
+impl From<TestVector<KaspaAddress>> for TestVector<TokenAccountId> {
+    fn from(value: TestVector<KaspaAddress>) -> Self {
+        let TestVector {
+            name,
+            input,
+            expected,
+        } = value;
+        TestVector {
+            name,
+            input,
+            expected: expected
+                .map(TokenAccountId::Kaspa)
+                .map_err(|_| ParseError::UnsupportedFormat),
+        }
+    }
+}
+
 impl From<TestVector<BtcAddress>> for TestVector<TokenAccountId> {
     fn from(value: TestVector<BtcAddress>) -> Self {
         let TestVector {
@@ -257,6 +448,11 @@ fn all_test_vectors() -> Vec<TestVector<TokenAccountId>> {
                 .into_iter()
                 .map(TestVector::<TokenAccountId>::from),
         )
+        .chain(
+            kaspa_test_vectors()
+                .into_iter()
+                .map(TestVector::<TokenAccountId>::from),
+        )
         .collect()
 }
 
@@ -295,9 +491,105 @@ fn btc_account_ids_can_be_parsed() {
     }
 }
 
+#[test]
+fn kaspa_account_ids_can_be_parsed() {
+    for vector in kaspa_test_vectors() {
+        assert_eq!(vector.expected, vector.input.parse(), "{}", vector.name);
+    }
+}
+
 #[test]
 fn all_test_vectors_can_be_parsed() {
     for vector in all_test_vectors() {
         assert_eq!(vector.expected, vector.input.parse(), "{}", vector.name);
     }
 }
+
+#[test]
+fn btc_addresses_report_their_network() {
+    let cases = [
+        ("1RainRzqJtJxHTngafpCejDLfYq2y4KBc", BitcoinNetwork::Mainnet),
+        (
+            "bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7c",
+            BitcoinNetwork::Mainnet,
+        ),
+        (
+            "2MsLZ5FqqYpjM1Q1W4X81zMVZTF9gdbhVwd",
+            BitcoinNetwork::Testnet,
+        ),
+        (
+            "tb1qqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5r7fxez",
+            BitcoinNetwork::Testnet,
+        ),
+        (
+            "bcrt1qqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5phstwt",
+            BitcoinNetwork::Regtest,
+        ),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(
+            BtcAddress::parse_with_network(input).map(|(_, network)| network),
+            Ok(expected),
+            "{input}"
+        );
+    }
+}
+
+#[test]
+fn btc_addresses_of_another_network_are_rejected() {
+    let cases = [
+        (
+            "bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7c",
+            BitcoinNetwork::Mainnet,
+            true,
+        ),
+        (
+            "bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7c",
+            BitcoinNetwork::Testnet,
+            false,
+        ),
+        (
+            "tb1qqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5r7fxez",
+            BitcoinNetwork::Mainnet,
+            false,
+        ),
+        (
+            "tb1qqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5r7fxez",
+            BitcoinNetwork::Regtest,
+            false,
+        ),
+        (
+            "bcrt1qqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5phstwt",
+            BitcoinNetwork::Testnet,
+            false,
+        ),
+        // Legacy addresses can't tell testnet from regtest.
+        (
+            "mfcHP2WMCVLsVZA8yrovmhMgxNFW9r98xw",
+            BitcoinNetwork::Testnet,
+            true,
+        ),
+        (
+            "mfcHP2WMCVLsVZA8yrovmhMgxNFW9r98xw",
+            BitcoinNetwork::Regtest,
+            true,
+        ),
+        (
+            "mfcHP2WMCVLsVZA8yrovmhMgxNFW9r98xw",
+            BitcoinNetwork::Mainnet,
+            false,
+        ),
+    ];
+    for (input, network, is_accepted) in cases {
+        let expected = if is_accepted {
+            Ok(input.parse::<BtcAddress>().expect("valid test address"))
+        } else {
+            Err(ParseError::NetworkMismatch)
+        };
+        assert_eq!(
+            BtcAddress::parse_for_network(input, network),
+            expected,
+            "{input} on {network:?}"
+        );
+    }
+}
@@ -2,7 +2,10 @@ use std::fmt::Debug;
 
 use candid::{CandidType, Deserialize, Principal};
 
-use crate::types::verifiable_credential::SupportedCredential;
+use crate::types::{
+    bitcoin::{BtcFeeTiersConfig, BtcPendingTransactionTtlConfig},
+    verifiable_credential::SupportedCredential,
+};
 
 #[derive(CandidType, Deserialize)]
 pub struct InitArg {
@@ -18,11 +21,16 @@ pub struct InitArg {
     /// Used to validate the id alias credential which includes the derivation origin of the id
     /// alias.
     pub derivation_origin: Option<String>,
+    /// Fee rates of the bitcoin priority tiers.  Defaults to `BtcFeeTiersConfig::default()`.
+    pub btc_fee_tiers: Option<BtcFeeTiersConfig>,
+    /// Time to live of the pending bitcoin transactions.  Defaults to
+    /// `BtcPendingTransactionTtlConfig::default()`.
+    pub btc_pending_transaction_ttls: Option<BtcPendingTransactionTtlConfig>,
 }
 
 #[derive(CandidType, Deserialize)]
 pub enum Arg {
-    Init(InitArg),
+    Init(Box<InitArg>),
     Upgrade,
 }
 
@@ -42,4 +50,9 @@ pub struct Config {
     /// Used to validate the id alias credential which includes the derivation origin of the id
     /// alias.
     pub derivation_origin: Option<String>,
+    /// Fee rates of the bitcoin priority tiers.  Defaults to `BtcFeeTiersConfig::default()`.
+    pub btc_fee_tiers: Option<BtcFeeTiersConfig>,
+    /// Time to live of the pending bitcoin transactions.  Defaults to
+    /// `BtcPendingTransactionTtlConfig::default()`.
+    pub btc_pending_transaction_ttls: Option<BtcPendingTransactionTtlConfig>,
 }
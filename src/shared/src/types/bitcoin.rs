@@ -1,18 +1,23 @@
 pub mod impls;
+pub mod payment_uri;
 
 use std::time::Duration;
 
-use candid::CandidType;
-use ic_cdk::api::management_canister::bitcoin::{BitcoinNetwork, MillisatoshiPerByte, Utxo};
+use candid::{CandidType, Principal};
+use ic_cdk::api::management_canister::bitcoin::{
+    BitcoinNetwork, MillisatoshiPerByte, Outpoint, Utxo,
+};
 use serde::Deserialize;
 
-use crate::types::signer::RateLimitError;
+use crate::types::{
+    account::BtcAddress, signer::RateLimitError, transaction::Transaction, Timestamp, Version,
+};
 
 /// The maximum length of a bitcoin address, expressed as a string.
 /// - The longest current formats seem to be `Bech32` and `Bech32m` which are up to 62 characters
-///   long.
+///   long on mainnet and testnet, and 64 characters long on regtest, whose prefix is `bcrt`.
 /// - Some obsolete formats seem to be at most 160 characters long.
-pub const MAX_ADDRESS_LEN: usize = 62;
+pub const MAX_ADDRESS_LEN: usize = 64;
 
 /// The maximum length of a single `txid`:
 ///
@@ -25,6 +30,16 @@ pub const MAX_TXID_BYTES: usize = 32;
 /// - Typical transactions apparently take 1-3 UTXOs;
 /// - Consolidation transactions typically take many more, however that doesn't apply to this API.
 pub const MAX_UTXOS_LEN: usize = 128;
+/// The maximum number of destinations of a single (batch) payment.
+pub const MAX_OUTPUTS_LEN: usize = 100;
+/// The maximum number of UTXOs that a user can freeze.
+pub const MAX_FROZEN_UTXOS: usize = 1000;
+/// The maximum number of unconfirmed ancestors of a pending transaction.  The change of a pending
+/// transaction can be spent before it confirms, and so on, up to this depth.
+pub const MAX_UNCONFIRMED_CHAIN_DEPTH: usize = 5;
+/// The height of the unconfirmed change outputs of pending transactions, which have no
+/// confirmations.
+pub const UNCONFIRMED_UTXO_HEIGHT: u32 = u32::MAX;
 
 /// Delay before the first async fee update, giving the canister time to settle after
 /// `init` or `post_upgrade` (stable memory deserialization uses heap).
@@ -48,11 +63,246 @@ pub struct BtcGetFeePercentilesResponse {
     pub fee_percentiles: Vec<MillisatoshiPerByte>,
 }
 
+/// Minimum time between two snapshots of the fee history of a network (10 minutes).
+pub const FEE_HISTORY_SNAPSHOT_INTERVAL_NS: u64 = 10 * 60 * 1_000_000_000;
+
+/// The maximum number of snapshots kept in the fee history of a network: a week of snapshots.
+pub const MAX_FEE_HISTORY_LEN: usize = 7 * 24 * 6;
+
+/// The fee rate of a network at a point in time.
+#[derive(CandidType, Deserialize, Clone, Copy, Eq, PartialEq, Debug)]
+pub struct BtcFeeSnapshot {
+    pub timestamp_ns: Timestamp,
+    /// The median of the fee percentiles, in millisatoshi per vbyte.
+    pub fee_millisatoshi_per_vbyte: MillisatoshiPerByte,
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub struct BtcGetFeeHistoryRequest {
+    pub network: BitcoinNetwork,
+    /// Only snapshots taken in the last `window_ns` nanoseconds are returned.  Defaults to the
+    /// whole history.
+    pub window_ns: Option<u64>,
+}
+
+/// Aggregation of the fee rates of a fee history, in millisatoshi per vbyte.
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct BtcFeeHistoryStats {
+    pub min_fee_millisatoshi_per_vbyte: MillisatoshiPerByte,
+    pub median_fee_millisatoshi_per_vbyte: MillisatoshiPerByte,
+    pub max_fee_millisatoshi_per_vbyte: MillisatoshiPerByte,
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct BtcGetFeeHistoryResponse {
+    /// The snapshots in the requested window, oldest first.
+    pub snapshots: Vec<BtcFeeSnapshot>,
+    /// `None` if there are no snapshots in the requested window.
+    pub stats: Option<BtcFeeHistoryStats>,
+}
+
+/// The maximum number of outgoing transactions kept in the send history of a user.  Recording a
+/// transaction into a full history evicts the oldest one.
+pub const MAX_SEND_HISTORY_LEN: usize = 1000;
+
+/// The maximum number of transactions in a page of the send history.
+pub const MAX_SEND_HISTORY_PAGE_LEN: u32 = 100;
+
+/// An outgoing bitcoin transaction of the send history of a user.
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct BtcSentTransaction {
+    /// Identifies the transaction in the history of the user, and increases with every send.
+    pub id: u64,
+    /// The latest txid of the transaction.
+    pub txid: Vec<u8>,
+    /// The payment, sent at `timestamp` in nanoseconds.
+    pub transaction: Transaction<BitcoinNetwork, BtcAddress>,
+    pub fee_satoshis: Option<u64>,
+    /// The transactions replaced by this one, oldest first.  `None` if it replaced none.
+    pub replaced_txids: Option<Vec<Vec<u8>>>,
+    /// When the transaction was last replaced, or sent if it was never replaced.
+    pub updated_at_timestamp_ns: Timestamp,
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub struct BtcGetSendHistoryRequest {
+    /// Only transactions of this network are returned.  Defaults to all networks.
+    pub network: Option<BitcoinNetwork>,
+    /// Only transactions older than the one with this `id` are returned.  Defaults to the newest
+    /// transactions.
+    pub before_id: Option<u64>,
+    /// The maximum number of transactions to return, at most `MAX_SEND_HISTORY_PAGE_LEN`.
+    /// Defaults to `MAX_SEND_HISTORY_PAGE_LEN`.
+    pub limit: Option<u32>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct BtcGetSendHistoryResponse {
+    /// The transactions of the page, newest first.
+    pub transactions: Vec<BtcSentTransaction>,
+    /// The `before_id` of the next page, or `None` if this is the last page.
+    pub next_before_id: Option<u64>,
+}
+
+/// The type of the caller's bitcoin addresses.
+#[derive(CandidType, Deserialize, Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum BtcAddressType {
+    /// A native segwit address, derived from the caller's ECDSA key.
+    #[default]
+    P2wpkh,
+    /// A taproot address, derived from the caller's BIP-340 Schnorr key and spent through its key
+    /// path.
+    P2tr,
+}
+
+/// The bitcoin settings of a user.
+#[derive(CandidType, Deserialize, Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub struct BtcSettings {
+    /// The address type used by the bitcoin endpoints when a request does not set one.
+    pub default_address_type: BtcAddressType,
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct UpdateUserBtcSettingsRequest {
+    pub btc: BtcSettings,
+    pub current_user_version: Option<Version>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub enum UpdateUserBtcSettingsError {
+    UserNotFound,
+    VersionMismatch,
+}
+
+/// How quickly a bitcoin transaction should be confirmed, which determines its fee rate.
+#[derive(CandidType, Deserialize, Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum BtcFeePriority {
+    Slow,
+    #[default]
+    Normal,
+    Fast,
+}
+
+/// The fee rate of a bitcoin transaction.
+#[derive(CandidType, Deserialize, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum BtcFeeRate {
+    /// The fee rate of a priority tier, derived from the cached fee percentiles.
+    Priority(BtcFeePriority),
+    /// A custom fee rate, in satoshi per vbyte.
+    SatoshiPerVbyte(u64),
+}
+
+/// Configures how the fee rates of the priority tiers are derived from the fee percentiles.
+///
+/// All fee rates are clamped to `[min_fee_millisatoshi_per_vbyte, max_fee_millisatoshi_per_vbyte]`,
+/// so that a bad percentile vector can't produce absurd fees.
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct BtcFeeTiersConfig {
+    /// The percentile, between 0 and 99, used for `BtcFeePriority::Slow`.
+    pub slow_percentile: u8,
+    /// The percentile, between 0 and 99, used for `BtcFeePriority::Normal`.
+    pub normal_percentile: u8,
+    /// The percentile, between 0 and 99, used for `BtcFeePriority::Fast`.
+    pub fast_percentile: u8,
+    pub min_fee_millisatoshi_per_vbyte: u64,
+    pub max_fee_millisatoshi_per_vbyte: u64,
+}
+
+/// How long a pending transaction reserves its UTXOs, by default: one hour.
+pub const DEFAULT_PENDING_TRANSACTION_TTL_NS: u64 = 60 * 60 * 1_000_000_000;
+
+/// Configures how long the pending transactions of each bitcoin network reserve their UTXOs.
+///
+/// A pending transaction that is not confirmed within the time to live of its network is
+/// considered failed: it is `Expired` and its UTXOs can be spent again.
+#[derive(CandidType, Deserialize, Clone, Copy, Eq, PartialEq, Debug)]
+pub struct BtcPendingTransactionTtlConfig {
+    pub mainnet_ns: u64,
+    pub testnet_ns: u64,
+    pub regtest_ns: u64,
+}
+
+/// The fee of a transaction at the fee rate of a priority tier.
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct BtcFeeQuote {
+    pub priority: BtcFeePriority,
+    pub fee_millisatoshi_per_vbyte: u64,
+    pub fee_satoshis: u64,
+}
+
+/// The goal that the UTXO selection should optimise for.
+#[derive(CandidType, Deserialize, Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum UtxosSelectionStrategy {
+    /// Picks the candidate selection with the lowest waste, i.e. the lowest fee now and later.
+    #[default]
+    MinimizeFee,
+    /// Picks the candidate selection that spends the fewest UTXOs.
+    MinimizeInputs,
+    /// Prefers a selection that needs no change output, falling back to one with change.
+    AvoidChange,
+}
+
+/// The algorithm that produced a UTXO selection.
+#[derive(CandidType, Deserialize, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum UtxosSelectionAlgorithm {
+    /// Changeless selection found by a branch-and-bound search.
+    BranchAndBound,
+    /// Selection found by the greedy algorithm, inspired by the ckBTC minter.
+    Greedy,
+    /// The UTXOs chosen by the caller.
+    Manual,
+    /// All the spendable UTXOs, spent without change.
+    SendMax,
+}
+
+/// A payment of `amount_satoshis` to `destination_address`.
 #[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct BtcTxOutput {
+    pub destination_address: String,
+    pub amount_satoshis: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+#[serde(remote = "Self")]
 pub struct SelectedUtxosFeeRequest {
     pub amount_satoshis: u64,
     pub network: BitcoinNetwork,
+    /// Defaults to the caller's `BtcSettings::default_address_type`.
+    pub address_type: Option<BtcAddressType>,
     pub min_confirmations: Option<u32>,
+    /// Defaults to `UtxosSelectionStrategy::MinimizeFee`.
+    pub strategy: Option<UtxosSelectionStrategy>,
+    /// The destinations of a batch payment.  If set, `amount_satoshis` must be their total.
+    /// If not set, the payment has a single destination.
+    pub outputs: Option<Vec<BtcTxOutput>>,
+    /// The destination of a single payment, used to estimate the size of its output.  Must not be
+    /// set together with `outputs`.
+    pub destination_address: Option<String>,
+    /// Defaults to `BtcFeeRate::Priority(BtcFeePriority::Normal)`.
+    pub fee_rate: Option<BtcFeeRate>,
+    /// The outpoints to spend, chosen by the caller.  If set, all of them are spent, even if they
+    /// are worth more than needed, and no UTXO is selected.  If not set, UTXOs are selected by
+    /// `strategy`, skipping the caller's frozen UTXOs.
+    pub inputs: Option<Vec<Outpoint>>,
+    /// Sends as much as possible to a single destination: every spendable UTXO of the caller, or
+    /// all the `inputs` if set, is spent without change, and the destination receives their value
+    /// net of the fee.  `amount_satoshis` is ignored.  Must not be set together with `outputs`.
+    pub send_max: Option<bool>,
+    /// Lets dust UTXOs, worth less than the dust threshold at the fee rate, be spent.  Dust is
+    /// often sent unsolicited to link addresses together, so it is otherwise never selected and
+    /// can't be among the `inputs`.
+    pub include_dust: Option<bool>,
+}
+
+/// The share of a transaction fee paid for one of its outputs.
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct SelectedUtxosFeeOutput {
+    pub destination_address: String,
+    pub amount_satoshis: u64,
+    /// The part of the transaction fee due to this output.
+    pub fee_satoshis: u64,
 }
 
 #[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
@@ -60,6 +310,19 @@ pub struct SelectedUtxosFeeRequest {
 pub struct SelectedUtxosFeeResponse {
     pub utxos: Vec<Utxo>,
     pub fee_satoshis: u64,
+    /// The amount received by the destinations.  This is the requested amount, or with
+    /// `send_max`, the value of the selected UTXOs net of the fee.
+    pub amount_satoshis: u64,
+    /// The algorithm whose selection was returned.
+    pub algorithm: UtxosSelectionAlgorithm,
+    /// Whether the transaction needs an output returning the change to the sender.
+    pub change_output: bool,
+    /// Breakdown per requested output.  Empty if the request had no `outputs`.
+    pub outputs: Vec<SelectedUtxosFeeOutput>,
+    /// The fee rate of `fee_satoshis`, after clamping.
+    pub fee_millisatoshi_per_vbyte: u64,
+    /// The fee of the transaction for every priority tier.  Empty if no UTXOs were selected.
+    pub fee_quotes: Vec<BtcFeeQuote>,
 }
 
 #[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
@@ -67,9 +330,43 @@ pub enum SelectedUtxosFeeError {
     InternalError {
         msg: String,
     },
+    /// No longer returned: the change of pending transactions can be spent.  Kept for
+    /// compatibility.
     PendingTransactions,
     /// The caller has exceeded the call rate limit.
     RateLimited(RateLimitError),
+    /// One or more of the `inputs` are not unspent outputs of the caller's address with enough
+    /// confirmations, or are duplicates.
+    InvalidInputs,
+    /// One or more of the `inputs` are reserved by a pending transaction.
+    InputsAlreadyReserved,
+    /// One or more of the `inputs` are frozen by the caller.
+    FrozenInputs,
+    /// The `inputs` are not worth the amount and the fee of the transaction.
+    InsufficientInputs {
+        available_satoshis: u64,
+        required_satoshis: u64,
+    },
+    /// One or more of the `inputs` are dust, which is only spent with `include_dust`.
+    DustInputs {
+        dust_threshold_satoshis: u64,
+    },
+}
+
+/// Freezes and unfreezes UTXOs of the caller.  Frozen UTXOs are never selected to be spent.
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub struct BtcUpdateFrozenUtxosRequest {
+    /// The outpoints to freeze.
+    pub freeze: Vec<Outpoint>,
+    /// The outpoints to unfreeze, after freezing.
+    pub unfreeze: Vec<Outpoint>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub enum BtcUpdateFrozenUtxosError {
+    /// The caller would have more than `max` frozen UTXOs.
+    TooManyFrozenUtxos { max: u64 },
 }
 
 #[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
@@ -78,6 +375,204 @@ pub struct BtcAddPendingTransactionRequest {
     pub txid: Vec<u8>,
     pub utxos: Vec<Utxo>,
     pub network: BitcoinNetwork,
+    /// Defaults to the caller's `BtcSettings::default_address_type`.
+    pub address_type: Option<BtcAddressType>,
+    /// The fee paid by the transaction.  Without it, the transaction can't be replaced.
+    pub fee_satoshis: Option<u64>,
+    /// The recipient of the payment.
+    pub destination_address: Option<String>,
+    /// The amount paid to `destination_address`.
+    pub amount_satoshis: Option<u64>,
+    /// The output returning the change to the caller.  Follow-up transactions may spend it before
+    /// this transaction confirms.
+    pub change_output: Option<BtcChangeOutput>,
+}
+
+/// The output of a transaction that returns the change to its sender.
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct BtcChangeOutput {
+    pub vout: u32,
+    pub amount_satoshis: u64,
+}
+
+/// The maximum number of times a pending transaction can be replaced.
+pub const MAX_REPLACEMENTS: usize = 20;
+
+/// Replaces a pending transaction by one spending (some of) the same UTXOs with a higher fee, as
+/// specified in BIP-125.
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub struct BtcReplacePendingTransactionRequest {
+    /// The pending transaction being replaced.
+    pub replaced_txid: Vec<u8>,
+    pub txid: Vec<u8>,
+    pub utxos: Vec<Utxo>,
+    pub network: BitcoinNetwork,
+    /// Defaults to the caller's `BtcSettings::default_address_type`.
+    pub address_type: Option<BtcAddressType>,
+    pub fee_satoshis: u64,
+    /// The recipient of the replacement.
+    pub destination_address: Option<String>,
+    /// The amount paid to `destination_address`, which a fee bump may lower.
+    pub amount_satoshis: Option<u64>,
+    /// The output returning the change to the caller.
+    pub change_output: Option<BtcChangeOutput>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub enum BtcReplacePendingTransactionError {
+    /// The provided list of UTXOs is empty
+    EmptyUtxos,
+    /// One or more provided UTXOs are duplicates among themselves
+    DuplicateUtxos,
+    /// One or more provided UTXOs not in current UTXO list for the address
+    InvalidUtxos,
+    /// The caller has no pending transaction with the replaced txid.
+    ReplacedTransactionNotFound,
+    /// The replaced transaction was registered without its fee.
+    ReplacedTransactionFeeUnknown,
+    /// None of the provided UTXOs is spent by the replaced transaction.
+    NoReplacedUtxos,
+    /// Intersects with caller's other pending reservations
+    UtxosAlreadyReserved,
+    /// The fee does not exceed the fee of the replaced transaction by enough.
+    InsufficientFee { min_fee_satoshis: u64 },
+    /// The replaced transaction has already been replaced `MAX_REPLACEMENTS` times.
+    MaxReplacementsReached,
+    /// The change of the replaced transaction is spent by other pending transactions, which the
+    /// replacement would invalidate.
+    ChangeSpentByPendingTransactions,
+    /// Server-side / unexpected
+    InternalError { msg: String },
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub struct BtcGetFeeBumpQuotesRequest {
+    /// The pending transaction to replace.
+    pub txid: Vec<u8>,
+    pub network: BitcoinNetwork,
+    /// Defaults to the caller's `BtcSettings::default_address_type`.
+    pub address_type: Option<BtcAddressType>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct BtcGetFeeBumpQuotesResponse {
+    /// The lowest fee that a replacement transaction may pay.
+    pub min_fee_satoshis: u64,
+    /// The fee of a replacement transaction for every priority tier, never below
+    /// `min_fee_satoshis`.
+    pub fee_quotes: Vec<BtcFeeQuote>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub enum BtcGetFeeBumpQuotesError {
+    /// The caller has no pending transaction with the requested txid.
+    TransactionNotFound,
+    /// The transaction was registered without its fee.
+    TransactionFeeUnknown,
+    InternalError {
+        msg: String,
+    },
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub struct BtcBuildPsbtRequest {
+    pub network: BitcoinNetwork,
+    /// Defaults to the caller's `BtcSettings::default_address_type`.
+    pub address_type: Option<BtcAddressType>,
+    /// The destinations of the payment.
+    pub outputs: Vec<BtcTxOutput>,
+    /// Defaults to `BtcFeeRate::Priority(BtcFeePriority::Normal)`.
+    pub fee_rate: Option<BtcFeeRate>,
+    /// Defaults to `UtxosSelectionStrategy::MinimizeFee`.
+    pub strategy: Option<UtxosSelectionStrategy>,
+    pub min_confirmations: Option<u32>,
+    /// Lets dust UTXOs be selected, as in `SelectedUtxosFeeRequest`.
+    pub include_dust: Option<bool>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub struct BtcBuildPsbtResponse {
+    /// The unsigned transaction as a PSBT, serialized as specified in BIP-174.
+    pub psbt: Vec<u8>,
+    /// The UTXOs spent by the transaction, in the order of its inputs.
+    pub utxos: Vec<Utxo>,
+    pub fee_satoshis: u64,
+    /// The amount returned to the caller's address, or 0 if the transaction has no change output.
+    pub change_satoshis: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub enum BtcBuildPsbtError {
+    /// A destination is not a valid address on the requested network.
+    InvalidDestinationAddress {
+        address: String,
+    },
+    /// The caller's UTXOs can't pay for the outputs and the fee.
+    InsufficientFunds,
+    /// No longer returned: the change of pending transactions can be spent.  Kept for
+    /// compatibility.
+    PendingTransactions,
+    /// The caller has exceeded the call rate limit.
+    RateLimited(RateLimitError),
+    InternalError {
+        msg: String,
+    },
+}
+
+/// The fewest UTXOs for which a consolidation is recommended.
+pub const MIN_CONSOLIDATION_UTXOS: usize = 10;
+
+/// The most UTXOs spent by a consolidation transaction, which bounds its size.
+pub const MAX_CONSOLIDATION_UTXOS: usize = 100;
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub struct BtcGetConsolidationAdviceRequest {
+    pub network: BitcoinNetwork,
+    /// Defaults to the caller's `BtcSettings::default_address_type`.
+    pub address_type: Option<BtcAddressType>,
+    /// Defaults to `BtcFeeRate::Priority(BtcFeePriority::Slow)`, since a consolidation is not
+    /// urgent.
+    pub fee_rate: Option<BtcFeeRate>,
+    pub min_confirmations: Option<u32>,
+    /// Whether to build the consolidation transaction, if it is recommended.
+    pub build_psbt: bool,
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub struct BtcGetConsolidationAdviceResponse {
+    /// Whether consolidating the UTXOs now is expected to save fees.
+    pub recommended: bool,
+    /// The UTXOs to consolidate into a single output, smallest first.
+    pub utxos: Vec<Utxo>,
+    /// The fee of the consolidation transaction.
+    pub fee_satoshis: u64,
+    /// The fee rate of the consolidation transaction.
+    pub fee_millisatoshi_per_vbyte: u64,
+    /// The fee rate at which the UTXOs are expected to be spent otherwise: the median of the fee
+    /// history.
+    pub reference_fee_millisatoshi_per_vbyte: u64,
+    /// The fees expected to be saved by spending the consolidated output instead of the UTXOs at
+    /// the reference fee rate, net of the fee of the consolidation transaction.
+    pub savings_satoshis: u64,
+    /// The unsigned consolidation transaction as a PSBT, if it was requested and the consolidation
+    /// is recommended.
+    pub psbt: Option<Vec<u8>>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub enum BtcGetConsolidationAdviceError {
+    PendingTransactions,
+    /// The caller has exceeded the call rate limit.
+    RateLimited(RateLimitError),
+    InternalError {
+        msg: String,
+    },
 }
 
 #[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
@@ -94,6 +589,62 @@ pub enum BtcAddPendingTransactionError {
     InternalError { msg: String },
 }
 
+/// The largest signed transaction accepted for broadcast, in bytes.  Standard transactions weigh
+/// at most 400 000 weight units, so none is bigger.
+pub const MAX_TRANSACTION_BYTES: usize = 400_000;
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub struct BtcSendTransactionRequest {
+    pub network: BitcoinNetwork,
+    /// Defaults to the caller's `BtcSettings::default_address_type`.
+    pub address_type: Option<BtcAddressType>,
+    /// The signed transaction, serialized in the consensus format.
+    pub transaction: Vec<u8>,
+    /// The destinations of the payment, as quoted to the caller.  The transaction must pay exactly
+    /// these outputs, and at most one change output to the caller's address.
+    pub outputs: Vec<BtcTxOutput>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct BtcSendTransactionResponse {
+    pub txid: Vec<u8>,
+    /// The UTXOs spent by the transaction, in the order of its inputs.
+    pub utxos: Vec<Utxo>,
+    pub fee_satoshis: u64,
+    /// The output returning the change to the caller, if any.
+    pub change_output: Option<BtcChangeOutput>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub enum BtcSendTransactionError {
+    /// The transaction can't be decoded, or its outputs are worth more than its inputs.
+    InvalidTransaction {
+        msg: String,
+    },
+    /// A quoted destination is not a valid address on the requested network.
+    InvalidDestinationAddress {
+        address: String,
+    },
+    /// The transaction spends the same UTXO twice.
+    DuplicateUtxos,
+    /// The transaction spends UTXOs that are not current UTXOs of the caller's address.
+    InvalidUtxos,
+    /// The transaction spends UTXOs reserved by pending transactions.
+    UtxosAlreadyReserved,
+    /// The outputs of the transaction are not the quoted outputs and a change output.
+    OutputsMismatch,
+    /// The bitcoin canister rejected the transaction.  No UTXO is reserved.
+    BroadcastFailed {
+        msg: String,
+    },
+    /// The caller has exceeded the call rate limit.
+    RateLimited(RateLimitError),
+    InternalError {
+        msg: String,
+    },
+}
+
 #[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
 #[serde(remote = "Self")]
 pub struct BtcGetPendingTransactionsRequest {
@@ -101,11 +652,36 @@ pub struct BtcGetPendingTransactionsRequest {
     pub network: BitcoinNetwork,
 }
 
+/// The lifecycle of a pending transaction.
+#[derive(CandidType, Deserialize, Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum BtcPendingTransactionStatus {
+    /// The UTXOs of the transaction are reserved until it is confirmed or expires.
+    #[default]
+    Pending,
+    /// None of the UTXOs of the transaction is unspent any more.
+    Confirmed,
+    /// The transaction was not confirmed in time, and its UTXOs are available again.
+    Expired,
+}
+
 #[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
 #[serde(remote = "Self")]
 pub struct PendingTransaction {
     pub txid: Vec<u8>,
     pub utxos: Vec<Utxo>,
+    pub fee_satoshis: Option<u64>,
+    /// The transactions replaced by this one, oldest first.
+    pub replaced_txids: Vec<Vec<u8>>,
+    pub destination_address: Option<String>,
+    pub amount_satoshis: Option<u64>,
+    pub network: Option<BitcoinNetwork>,
+    pub status: BtcPendingTransactionStatus,
+    pub created_at_timestamp_ns: Timestamp,
+    /// When the transaction was confirmed or expired.
+    pub finalized_at_timestamp_ns: Option<Timestamp>,
+    pub change_output: Option<BtcChangeOutput>,
+    /// The pending transactions whose change this one spends.
+    pub parent_txids: Vec<Vec<u8>>,
 }
 
 #[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
@@ -114,6 +690,32 @@ pub struct StoredPendingTransaction {
     pub txid: Vec<u8>,
     pub utxos: Vec<Utxo>,
     pub created_at_timestamp_ns: u64,
+    pub fee_satoshis: Option<u64>,
+    /// The transactions replaced by this one, oldest first.  `None` if it replaced none.
+    pub replaced_txids: Option<Vec<Vec<u8>>>,
+    pub destination_address: Option<String>,
+    pub amount_satoshis: Option<u64>,
+    pub network: Option<BitcoinNetwork>,
+    /// `None` for transactions stored before their status was tracked, which are pending.
+    pub status: Option<BtcPendingTransactionStatus>,
+    /// When the transaction was confirmed or expired.
+    pub finalized_at_timestamp_ns: Option<Timestamp>,
+    /// The output returning the change to the sender, spendable before confirmation.
+    pub change_output: Option<BtcChangeOutput>,
+    /// The pending transactions whose change this one spends.  `None` if it spends none.
+    pub parent_txids: Option<Vec<Vec<u8>>>,
+}
+
+/// The pending transaction that reserves an outpoint.
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct StoredOutpointReservation {
+    pub principal: Principal,
+    pub address: String,
+    pub txid: Vec<u8>,
+    /// When the reserving transaction was created: the reservation lapses with its expiry.
+    pub created_at_timestamp_ns: Timestamp,
+    /// The network of the reserving transaction, which sets its time to live.
+    pub network: Option<BitcoinNetwork>,
 }
 
 #[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
@@ -125,3 +727,180 @@ pub struct BtcGetPendingTransactionsReponse {
 pub enum BtcGetPendingTransactionsError {
     InternalError { msg: String },
 }
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct BtcGetBalanceRequest {
+    pub network: BitcoinNetwork,
+    /// Defaults to the caller's `BtcSettings::default_address_type`.
+    pub address_type: Option<BtcAddressType>,
+    /// The confirmations for a UTXO to count as confirmed.  Defaults to the confirmations that
+    /// the other endpoints require to spend a UTXO.
+    pub min_confirmations: Option<u32>,
+}
+
+/// The balance of the caller's address, in satoshi.
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug, Default)]
+pub struct BtcGetBalanceResponse {
+    /// The value of all the UTXOs of the address.
+    pub total_satoshis: u64,
+    /// The value of the UTXOs with at least `min_confirmations` confirmations.
+    pub confirmed_satoshis: u64,
+    /// The value of the UTXOs with fewer than `min_confirmations` confirmations.
+    pub unconfirmed_satoshis: u64,
+    /// The value of the UTXOs spent by the caller's pending transactions.
+    pub reserved_satoshis: u64,
+    /// The value of the UTXOs frozen by the caller that no pending transaction spends.
+    pub frozen_satoshis: u64,
+    /// The value of the confirmed UTXOs that are neither frozen nor spent by a pending
+    /// transaction.
+    pub spendable_satoshis: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub enum BtcGetBalanceError {
+    InternalError {
+        msg: String,
+    },
+    /// The caller has exceeded the call rate limit.
+    RateLimited(RateLimitError),
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct BtcGetDustReportRequest {
+    pub network: BitcoinNetwork,
+    /// Defaults to the caller's `BtcSettings::default_address_type`.
+    pub address_type: Option<BtcAddressType>,
+    /// The fee rate of the dust threshold.  Defaults to
+    /// `BtcFeeRate::Priority(BtcFeePriority::Normal)`.
+    pub fee_rate: Option<BtcFeeRate>,
+}
+
+/// A dust UTXO of the caller's address.
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct BtcDustUtxo {
+    pub utxo: Utxo,
+    /// Whether the UTXO was not created by one of the caller's own transactions, and may have been
+    /// sent to link the caller's addresses once spent together with their other UTXOs.
+    pub suspected_attack: bool,
+    /// Whether the caller froze the UTXO.
+    pub frozen: bool,
+}
+
+/// The dust UTXOs of the caller's address, which are excluded from the automatic selection.
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct BtcGetDustReportResponse {
+    /// The value, in satoshi, below which a UTXO is dust at the fee rate.
+    pub dust_threshold_satoshis: u64,
+    pub utxos: Vec<BtcDustUtxo>,
+    /// The value of the dust UTXOs.
+    pub total_satoshis: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub enum BtcGetDustReportError {
+    InternalError {
+        msg: String,
+    },
+    /// The caller has exceeded the call rate limit.
+    RateLimited(RateLimitError),
+}
+
+/// Interval between two polls of the addresses subscribed to deposit detection.
+pub const DEPOSIT_POLL_INTERVAL: Duration = Duration::from_secs(2 * 60);
+
+/// Safety timeout: if a poll has been "in progress" for longer than this, assume it was lost to a
+/// trap and allow a new one.  Set to 5× the poll interval.
+pub const DEPOSIT_POLL_TIMEOUT_NS: u64 = 5 * DEPOSIT_POLL_INTERVAL.as_secs() * 1_000_000_000;
+
+/// The maximum number of users whose subscribed addresses are polled for deposits at every
+/// `DEPOSIT_POLL_INTERVAL`.
+pub const DEPOSIT_POLL_BATCH_LEN: usize = 10;
+
+/// The number of blocks below the scanned height that every poll scans again, since a
+/// reorganization of the chain can mine a deposit again in a block that was already scanned.
+pub const DEPOSIT_REORG_DEPTH: u32 = 6;
+
+/// The maximum number of deposits kept in the deposit feed of a user.  Recording a deposit into a
+/// full feed evicts the oldest one.
+pub const MAX_DEPOSIT_EVENTS_LEN: usize = 1000;
+
+/// The maximum number of deposits in a page of the deposit feed.
+pub const MAX_DEPOSIT_EVENTS_PAGE_LEN: u32 = 100;
+
+/// An address of the user that is polled for incoming deposits.
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct BtcDepositSubscription {
+    pub network: BitcoinNetwork,
+    pub address_type: BtcAddressType,
+    pub address: String,
+    pub subscribed_at_timestamp_ns: Timestamp,
+    /// The height of the tip of the chain at subscription: the UTXOs at or below it are not
+    /// deposits.  Set by the first poll if missing.
+    pub subscribed_at_height: Option<u32>,
+    /// The height of the tip of the chain at the last poll, or at subscription before the first
+    /// poll.  The UTXOs above it are new deposits.
+    pub scanned_height: Option<u32>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct BtcSubscribeDepositsRequest {
+    pub network: BitcoinNetwork,
+    /// Defaults to the caller's `BtcSettings::default_address_type`.
+    pub address_type: Option<BtcAddressType>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub enum BtcSubscribeDepositsError {
+    InternalError {
+        msg: String,
+    },
+    /// The caller has exceeded the call rate limit.
+    RateLimited(RateLimitError),
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct BtcUnsubscribeDepositsRequest {
+    pub network: BitcoinNetwork,
+    /// Defaults to the caller's `BtcSettings::default_address_type`.
+    pub address_type: Option<BtcAddressType>,
+}
+
+/// An incoming payment to a subscribed address of the user, i.e. a new UTXO that was not created
+/// by one of the user's own transactions.
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct BtcDepositEvent {
+    /// Identifies the deposit in the feed of the user, and increases with every deposit.
+    pub id: u64,
+    pub network: BitcoinNetwork,
+    /// The subscribed address that received the deposit.
+    pub address: String,
+    pub txid: Vec<u8>,
+    pub vout: u32,
+    pub amount_satoshis: u64,
+    /// The height of the block that includes the deposit.
+    pub height: u32,
+    /// The number of confirmations of the deposit when it was detected.
+    pub confirmations: u32,
+    pub detected_at_timestamp_ns: Timestamp,
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub struct BtcGetDepositEventsRequest {
+    /// Only deposits of this network are returned.  Defaults to all networks.
+    pub network: Option<BitcoinNetwork>,
+    /// Only deposits older than the one with this `id` are returned.  Defaults to the newest
+    /// deposits.
+    pub before_id: Option<u64>,
+    /// The maximum number of deposits to return, at most `MAX_DEPOSIT_EVENTS_PAGE_LEN`.
+    /// Defaults to `MAX_DEPOSIT_EVENTS_PAGE_LEN`.
+    pub limit: Option<u32>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct BtcGetDepositEventsResponse {
+    /// The deposits of the page, newest first.
+    pub events: Vec<BtcDepositEvent>,
+    /// The `before_id` of the next page, or `None` if this is the last page.
+    pub next_before_id: Option<u64>,
+}
@@ -1,15 +1,26 @@
 //! Methods for Bitcoin data transfer objects
 
 use candid::Deserialize;
-use ic_cdk::api::management_canister::bitcoin::Utxo;
+use ic_cdk::api::management_canister::bitcoin::{BitcoinNetwork, Outpoint, Utxo};
 use serde::{de, Deserializer};
 
 use super::{
-    BtcAddPendingTransactionRequest, BtcGetPendingTransactionsRequest, PendingTransaction,
-    SelectedUtxosFeeResponse, StoredPendingTransaction, MAX_ADDRESS_LEN, MAX_TXID_BYTES,
-    MAX_UTXOS_LEN,
+    BtcAddPendingTransactionRequest, BtcBuildPsbtRequest, BtcBuildPsbtResponse, BtcChangeOutput,
+    BtcFeeRate, BtcFeeTiersConfig, BtcGetConsolidationAdviceRequest,
+    BtcGetConsolidationAdviceResponse, BtcGetDepositEventsRequest, BtcGetFeeBumpQuotesRequest,
+    BtcGetFeeHistoryRequest, BtcGetPendingTransactionsRequest, BtcGetSendHistoryRequest,
+    BtcPendingTransactionStatus, BtcPendingTransactionTtlConfig,
+    BtcReplacePendingTransactionRequest, BtcSendTransactionRequest, BtcTxOutput,
+    BtcUpdateFrozenUtxosRequest, PendingTransaction, SelectedUtxosFeeRequest,
+    SelectedUtxosFeeResponse, StoredPendingTransaction, DEFAULT_PENDING_TRANSACTION_TTL_NS,
+    MAX_ADDRESS_LEN, MAX_DEPOSIT_EVENTS_PAGE_LEN, MAX_FROZEN_UTXOS, MAX_OUTPUTS_LEN,
+    MAX_REPLACEMENTS, MAX_SEND_HISTORY_PAGE_LEN, MAX_TRANSACTION_BYTES, MAX_TXID_BYTES,
+    MAX_UTXOS_LEN, UNCONFIRMED_UTXO_HEIGHT,
+};
+use crate::{
+    types::account::{conversion::ParseError, BtcAddress},
+    validate::{validate_on_deserialize, Validate},
 };
-use crate::validate::{validate_on_deserialize, Validate};
 
 fn validate_utxo(utxo: &Utxo) -> Result<(), candid::Error> {
     let len = utxo.outpoint.txid.len();
@@ -33,6 +44,18 @@ fn validate_utxo_vec(utxos: &[Utxo]) -> Result<(), candid::Error> {
     }
     Ok(())
 }
+fn validate_outpoint_vec(outpoints: &[Outpoint], max_len: usize) -> Result<(), candid::Error> {
+    if outpoints.len() > max_len {
+        return Err(candid::Error::msg(format!(
+            "Too many outpoints: {} > {max_len}",
+            outpoints.len()
+        )));
+    }
+    for outpoint in outpoints {
+        validate_txid_bytes(&outpoint.txid)?;
+    }
+    Ok(())
+}
 fn validate_txid_bytes(txid: &[u8]) -> Result<(), candid::Error> {
     let len = txid.len();
     if len > MAX_TXID_BYTES {
@@ -42,6 +65,39 @@ fn validate_txid_bytes(txid: &[u8]) -> Result<(), candid::Error> {
     }
     Ok(())
 }
+fn validate_replaced_txids(replaced_txids: &[Vec<u8>]) -> Result<(), candid::Error> {
+    if replaced_txids.len() > MAX_REPLACEMENTS {
+        return Err(candid::Error::msg(format!(
+            "Too many replaced transactions: {} > {}",
+            replaced_txids.len(),
+            MAX_REPLACEMENTS
+        )));
+    }
+    for txid in replaced_txids {
+        validate_txid_bytes(txid)?;
+    }
+    Ok(())
+}
+fn validate_parent_txids(parent_txids: &[Vec<u8>]) -> Result<(), candid::Error> {
+    // A transaction spends the change of at most one parent per input.
+    if parent_txids.len() > MAX_UTXOS_LEN {
+        return Err(candid::Error::msg(format!(
+            "Too many parent transactions: {} > {}",
+            parent_txids.len(),
+            MAX_UTXOS_LEN
+        )));
+    }
+    for txid in parent_txids {
+        validate_txid_bytes(txid)?;
+    }
+    Ok(())
+}
+fn validate_change_output(change_output: Option<&BtcChangeOutput>) -> Result<(), candid::Error> {
+    if change_output.is_some_and(|change_output| change_output.amount_satoshis == 0) {
+        return Err(candid::Error::msg("Change amount must be positive"));
+    }
+    Ok(())
+}
 fn validate_address(address: &str) -> Result<(), candid::Error> {
     let len = address.len();
     if len > MAX_ADDRESS_LEN {
@@ -51,22 +107,222 @@ fn validate_address(address: &str) -> Result<(), candid::Error> {
     }
     Ok(())
 }
+/// Validates the destination address of a payment on the given network.
+///
+/// Addresses that can't be parsed are left to the caller, but a valid address of another network
+/// is rejected, so that e.g. a testnet address can't be used in a mainnet payment.
+fn validate_destination_address(
+    address: &str,
+    network: BitcoinNetwork,
+) -> Result<(), candid::Error> {
+    validate_address(address)?;
+    if BtcAddress::parse_for_network(address, network) == Err(ParseError::NetworkMismatch) {
+        return Err(candid::Error::msg(format!(
+            "Bitcoin address is not a {network:?} address: {address}"
+        )));
+    }
+    Ok(())
+}
+
+/// Validates the outputs of a payment on the given network and returns their total amount.
+fn validate_outputs(
+    outputs: &[BtcTxOutput],
+    network: BitcoinNetwork,
+) -> Result<u64, candid::Error> {
+    if outputs.is_empty() {
+        return Err(candid::Error::msg("Outputs must not be empty"));
+    }
+    if outputs.len() > MAX_OUTPUTS_LEN {
+        return Err(candid::Error::msg(format!(
+            "Too many outputs: {} > {}",
+            outputs.len(),
+            MAX_OUTPUTS_LEN
+        )));
+    }
+    let mut total: u64 = 0;
+    for output in outputs {
+        validate_destination_address(&output.destination_address, network)?;
+        if output.amount_satoshis == 0 {
+            return Err(candid::Error::msg("Output amount must be positive"));
+        }
+        total = total
+            .checked_add(output.amount_satoshis)
+            .ok_or_else(|| candid::Error::msg("Total output amount overflows"))?;
+    }
+    Ok(total)
+}
+
+fn validate_fee_rate(fee_rate: Option<BtcFeeRate>) -> Result<(), candid::Error> {
+    if fee_rate == Some(BtcFeeRate::SatoshiPerVbyte(0)) {
+        return Err(candid::Error::msg("Fee rate must be positive"));
+    }
+    Ok(())
+}
+
+impl Validate for SelectedUtxosFeeRequest {
+    fn validate(&self) -> Result<(), candid::Error> {
+        validate_fee_rate(self.fee_rate)?;
+        if let Some(inputs) = &self.inputs {
+            validate_outpoint_vec(inputs, MAX_UTXOS_LEN)?;
+        }
+        if self.send_max == Some(true) && self.outputs.is_some() {
+            return Err(candid::Error::msg(
+                "Send max must not be set together with outputs",
+            ));
+        }
+        match (&self.outputs, &self.destination_address) {
+            (Some(_), Some(_)) => Err(candid::Error::msg(
+                "Destination address must not be set together with outputs",
+            )),
+            (Some(outputs), None) => {
+                let total = validate_outputs(outputs, self.network)?;
+                if total != self.amount_satoshis {
+                    return Err(candid::Error::msg(format!(
+                        "Amount does not match the total of the outputs: {} != {total}",
+                        self.amount_satoshis
+                    )));
+                }
+                Ok(())
+            }
+            (None, Some(address)) => validate_destination_address(address, self.network),
+            (None, None) => Ok(()),
+        }
+    }
+}
+validate_on_deserialize!(SelectedUtxosFeeRequest);
+
+impl Validate for BtcUpdateFrozenUtxosRequest {
+    fn validate(&self) -> Result<(), candid::Error> {
+        validate_outpoint_vec(&self.freeze, MAX_FROZEN_UTXOS)?;
+        validate_outpoint_vec(&self.unfreeze, MAX_FROZEN_UTXOS)
+    }
+}
+validate_on_deserialize!(BtcUpdateFrozenUtxosRequest);
 
 impl Validate for SelectedUtxosFeeResponse {
     fn validate(&self) -> Result<(), candid::Error> {
+        if self.outputs.len() > MAX_OUTPUTS_LEN {
+            return Err(candid::Error::msg(format!(
+                "Too many outputs: {} > {}",
+                self.outputs.len(),
+                MAX_OUTPUTS_LEN
+            )));
+        }
         validate_utxo_vec(&self.utxos)
     }
 }
 validate_on_deserialize!(SelectedUtxosFeeResponse);
 
+impl Validate for BtcBuildPsbtRequest {
+    fn validate(&self) -> Result<(), candid::Error> {
+        validate_fee_rate(self.fee_rate)?;
+        validate_outputs(&self.outputs, self.network).map(|_| ())
+    }
+}
+validate_on_deserialize!(BtcBuildPsbtRequest);
+
+impl Validate for BtcBuildPsbtResponse {
+    fn validate(&self) -> Result<(), candid::Error> {
+        validate_utxo_vec(&self.utxos)
+    }
+}
+validate_on_deserialize!(BtcBuildPsbtResponse);
+
+impl Validate for BtcGetConsolidationAdviceRequest {
+    fn validate(&self) -> Result<(), candid::Error> {
+        validate_fee_rate(self.fee_rate)
+    }
+}
+validate_on_deserialize!(BtcGetConsolidationAdviceRequest);
+
+impl Validate for BtcGetConsolidationAdviceResponse {
+    fn validate(&self) -> Result<(), candid::Error> {
+        validate_utxo_vec(&self.utxos)
+    }
+}
+validate_on_deserialize!(BtcGetConsolidationAdviceResponse);
+
 impl Validate for BtcAddPendingTransactionRequest {
     fn validate(&self) -> Result<(), candid::Error> {
         validate_txid_bytes(&self.txid)?;
+        if let Some(address) = &self.destination_address {
+            validate_destination_address(address, self.network)?;
+        }
+        validate_change_output(self.change_output.as_ref())?;
         validate_utxo_vec(&self.utxos)
     }
 }
 validate_on_deserialize!(BtcAddPendingTransactionRequest);
 
+impl Validate for BtcSendTransactionRequest {
+    fn validate(&self) -> Result<(), candid::Error> {
+        let len = self.transaction.len();
+        if len > MAX_TRANSACTION_BYTES {
+            return Err(candid::Error::msg(format!(
+                "Transaction has too many bytes: {len} > {MAX_TRANSACTION_BYTES}"
+            )));
+        }
+        validate_outputs(&self.outputs, self.network).map(|_| ())
+    }
+}
+validate_on_deserialize!(BtcSendTransactionRequest);
+
+impl Validate for BtcReplacePendingTransactionRequest {
+    fn validate(&self) -> Result<(), candid::Error> {
+        validate_txid_bytes(&self.replaced_txid)?;
+        validate_txid_bytes(&self.txid)?;
+        if let Some(address) = &self.destination_address {
+            validate_destination_address(address, self.network)?;
+        }
+        validate_change_output(self.change_output.as_ref())?;
+        validate_utxo_vec(&self.utxos)
+    }
+}
+validate_on_deserialize!(BtcReplacePendingTransactionRequest);
+
+impl Validate for BtcGetFeeBumpQuotesRequest {
+    fn validate(&self) -> Result<(), candid::Error> {
+        validate_txid_bytes(&self.txid)
+    }
+}
+validate_on_deserialize!(BtcGetFeeBumpQuotesRequest);
+
+impl Validate for BtcGetFeeHistoryRequest {
+    fn validate(&self) -> Result<(), candid::Error> {
+        if self.window_ns == Some(0) {
+            return Err(candid::Error::msg("Fee history window must be positive"));
+        }
+        Ok(())
+    }
+}
+validate_on_deserialize!(BtcGetFeeHistoryRequest);
+
+impl Validate for BtcGetSendHistoryRequest {
+    fn validate(&self) -> Result<(), candid::Error> {
+        match self.limit {
+            Some(0) => Err(candid::Error::msg("Send history limit must be positive")),
+            Some(limit) if limit > MAX_SEND_HISTORY_PAGE_LEN => Err(candid::Error::msg(format!(
+                "Send history limit is too large: {limit} > {MAX_SEND_HISTORY_PAGE_LEN}"
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+validate_on_deserialize!(BtcGetSendHistoryRequest);
+
+impl Validate for BtcGetDepositEventsRequest {
+    fn validate(&self) -> Result<(), candid::Error> {
+        match self.limit {
+            Some(0) => Err(candid::Error::msg("Deposit events limit must be positive")),
+            Some(limit) if limit > MAX_DEPOSIT_EVENTS_PAGE_LEN => Err(candid::Error::msg(format!(
+                "Deposit events limit is too large: {limit} > {MAX_DEPOSIT_EVENTS_PAGE_LEN}"
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+validate_on_deserialize!(BtcGetDepositEventsRequest);
+
 impl Validate for BtcGetPendingTransactionsRequest {
     fn validate(&self) -> Result<(), candid::Error> {
         validate_address(&self.address)
@@ -77,7 +333,12 @@ validate_on_deserialize!(BtcGetPendingTransactionsRequest);
 impl Validate for PendingTransaction {
     fn validate(&self) -> Result<(), candid::Error> {
         validate_txid_bytes(&self.txid)?;
-        validate_utxo_vec(&self.utxos)
+        if let Some(address) = &self.destination_address {
+            validate_address(address)?;
+        }
+        validate_utxo_vec(&self.utxos)?;
+        validate_replaced_txids(&self.replaced_txids)?;
+        validate_parent_txids(&self.parent_txids)
     }
 }
 validate_on_deserialize!(PendingTransaction);
@@ -85,7 +346,132 @@ validate_on_deserialize!(PendingTransaction);
 impl Validate for StoredPendingTransaction {
     fn validate(&self) -> Result<(), candid::Error> {
         validate_txid_bytes(&self.txid)?;
-        validate_utxo_vec(&self.utxos)
+        if let Some(address) = &self.destination_address {
+            validate_address(address)?;
+        }
+        validate_utxo_vec(&self.utxos)?;
+        validate_replaced_txids(self.replaced_txids.as_deref().unwrap_or_default())?;
+        validate_parent_txids(self.parent_txids.as_deref().unwrap_or_default())
     }
 }
 validate_on_deserialize!(StoredPendingTransaction);
+
+impl StoredPendingTransaction {
+    /// Returns the lifecycle status of the transaction.
+    #[must_use]
+    pub fn status(&self) -> BtcPendingTransactionStatus {
+        self.status.unwrap_or_default()
+    }
+
+    /// Returns whether the transaction still reserves its UTXOs.
+    #[must_use]
+    pub fn is_pending(&self) -> bool {
+        self.status() == BtcPendingTransactionStatus::Pending
+    }
+
+    /// Returns the unconfirmed UTXO of the change output of the transaction, if known.
+    #[must_use]
+    pub fn change_utxo(&self) -> Option<Utxo> {
+        self.change_output.as_ref().map(|change_output| Utxo {
+            outpoint: Outpoint {
+                txid: self.txid.clone(),
+                vout: change_output.vout,
+            },
+            value: change_output.amount_satoshis,
+            height: UNCONFIRMED_UTXO_HEIGHT,
+        })
+    }
+
+    /// Returns the pending transactions whose change this one spends.
+    #[must_use]
+    pub fn parent_txids(&self) -> &[Vec<u8>] {
+        self.parent_txids.as_deref().unwrap_or_default()
+    }
+}
+
+impl From<&StoredPendingTransaction> for PendingTransaction {
+    fn from(tx: &StoredPendingTransaction) -> Self {
+        PendingTransaction {
+            txid: tx.txid.clone(),
+            utxos: tx.utxos.clone(),
+            fee_satoshis: tx.fee_satoshis,
+            replaced_txids: tx.replaced_txids.clone().unwrap_or_default(),
+            destination_address: tx.destination_address.clone(),
+            amount_satoshis: tx.amount_satoshis,
+            network: tx.network,
+            status: tx.status(),
+            created_at_timestamp_ns: tx.created_at_timestamp_ns,
+            finalized_at_timestamp_ns: tx.finalized_at_timestamp_ns,
+            change_output: tx.change_output.clone(),
+            parent_txids: tx.parent_txids().to_vec(),
+        }
+    }
+}
+
+impl Default for BtcFeeTiersConfig {
+    /// Quartiles and median of the fee percentiles, between 1 and 1,000 sat/vB.
+    fn default() -> Self {
+        BtcFeeTiersConfig {
+            slow_percentile: 25,
+            normal_percentile: 50,
+            fast_percentile: 75,
+            min_fee_millisatoshi_per_vbyte: 1_000,
+            max_fee_millisatoshi_per_vbyte: 1_000_000,
+        }
+    }
+}
+
+impl Default for BtcPendingTransactionTtlConfig {
+    fn default() -> Self {
+        BtcPendingTransactionTtlConfig {
+            mainnet_ns: DEFAULT_PENDING_TRANSACTION_TTL_NS,
+            testnet_ns: DEFAULT_PENDING_TRANSACTION_TTL_NS,
+            regtest_ns: DEFAULT_PENDING_TRANSACTION_TTL_NS,
+        }
+    }
+}
+
+impl BtcPendingTransactionTtlConfig {
+    /// Returns the time to live of the pending transactions of the given network.
+    ///
+    /// Transactions stored without their network have the default time to live.
+    #[must_use]
+    pub fn ttl_ns(&self, network: Option<BitcoinNetwork>) -> u64 {
+        match network {
+            Some(BitcoinNetwork::Mainnet) => self.mainnet_ns,
+            Some(BitcoinNetwork::Testnet) => self.testnet_ns,
+            Some(BitcoinNetwork::Regtest) => self.regtest_ns,
+            None => DEFAULT_PENDING_TRANSACTION_TTL_NS,
+        }
+    }
+
+    /// Checks that no time to live is zero.
+    ///
+    /// # Errors
+    /// - If a time to live is zero.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.mainnet_ns == 0 || self.testnet_ns == 0 || self.regtest_ns == 0 {
+            return Err("Pending transaction time to live must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl BtcFeeTiersConfig {
+    /// Checks that the percentiles are increasing and below 100, and that the bounds are ordered.
+    ///
+    /// # Errors
+    /// - If the configuration is inconsistent.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.slow_percentile <= self.normal_percentile
+            && self.normal_percentile <= self.fast_percentile
+            && self.fast_percentile < 100)
+        {
+            return Err("Fee percentiles must be increasing and below 100".to_string());
+        }
+        if self.min_fee_millisatoshi_per_vbyte > self.max_fee_millisatoshi_per_vbyte {
+            return Err("Minimum fee rate must not exceed the maximum fee rate".to_string());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,248 @@
+//! BIP-21 payment URIs, such as `bitcoin:bc1q...?amount=0.001&label=Shop`.
+//!
+//! See [BIP-21](https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki).
+use std::{fmt, str::FromStr};
+
+use candid::{CandidType, Deserialize};
+use ic_cdk::api::management_canister::bitcoin::BitcoinNetwork;
+use serde::Serialize;
+
+use super::SelectedUtxosFeeRequest;
+use crate::types::{
+    account::{conversion::ParseError, BtcAddress, TokenAccountId},
+    contact::ContactAddressData,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// The URI scheme of Bitcoin payments.  Matched case-insensitively when parsing.
+const SCHEME: &str = "bitcoin:";
+
+/// The number of satoshi in a bitcoin; URIs express amounts in bitcoin.
+const SATOSHIS_PER_BTC: u64 = 100_000_000;
+
+/// The number of decimals of an amount in bitcoin.
+const AMOUNT_DECIMALS: usize = 8;
+
+/// A payment request, as shared in a `bitcoin:` URI.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct BtcPaymentUri {
+    pub address: BtcAddress,
+    /// The network of `address`.  Legacy addresses can't tell testnet from regtest, and are
+    /// reported as `Testnet`.
+    pub network: BitcoinNetwork,
+    /// The requested amount, converted from bitcoin.
+    pub amount_satoshis: Option<u64>,
+    /// The name of the recipient.
+    pub label: Option<String>,
+    /// A description of the payment.
+    pub message: Option<String>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub enum BtcPaymentUriError {
+    /// The URI does not start with `bitcoin:`.
+    InvalidScheme,
+    /// The URI has no valid Bitcoin address.
+    InvalidAddress(ParseError),
+    /// The amount is not a positive number of bitcoin with at most 8 decimals.
+    InvalidAmount,
+    /// A parameter is not properly percent-encoded UTF-8.
+    InvalidEncoding,
+    /// A parameter is given more than once.
+    DuplicateParameter(String),
+    /// The URI requires a parameter, prefixed by `req-`, that is not supported.
+    UnsupportedRequiredParameter(String),
+}
+
+impl FromStr for BtcPaymentUri {
+    type Err = BtcPaymentUriError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let uri = s
+            .get(..SCHEME.len())
+            .filter(|scheme| scheme.eq_ignore_ascii_case(SCHEME))
+            .map(|_| &s[SCHEME.len()..])
+            .ok_or(BtcPaymentUriError::InvalidScheme)?;
+        let (address, query) = uri.split_once('?').unwrap_or((uri, ""));
+        let (address, network) =
+            BtcAddress::parse_with_network(address).map_err(BtcPaymentUriError::InvalidAddress)?;
+
+        let mut payment_uri = BtcPaymentUri {
+            address,
+            network,
+            amount_satoshis: None,
+            label: None,
+            message: None,
+        };
+        for parameter in query.split('&').filter(|parameter| !parameter.is_empty()) {
+            let (key, value) = parameter.split_once('=').unwrap_or((parameter, ""));
+            let field = match key {
+                "amount" => {
+                    let amount_satoshis = parse_amount(value)?;
+                    if payment_uri
+                        .amount_satoshis
+                        .replace(amount_satoshis)
+                        .is_some()
+                    {
+                        return Err(BtcPaymentUriError::DuplicateParameter(key.to_string()));
+                    }
+                    continue;
+                }
+                "label" => &mut payment_uri.label,
+                "message" => &mut payment_uri.message,
+                _ if key.starts_with("req-") => {
+                    return Err(BtcPaymentUriError::UnsupportedRequiredParameter(
+                        key.to_string(),
+                    ))
+                }
+                // Optional parameters that are not supported are ignored.
+                _ => continue,
+            };
+            if field.replace(percent_decode(value)?).is_some() {
+                return Err(BtcPaymentUriError::DuplicateParameter(key.to_string()));
+            }
+        }
+        Ok(payment_uri)
+    }
+}
+
+impl fmt::Display for BtcPaymentUri {
+    /// Formats the payment request as a `bitcoin:` URI.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{SCHEME}{}", self.address.as_str())?;
+        let parameters = [
+            ("amount", self.amount_satoshis.map(format_amount)),
+            ("label", self.label.as_deref().map(percent_encode)),
+            ("message", self.message.as_deref().map(percent_encode)),
+        ];
+        let mut separator = '?';
+        for (key, value) in parameters {
+            if let Some(value) = value {
+                write!(f, "{separator}{key}={value}")?;
+                separator = '&';
+            }
+        }
+        Ok(())
+    }
+}
+
+impl BtcPaymentUri {
+    /// Returns the fee quote request for paying this payment request on the given network.
+    ///
+    /// Without a requested amount, the amount is 0 and must be set by the payer.
+    ///
+    /// # Errors
+    /// - `NetworkMismatch` if the address does not belong to the given network.
+    pub fn fee_request(
+        &self,
+        network: BitcoinNetwork,
+    ) -> Result<SelectedUtxosFeeRequest, ParseError> {
+        BtcAddress::parse_for_network(self.address.as_str(), network)?;
+        Ok(SelectedUtxosFeeRequest {
+            amount_satoshis: self.amount_satoshis.unwrap_or_default(),
+            network,
+            address_type: None,
+            min_confirmations: None,
+            strategy: None,
+            outputs: None,
+            destination_address: Some(self.address.as_str().to_string()),
+            fee_rate: None,
+            inputs: None,
+            send_max: None,
+            include_dust: None,
+        })
+    }
+
+    /// Returns the address of the recipient, to save as a contact address labelled with the name
+    /// of the recipient.
+    #[must_use]
+    pub fn contact_address(&self) -> ContactAddressData {
+        ContactAddressData {
+            token_account_id: TokenAccountId::Btc(self.address.clone()),
+            label: self.label.clone(),
+        }
+    }
+}
+
+/// Parses an amount in bitcoin, such as `0.0005`, into satoshi.
+fn parse_amount(value: &str) -> Result<u64, BtcPaymentUriError> {
+    let (whole, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let is_number = |digits: &str| digits.bytes().all(|c| c.is_ascii_digit());
+    if (whole.is_empty() && fraction.is_empty())
+        || !is_number(whole)
+        || !is_number(fraction)
+        || fraction.len() > AMOUNT_DECIMALS
+    {
+        return Err(BtcPaymentUriError::InvalidAmount);
+    }
+    let whole = if whole.is_empty() {
+        0
+    } else {
+        whole
+            .parse::<u64>()
+            .map_err(|_| BtcPaymentUriError::InvalidAmount)?
+    };
+    let fraction = format!("{fraction:0<AMOUNT_DECIMALS$}")
+        .parse::<u64>()
+        .map_err(|_| BtcPaymentUriError::InvalidAmount)?;
+    let amount_satoshis = whole
+        .checked_mul(SATOSHIS_PER_BTC)
+        .and_then(|whole| whole.checked_add(fraction))
+        .ok_or(BtcPaymentUriError::InvalidAmount)?;
+    if amount_satoshis == 0 {
+        return Err(BtcPaymentUriError::InvalidAmount);
+    }
+    Ok(amount_satoshis)
+}
+
+/// Formats an amount in satoshi as bitcoin, without trailing zeros.
+fn format_amount(amount_satoshis: u64) -> String {
+    let whole = amount_satoshis / SATOSHIS_PER_BTC;
+    let fraction = amount_satoshis % SATOSHIS_PER_BTC;
+    if fraction == 0 {
+        return whole.to_string();
+    }
+    let fraction = format!("{fraction:0>AMOUNT_DECIMALS$}");
+    format!("{whole}.{}", fraction.trim_end_matches('0'))
+}
+
+/// Decodes the percent-encoded UTF-8 value of a parameter.
+fn percent_decode(value: &str) -> Result<String, BtcPaymentUriError> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut remaining = value.bytes();
+    while let Some(byte) = remaining.next() {
+        if byte == b'%' {
+            let hex = [
+                remaining
+                    .next()
+                    .ok_or(BtcPaymentUriError::InvalidEncoding)?,
+                remaining
+                    .next()
+                    .ok_or(BtcPaymentUriError::InvalidEncoding)?,
+            ];
+            let mut decoded = [0u8; 1];
+            hex::decode_to_slice(hex, &mut decoded)
+                .map_err(|_| BtcPaymentUriError::InvalidEncoding)?;
+            bytes.push(decoded[0]);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| BtcPaymentUriError::InvalidEncoding)
+}
+
+/// Percent-encodes a parameter value, keeping only the unreserved characters of RFC 3986.
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+                char::from(byte).to_string()
+            } else {
+                format!("%{byte:02X}")
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,188 @@
+//! Tests for BIP-21 payment URIs.
+
+use pretty_assertions::assert_eq;
+
+use super::*;
+
+const ADDRESS: &str = "bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7c";
+const TESTNET_ADDRESS: &str = "tb1qqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5r7fxez";
+
+fn payment_uri(
+    amount_satoshis: Option<u64>,
+    label: Option<&str>,
+    message: Option<&str>,
+) -> BtcPaymentUri {
+    BtcPaymentUri {
+        address: BtcAddress::P2WPKH(ADDRESS.to_string()),
+        network: BitcoinNetwork::Mainnet,
+        amount_satoshis,
+        label: label.map(str::to_string),
+        message: message.map(str::to_string),
+    }
+}
+
+struct TestVector {
+    name: &'static str,
+    input: String,
+    expected: Result<BtcPaymentUri, BtcPaymentUriError>,
+}
+
+fn test_vectors() -> Vec<TestVector> {
+    vec![
+        TestVector {
+            name: "Address only",
+            input: format!("bitcoin:{ADDRESS}"),
+            expected: Ok(payment_uri(None, None, None)),
+        },
+        TestVector {
+            name: "Uppercase scheme",
+            input: format!("BITCOIN:{ADDRESS}"),
+            expected: Ok(payment_uri(None, None, None)),
+        },
+        TestVector {
+            name: "Amount, label and message",
+            input: format!(
+                "bitcoin:{ADDRESS}?amount=0.0005&label=Luke-Jr&message=Donation%20for%20xyz"
+            ),
+            expected: Ok(payment_uri(
+                Some(50_000),
+                Some("Luke-Jr"),
+                Some("Donation for xyz"),
+            )),
+        },
+        TestVector {
+            name: "Whole amount",
+            input: format!("bitcoin:{ADDRESS}?amount=20.3"),
+            expected: Ok(payment_uri(Some(2_030_000_000), None, None)),
+        },
+        TestVector {
+            name: "UTF-8 label",
+            input: format!("bitcoin:{ADDRESS}?label=Caf%C3%A9"),
+            expected: Ok(payment_uri(None, Some("Café"), None)),
+        },
+        TestVector {
+            name: "Unknown optional parameter",
+            input: format!("bitcoin:{ADDRESS}?amount=1&somethingyoudontunderstand=50"),
+            expected: Ok(payment_uri(Some(100_000_000), None, None)),
+        },
+        TestVector {
+            name: "Testnet address",
+            input: format!("bitcoin:{TESTNET_ADDRESS}"),
+            expected: Ok(BtcPaymentUri {
+                address: BtcAddress::P2WPKH(TESTNET_ADDRESS.to_string()),
+                network: BitcoinNetwork::Testnet,
+                amount_satoshis: None,
+                label: None,
+                message: None,
+            }),
+        },
+        TestVector {
+            name: "Unknown required parameter",
+            input: format!("bitcoin:{ADDRESS}?req-somethingyoudontunderstand=50"),
+            expected: Err(BtcPaymentUriError::UnsupportedRequiredParameter(
+                "req-somethingyoudontunderstand".to_string(),
+            )),
+        },
+        TestVector {
+            name: "Other scheme",
+            input: format!("litecoin:{ADDRESS}"),
+            expected: Err(BtcPaymentUriError::InvalidScheme),
+        },
+        TestVector {
+            name: "Address with a bad checksum",
+            input: "bitcoin:bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7d".to_string(),
+            expected: Err(BtcPaymentUriError::InvalidAddress(
+                ParseError::InvalidChecksum,
+            )),
+        },
+        TestVector {
+            name: "Too many decimals",
+            input: format!("bitcoin:{ADDRESS}?amount=0.000000001"),
+            expected: Err(BtcPaymentUriError::InvalidAmount),
+        },
+        TestVector {
+            name: "Zero amount",
+            input: format!("bitcoin:{ADDRESS}?amount=0"),
+            expected: Err(BtcPaymentUriError::InvalidAmount),
+        },
+        TestVector {
+            name: "Amount with an exponent",
+            input: format!("bitcoin:{ADDRESS}?amount=1e-3"),
+            expected: Err(BtcPaymentUriError::InvalidAmount),
+        },
+        TestVector {
+            name: "Overflowing amount",
+            input: format!("bitcoin:{ADDRESS}?amount=184467440738"),
+            expected: Err(BtcPaymentUriError::InvalidAmount),
+        },
+        TestVector {
+            name: "Duplicate amount",
+            input: format!("bitcoin:{ADDRESS}?amount=1&amount=2"),
+            expected: Err(BtcPaymentUriError::DuplicateParameter("amount".to_string())),
+        },
+        TestVector {
+            name: "Truncated percent-encoding",
+            input: format!("bitcoin:{ADDRESS}?label=Shop%2"),
+            expected: Err(BtcPaymentUriError::InvalidEncoding),
+        },
+    ]
+}
+
+#[test]
+fn payment_uris_can_be_parsed() {
+    for vector in test_vectors() {
+        assert_eq!(vector.expected, vector.input.parse(), "{}", vector.name);
+    }
+}
+
+#[test]
+fn payment_uris_can_be_formatted() {
+    let cases = [
+        (payment_uri(None, None, None), format!("bitcoin:{ADDRESS}")),
+        (
+            payment_uri(Some(2_030_000_000), Some("Café & co"), None),
+            format!("bitcoin:{ADDRESS}?amount=20.3&label=Caf%C3%A9%20%26%20co"),
+        ),
+        (
+            payment_uri(Some(1), None, Some("Order #42")),
+            format!("bitcoin:{ADDRESS}?amount=0.00000001&message=Order%20%2342"),
+        ),
+        (
+            payment_uri(Some(300_000_000), None, None),
+            format!("bitcoin:{ADDRESS}?amount=3"),
+        ),
+    ];
+    for (payment_uri, expected) in cases {
+        let uri = payment_uri.to_string();
+        assert_eq!(uri, expected);
+        assert_eq!(uri.parse(), Ok(payment_uri), "{uri}");
+    }
+}
+
+#[test]
+fn payment_uri_gives_a_fee_request_on_its_network() {
+    let payment_uri = payment_uri(Some(50_000), Some("Shop"), None);
+
+    let request = payment_uri
+        .fee_request(BitcoinNetwork::Mainnet)
+        .expect("the address is a mainnet address");
+    assert_eq!(request.amount_satoshis, 50_000);
+    assert_eq!(request.network, BitcoinNetwork::Mainnet);
+    assert_eq!(request.destination_address.as_deref(), Some(ADDRESS));
+
+    assert_eq!(
+        payment_uri.fee_request(BitcoinNetwork::Testnet),
+        Err(ParseError::NetworkMismatch)
+    );
+}
+
+#[test]
+fn payment_uri_gives_a_labelled_contact_address() {
+    let contact_address = payment_uri(None, Some("Shop"), None).contact_address();
+
+    assert_eq!(
+        contact_address.token_account_id,
+        TokenAccountId::Btc(BtcAddress::P2WPKH(ADDRESS.to_string()))
+    );
+    assert_eq!(contact_address.label.as_deref(), Some("Shop"));
+}
@@ -3,6 +3,7 @@
 use std::collections::BTreeMap;
 
 use candid::{CandidType, Deserialize};
+use ic_cdk::api::management_canister::bitcoin::BitcoinNetwork;
 
 use crate::types::{network::marker_trait::Network, Version};
 
@@ -61,6 +62,8 @@ pub enum BitcoinNetworkId {
     Mainnet,
 }
 impl Network for BitcoinNetworkId {}
+/// The bitcoin network of the management canister, for data of any bitcoin network.
+impl Network for BitcoinNetwork {}
 
 /// The authoritative list of EVM networks.
 ///
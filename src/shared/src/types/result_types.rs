@@ -1,10 +1,17 @@
 use candid::{CandidType, Deserialize};
+use ic_cdk::api::management_canister::bitcoin::Outpoint;
 use serde::Serialize;
 
 use super::{
     bitcoin::{
-        BtcAddPendingTransactionError, BtcGetPendingTransactionsError,
-        BtcGetPendingTransactionsReponse, SelectedUtxosFeeError, SelectedUtxosFeeResponse,
+        BtcAddPendingTransactionError, BtcBuildPsbtError, BtcBuildPsbtResponse,
+        BtcDepositSubscription, BtcGetBalanceError, BtcGetBalanceResponse,
+        BtcGetConsolidationAdviceError, BtcGetConsolidationAdviceResponse, BtcGetDustReportError,
+        BtcGetDustReportResponse, BtcGetFeeBumpQuotesError, BtcGetFeeBumpQuotesResponse,
+        BtcGetPendingTransactionsError, BtcGetPendingTransactionsReponse,
+        BtcReplacePendingTransactionError, BtcSendTransactionError, BtcSendTransactionResponse,
+        BtcSubscribeDepositsError, BtcUpdateFrozenUtxosError, SelectedUtxosFeeError,
+        SelectedUtxosFeeResponse, UpdateUserBtcSettingsError,
     },
     dapp::AddDappSettingsError,
     pow::{CreateChallengeError, CreateChallengeResponse},
@@ -12,6 +19,10 @@ use super::{
         AllowSigningError, AllowSigningResponse, GetAllowedCyclesError, GetAllowedCyclesResponse,
     },
     user_profile::{GetUserProfileError, UserProfile},
+    watch_only::{
+        GetWatchOnlyBtcBalanceError, GetWatchOnlyBtcBalanceResponse, WatchOnlyAddress,
+        WatchOnlyAddressError,
+    },
 };
 use crate::types::{
     agreement::UpdateAgreementsError,
@@ -236,6 +247,22 @@ impl From<Result<SelectedUtxosFeeResponse, SelectedUtxosFeeError>> for BtcSelect
     }
 }
 
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub enum BtcBuildPsbtResult {
+    /// The PSBT was built successfully.
+    Ok(BtcBuildPsbtResponse),
+    /// The PSBT was not built due to an error.
+    Err(BtcBuildPsbtError),
+}
+impl From<Result<BtcBuildPsbtResponse, BtcBuildPsbtError>> for BtcBuildPsbtResult {
+    fn from(result: Result<BtcBuildPsbtResponse, BtcBuildPsbtError>) -> Self {
+        match result {
+            Ok(response) => BtcBuildPsbtResult::Ok(response),
+            Err(err) => BtcBuildPsbtResult::Err(err),
+        }
+    }
+}
+
 #[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
 pub enum BtcGetFeePercentilesResult {
     /// The fee was selected successfully.
@@ -290,6 +317,58 @@ impl From<Result<(), BtcAddPendingTransactionError>> for BtcAddPendingTransactio
     }
 }
 
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub enum BtcReplacePendingTransactionResult {
+    /// The pending transaction was replaced successfully.
+    Ok(()),
+    /// The pending transaction was not replaced due to an error.
+    Err(BtcReplacePendingTransactionError),
+}
+impl From<Result<(), BtcReplacePendingTransactionError>> for BtcReplacePendingTransactionResult {
+    fn from(result: Result<(), BtcReplacePendingTransactionError>) -> Self {
+        match result {
+            Ok(()) => BtcReplacePendingTransactionResult::Ok(()),
+            Err(err) => BtcReplacePendingTransactionResult::Err(err),
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub enum BtcSendTransactionResult {
+    /// The transaction was broadcast and added to the pending transactions.
+    Ok(BtcSendTransactionResponse),
+    /// The transaction was not broadcast due to an error.
+    Err(BtcSendTransactionError),
+}
+impl From<Result<BtcSendTransactionResponse, BtcSendTransactionError>>
+    for BtcSendTransactionResult
+{
+    fn from(result: Result<BtcSendTransactionResponse, BtcSendTransactionError>) -> Self {
+        match result {
+            Ok(response) => BtcSendTransactionResult::Ok(response),
+            Err(err) => BtcSendTransactionResult::Err(err),
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub enum BtcGetFeeBumpQuotesResult {
+    /// The fee bump was quoted successfully.
+    Ok(BtcGetFeeBumpQuotesResponse),
+    /// The fee bump was not quoted due to an error.
+    Err(BtcGetFeeBumpQuotesError),
+}
+impl From<Result<BtcGetFeeBumpQuotesResponse, BtcGetFeeBumpQuotesError>>
+    for BtcGetFeeBumpQuotesResult
+{
+    fn from(result: Result<BtcGetFeeBumpQuotesResponse, BtcGetFeeBumpQuotesError>) -> Self {
+        match result {
+            Ok(response) => BtcGetFeeBumpQuotesResult::Ok(response),
+            Err(err) => BtcGetFeeBumpQuotesResult::Err(err),
+        }
+    }
+}
+
 #[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
 pub enum AllowSigningResult {
     /// The signing was allowed successfully.
@@ -355,3 +434,140 @@ impl From<Result<(), UpdateExperimentalFeaturesSettingsError>>
         }
     }
 }
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub enum UpdateUserBtcSettingsResult {
+    /// The user's bitcoin settings were updated successfully.
+    Ok(()),
+    /// The user's bitcoin settings were not updated due to an error.
+    Err(UpdateUserBtcSettingsError),
+}
+impl From<Result<(), UpdateUserBtcSettingsError>> for UpdateUserBtcSettingsResult {
+    fn from(result: Result<(), UpdateUserBtcSettingsError>) -> Self {
+        match result {
+            Ok(()) => UpdateUserBtcSettingsResult::Ok(()),
+            Err(err) => UpdateUserBtcSettingsResult::Err(err),
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub enum BtcGetBalanceResult {
+    /// The balance was computed successfully.
+    Ok(BtcGetBalanceResponse),
+    /// The balance was not computed due to an error.
+    Err(BtcGetBalanceError),
+}
+impl From<Result<BtcGetBalanceResponse, BtcGetBalanceError>> for BtcGetBalanceResult {
+    fn from(result: Result<BtcGetBalanceResponse, BtcGetBalanceError>) -> Self {
+        match result {
+            Ok(response) => BtcGetBalanceResult::Ok(response),
+            Err(err) => BtcGetBalanceResult::Err(err),
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub enum BtcGetDustReportResult {
+    /// The dust report was computed successfully.
+    Ok(BtcGetDustReportResponse),
+    /// The dust report was not computed due to an error.
+    Err(BtcGetDustReportError),
+}
+impl From<Result<BtcGetDustReportResponse, BtcGetDustReportError>> for BtcGetDustReportResult {
+    fn from(result: Result<BtcGetDustReportResponse, BtcGetDustReportError>) -> Self {
+        match result {
+            Ok(response) => BtcGetDustReportResult::Ok(response),
+            Err(err) => BtcGetDustReportResult::Err(err),
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub enum BtcUpdateFrozenUtxosResult {
+    /// The frozen UTXOs were updated successfully.  Returns all frozen UTXOs of the caller.
+    Ok(Vec<Outpoint>),
+    /// The frozen UTXOs were not updated due to an error.
+    Err(BtcUpdateFrozenUtxosError),
+}
+impl From<Result<Vec<Outpoint>, BtcUpdateFrozenUtxosError>> for BtcUpdateFrozenUtxosResult {
+    fn from(result: Result<Vec<Outpoint>, BtcUpdateFrozenUtxosError>) -> Self {
+        match result {
+            Ok(outpoints) => BtcUpdateFrozenUtxosResult::Ok(outpoints),
+            Err(err) => BtcUpdateFrozenUtxosResult::Err(err),
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub enum BtcGetConsolidationAdviceResult {
+    /// The consolidation was assessed successfully.
+    Ok(BtcGetConsolidationAdviceResponse),
+    /// The consolidation was not assessed due to an error.
+    Err(BtcGetConsolidationAdviceError),
+}
+impl From<Result<BtcGetConsolidationAdviceResponse, BtcGetConsolidationAdviceError>>
+    for BtcGetConsolidationAdviceResult
+{
+    fn from(
+        result: Result<BtcGetConsolidationAdviceResponse, BtcGetConsolidationAdviceError>,
+    ) -> Self {
+        match result {
+            Ok(response) => BtcGetConsolidationAdviceResult::Ok(response),
+            Err(err) => BtcGetConsolidationAdviceResult::Err(err),
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub enum AddWatchOnlyAddressResult {
+    /// The address was added, or its label updated.  Returns all watch-only addresses of the
+    /// caller.
+    Ok(Vec<WatchOnlyAddress>),
+    /// The address was not added due to an error.
+    Err(WatchOnlyAddressError),
+}
+impl From<Result<Vec<WatchOnlyAddress>, WatchOnlyAddressError>> for AddWatchOnlyAddressResult {
+    fn from(result: Result<Vec<WatchOnlyAddress>, WatchOnlyAddressError>) -> Self {
+        match result {
+            Ok(addresses) => AddWatchOnlyAddressResult::Ok(addresses),
+            Err(err) => AddWatchOnlyAddressResult::Err(err),
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub enum GetWatchOnlyBtcBalanceResult {
+    /// The balance was read successfully.
+    Ok(GetWatchOnlyBtcBalanceResponse),
+    /// The balance was not read due to an error.
+    Err(GetWatchOnlyBtcBalanceError),
+}
+impl From<Result<GetWatchOnlyBtcBalanceResponse, GetWatchOnlyBtcBalanceError>>
+    for GetWatchOnlyBtcBalanceResult
+{
+    fn from(result: Result<GetWatchOnlyBtcBalanceResponse, GetWatchOnlyBtcBalanceError>) -> Self {
+        match result {
+            Ok(response) => GetWatchOnlyBtcBalanceResult::Ok(response),
+            Err(err) => GetWatchOnlyBtcBalanceResult::Err(err),
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub enum BtcSubscribeDepositsResult {
+    /// The address was subscribed.  Returns all subscriptions of the caller.
+    Ok(Vec<BtcDepositSubscription>),
+    /// The address was not subscribed due to an error.
+    Err(BtcSubscribeDepositsError),
+}
+impl From<Result<Vec<BtcDepositSubscription>, BtcSubscribeDepositsError>>
+    for BtcSubscribeDepositsResult
+{
+    fn from(result: Result<Vec<BtcDepositSubscription>, BtcSubscribeDepositsError>) -> Self {
+        match result {
+            Ok(subscriptions) => BtcSubscribeDepositsResult::Ok(subscriptions),
+            Err(err) => BtcSubscribeDepositsResult::Err(err),
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use candid::{CandidType, Deserialize};
 
 use crate::types::{
-    dapp::DappSettings, experimental_feature::ExperimentalFeaturesSettings,
+    bitcoin::BtcSettings, dapp::DappSettings, experimental_feature::ExperimentalFeaturesSettings,
     network::NetworksSettings,
 };
 
@@ -10,4 +10,6 @@ pub struct Settings {
     pub networks: NetworksSettings,
     pub dapp: DappSettings,
     pub experimental_features: ExperimentalFeaturesSettings,
+    /// `None` for profiles created before the bitcoin settings existed.
+    pub btc: Option<BtcSettings>,
 }
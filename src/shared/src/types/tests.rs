@@ -4,11 +4,19 @@ mod bitcoin {
     //! Tests for the bitcoin types.
     use candid::{Decode, Encode};
     use ic_cdk::api::management_canister::bitcoin::{BitcoinNetwork, Outpoint, Utxo};
+    use pretty_assertions::assert_eq;
 
     use crate::{
         types::bitcoin::{
-            BtcAddPendingTransactionRequest, BtcGetPendingTransactionsRequest, PendingTransaction,
-            MAX_ADDRESS_LEN, MAX_TXID_BYTES, MAX_UTXOS_LEN,
+            BtcAddPendingTransactionRequest, BtcBuildPsbtRequest, BtcChangeOutput, BtcFeePriority,
+            BtcFeeRate, BtcFeeTiersConfig, BtcGetConsolidationAdviceRequest,
+            BtcGetDepositEventsRequest, BtcGetFeeHistoryRequest, BtcGetPendingTransactionsRequest,
+            BtcGetSendHistoryRequest, BtcPendingTransactionStatus, BtcPendingTransactionTtlConfig,
+            BtcReplacePendingTransactionRequest, BtcSendTransactionRequest, BtcTxOutput,
+            BtcUpdateFrozenUtxosRequest, PendingTransaction, SelectedUtxosFeeRequest,
+            DEFAULT_PENDING_TRANSACTION_TTL_NS, MAX_ADDRESS_LEN, MAX_DEPOSIT_EVENTS_PAGE_LEN,
+            MAX_FROZEN_UTXOS, MAX_OUTPUTS_LEN, MAX_REPLACEMENTS, MAX_SEND_HISTORY_PAGE_LEN,
+            MAX_TRANSACTION_BYTES, MAX_TXID_BYTES, MAX_UTXOS_LEN,
         },
         validate::{test_validate_on_deserialize, TestVector, Validate},
     };
@@ -22,15 +30,87 @@ mod bitcoin {
                     txid: vec![0; MAX_TXID_BYTES],
                     utxos: vec![],
                     network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    fee_satoshis: None,
+                    destination_address: None,
+                    amount_satoshis: None,
+                    change_output: None,
                 },
                 valid: true,
             },
+            TestVector {
+                description: "BtcAddPendingTransactionRequest with payment details",
+                input: BtcAddPendingTransactionRequest {
+                    txid: vec![0; MAX_TXID_BYTES],
+                    utxos: vec![],
+                    network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    fee_satoshis: Some(1_000),
+                    destination_address: Some("1".repeat(MAX_ADDRESS_LEN)),
+                    amount_satoshis: Some(10_000),
+                    change_output: None,
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "BtcAddPendingTransactionRequest with destination address too long",
+                input: BtcAddPendingTransactionRequest {
+                    txid: vec![0; MAX_TXID_BYTES],
+                    utxos: vec![],
+                    network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    fee_satoshis: Some(1_000),
+                    destination_address: Some("1".repeat(MAX_ADDRESS_LEN + 1)),
+                    amount_satoshis: Some(10_000),
+                    change_output: None,
+                },
+                valid: false,
+            },
+            TestVector {
+                description: "BtcAddPendingTransactionRequest with a change output",
+                input: BtcAddPendingTransactionRequest {
+                    txid: vec![0; MAX_TXID_BYTES],
+                    utxos: vec![],
+                    network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    fee_satoshis: Some(1_000),
+                    destination_address: None,
+                    amount_satoshis: None,
+                    change_output: Some(BtcChangeOutput {
+                        vout: 1,
+                        amount_satoshis: 5_000,
+                    }),
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "BtcAddPendingTransactionRequest with an empty change output",
+                input: BtcAddPendingTransactionRequest {
+                    txid: vec![0; MAX_TXID_BYTES],
+                    utxos: vec![],
+                    network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    fee_satoshis: Some(1_000),
+                    destination_address: None,
+                    amount_satoshis: None,
+                    change_output: Some(BtcChangeOutput {
+                        vout: 1,
+                        amount_satoshis: 0,
+                    }),
+                },
+                valid: false,
+            },
             TestVector {
                 description: "BtcAddPendingTransactionRequest with txid too long",
                 input: BtcAddPendingTransactionRequest {
                     txid: vec![0; MAX_TXID_BYTES + 1],
                     utxos: vec![],
                     network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    fee_satoshis: None,
+                    destination_address: None,
+                    amount_satoshis: None,
+                    change_output: None,
                 },
                 valid: false,
             },
@@ -47,6 +127,11 @@ mod bitcoin {
                         height: 0,
                     }],
                     network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    fee_satoshis: None,
+                    destination_address: None,
+                    amount_satoshis: None,
+                    change_output: None,
                 },
                 valid: true,
             },
@@ -63,6 +148,11 @@ mod bitcoin {
                         height: 0,
                     }],
                     network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    fee_satoshis: None,
+                    destination_address: None,
+                    amount_satoshis: None,
+                    change_output: None,
                 },
                 valid: false,
             },
@@ -82,6 +172,11 @@ mod bitcoin {
                         MAX_UTXOS_LEN + 1
                     ],
                     network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    fee_satoshis: None,
+                    destination_address: None,
+                    amount_satoshis: None,
+                    change_output: None,
                 },
                 valid: false,
             },
@@ -118,6 +213,16 @@ mod bitcoin {
                 input: PendingTransaction {
                     txid: vec![0; MAX_TXID_BYTES],
                     utxos: vec![],
+                    fee_satoshis: None,
+                    replaced_txids: vec![],
+                    destination_address: None,
+                    amount_satoshis: None,
+                    network: None,
+                    status: BtcPendingTransactionStatus::Pending,
+                    created_at_timestamp_ns: 0,
+                    finalized_at_timestamp_ns: None,
+                    change_output: None,
+                    parent_txids: vec![],
                 },
                 valid: true,
             },
@@ -126,6 +231,16 @@ mod bitcoin {
                 input: PendingTransaction {
                     txid: vec![0; MAX_TXID_BYTES + 1],
                     utxos: vec![],
+                    fee_satoshis: None,
+                    replaced_txids: vec![],
+                    destination_address: None,
+                    amount_satoshis: None,
+                    network: None,
+                    status: BtcPendingTransactionStatus::Pending,
+                    created_at_timestamp_ns: 0,
+                    finalized_at_timestamp_ns: None,
+                    change_output: None,
+                    parent_txids: vec![],
                 },
                 valid: false,
             },
@@ -144,6 +259,16 @@ mod bitcoin {
                         };
                         MAX_UTXOS_LEN + 1
                     ],
+                    fee_satoshis: None,
+                    replaced_txids: vec![],
+                    destination_address: None,
+                    amount_satoshis: None,
+                    network: None,
+                    status: BtcPendingTransactionStatus::Pending,
+                    created_at_timestamp_ns: 0,
+                    finalized_at_timestamp_ns: None,
+                    change_output: None,
+                    parent_txids: vec![],
                 },
                 valid: false,
             },
@@ -159,11 +284,825 @@ mod bitcoin {
                         value: 0,
                         height: 0,
                     }],
+                    fee_satoshis: None,
+                    replaced_txids: vec![],
+                    destination_address: None,
+                    amount_satoshis: None,
+                    network: None,
+                    status: BtcPendingTransactionStatus::Pending,
+                    created_at_timestamp_ns: 0,
+                    finalized_at_timestamp_ns: None,
+                    change_output: None,
+                    parent_txids: vec![],
+                },
+                valid: false,
+            },
+            TestVector {
+                description: "PendingTransaction with max number of replaced transactions",
+                input: PendingTransaction {
+                    txid: vec![0; MAX_TXID_BYTES],
+                    utxos: vec![],
+                    fee_satoshis: Some(1_000),
+                    replaced_txids: vec![vec![0; MAX_TXID_BYTES]; MAX_REPLACEMENTS],
+                    destination_address: None,
+                    amount_satoshis: None,
+                    network: None,
+                    status: BtcPendingTransactionStatus::Pending,
+                    created_at_timestamp_ns: 0,
+                    finalized_at_timestamp_ns: None,
+                    change_output: None,
+                    parent_txids: vec![],
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "PendingTransaction with too many replaced transactions",
+                input: PendingTransaction {
+                    txid: vec![0; MAX_TXID_BYTES],
+                    utxos: vec![],
+                    fee_satoshis: Some(1_000),
+                    replaced_txids: vec![vec![0; MAX_TXID_BYTES]; MAX_REPLACEMENTS + 1],
+                    destination_address: None,
+                    amount_satoshis: None,
+                    network: None,
+                    status: BtcPendingTransactionStatus::Pending,
+                    created_at_timestamp_ns: 0,
+                    finalized_at_timestamp_ns: None,
+                    change_output: None,
+                    parent_txids: vec![],
+                },
+                valid: false,
+            },
+            TestVector {
+                description: "PendingTransaction with destination address too long",
+                input: PendingTransaction {
+                    txid: vec![0; MAX_TXID_BYTES],
+                    utxos: vec![],
+                    fee_satoshis: Some(1_000),
+                    replaced_txids: vec![],
+                    destination_address: Some("1".repeat(MAX_ADDRESS_LEN + 1)),
+                    amount_satoshis: Some(1_000),
+                    network: Some(BitcoinNetwork::Mainnet),
+                    status: BtcPendingTransactionStatus::Confirmed,
+                    created_at_timestamp_ns: 0,
+                    finalized_at_timestamp_ns: Some(1),
+                    change_output: None,
+                    parent_txids: vec![],
+                },
+                valid: false,
+            },
+            TestVector {
+                description: "PendingTransaction with max number of parent transactions",
+                input: PendingTransaction {
+                    txid: vec![0; MAX_TXID_BYTES],
+                    utxos: vec![],
+                    fee_satoshis: Some(1_000),
+                    replaced_txids: vec![],
+                    destination_address: None,
+                    amount_satoshis: None,
+                    network: None,
+                    status: BtcPendingTransactionStatus::Pending,
+                    created_at_timestamp_ns: 0,
+                    finalized_at_timestamp_ns: None,
+                    change_output: None,
+                    parent_txids: vec![vec![0; MAX_TXID_BYTES]; MAX_UTXOS_LEN],
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "PendingTransaction with too many parent transactions",
+                input: PendingTransaction {
+                    txid: vec![0; MAX_TXID_BYTES],
+                    utxos: vec![],
+                    fee_satoshis: Some(1_000),
+                    replaced_txids: vec![],
+                    destination_address: None,
+                    amount_satoshis: None,
+                    network: None,
+                    status: BtcPendingTransactionStatus::Pending,
+                    created_at_timestamp_ns: 0,
+                    finalized_at_timestamp_ns: None,
+                    change_output: None,
+                    parent_txids: vec![vec![0; MAX_TXID_BYTES]; MAX_UTXOS_LEN + 1],
+                },
+                valid: false,
+            },
+            TestVector {
+                description: "PendingTransaction with a parent txid too long",
+                input: PendingTransaction {
+                    txid: vec![0; MAX_TXID_BYTES],
+                    utxos: vec![],
+                    fee_satoshis: Some(1_000),
+                    replaced_txids: vec![],
+                    destination_address: None,
+                    amount_satoshis: None,
+                    network: None,
+                    status: BtcPendingTransactionStatus::Pending,
+                    created_at_timestamp_ns: 0,
+                    finalized_at_timestamp_ns: None,
+                    change_output: None,
+                    parent_txids: vec![vec![0; MAX_TXID_BYTES + 1]],
+                },
+                valid: false,
+            },
+            TestVector {
+                description: "PendingTransaction with a replaced txid too long",
+                input: PendingTransaction {
+                    txid: vec![0; MAX_TXID_BYTES],
+                    utxos: vec![],
+                    fee_satoshis: Some(1_000),
+                    replaced_txids: vec![vec![0; MAX_TXID_BYTES + 1]],
+                    destination_address: None,
+                    amount_satoshis: None,
+                    network: None,
+                    status: BtcPendingTransactionStatus::Pending,
+                    created_at_timestamp_ns: 0,
+                    finalized_at_timestamp_ns: None,
+                    change_output: None,
+                    parent_txids: vec![],
                 },
                 valid: false,
             },
         ]
     );
+
+    test_validate_on_deserialize!(
+        BtcReplacePendingTransactionRequest,
+        vec![
+            TestVector {
+                description: "BtcReplacePendingTransactionRequest with max length txids",
+                input: BtcReplacePendingTransactionRequest {
+                    replaced_txid: vec![0; MAX_TXID_BYTES],
+                    txid: vec![0; MAX_TXID_BYTES],
+                    utxos: vec![],
+                    network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    fee_satoshis: 1_000,
+                    destination_address: None,
+                    amount_satoshis: None,
+                    change_output: None,
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "BtcReplacePendingTransactionRequest with address too long",
+                input: BtcReplacePendingTransactionRequest {
+                    replaced_txid: vec![0; MAX_TXID_BYTES],
+                    txid: vec![0; MAX_TXID_BYTES],
+                    utxos: vec![],
+                    network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    fee_satoshis: 1_000,
+                    destination_address: Some("1".repeat(MAX_ADDRESS_LEN + 1)),
+                    amount_satoshis: Some(1_000),
+                    change_output: None,
+                },
+                valid: false,
+            },
+            TestVector {
+                description: "BtcReplacePendingTransactionRequest with replaced txid too long",
+                input: BtcReplacePendingTransactionRequest {
+                    replaced_txid: vec![0; MAX_TXID_BYTES + 1],
+                    txid: vec![0; MAX_TXID_BYTES],
+                    utxos: vec![],
+                    network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    fee_satoshis: 1_000,
+                    destination_address: None,
+                    amount_satoshis: None,
+                    change_output: None,
+                },
+                valid: false,
+            },
+            TestVector {
+                description: "BtcReplacePendingTransactionRequest with too many utxos",
+                input: BtcReplacePendingTransactionRequest {
+                    replaced_txid: vec![0; MAX_TXID_BYTES],
+                    txid: vec![0; MAX_TXID_BYTES],
+                    utxos: vec![
+                        Utxo {
+                            outpoint: Outpoint {
+                                txid: vec![0; MAX_TXID_BYTES],
+                                vout: 0,
+                            },
+                            value: 0,
+                            height: 0,
+                        };
+                        MAX_UTXOS_LEN + 1
+                    ],
+                    network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    fee_satoshis: 1_000,
+                    destination_address: None,
+                    amount_satoshis: None,
+                    change_output: None,
+                },
+                valid: false,
+            },
+        ]
+    );
+
+    test_validate_on_deserialize!(
+        BtcGetFeeHistoryRequest,
+        vec![
+            TestVector {
+                description: "BtcGetFeeHistoryRequest without window",
+                input: BtcGetFeeHistoryRequest {
+                    network: BitcoinNetwork::Mainnet,
+                    window_ns: None,
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "BtcGetFeeHistoryRequest with window",
+                input: BtcGetFeeHistoryRequest {
+                    network: BitcoinNetwork::Mainnet,
+                    window_ns: Some(1),
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "BtcGetFeeHistoryRequest with empty window",
+                input: BtcGetFeeHistoryRequest {
+                    network: BitcoinNetwork::Mainnet,
+                    window_ns: Some(0),
+                },
+                valid: false,
+            },
+        ]
+    );
+
+    test_validate_on_deserialize!(
+        BtcGetSendHistoryRequest,
+        vec![
+            TestVector {
+                description: "BtcGetSendHistoryRequest with defaults",
+                input: BtcGetSendHistoryRequest {
+                    network: None,
+                    before_id: None,
+                    limit: None,
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "BtcGetSendHistoryRequest with max limit",
+                input: BtcGetSendHistoryRequest {
+                    network: Some(BitcoinNetwork::Mainnet),
+                    before_id: Some(10),
+                    limit: Some(MAX_SEND_HISTORY_PAGE_LEN),
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "BtcGetSendHistoryRequest with zero limit",
+                input: BtcGetSendHistoryRequest {
+                    network: None,
+                    before_id: None,
+                    limit: Some(0),
+                },
+                valid: false,
+            },
+            TestVector {
+                description: "BtcGetSendHistoryRequest with limit too large",
+                input: BtcGetSendHistoryRequest {
+                    network: None,
+                    before_id: None,
+                    limit: Some(MAX_SEND_HISTORY_PAGE_LEN + 1),
+                },
+                valid: false,
+            },
+        ]
+    );
+
+    test_validate_on_deserialize!(
+        BtcGetDepositEventsRequest,
+        vec![
+            TestVector {
+                description: "BtcGetDepositEventsRequest with defaults",
+                input: BtcGetDepositEventsRequest {
+                    network: None,
+                    before_id: None,
+                    limit: None,
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "BtcGetDepositEventsRequest with max limit",
+                input: BtcGetDepositEventsRequest {
+                    network: Some(BitcoinNetwork::Mainnet),
+                    before_id: Some(10),
+                    limit: Some(MAX_DEPOSIT_EVENTS_PAGE_LEN),
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "BtcGetDepositEventsRequest with zero limit",
+                input: BtcGetDepositEventsRequest {
+                    network: None,
+                    before_id: None,
+                    limit: Some(0),
+                },
+                valid: false,
+            },
+            TestVector {
+                description: "BtcGetDepositEventsRequest with limit too large",
+                input: BtcGetDepositEventsRequest {
+                    network: None,
+                    before_id: None,
+                    limit: Some(MAX_DEPOSIT_EVENTS_PAGE_LEN + 1),
+                },
+                valid: false,
+            },
+        ]
+    );
+
+    test_validate_on_deserialize!(
+        BtcBuildPsbtRequest,
+        vec![
+            TestVector {
+                description: "BtcBuildPsbtRequest with outputs",
+                input: BtcBuildPsbtRequest {
+                    network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    outputs: vec![output(1_000), output(2_000)],
+                    fee_rate: Some(BtcFeeRate::SatoshiPerVbyte(5)),
+                    strategy: None,
+                    min_confirmations: None,
+                    include_dust: None,
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "BtcBuildPsbtRequest without outputs",
+                input: BtcBuildPsbtRequest {
+                    network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    outputs: vec![],
+                    fee_rate: None,
+                    strategy: None,
+                    min_confirmations: None,
+                    include_dust: None,
+                },
+                valid: false,
+            },
+            TestVector {
+                description: "BtcBuildPsbtRequest with overflowing outputs",
+                input: BtcBuildPsbtRequest {
+                    network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    outputs: vec![output(u64::MAX), output(1)],
+                    fee_rate: None,
+                    strategy: None,
+                    min_confirmations: None,
+                    include_dust: None,
+                },
+                valid: false,
+            },
+            TestVector {
+                description: "BtcBuildPsbtRequest with an output on another network",
+                input: BtcBuildPsbtRequest {
+                    network: BitcoinNetwork::Testnet,
+                    address_type: None,
+                    outputs: vec![
+                        output(1_000),
+                        BtcTxOutput {
+                            destination_address: "bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7c"
+                                .to_string(),
+                            amount_satoshis: 2_000,
+                        },
+                    ],
+                    fee_rate: None,
+                    strategy: None,
+                    min_confirmations: None,
+                    include_dust: None,
+                },
+                valid: false,
+            },
+            TestVector {
+                description: "BtcBuildPsbtRequest with a zero fee rate",
+                input: BtcBuildPsbtRequest {
+                    network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    outputs: vec![output(1_000)],
+                    fee_rate: Some(BtcFeeRate::SatoshiPerVbyte(0)),
+                    strategy: None,
+                    min_confirmations: None,
+                    include_dust: None,
+                },
+                valid: false,
+            },
+        ]
+    );
+
+    test_validate_on_deserialize!(
+        BtcSendTransactionRequest,
+        vec![
+            TestVector {
+                description: "BtcSendTransactionRequest with max length transaction",
+                input: BtcSendTransactionRequest {
+                    network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    transaction: vec![0; MAX_TRANSACTION_BYTES],
+                    outputs: vec![output(1_000), output(2_000)],
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "BtcSendTransactionRequest with too long transaction",
+                input: BtcSendTransactionRequest {
+                    network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    transaction: vec![0; MAX_TRANSACTION_BYTES + 1],
+                    outputs: vec![output(1_000)],
+                },
+                valid: false,
+            },
+            TestVector {
+                description: "BtcSendTransactionRequest without outputs",
+                input: BtcSendTransactionRequest {
+                    network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    transaction: vec![0; 100],
+                    outputs: vec![],
+                },
+                valid: false,
+            },
+        ]
+    );
+
+    fn batch_request(outputs: Vec<BtcTxOutput>, amount_satoshis: u64) -> SelectedUtxosFeeRequest {
+        SelectedUtxosFeeRequest {
+            amount_satoshis,
+            network: BitcoinNetwork::Mainnet,
+            address_type: None,
+            min_confirmations: None,
+            strategy: None,
+            outputs: Some(outputs),
+            destination_address: None,
+            fee_rate: None,
+            inputs: None,
+            send_max: None,
+            include_dust: None,
+        }
+    }
+
+    fn output(amount_satoshis: u64) -> BtcTxOutput {
+        BtcTxOutput {
+            destination_address: "1".repeat(MAX_ADDRESS_LEN),
+            amount_satoshis,
+        }
+    }
+
+    test_validate_on_deserialize!(
+        BtcGetConsolidationAdviceRequest,
+        vec![
+            TestVector {
+                description: "BtcGetConsolidationAdviceRequest with default fee rate",
+                input: BtcGetConsolidationAdviceRequest {
+                    network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    fee_rate: None,
+                    min_confirmations: None,
+                    build_psbt: true,
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "BtcGetConsolidationAdviceRequest with a custom fee rate",
+                input: BtcGetConsolidationAdviceRequest {
+                    network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    fee_rate: Some(BtcFeeRate::SatoshiPerVbyte(1)),
+                    min_confirmations: None,
+                    build_psbt: false,
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "BtcGetConsolidationAdviceRequest with a zero fee rate",
+                input: BtcGetConsolidationAdviceRequest {
+                    network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    fee_rate: Some(BtcFeeRate::SatoshiPerVbyte(0)),
+                    min_confirmations: None,
+                    build_psbt: false,
+                },
+                valid: false,
+            },
+        ]
+    );
+
+    test_validate_on_deserialize!(
+        SelectedUtxosFeeRequest,
+        vec![
+            TestVector {
+                description: "SelectedUtxosFeeRequest without outputs",
+                input: SelectedUtxosFeeRequest {
+                    amount_satoshis: 1_000,
+                    network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    min_confirmations: None,
+                    strategy: None,
+                    outputs: None,
+                    destination_address: None,
+                    fee_rate: None,
+                    inputs: None,
+                    send_max: None,
+                    include_dust: None,
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "SelectedUtxosFeeRequest with a destination address",
+                input: SelectedUtxosFeeRequest {
+                    amount_satoshis: 1_000,
+                    network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    min_confirmations: None,
+                    strategy: None,
+                    outputs: None,
+                    destination_address: Some("1".repeat(MAX_ADDRESS_LEN)),
+                    fee_rate: None,
+                    inputs: None,
+                    send_max: None,
+                    include_dust: None,
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "SelectedUtxosFeeRequest with a destination address too long",
+                input: SelectedUtxosFeeRequest {
+                    amount_satoshis: 1_000,
+                    network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    min_confirmations: None,
+                    strategy: None,
+                    outputs: None,
+                    destination_address: Some("1".repeat(MAX_ADDRESS_LEN + 1)),
+                    fee_rate: None,
+                    inputs: None,
+                    send_max: None,
+                    include_dust: None,
+                },
+                valid: false,
+            },
+            TestVector {
+                description:
+                    "SelectedUtxosFeeRequest with a regtest destination address on regtest",
+                input: SelectedUtxosFeeRequest {
+                    amount_satoshis: 1_000,
+                    network: BitcoinNetwork::Regtest,
+                    address_type: None,
+                    min_confirmations: None,
+                    strategy: None,
+                    outputs: None,
+                    destination_address: Some(
+                        "bcrt1qqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5z5tpwxqergd3c8g7rusq7snjn6"
+                            .to_string()
+                    ),
+                    fee_rate: None,
+                    inputs: None,
+                    send_max: None,
+                    include_dust: None,
+                },
+                valid: true,
+            },
+            TestVector {
+                description:
+                    "SelectedUtxosFeeRequest with a testnet destination address on mainnet",
+                input: SelectedUtxosFeeRequest {
+                    amount_satoshis: 1_000,
+                    network: BitcoinNetwork::Mainnet,
+                    address_type: None,
+                    min_confirmations: None,
+                    strategy: None,
+                    outputs: None,
+                    destination_address: Some(
+                        "tb1qqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5r7fxez".to_string()
+                    ),
+                    fee_rate: None,
+                    inputs: None,
+                    send_max: None,
+                    include_dust: None,
+                },
+                valid: false,
+            },
+            TestVector {
+                description: "SelectedUtxosFeeRequest with a priority fee rate",
+                input: SelectedUtxosFeeRequest {
+                    fee_rate: Some(BtcFeeRate::Priority(BtcFeePriority::Fast)),
+                    ..batch_request(vec![output(1_000)], 1_000)
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "SelectedUtxosFeeRequest with a custom fee rate",
+                input: SelectedUtxosFeeRequest {
+                    fee_rate: Some(BtcFeeRate::SatoshiPerVbyte(12)),
+                    ..batch_request(vec![output(1_000)], 1_000)
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "SelectedUtxosFeeRequest with a zero custom fee rate",
+                input: SelectedUtxosFeeRequest {
+                    fee_rate: Some(BtcFeeRate::SatoshiPerVbyte(0)),
+                    ..batch_request(vec![output(1_000)], 1_000)
+                },
+                valid: false,
+            },
+            TestVector {
+                description: "SelectedUtxosFeeRequest with both outputs and a destination address",
+                input: SelectedUtxosFeeRequest {
+                    destination_address: Some("1".repeat(MAX_ADDRESS_LEN)),
+                    ..batch_request(vec![output(1_000)], 1_000)
+                },
+                valid: false,
+            },
+            TestVector {
+                description: "SelectedUtxosFeeRequest with send max and outputs",
+                input: SelectedUtxosFeeRequest {
+                    send_max: Some(true),
+                    ..batch_request(vec![output(1_000)], 1_000)
+                },
+                valid: false,
+            },
+            TestVector {
+                description: "SelectedUtxosFeeRequest with send max to a destination address",
+                input: SelectedUtxosFeeRequest {
+                    outputs: None,
+                    destination_address: Some("1".repeat(MAX_ADDRESS_LEN)),
+                    send_max: Some(true),
+                    ..batch_request(vec![], 0)
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "SelectedUtxosFeeRequest with outputs matching the amount",
+                input: batch_request(vec![output(1_000), output(2_000)], 3_000),
+                valid: true,
+            },
+            TestVector {
+                description: "SelectedUtxosFeeRequest with outputs not matching the amount",
+                input: batch_request(vec![output(1_000), output(2_000)], 1_000),
+                valid: false,
+            },
+            TestVector {
+                description: "SelectedUtxosFeeRequest with empty outputs",
+                input: batch_request(vec![], 0),
+                valid: false,
+            },
+            TestVector {
+                description: "SelectedUtxosFeeRequest with a zero amount output",
+                input: batch_request(vec![output(1_000), output(0)], 1_000),
+                valid: false,
+            },
+            TestVector {
+                description: "SelectedUtxosFeeRequest with max number of outputs",
+                input: batch_request(vec![output(1); MAX_OUTPUTS_LEN], MAX_OUTPUTS_LEN as u64),
+                valid: true,
+            },
+            TestVector {
+                description: "SelectedUtxosFeeRequest with too many outputs",
+                input: batch_request(
+                    vec![output(1); MAX_OUTPUTS_LEN + 1],
+                    MAX_OUTPUTS_LEN as u64 + 1
+                ),
+                valid: false,
+            },
+            TestVector {
+                description: "SelectedUtxosFeeRequest with destination address too long",
+                input: batch_request(
+                    vec![BtcTxOutput {
+                        destination_address: "1".repeat(MAX_ADDRESS_LEN + 1),
+                        amount_satoshis: 1_000,
+                    }],
+                    1_000
+                ),
+                valid: false,
+            },
+            TestVector {
+                description: "SelectedUtxosFeeRequest with overflowing outputs",
+                input: batch_request(vec![output(u64::MAX), output(1)], 0),
+                valid: false,
+            },
+            TestVector {
+                description: "SelectedUtxosFeeRequest with max number of inputs",
+                input: SelectedUtxosFeeRequest {
+                    inputs: Some(vec![outpoint(MAX_TXID_BYTES); MAX_UTXOS_LEN]),
+                    send_max: None,
+                    ..batch_request(vec![output(1_000)], 1_000)
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "SelectedUtxosFeeRequest with too many inputs",
+                input: SelectedUtxosFeeRequest {
+                    inputs: Some(vec![outpoint(MAX_TXID_BYTES); MAX_UTXOS_LEN + 1]),
+                    send_max: None,
+                    ..batch_request(vec![output(1_000)], 1_000)
+                },
+                valid: false,
+            },
+            TestVector {
+                description: "SelectedUtxosFeeRequest with an input txid too long",
+                input: SelectedUtxosFeeRequest {
+                    inputs: Some(vec![outpoint(MAX_TXID_BYTES + 1)]),
+                    send_max: None,
+                    ..batch_request(vec![output(1_000)], 1_000)
+                },
+                valid: false,
+            },
+        ]
+    );
+
+    fn outpoint(txid_len: usize) -> Outpoint {
+        Outpoint {
+            txid: vec![0; txid_len],
+            vout: 0,
+        }
+    }
+
+    test_validate_on_deserialize!(
+        BtcUpdateFrozenUtxosRequest,
+        vec![
+            TestVector {
+                description: "BtcUpdateFrozenUtxosRequest with max number of outpoints",
+                input: BtcUpdateFrozenUtxosRequest {
+                    freeze: vec![outpoint(MAX_TXID_BYTES); MAX_FROZEN_UTXOS],
+                    unfreeze: vec![outpoint(MAX_TXID_BYTES); MAX_FROZEN_UTXOS],
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "BtcUpdateFrozenUtxosRequest with too many outpoints to freeze",
+                input: BtcUpdateFrozenUtxosRequest {
+                    freeze: vec![outpoint(MAX_TXID_BYTES); MAX_FROZEN_UTXOS + 1],
+                    unfreeze: vec![],
+                },
+                valid: false,
+            },
+            TestVector {
+                description: "BtcUpdateFrozenUtxosRequest with too many outpoints to unfreeze",
+                input: BtcUpdateFrozenUtxosRequest {
+                    freeze: vec![],
+                    unfreeze: vec![outpoint(MAX_TXID_BYTES); MAX_FROZEN_UTXOS + 1],
+                },
+                valid: false,
+            },
+            TestVector {
+                description: "BtcUpdateFrozenUtxosRequest with a txid too long",
+                input: BtcUpdateFrozenUtxosRequest {
+                    freeze: vec![outpoint(MAX_TXID_BYTES + 1)],
+                    unfreeze: vec![],
+                },
+                valid: false,
+            },
+        ]
+    );
+
+    #[test]
+    fn btc_fee_tiers_config_validate() {
+        assert!(BtcFeeTiersConfig::default().validate().is_ok());
+        assert!(BtcFeeTiersConfig {
+            slow_percentile: 60,
+            ..BtcFeeTiersConfig::default()
+        }
+        .validate()
+        .is_err());
+        assert!(BtcFeeTiersConfig {
+            fast_percentile: 100,
+            ..BtcFeeTiersConfig::default()
+        }
+        .validate()
+        .is_err());
+        assert!(BtcFeeTiersConfig {
+            min_fee_millisatoshi_per_vbyte: 2_000_000,
+            ..BtcFeeTiersConfig::default()
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn btc_pending_transaction_ttl_config() {
+        let config = BtcPendingTransactionTtlConfig {
+            mainnet_ns: 1,
+            testnet_ns: 2,
+            regtest_ns: 3,
+        };
+        assert!(config.validate().is_ok());
+        assert_eq!(config.ttl_ns(Some(BitcoinNetwork::Mainnet)), 1);
+        assert_eq!(config.ttl_ns(Some(BitcoinNetwork::Testnet)), 2);
+        assert_eq!(config.ttl_ns(Some(BitcoinNetwork::Regtest)), 3);
+        assert_eq!(config.ttl_ns(None), DEFAULT_PENDING_TRANSACTION_TTL_NS);
+        assert!(BtcPendingTransactionTtlConfig {
+            testnet_ns: 0,
+            ..config
+        }
+        .validate()
+        .is_err());
+    }
 }
 
 mod contact_image {
@@ -866,3 +1805,96 @@ mod user_profile {
         ]
     );
 }
+
+mod watch_only {
+    //! Tests for the watch-only address types.
+    use candid::{Decode, Encode};
+
+    use crate::{
+        types::watch_only::{
+            AddWatchOnlyAddressRequest, GetWatchOnlyBtcBalanceRequest,
+            RemoveWatchOnlyAddressRequest, MAX_WATCH_ONLY_ADDRESS_LEN, MAX_WATCH_ONLY_LABEL_LEN,
+        },
+        validate::{test_validate_on_deserialize, TestVector, Validate},
+    };
+
+    test_validate_on_deserialize!(
+        AddWatchOnlyAddressRequest,
+        vec![
+            TestVector {
+                description: "AddWatchOnlyAddressRequest with max length address and label",
+                input: AddWatchOnlyAddressRequest {
+                    address: "a".repeat(MAX_WATCH_ONLY_ADDRESS_LEN),
+                    label: "a".repeat(MAX_WATCH_ONLY_LABEL_LEN),
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "AddWatchOnlyAddressRequest with too long address",
+                input: AddWatchOnlyAddressRequest {
+                    address: "a".repeat(MAX_WATCH_ONLY_ADDRESS_LEN + 1),
+                    label: "Vault".to_string(),
+                },
+                valid: false,
+            },
+            TestVector {
+                description: "AddWatchOnlyAddressRequest with too long label",
+                input: AddWatchOnlyAddressRequest {
+                    address: "a".repeat(MAX_WATCH_ONLY_ADDRESS_LEN),
+                    label: "a".repeat(MAX_WATCH_ONLY_LABEL_LEN + 1),
+                },
+                valid: false,
+            },
+            TestVector {
+                description: "AddWatchOnlyAddressRequest with padded label",
+                input: AddWatchOnlyAddressRequest {
+                    address: "a".repeat(MAX_WATCH_ONLY_ADDRESS_LEN),
+                    label: " Vault ".to_string(),
+                },
+                valid: false,
+            },
+        ]
+    );
+
+    test_validate_on_deserialize!(
+        RemoveWatchOnlyAddressRequest,
+        vec![
+            TestVector {
+                description: "RemoveWatchOnlyAddressRequest with max length address",
+                input: RemoveWatchOnlyAddressRequest {
+                    address: "a".repeat(MAX_WATCH_ONLY_ADDRESS_LEN),
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "RemoveWatchOnlyAddressRequest with too long address",
+                input: RemoveWatchOnlyAddressRequest {
+                    address: "a".repeat(MAX_WATCH_ONLY_ADDRESS_LEN + 1),
+                },
+                valid: false,
+            },
+        ]
+    );
+
+    test_validate_on_deserialize!(
+        GetWatchOnlyBtcBalanceRequest,
+        vec![
+            TestVector {
+                description: "GetWatchOnlyBtcBalanceRequest with max length address",
+                input: GetWatchOnlyBtcBalanceRequest {
+                    address: "a".repeat(MAX_WATCH_ONLY_ADDRESS_LEN),
+                    min_confirmations: Some(6),
+                },
+                valid: true,
+            },
+            TestVector {
+                description: "GetWatchOnlyBtcBalanceRequest with too long address",
+                input: GetWatchOnlyBtcBalanceRequest {
+                    address: "a".repeat(MAX_WATCH_ONLY_ADDRESS_LEN + 1),
+                    min_confirmations: None,
+                },
+                valid: false,
+            },
+        ]
+    );
+}
@@ -0,0 +1,75 @@
+//! Watch-only addresses.
+//!
+//! A user can watch addresses whose keys are held elsewhere, e.g. in cold storage, to monitor them
+//! in the same wallet.  Watch-only addresses can't be spent from.
+
+use candid::{CandidType, Deserialize};
+use ic_cdk::api::management_canister::bitcoin::BitcoinNetwork;
+
+use super::{account::TokenAccountId, signer::RateLimitError};
+
+/// The maximum number of watch-only addresses of a user.
+pub const MAX_WATCH_ONLY_ADDRESSES: usize = 50;
+
+/// The maximum length of a watch-only address, in characters.  The longest addresses are the
+/// Kaspa testnet ones, 73 characters long.
+pub const MAX_WATCH_ONLY_ADDRESS_LEN: usize = 80;
+
+/// The maximum length of the label of a watch-only address, in characters.
+pub const MAX_WATCH_ONLY_LABEL_LEN: usize = 50;
+
+#[derive(CandidType, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct WatchOnlyAddress {
+    pub token_account_id: TokenAccountId,
+    pub label: String,
+    pub created_at_timestamp_ns: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, Eq, PartialEq)]
+#[serde(remote = "Self")]
+pub struct AddWatchOnlyAddressRequest {
+    /// A BTC, ETH, SOL or Kaspa address.
+    pub address: String,
+    pub label: String,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, Eq, PartialEq)]
+#[serde(remote = "Self")]
+pub struct RemoveWatchOnlyAddressRequest {
+    pub address: String,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub enum WatchOnlyAddressError {
+    /// The address is not a valid BTC, ETH, SOL or Kaspa address.
+    InvalidAddress,
+    /// The user already watches the maximum number of addresses.
+    TooManyAddresses { max: u64 },
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, Eq, PartialEq)]
+#[serde(remote = "Self")]
+pub struct GetWatchOnlyBtcBalanceRequest {
+    /// A bitcoin address watched by the caller.
+    pub address: String,
+    pub min_confirmations: Option<u32>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct GetWatchOnlyBtcBalanceResponse {
+    /// The network of the address, given by its prefix.
+    pub network: BitcoinNetwork,
+    pub balance_satoshis: u64,
+    pub utxo_count: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub enum GetWatchOnlyBtcBalanceError {
+    /// The address is not a bitcoin address watched by the caller.
+    AddressNotWatched,
+    /// The caller has exceeded the call rate limit.
+    RateLimited(RateLimitError),
+    InternalError {
+        msg: String,
+    },
+}